@@ -0,0 +1,103 @@
+//! Measures the per-frame cost of unwinding a deep stack with cached unwind rules, with
+//! unwind rules that are translated from DWARF CFI for every frame, and with DWARF CFI
+//! that needs to be evaluated for every frame.
+//!
+//! Run with `cargo bench --manifest-path benchmarks/Cargo.toml`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Bencher, Criterion, Throughput};
+use framehop::test_utils::{module_with_eh_frame, CfiInstruction, EhFrameBuilder};
+use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+use framehop::{FrameAddress, Unwinder};
+
+const FRAME_COUNT: usize = 500;
+
+/// An unwinder with a module whose code at 0x1000100..0x1000120 is covered by an FDE with
+/// the given instructions.
+fn unwinder_with_fde(fde_instructions: &[CfiInstruction]) -> UnwinderX86_64<Vec<u8>> {
+    let mut builder = EhFrameBuilder::x86_64();
+    builder.add_fde(0x100..0x120, fde_instructions);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(module_with_eh_frame(
+        "synthetic",
+        0x1000000..0x1001000,
+        builder.build(),
+    ));
+    unwinder
+}
+
+/// Unwinds a stack of `FRAME_COUNT` frames, which all return to `return_address`, in
+/// every iteration. If `cold_cache` is true, the cache is cleared before each frame.
+fn unwind_deep_stack(
+    b: &mut Bencher,
+    unwinder: &UnwinderX86_64<Vec<u8>>,
+    return_address: u64,
+    cold_cache: bool,
+) {
+    // Every frame is 16 bytes large, with the caller's rbp at [rsp] and the return
+    // address at [rsp + 8], so that it can be unwound both with rbp and with rsp+16. The
+    // outermost frame returns to address zero, which ends unwinding.
+    // The innermost frame starts at 0x10, because a null frame pointer ends unwinding.
+    let mut stack = vec![0u64; FRAME_COUNT * 2 + 4];
+    for frame in 1..FRAME_COUNT {
+        stack[frame * 2] = (frame as u64 + 1) * 16;
+        stack[frame * 2 + 1] = return_address;
+    }
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut cache = CacheX86_64::new();
+
+    b.iter(|| {
+        let mut regs = UnwindRegsX86_64::new(return_address, 0x10, 0x10);
+        let mut address = FrameAddress::from_return_address(return_address).unwrap();
+        let mut frames = 1;
+        loop {
+            if cold_cache {
+                cache.clear();
+            }
+            match unwinder
+                .unwind_frame(address, &mut regs, &mut cache, &mut read_stack)
+                .unwrap()
+            {
+                Some(return_address) => {
+                    address = FrameAddress::from_return_address(return_address).unwrap();
+                    frames += 1;
+                }
+                None => break,
+            }
+        }
+        assert_eq!(black_box(frames), FRAME_COUNT);
+    });
+}
+
+fn deep_stack(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deep_stack");
+    group.throughput(Throughput::Elements(FRAME_COUNT as u64));
+
+    let mut framepointer_unwinder = UnwinderX86_64::new();
+    framepointer_unwinder.add_framepointer_range(0x2000000..0x2001000);
+    group.bench_function("frame pointer rule, cached", |b| {
+        unwind_deep_stack(b, &framepointer_unwinder, 0x2000108, false)
+    });
+
+    // CFA=rsp+16. This is translated into an unwind rule.
+    let rule_unwinder = unwinder_with_fde(&[CfiInstruction::DefCfaOffset(16)]);
+    group.bench_function("DWARF rule, cached", |b| {
+        unwind_deep_stack(b, &rule_unwinder, 0x1000108, false)
+    });
+    group.bench_function("DWARF rule, cold cache", |b| {
+        unwind_deep_stack(b, &rule_unwinder, 0x1000108, true)
+    });
+
+    // CFA=DW_OP_breg7 (rsp) +16. Expressions can't be translated into unwind rules, so
+    // every frame is unwound by evaluating the CFI.
+    let expression_unwinder = unwinder_with_fde(&[CfiInstruction::DefCfaExpression(&[0x77, 0x10])]);
+    group.bench_function("DWARF CFA expression", |b| {
+        unwind_deep_stack(b, &expression_unwinder, 0x1000108, false)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, deep_stack);
+criterion_main!(benches);
@@ -1,10 +1,32 @@
+use gimli::Vendor;
+
 use super::unwind_rule::UnwindRuleAarch64;
 use super::unwindregs::UnwindRegsAarch64;
 use crate::arch::Arch;
+use crate::register_set::RegisterSet;
 
 /// The Aarch64 CPU architecture.
 pub struct ArchAarch64;
 impl Arch for ArchAarch64 {
     type UnwindRule = UnwindRuleAarch64;
     type UnwindRegs = UnwindRegsAarch64;
+    const ADDRESS_SIZE: u8 = 8;
+    const VENDOR: Vendor = Vendor::AArch64;
+
+    fn placeholder_regs() -> UnwindRegsAarch64 {
+        UnwindRegsAarch64::new(0, 0, 0)
+    }
+
+    fn bypasses_rule_cache(regs: &UnwindRegsAarch64) -> bool {
+        regs.callee_saved().is_some()
+    }
+
+    fn unknown_registers(regs: &UnwindRegsAarch64) -> RegisterSet {
+        regs.unknown_registers()
+    }
+
+    fn is_past_stack_end(regs: &UnwindRegsAarch64) -> bool {
+        regs.stack_end()
+            .is_some_and(|stack_end| regs.sp() >= stack_end)
+    }
 }
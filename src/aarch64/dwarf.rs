@@ -5,35 +5,54 @@ use gimli::{
 
 use super::{arch::ArchAarch64, unwind_rule::UnwindRuleAarch64, unwindregs::UnwindRegsAarch64};
 
+use crate::diagnostics::diagnostic;
+use crate::error::Error;
+use crate::memory::{
+    complete_sync, AsyncMemoryAccess, MemoryAccess, SyncMemory, TrackReadFailures,
+};
+use crate::register_set::RegisterSet;
 use crate::unwind_result::UnwindResult;
+use crate::FrameAddress;
 
 use crate::dwarf::{
-    eval_cfa_rule, eval_register_rule, ConversionError, DwarfUnwindRegs, DwarfUnwinderError,
-    DwarfUnwinding,
+    eval_cfa_rule, eval_error, eval_register_rule, scaled_offset, ConversionError, DwarfUnwindRegs,
+    DwarfUnwinderError, DwarfUnwinding,
 };
 
 impl DwarfUnwindRegs for UnwindRegsAarch64 {
     fn get(&self, register: Register) -> Option<u64> {
+        let unknown = self.unknown_registers();
         match register {
+            AArch64::SP if unknown.contains(RegisterSet::SP) => None,
+            AArch64::X29 if unknown.contains(RegisterSet::FP) => None,
+            AArch64::X30 if unknown.contains(RegisterSet::LR) => None,
             AArch64::SP => Some(self.sp()),
             AArch64::X29 => Some(self.fp()),
             AArch64::X30 => Some(self.lr()),
+            Register(n @ 19..=28) => Some(self.callee_saved()?[usize::from(n - 19)]),
             _ => None,
         }
     }
 }
 
 impl DwarfUnwinding for ArchAarch64 {
-    fn unwind_frame<F, R, UCS, ES>(
+    const RETURN_ADDRESS_REGISTER: Register = AArch64::X30;
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        AArch64::register_name(register)
+    }
+
+    async fn unwind_frame<F, R, UCS, ES>(
         section: &impl UnwindSection<R>,
         unwind_info: &UnwindTableRow<R::Offset, UCS>,
         encoding: Encoding,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
+        lookup_svma: u64,
         read_stack: &mut F,
     ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         R: Reader,
         UCS: UnwindContextStorage<R::Offset>,
         ES: EvaluationStorage<R>,
@@ -42,16 +61,32 @@ impl DwarfUnwinding for ArchAarch64 {
         let fp_rule = unwind_info.register(AArch64::X29);
         let lr_rule = unwind_info.register(AArch64::X30);
 
-        match translate_into_unwind_rule(cfa_rule, &fp_rule, &lr_rule) {
-            Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
-            Err(_err) => {
-                // Could not translate into a cacheable unwind rule. Fall back to the generic path.
-                // eprintln!("Unwind rule translation failed: {:?}", err);
+        // Unwind rules only restore fp and lr, so they can't be used if the callee-saved
+        // registers are tracked.
+        if regs.callee_saved().is_none() {
+            match Self::rule_for_row(unwind_info) {
+                Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
+                Err(err) => {
+                    // Could not translate into a cacheable unwind rule. Fall back to the generic path.
+                    diagnostic!(
+                        "Unwind rule translation failed at address 0x{:x}: {:?}",
+                        lookup_svma,
+                        err
+                    );
+                }
             }
         }
 
-        let cfa = eval_cfa_rule::<R, _, ES>(section, cfa_rule, encoding, regs)
-            .ok_or(DwarfUnwinderError::CouldNotRecoverCfa)?;
+        let read_stack = &mut TrackReadFailures::new(read_stack);
+        let cfa = eval_cfa_rule::<R, _, _, ES>(section, cfa_rule, encoding, regs, read_stack)
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverCfa,
+                )
+            })?;
 
         let lr = regs.lr();
         let fp = regs.fp();
@@ -61,40 +96,104 @@ impl DwarfUnwinding for ArchAarch64 {
             if cfa <= sp {
                 return Err(DwarfUnwinderError::StackPointerMovedBackwards);
             }
-            let fp = eval_register_rule::<R, F, _, ES>(
+            let fp = eval_register_rule::<R, _, _, ES>(
                 section, fp_rule, cfa, encoding, fp, regs, read_stack,
             )
+            .await
             .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
-            let lr = eval_register_rule::<R, F, _, ES>(
+            let lr = eval_register_rule::<R, _, _, ES>(
                 section, lr_rule, cfa, encoding, lr, regs, read_stack,
             )
-            .ok_or(DwarfUnwinderError::CouldNotRecoverReturnAddress)?;
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverReturnAddress,
+                )
+            })?;
             (fp, lr)
         } else {
             // For the first frame, be more lenient when encountering errors.
             // TODO: Find evidence of what this gives us. I think on macOS the prologue often has Unknown register rules
             // and we only encounter prologues for the first frame.
-            let fp = eval_register_rule::<R, F, _, ES>(
+            let fp = eval_register_rule::<R, _, _, ES>(
                 section, fp_rule, cfa, encoding, fp, regs, read_stack,
             )
+            .await
             .unwrap_or(fp);
-            let lr = eval_register_rule::<R, F, _, ES>(
+            let lr = eval_register_rule::<R, _, _, ES>(
                 section, lr_rule, cfa, encoding, lr, regs, read_stack,
             )
+            .await
             .unwrap_or(lr);
             (fp, lr)
         };
 
+        // All rules need to be evaluated with the register values of this frame, so the
+        // callee-saved registers are only updated at the end.
+        let mut callee_saved = regs.callee_saved();
+        if let Some(callee_saved) = &mut callee_saved {
+            for (i, value) in callee_saved.iter_mut().enumerate() {
+                let rule = unwind_info.register(Register(19 + i as u16));
+                *value = eval_register_rule::<R, _, _, ES>(
+                    section, rule, cfa, encoding, *value, regs, read_stack,
+                )
+                .await
+                .unwrap_or(*value);
+            }
+        }
+
         regs.set_fp(fp);
         regs.set_sp(cfa);
         regs.set_lr(lr);
+        if let Some(callee_saved) = callee_saved {
+            regs.track_callee_saved(callee_saved);
+        }
 
-        Ok(UnwindResult::Uncacheable(lr))
+        // Code which signs its return address with pointer authentication tracks this in
+        // the unwind information, see `is_return_address_signed`. Unsigned return
+        // addresses are used as they are.
+        let return_address = if is_return_address_signed(unwind_info) {
+            regs.lr_mask().strip_ptr_auth(lr)
+        } else {
+            lr
+        };
+        Ok(UnwindResult::Uncacheable(return_address))
     }
 
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
         UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp
     }
+
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        translate_into_unwind_rule(
+            row.cfa(),
+            &row.register(AArch64::X29),
+            &row.register(AArch64::X30),
+        )
+    }
+}
+
+/// Returns true if the return address is signed at this row, i.e. if bit 0 of the
+/// `RA_SIGN_STATE` pseudo-register is set. `DW_CFA_AARCH64_negate_ra_state` toggles
+/// this bit, for example after a `paciasp` instruction in the prologue and after an
+/// `autiasp` instruction in the epilogue.
+fn is_return_address_signed<RO, UCS>(row: &UnwindTableRow<RO, UCS>) -> bool
+where
+    RO: ReaderOffset,
+    UCS: UnwindContextStorage<RO>,
+{
+    matches!(
+        row.register(AArch64::RA_SIGN_STATE),
+        RegisterRule::Constant(state) if state & 1 != 0
+    )
 }
 
 fn register_rule_to_cfa_offset<RO: ReaderOffset>(
@@ -116,7 +215,7 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
         CfaRule::RegisterAndOffset { register, offset } => match *register {
             AArch64::SP => {
                 let sp_offset_by_16 =
-                    u16::try_from(offset / 16).map_err(|_| ConversionError::SpOffsetDoesNotFit)?;
+                    scaled_offset(*offset, 16, ConversionError::SpOffsetDoesNotFit)?;
                 let lr_cfa_offset = register_rule_to_cfa_offset(lr_rule)?;
                 let fp_cfa_offset = register_rule_to_cfa_offset(fp_rule)?;
                 match (lr_cfa_offset, fp_cfa_offset) {
@@ -142,21 +241,27 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
                         }
                     }
                     (Some(lr_cfa_offset), None) => {
-                        let lr_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + lr_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
+                        let lr_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + lr_cfa_offset,
+                            8,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
                         Ok(UnwindRuleAarch64::OffsetSpAndRestoreLr {
                             sp_offset_by_16,
                             lr_storage_offset_from_sp_by_8,
                         })
                     }
                     (Some(lr_cfa_offset), Some(fp_cfa_offset)) => {
-                        let lr_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + lr_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
-                        let fp_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + fp_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                        let lr_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + lr_cfa_offset,
+                            8,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
+                        let fp_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + fp_cfa_offset,
+                            8,
+                            ConversionError::FpStorageOffsetDoesNotFit,
+                        )?;
                         Ok(UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
                             sp_offset_by_16,
                             fp_storage_offset_from_sp_by_8,
@@ -173,14 +278,22 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
                 if *offset == 16 && fp_cfa_offset == -16 && lr_cfa_offset == -8 {
                     Ok(UnwindRuleAarch64::UseFramePointer)
                 } else {
-                    let sp_offset_from_fp_by_8 = u16::try_from(offset / 8)
-                        .map_err(|_| ConversionError::SpOffsetFromFpDoesNotFit)?;
-                    let lr_storage_offset_from_fp_by_8 =
-                        i16::try_from((offset + lr_cfa_offset) / 8)
-                            .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
-                    let fp_storage_offset_from_fp_by_8 =
-                        i16::try_from((offset + fp_cfa_offset) / 8)
-                            .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                    // The CFA can be at any offset from fp, for example in functions
+                    // which realign sp, and fp and lr don't have to be stored in a frame
+                    // record at the CFA. Offsets which the rule can't represent exactly
+                    // are evaluated with gimli instead.
+                    let sp_offset_from_fp_by_8 =
+                        scaled_offset(*offset, 8, ConversionError::SpOffsetFromFpDoesNotFit)?;
+                    let lr_storage_offset_from_fp_by_8 = scaled_offset(
+                        offset + lr_cfa_offset,
+                        8,
+                        ConversionError::LrStorageOffsetDoesNotFit,
+                    )?;
+                    let fp_storage_offset_from_fp_by_8 = scaled_offset(
+                        offset + fp_cfa_offset,
+                        8,
+                        ConversionError::FpStorageOffsetDoesNotFit,
+                    )?;
                     Ok(UnwindRuleAarch64::UseFramepointerWithOffsets {
                         sp_offset_from_fp_by_8,
                         fp_storage_offset_from_fp_by_8,
@@ -193,3 +306,32 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
         CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
     }
 }
+
+/// Unwind a single frame with the FDE at `fde_offset` in the given `.eh_frame` data,
+/// without creating a [`Module`](crate::Module) and an unwinder. This is meant for
+/// fuzzing and testing the DWARF CFI evaluation.
+///
+/// `eh_frame_svma` is the address of the `.eh_frame` section, which is needed for
+/// pc-relative pointers, and the code address in `address` is used as an SVMA. The data
+/// needs to be little-endian. If the FDE doesn't cover the address, the frame pointer
+/// is used, like during normal unwinding. Unlike during normal unwinding, evaluation
+/// errors are returned as [`Error::Dwarf`](crate::Error::Dwarf).
+pub fn unwind_frame_with_raw_eh_frame<F: MemoryAccess>(
+    eh_frame: &[u8],
+    eh_frame_svma: u64,
+    fde_offset: u32,
+    address: FrameAddress,
+    regs: &mut UnwindRegsAarch64,
+    read_stack: &mut F,
+) -> Result<Option<u64>, Error> {
+    complete_sync(
+        crate::dwarf::unwind_frame_with_raw_eh_frame::<ArchAarch64, _>(
+            eh_frame,
+            eh_frame_svma,
+            fde_offset,
+            address,
+            regs,
+            &mut SyncMemory(read_stack),
+        ),
+    )
+}
@@ -18,6 +18,7 @@ enum EpilogueStepResult {
 enum EpilogueResult {
     ProbablyStillInBody(UnexpectedInstructionType),
     ReachedFunctionEndWithoutReturn,
+    FoundStackPointerRestoreFromFramePointer,
     FoundReturnOrTailCall {
         sp_offset: i32,
         fp_offset_from_initial_sp: Option<i32>,
@@ -67,6 +68,14 @@ impl EpilogueDetectorAarch64 {
         }
         let mut word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
         bytes = &bytes[4..];
+        // Detect `mov sp, fp` followed by `ldp fp, lr, [sp], #0xXX`. At this point the
+        // frame record is still at fp, so the frame pointer rule applies.
+        if word == 0x910003bf && bytes.len() >= 4 {
+            let next_word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if next_word & 0xffc07fff == 0xa8c07bfd {
+                return EpilogueResult::FoundStackPointerRestoreFromFramePointer;
+            }
+        }
         match Self::analyze_instruction(word) {
             EpilogueInstructionType::NotExpectedInEpilogue => {
                 return EpilogueResult::ProbablyStillInBody(UnexpectedInstructionType::Unknown)
@@ -405,6 +414,9 @@ pub fn unwind_rule_from_detected_epilogue(
     match detector.analyze_slice(bytes, pc_offset) {
         EpilogueResult::ProbablyStillInBody(_)
         | EpilogueResult::ReachedFunctionEndWithoutReturn => None,
+        EpilogueResult::FoundStackPointerRestoreFromFramePointer => {
+            Some(UnwindRuleAarch64::UseFramePointer)
+        }
         EpilogueResult::FoundReturnOrTailCall {
             sp_offset,
             fp_offset_from_initial_sp,
@@ -475,6 +487,37 @@ mod test {
         assert_eq!(unwind_rule_from_detected_epilogue(bytes, 20), None);
     }
 
+    #[test]
+    fn test_epilogue_with_mov_sp_fp() {
+        // 100003f50 bf 03 00 91     mov        sp, x29
+        // 100003f54 fd 7b c1 a8     ldp        x29, x30, [sp], #0x10
+        // 100003f58 c0 03 5f d6     ret
+
+        let bytes = &[
+            0xbf, 0x03, 0x00, 0x91, 0xfd, 0x7b, 0xc1, 0xa8, 0xc0, 0x03, 0x5f, 0xd6,
+        ];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 0),
+            Some(UnwindRuleAarch64::UseFramePointer)
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 4),
+            Some(UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
+                sp_offset_by_16: 1,
+                fp_storage_offset_from_sp_by_8: 0,
+                lr_storage_offset_from_sp_by_8: 1,
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 8),
+            Some(UnwindRuleAarch64::NoOp)
+        );
+
+        // `mov sp, x29` without the frame record load is not treated as an epilogue.
+        let bytes = &[0xbf, 0x03, 0x00, 0x91, 0xe0, 0x03, 0x00, 0x91];
+        assert_eq!(unwind_rule_from_detected_epilogue(bytes, 0), None);
+    }
+
     #[test]
     fn test_epilogue_with_retab() {
         //         _malloc_zone_realloc epilogue
@@ -22,4 +22,18 @@ impl InstructionAnalysis for ArchAarch64 {
     ) -> Option<Self::UnwindRule> {
         unwind_rule_from_detected_epilogue(text_bytes, pc_offset)
     }
+
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+        let Some(bytes) = return_offset
+            .checked_sub(4)
+            .and_then(|start| text_bytes.get(start..return_offset))
+        else {
+            return false;
+        };
+        let insn = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        // bl, blr, and the pointer authenticating blraa / blrab / blraaz / blrabz
+        insn & 0xfc00_0000 == 0x9400_0000
+            || insn & 0xffff_fc1f == 0xd63f_0000
+            || insn & 0xfeff_f800 == 0xd63f_0800
+    }
 }
@@ -94,3 +94,50 @@ impl CompactUnwindInfoUnwinding for ArchAarch64 {
         Ok(CuiUnwindResult::ExecRule(rule))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn function(opcode: u32) -> Function {
+        Function {
+            start_address: 0x1000,
+            end_address: 0x1100,
+            opcode,
+        }
+    }
+
+    #[test]
+    fn test_arm64_opcodes() {
+        // UNWIND_ARM64_MODE_FRAMELESS with a stack size of 3 * 16 bytes.
+        let frameless = 0x0200_3000;
+        assert!(matches!(
+            ArchAarch64::unwind_frame(function(frameless), true, 0x20, None),
+            Ok(CuiUnwindResult::ExecRule(UnwindRuleAarch64::OffsetSp {
+                sp_offset_by_16: 3
+            }))
+        ));
+        assert!(matches!(
+            ArchAarch64::unwind_frame(function(frameless), false, 0x20, None),
+            Err(CompactUnwindInfoUnwinderError::CallerCannotBeFrameless)
+        ));
+
+        // UNWIND_ARM64_MODE_FRAME, with x19/x20 and d8/d9 saved. Only fp and lr matter.
+        let frame = 0x0400_0021;
+        for is_first_frame in [true, false] {
+            assert!(matches!(
+                ArchAarch64::unwind_frame(function(frame), is_first_frame, 0x20, None),
+                Ok(CuiUnwindResult::ExecRule(
+                    UnwindRuleAarch64::UseFramePointer
+                ))
+            ));
+        }
+
+        // UNWIND_ARM64_MODE_DWARF defers to the FDE at the given eh_frame offset.
+        let dwarf = 0x0300_1234;
+        assert!(matches!(
+            ArchAarch64::unwind_frame(function(dwarf), false, 0x20, None),
+            Ok(CuiUnwindResult::NeedDwarf(0x1234))
+        ));
+    }
+}
@@ -12,6 +12,7 @@ mod unwindregs;
 
 pub use arch::*;
 pub use cache::*;
+pub use dwarf::unwind_frame_with_raw_eh_frame;
 pub use unwind_rule::*;
 pub use unwinder::*;
 pub use unwindregs::*;
@@ -1,10 +1,19 @@
 use super::unwindregs::UnwindRegsAarch64;
 use crate::add_signed::checked_add_signed;
 use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
 
 use crate::unwind_rule::UnwindRule;
 
+/// Unwind rules are `Copy` and at most 8 bytes large, which is checked at compile time,
+/// so that they can be cached and stored in large numbers. The variants and their fields
+/// are public, so rules can also be built outside of framehop, for example for
+/// [`UnwinderAarch64::add_module_with_rules`](crate::aarch64::UnwinderAarch64::add_module_with_rules). The
+/// memory layout is not guaranteed; to persist rules, store their variants and fields, or
+/// enable the `serde` feature, which implements `Serialize` and `Deserialize` for them.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnwindRuleAarch64 {
     /// (sp, fp, lr) = (sp, fp, lr)
     /// Only possible for the first frame. Subsequent frames must get the
@@ -45,9 +54,42 @@ pub enum UnwindRuleAarch64 {
     },
 }
 
+const _: () = assert!(core::mem::size_of::<UnwindRuleAarch64>() <= 8);
+
+impl UnwindRuleAarch64 {
+    /// The registers whose current values this rule reads, see [`RegisterSet`].
+    ///
+    /// Registers which the rule passes through to the caller unchanged are not included.
+    /// If the rule behaves differently for the first frame, the registers needed by either
+    /// case are included. This lets a caller which didn't capture all registers know in
+    /// advance whether a rule can be applied.
+    pub fn used_registers(&self) -> RegisterSet {
+        match self {
+            UnwindRuleAarch64::NoOp => RegisterSet::LR,
+            UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp => {
+                RegisterSet::LR | RegisterSet::SP | RegisterSet::FP
+            }
+            UnwindRuleAarch64::OffsetSp { .. }
+            | UnwindRuleAarch64::OffsetSpIfFirstFrameOtherwiseStackEndsHere { .. } => {
+                RegisterSet::SP | RegisterSet::LR
+            }
+            UnwindRuleAarch64::OffsetSpAndRestoreLr { .. }
+            | UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr { .. } => RegisterSet::SP,
+            UnwindRuleAarch64::UseFramePointer
+            | UnwindRuleAarch64::UseFramepointerWithOffsets { .. } => {
+                RegisterSet::SP | RegisterSet::FP
+            }
+        }
+    }
+}
+
 impl UnwindRule for UnwindRuleAarch64 {
     type UnwindRegs = UnwindRegsAarch64;
 
+    fn used_registers(&self) -> RegisterSet {
+        UnwindRuleAarch64::used_registers(self)
+    }
+
     fn rule_for_stub_functions() -> Self {
         UnwindRuleAarch64::NoOp
     }
@@ -58,14 +100,14 @@ impl UnwindRule for UnwindRuleAarch64 {
         UnwindRuleAarch64::UseFramePointer
     }
 
-    fn exec<F>(
+    async fn exec<F>(
         self,
         is_first_frame: bool,
         regs: &mut UnwindRegsAarch64,
         read_stack: &mut F,
     ) -> Result<Option<u64>, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
     {
         let lr = regs.lr();
         let sp = regs.sp();
@@ -84,9 +126,8 @@ impl UnwindRule for UnwindRuleAarch64 {
                 } else {
                     let fp = regs.fp();
                     let new_sp = fp.checked_add(16).ok_or(Error::IntegerOverflow)?;
-                    let new_lr =
-                        read_stack(fp + 8).map_err(|_| Error::CouldNotReadStack(fp + 8))?;
-                    let new_fp = read_stack(fp).map_err(|_| Error::CouldNotReadStack(fp))?;
+                    let new_lr = read_stack.read_u64(fp + 8).await?;
+                    let new_fp = read_stack.read_u64(fp).await?;
                     if new_sp <= sp {
                         return Err(Error::FramepointerUnwindingMovedBackwards);
                     }
@@ -118,8 +159,7 @@ impl UnwindRule for UnwindRuleAarch64 {
                 let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_8) * 8;
                 let lr_location =
                     checked_add_signed(sp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
-                let new_lr =
-                    read_stack(lr_location).map_err(|_| Error::CouldNotReadStack(lr_location))?;
+                let new_lr = read_stack.read_u64(lr_location).await?;
                 (new_lr, new_sp, fp)
             }
             UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
@@ -132,13 +172,11 @@ impl UnwindRule for UnwindRuleAarch64 {
                 let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_8) * 8;
                 let lr_location =
                     checked_add_signed(sp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
-                let new_lr =
-                    read_stack(lr_location).map_err(|_| Error::CouldNotReadStack(lr_location))?;
+                let new_lr = read_stack.read_u64(lr_location).await?;
                 let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_8) * 8;
                 let fp_location =
                     checked_add_signed(sp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
-                let new_fp =
-                    read_stack(fp_location).map_err(|_| Error::CouldNotReadStack(fp_location))?;
+                let new_fp = read_stack.read_u64(fp_location).await?;
                 (new_lr, new_sp, new_fp)
             }
             UnwindRuleAarch64::UseFramePointer => {
@@ -182,8 +220,8 @@ impl UnwindRule for UnwindRuleAarch64 {
                 // So: *fp is the caller's frame pointer, and *(fp + 8) is the return address.
                 let fp = regs.fp();
                 let new_sp = fp.checked_add(16).ok_or(Error::IntegerOverflow)?;
-                let new_lr = read_stack(fp + 8).map_err(|_| Error::CouldNotReadStack(fp + 8))?;
-                let new_fp = read_stack(fp).map_err(|_| Error::CouldNotReadStack(fp))?;
+                let new_lr = read_stack.read_u64(fp + 8).await?;
+                let new_fp = read_stack.read_u64(fp).await?;
                 if new_fp == 0 {
                     return Ok(None);
                 }
@@ -204,13 +242,11 @@ impl UnwindRule for UnwindRuleAarch64 {
                 let lr_storage_offset = i64::from(lr_storage_offset_from_fp_by_8) * 8;
                 let lr_location =
                     checked_add_signed(fp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
-                let new_lr =
-                    read_stack(lr_location).map_err(|_| Error::CouldNotReadStack(lr_location))?;
+                let new_lr = read_stack.read_u64(lr_location).await?;
                 let fp_storage_offset = i64::from(fp_storage_offset_from_fp_by_8) * 8;
                 let fp_location =
                     checked_add_signed(fp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
-                let new_fp =
-                    read_stack(fp_location).map_err(|_| Error::CouldNotReadStack(fp_location))?;
+                let new_fp = read_stack.read_u64(fp_location).await?;
 
                 if new_fp == 0 {
                     return Ok(None);
@@ -239,6 +275,7 @@ impl UnwindRule for UnwindRuleAarch64 {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
 
     #[test]
     fn test_basic() {
@@ -247,18 +284,34 @@ mod test {
         ];
         let mut read_stack = |addr| Ok(stack[(addr / 8) as usize]);
         let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x20);
-        let res = UnwindRuleAarch64::NoOp.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleAarch64::NoOp.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100300)));
         assert_eq!(regs.sp(), 0x10);
-        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleAarch64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100200)));
         assert_eq!(regs.sp(), 0x30);
         assert_eq!(regs.fp(), 0x40);
-        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleAarch64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100100)));
         assert_eq!(regs.sp(), 0x50);
         assert_eq!(regs.fp(), 0x70);
-        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleAarch64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(None));
     }
 }
@@ -1,10 +1,14 @@
-use core::fmt::Debug;
+use core::fmt::{Debug, Display};
 
-use crate::display_utils::HexNum;
+use crate::display_utils::{HexNum, RegValue};
+use crate::register_set::RegisterSet;
 
 /// The registers used for unwinding on Aarch64. We only need lr (x30), sp (x31),
 /// and fp (x29).
 ///
+/// The callee-saved registers x19 to x28 can also be tracked, see
+/// [`track_callee_saved`](UnwindRegsAarch64::track_callee_saved).
+///
 /// We also have a [`PtrAuthMask`] which allows stripping off the pointer authentication
 /// hash bits from the return address when unwinding through libraries which use pointer
 /// authentication, e.g. in system libraries on macOS.
@@ -14,6 +18,9 @@ pub struct UnwindRegsAarch64 {
     lr: u64,
     sp: u64,
     fp: u64,
+    callee_saved: Option<[u64; 10]>,
+    unknown: RegisterSet,
+    stack_end: Option<u64>,
 }
 
 /// Aarch64 CPUs support special instructions which interpret pointers as pair
@@ -74,6 +81,9 @@ impl UnwindRegsAarch64 {
             lr,
             sp,
             fp,
+            callee_saved: None,
+            unknown: RegisterSet::EMPTY,
+            stack_end: None,
         }
     }
 
@@ -90,9 +100,34 @@ impl UnwindRegsAarch64 {
             lr: code_ptr_auth_mask.strip_ptr_auth(lr),
             sp,
             fp,
+            callee_saved: None,
+            unknown: RegisterSet::EMPTY,
+            stack_end: None,
         }
     }
 
+    /// Create the registers from a Linux `struct user_pt_regs`, as returned by
+    /// `ptrace(PTRACE_GETREGSET)` with `NT_PRSTATUS`, given as its 34 64-bit words:
+    /// x0 to x30, sp, pc and pstate.
+    ///
+    /// The pc isn't part of these registers. Pass `words[32]` to the unwinder as the
+    /// address of the first frame. The callee-saved registers are not tracked; call
+    /// [`track_callee_saved`](Self::track_callee_saved) with `words[19..29]` for that.
+    pub fn from_user_pt_regs(code_ptr_auth_mask: PtrAuthMask, words: &[u64; 34]) -> Self {
+        Self::new_with_ptr_auth_mask(code_ptr_auth_mask, words[30], words[31], words[29])
+    }
+
+    /// Create the registers from a Mach `arm_thread_state64_t`, as returned by
+    /// `thread_get_state` with `ARM_THREAD_STATE64`, given as its 34 64-bit words: x0 to
+    /// x28, fp, lr, sp, pc, and cpsr with padding.
+    ///
+    /// The register layout is the same as in [`from_user_pt_regs`](Self::from_user_pt_regs),
+    /// so the pc is `words[32]`. On arm64e, lr is signed, so pass a mask which strips
+    /// the pointer authentication bits.
+    pub fn from_arm_thread_state64(code_ptr_auth_mask: PtrAuthMask, words: &[u64; 34]) -> Self {
+        Self::from_user_pt_regs(code_ptr_auth_mask, words)
+    }
+
     /// Get the [`PtrAuthMask`] which we apply to the `lr` value.
     #[inline(always)]
     pub fn lr_mask(&self) -> PtrAuthMask {
@@ -108,6 +143,9 @@ impl UnwindRegsAarch64 {
     /// Set the stack pointer value.
     #[inline(always)]
     pub fn set_sp(&mut self, sp: u64) {
+        if sp != self.sp {
+            self.unknown &= !RegisterSet::SP;
+        }
         self.sp = sp
     }
 
@@ -120,6 +158,9 @@ impl UnwindRegsAarch64 {
     /// Set the frame pointer value (x29).
     #[inline(always)]
     pub fn set_fp(&mut self, fp: u64) {
+        if fp != self.fp {
+            self.unknown &= !RegisterSet::FP;
+        }
         self.fp = fp
     }
 
@@ -132,23 +173,179 @@ impl UnwindRegsAarch64 {
     /// Set the lr register value.
     #[inline(always)]
     pub fn set_lr(&mut self, lr: u64) {
-        self.lr = self.lr_mask.strip_ptr_auth(lr)
+        let lr = self.lr_mask.strip_ptr_auth(lr);
+        if lr != self.lr {
+            self.unknown &= !RegisterSet::LR;
+        }
+        self.lr = lr
+    }
+
+    /// Start tracking the callee-saved registers x19 to x28, with the given values for
+    /// the current frame, or replace their values if they're already tracked.
+    ///
+    /// When these registers are tracked, unwinding with DWARF CFI restores them from the
+    /// register rules in the CFI, so that their values are the caller's values after each
+    /// unwound frame. Registers without a rule keep their value. Tracking is off by
+    /// default because it makes unwinding slower: unwind rules can't be cached, and all
+    /// register rules need to be evaluated. Unwind information other than DWARF CFI, and
+    /// the frame pointer fallback, doesn't update these registers.
+    pub fn track_callee_saved(&mut self, x19_to_x28: [u64; 10]) {
+        self.callee_saved = Some(x19_to_x28);
+    }
+
+    /// Mark the values of some of lr, sp and fp as unknown, for example because the
+    /// sampler didn't capture fp. Registers which are not in `unknown` are known.
+    ///
+    /// Unwinding a frame whose unwind rule needs an unknown register fails with
+    /// [`Error::MissingRegisterForRule`](crate::Error::MissingRegisterForRule), instead
+    /// of using the placeholder value. Rules which only need sp still work. A register
+    /// stays unknown in the caller's frame as long as unwinding passes it through
+    /// unchanged, and becomes known when it is set to a different value, for example
+    /// when the unwind rule restores fp from the stack.
+    pub fn set_unknown_registers(&mut self, unknown: RegisterSet) {
+        self.unknown = unknown;
+    }
+
+    /// The registers which are marked as unknown, see
+    /// [`set_unknown_registers`](Self::set_unknown_registers).
+    #[inline(always)]
+    pub fn unknown_registers(&self) -> RegisterSet {
+        self.unknown
+    }
+
+    /// Set the end of the thread's stack, i.e. the address just above its highest byte,
+    /// for example the stack address plus the stack size from `pthread_getattr_np`.
+    ///
+    /// Unwinding stops when the caller's sp is at or above this address, because its
+    /// frame can't be on the stack: the unwinder returns `Ok(None)` instead of the
+    /// return address, which was read from outside the stack. The value is kept for
+    /// the caller's frame, so it only needs to be set for the first frame.
+    pub fn set_stack_end(&mut self, stack_end: u64) {
+        self.stack_end = Some(stack_end);
+    }
+
+    /// The end of the stack, if it was set with [`set_stack_end`](Self::set_stack_end).
+    #[inline(always)]
+    pub fn stack_end(&self) -> Option<u64> {
+        self.stack_end
+    }
+
+    /// Stop tracking the callee-saved registers x19 to x28.
+    pub fn stop_tracking_callee_saved(&mut self) {
+        self.callee_saved = None;
+    }
+
+    /// The values of the callee-saved registers x19 to x28, if they're tracked.
+    #[inline(always)]
+    pub fn callee_saved(&self) -> Option<[u64; 10]> {
+        self.callee_saved
+    }
+
+    /// Return a copy of these register values with `lr` replaced. The
+    /// [`PtrAuthMask`] is applied to the new value.
+    #[inline(always)]
+    pub fn with_lr(mut self, lr: u64) -> Self {
+        self.set_lr(lr);
+        self
+    }
+
+    /// Return a copy of these register values with `sp` replaced.
+    #[inline(always)]
+    pub fn with_sp(mut self, sp: u64) -> Self {
+        self.set_sp(sp);
+        self
+    }
+
+    /// Return a copy of these register values with `fp` replaced.
+    #[inline(always)]
+    pub fn with_fp(mut self, fp: u64) -> Self {
+        self.set_fp(fp);
+        self
     }
 }
 
 impl Debug for UnwindRegsAarch64 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("UnwindRegsAarch64")
-            .field("lr", &HexNum(self.lr))
+        let mut s = f.debug_struct("UnwindRegsAarch64");
+        s.field("lr", &HexNum(self.lr))
             .field("sp", &HexNum(self.sp))
-            .field("fp", &HexNum(self.fp))
-            .finish()
+            .field("fp", &HexNum(self.fp));
+        if let Some(callee_saved) = &self.callee_saved {
+            s.field("x19_to_x28", &callee_saved.map(HexNum));
+        }
+        if !self.unknown.is_empty() {
+            s.field("unknown", &self.unknown);
+        }
+        if let Some(stack_end) = self.stack_end {
+            s.field("stack_end", &HexNum(stack_end));
+        }
+        s.finish()
+    }
+}
+
+/// Formats the registers which are used for unwinding on one line, e.g.
+/// `lr=0x1000 sp=0x28 fp=0x40`, with `?` for unknown values, see
+/// [`UnwindRegsAarch64::set_unknown_registers`]. Use `Debug` to see the callee-saved
+/// registers.
+impl Display for UnwindRegsAarch64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let value = |register, value| (!self.unknown.contains(register)).then_some(value);
+        write!(
+            f,
+            "lr={} sp={} fp={}",
+            RegValue(value(RegisterSet::LR, self.lr)),
+            RegValue(value(RegisterSet::SP, self.sp)),
+            RegValue(value(RegisterSet::FP, self.fp)),
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::aarch64::PtrAuthMask;
+    use crate::aarch64::{PtrAuthMask, UnwindRegsAarch64};
+    use crate::register_set::RegisterSet;
+    use alloc::format;
+
+    #[test]
+    fn test_with_accessors() {
+        let regs =
+            UnwindRegsAarch64::new_with_ptr_auth_mask(PtrAuthMask::new_24_40(), 0, 0x20, 0x30);
+        let adjusted = regs
+            .with_lr(0xff00_0000_0000_1000)
+            .with_sp(0x28)
+            .with_fp(0x40);
+        assert_eq!(regs.lr(), 0);
+        assert_eq!(adjusted.lr(), 0x1000);
+        assert_eq!(adjusted.sp(), 0x28);
+        assert_eq!(adjusted.fp(), 0x40);
+        assert_eq!(
+            format!("{adjusted:?}"),
+            "UnwindRegsAarch64 { lr: 1000, sp: 28, fp: 40 }"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let mut regs = UnwindRegsAarch64::new(0x1000, 0x28, 0x40);
+        assert_eq!(format!("{regs}"), "lr=0x1000 sp=0x28 fp=0x40");
+        regs.set_unknown_registers(RegisterSet::LR | RegisterSet::FP);
+        assert_eq!(format!("{regs}"), "lr=? sp=0x28 fp=?");
+        assert_ne!(regs, UnwindRegsAarch64::new(0x1000, 0x28, 0x40));
+    }
+
+    #[test]
+    fn test_from_kernel_register_layouts() {
+        let mut words: [u64; 34] = core::array::from_fn(|i| 0x100 + i as u64);
+        words[30] |= 0xff00_0000_0000_0000;
+        for regs in [
+            UnwindRegsAarch64::from_user_pt_regs(PtrAuthMask::new_24_40(), &words),
+            UnwindRegsAarch64::from_arm_thread_state64(PtrAuthMask::new_24_40(), &words),
+        ] {
+            assert_eq!(regs.lr(), 0x11e);
+            assert_eq!(regs.sp(), 0x11f);
+            assert_eq!(regs.fp(), 0x11d);
+        }
+    }
 
     #[test]
     fn test() {
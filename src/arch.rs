@@ -1,6 +1,54 @@
+use gimli::Vendor;
+
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
 use crate::unwind_rule::UnwindRule;
 
 pub trait Arch {
-    type UnwindRegs;
+    type UnwindRegs: Clone;
     type UnwindRule: UnwindRule<UnwindRegs = Self::UnwindRegs>;
+
+    /// The size of a pointer in bytes, used for absolute addresses in DWARF CFI.
+    const ADDRESS_SIZE: u8;
+
+    /// The vendor extensions which are allowed in DWARF CFI, for example
+    /// `DW_CFA_AARCH64_negate_ra_state` on aarch64.
+    const VENDOR: Vendor = Vendor::Default;
+
+    /// Register values which are used when unwind rules are computed without unwinding
+    /// an actual frame, see `precompute_rules`.
+    fn placeholder_regs() -> Self::UnwindRegs;
+
+    /// Returns true if unwinding with these register values must not use cached unwind
+    /// rules, because the registers track values which the unwind rules don't restore.
+    fn bypasses_rule_cache(_regs: &Self::UnwindRegs) -> bool {
+        false
+    }
+
+    /// The registers whose values the caller marked as unknown, which unwind rules must
+    /// not use.
+    fn unknown_registers(_regs: &Self::UnwindRegs) -> RegisterSet {
+        RegisterSet::EMPTY
+    }
+
+    /// Returns true if the stack pointer is at or above the end of the stack which the
+    /// caller set in the registers, so that the frame isn't on the stack.
+    fn is_past_stack_end(_regs: &Self::UnwindRegs) -> bool {
+        false
+    }
+
+    /// Execute `rule`, after checking that it doesn't need any unknown registers.
+    async fn exec_rule<F: AsyncMemoryAccess>(
+        rule: Self::UnwindRule,
+        is_first_frame: bool,
+        regs: &mut Self::UnwindRegs,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error> {
+        let missing = rule.used_registers() & Self::unknown_registers(regs);
+        if !missing.is_empty() {
+            return Err(Error::MissingRegisterForRule(missing));
+        }
+        rule.exec(is_first_frame, regs, read_stack).await
+    }
 }
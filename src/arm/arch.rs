@@ -0,0 +1,15 @@
+use super::unwind_rule::UnwindRuleArm;
+use super::unwindregs::UnwindRegsArm;
+use crate::arch::Arch;
+
+/// The 32-bit ARM CPU architecture.
+pub struct ArchArm;
+impl Arch for ArchArm {
+    type UnwindRule = UnwindRuleArm;
+    type UnwindRegs = UnwindRegsArm;
+    const ADDRESS_SIZE: u8 = 4;
+
+    fn placeholder_regs() -> UnwindRegsArm {
+        UnwindRegsArm::new(0, 0, 0, 0, 0)
+    }
+}
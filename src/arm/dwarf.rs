@@ -0,0 +1,280 @@
+use gimli::{
+    Arm, CfaRule, Encoding, EvaluationStorage, Reader, ReaderOffset, Register, RegisterRule,
+    UnwindContextStorage, UnwindSection, UnwindTableRow,
+};
+
+use super::{
+    arch::ArchArm,
+    unwind_rule::UnwindRuleArm,
+    unwindregs::{FramePointerArm, UnwindRegsArm},
+};
+
+use crate::diagnostics::diagnostic;
+use crate::memory::{AsyncMemoryAccess, Memory32, TrackReadFailures};
+use crate::unwind_result::UnwindResult;
+
+use crate::dwarf::{
+    eval_cfa_rule, eval_error, eval_register_rule, scaled_offset, ConversionError, DwarfUnwindRegs,
+    DwarfUnwinderError, DwarfUnwinding,
+};
+
+impl DwarfUnwindRegs for UnwindRegsArm {
+    fn get(&self, register: Register) -> Option<u64> {
+        match register {
+            Arm::SP => Some(self.sp()),
+            Arm::LR => Some(self.lr()),
+            Arm::PC => Some(self.pc()),
+            Arm::R7 => Some(self.r7()),
+            Arm::R11 => Some(self.r11()),
+            _ => None,
+        }
+    }
+}
+
+impl DwarfUnwinding for ArchArm {
+    const RETURN_ADDRESS_REGISTER: Register = Arm::LR;
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        Arm::register_name(register)
+    }
+
+    async fn unwind_frame<F, R, UCS, ES>(
+        section: &impl UnwindSection<R>,
+        unwind_info: &UnwindTableRow<R::Offset, UCS>,
+        encoding: Encoding,
+        regs: &mut Self::UnwindRegs,
+        is_first_frame: bool,
+        _lookup_svma: u64,
+        read_stack: &mut F,
+    ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
+    where
+        F: AsyncMemoryAccess,
+        R: Reader,
+        UCS: UnwindContextStorage<R::Offset>,
+        ES: EvaluationStorage<R>,
+    {
+        let cfa_rule = unwind_info.cfa();
+        let r7_rule = unwind_info.register(Arm::R7);
+        let r11_rule = unwind_info.register(Arm::R11);
+        let lr_rule = unwind_info.register(Arm::LR);
+
+        match Self::rule_for_row(unwind_info) {
+            Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
+            Err(err) => {
+                // Could not translate into a cacheable unwind rule. Fall back to the generic path.
+                diagnostic!(
+                    "Unwind rule translation failed at pc 0x{:x}: {:?}",
+                    regs.pc(),
+                    err
+                );
+            }
+        }
+
+        // Stack slots are 32 bits wide.
+        let read_stack = &mut Memory32(read_stack);
+        let read_stack = &mut TrackReadFailures::new(read_stack);
+
+        let cfa = eval_cfa_rule::<R, _, _, ES>(section, cfa_rule, encoding, regs, read_stack)
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverCfa,
+                )
+            })?;
+
+        let lr = regs.lr();
+        let r7 = regs.r7();
+        let r11 = regs.r11();
+        let sp = regs.sp();
+
+        let (r7, r11, lr) = if !is_first_frame {
+            if cfa <= sp {
+                return Err(DwarfUnwinderError::StackPointerMovedBackwards);
+            }
+            let r7 = eval_register_rule::<R, _, _, ES>(
+                section, r7_rule, cfa, encoding, r7, regs, read_stack,
+            )
+            .await
+            .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
+            let r11 = eval_register_rule::<R, _, _, ES>(
+                section, r11_rule, cfa, encoding, r11, regs, read_stack,
+            )
+            .await
+            .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
+            let lr = eval_register_rule::<R, _, _, ES>(
+                section, lr_rule, cfa, encoding, lr, regs, read_stack,
+            )
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverReturnAddress,
+                )
+            })?;
+            (r7, r11, lr)
+        } else {
+            // For the first frame, be more lenient when encountering errors.
+            let r7 = eval_register_rule::<R, _, _, ES>(
+                section, r7_rule, cfa, encoding, r7, regs, read_stack,
+            )
+            .await
+            .unwrap_or(r7);
+            let r11 = eval_register_rule::<R, _, _, ES>(
+                section, r11_rule, cfa, encoding, r11, regs, read_stack,
+            )
+            .await
+            .unwrap_or(r11);
+            let lr = eval_register_rule::<R, _, _, ES>(
+                section, lr_rule, cfa, encoding, lr, regs, read_stack,
+            )
+            .await
+            .unwrap_or(lr);
+            (r7, r11, lr)
+        };
+
+        regs.set_r7(r7);
+        regs.set_r11(r11);
+        regs.set_sp(cfa);
+        regs.set_lr(lr);
+        regs.set_pc(lr);
+
+        Ok(UnwindResult::Uncacheable(lr & !1))
+    }
+
+    fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
+        UnwindRuleArm::NoOpIfFirstFrameOtherwiseFp
+    }
+
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        translate_into_unwind_rule(
+            row.cfa(),
+            &row.register(Arm::R7),
+            &row.register(Arm::R11),
+            &row.register(Arm::LR),
+        )
+    }
+}
+
+fn register_rule_to_cfa_offset<RO: ReaderOffset>(
+    rule: &RegisterRule<RO>,
+) -> Result<Option<i64>, ConversionError> {
+    match *rule {
+        RegisterRule::Undefined | RegisterRule::SameValue => Ok(None),
+        RegisterRule::Offset(offset) => Ok(Some(offset)),
+        _ => Err(ConversionError::RegisterNotStoredRelativeToCfa),
+    }
+}
+
+fn translate_into_unwind_rule<RO: ReaderOffset>(
+    cfa_rule: &CfaRule<RO>,
+    r7_rule: &RegisterRule<RO>,
+    r11_rule: &RegisterRule<RO>,
+    lr_rule: &RegisterRule<RO>,
+) -> Result<UnwindRuleArm, ConversionError> {
+    // Our rules can restore at most one of the two frame pointer candidates.
+    let fp_and_cfa_offset = match (
+        register_rule_to_cfa_offset(r7_rule)?,
+        register_rule_to_cfa_offset(r11_rule)?,
+    ) {
+        (Some(_), Some(_)) => return Err(ConversionError::RestoringBothFramePointers),
+        (Some(offset), None) => Some((FramePointerArm::R7, offset)),
+        (None, Some(offset)) => Some((FramePointerArm::R11, offset)),
+        (None, None) => None,
+    };
+    let lr_cfa_offset = register_rule_to_cfa_offset(lr_rule)?;
+
+    match cfa_rule {
+        CfaRule::RegisterAndOffset { register, offset } => match *register {
+            Arm::SP => {
+                let sp_offset_by_4 =
+                    scaled_offset(*offset, 4, ConversionError::SpOffsetDoesNotFit)?;
+                match (lr_cfa_offset, fp_and_cfa_offset) {
+                    (None, Some(_)) => Err(ConversionError::RestoringFpButNotLr),
+                    (None, None) => {
+                        if let RegisterRule::Undefined = lr_rule {
+                            // An undefined return address either marks the root of the stack,
+                            // or was omitted by a compiler which meant "same value".
+                            Ok(UnwindRuleArm::OffsetSpIfFirstFrameOtherwiseStackEndsHere {
+                                sp_offset_by_4,
+                            })
+                        } else {
+                            Ok(UnwindRuleArm::OffsetSp { sp_offset_by_4 })
+                        }
+                    }
+                    (Some(lr_cfa_offset), None) => {
+                        let lr_storage_offset_from_sp_by_4 = scaled_offset(
+                            offset + lr_cfa_offset,
+                            4,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
+                        Ok(UnwindRuleArm::OffsetSpAndRestoreLr {
+                            sp_offset_by_4,
+                            lr_storage_offset_from_sp_by_4,
+                        })
+                    }
+                    (Some(lr_cfa_offset), Some((fp, fp_cfa_offset))) => {
+                        let lr_storage_offset_from_sp_by_4 = scaled_offset(
+                            offset + lr_cfa_offset,
+                            4,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
+                        let fp_storage_offset_from_sp_by_4 = scaled_offset(
+                            offset + fp_cfa_offset,
+                            4,
+                            ConversionError::FpStorageOffsetDoesNotFit,
+                        )?;
+                        Ok(UnwindRuleArm::OffsetSpAndRestoreFpAndLr {
+                            fp,
+                            sp_offset_by_4,
+                            fp_storage_offset_from_sp_by_4,
+                            lr_storage_offset_from_sp_by_4,
+                        })
+                    }
+                }
+            }
+            Arm::R7 | Arm::R11 => {
+                let cfa_fp = if *register == Arm::R7 {
+                    FramePointerArm::R7
+                } else {
+                    FramePointerArm::R11
+                };
+                let lr_cfa_offset =
+                    lr_cfa_offset.ok_or(ConversionError::FramePointerRuleDoesNotRestoreLr)?;
+                // Frame-based rules always name the register, UseFramePointer is only for
+                // the fallback where we have to guess.
+                let (fp, fp_cfa_offset) = fp_and_cfa_offset
+                    .filter(|(fp, _)| *fp == cfa_fp)
+                    .ok_or(ConversionError::FramePointerRuleDoesNotRestoreFp)?;
+                let sp_offset_from_fp_by_4 =
+                    scaled_offset(*offset, 4, ConversionError::SpOffsetFromFpDoesNotFit)?;
+                let lr_storage_offset_from_fp_by_4 = scaled_offset(
+                    offset + lr_cfa_offset,
+                    4,
+                    ConversionError::LrStorageOffsetDoesNotFit,
+                )?;
+                let fp_storage_offset_from_fp_by_4 = scaled_offset(
+                    offset + fp_cfa_offset,
+                    4,
+                    ConversionError::FpStorageOffsetDoesNotFit,
+                )?;
+                Ok(UnwindRuleArm::UseFramepointerWithOffsets {
+                    fp,
+                    sp_offset_from_fp_by_4,
+                    fp_storage_offset_from_fp_by_4,
+                    lr_storage_offset_from_fp_by_4,
+                })
+            }
+            _ => Err(ConversionError::CfaIsOffsetFromUnknownRegister),
+        },
+        CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
+    }
+}
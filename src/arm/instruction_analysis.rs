@@ -0,0 +1,49 @@
+use super::arch::ArchArm;
+use crate::instruction_analysis::InstructionAnalysis;
+
+// Instruction analysis is only used for mach-O compact unwind info, which framehop
+// doesn't support on 32-bit ARM.
+impl InstructionAnalysis for ArchArm {
+    fn rule_from_prologue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn rule_from_epilogue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+        // Return addresses into Thumb code have the lowest bit set.
+        let bytes = &text_bytes[..return_offset & !1];
+        if let [.., b0, b1, b2, b3] = *bytes {
+            // Thumb-2 bl / blx (immediate), a pair of 16-bit halfwords.
+            let hw1 = u16::from_le_bytes([b0, b1]);
+            let hw2 = u16::from_le_bytes([b2, b3]);
+            if hw1 & 0xf800 == 0xf000 && hw2 & 0xc000 == 0xc000 {
+                return true;
+            }
+            // ARM bl, blx (immediate) and blx (register)
+            let insn = u32::from_le_bytes([b0, b1, b2, b3]);
+            if insn & 0x0f00_0000 == 0x0b00_0000
+                || insn & 0xfe00_0000 == 0xfa00_0000
+                || insn & 0x0fff_fff0 == 0x012f_ff30
+            {
+                return true;
+            }
+        }
+        if let [.., b0, b1] = *bytes {
+            // Thumb blx (register)
+            let insn = u16::from_le_bytes([b0, b1]);
+            if insn & 0xff87 == 0x4780 {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,21 @@
+use super::arch::ArchArm;
+use super::unwind_rule::UnwindRuleArm;
+use crate::macho::{CompactUnwindInfoUnwinderError, CompactUnwindInfoUnwinding, CuiUnwindResult};
+use macho_unwind_info::Function;
+
+impl CompactUnwindInfoUnwinding for ArchArm {
+    fn unwind_frame(
+        _function: Function,
+        _is_first_frame: bool,
+        _address_offset_within_function: usize,
+        _function_bytes: Option<&[u8]>,
+    ) -> Result<CuiUnwindResult<UnwindRuleArm>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::ArmUnsupported)
+    }
+
+    fn rule_for_stub_helper(
+        _offset: u32,
+    ) -> Result<CuiUnwindResult<UnwindRuleArm>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::ArmUnsupported)
+    }
+}
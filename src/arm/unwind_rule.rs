@@ -0,0 +1,433 @@
+use super::unwindregs::{FramePointerArm, UnwindRegsArm};
+use crate::add_signed::checked_add_signed;
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
+
+use crate::unwind_rule::UnwindRule;
+
+/// Unwind rules are `Copy` and at most 8 bytes large, which is checked at compile time,
+/// so that they can be cached and stored in large numbers. The variants and their fields
+/// are public, so rules can also be built outside of framehop. The memory layout is not
+/// guaranteed; to persist rules, store their variants and fields, or enable the `serde`
+/// feature, which implements `Serialize` and `Deserialize` for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnwindRuleArm {
+    /// (sp, fp, lr) = (sp, fp, lr)
+    /// Only possible for the first frame. Subsequent frames must get the
+    /// return address from somewhere other than the lr register to avoid
+    /// infinite loops.
+    NoOp,
+    /// (sp, fp, lr) = if is_first_frame (sp, fp, lr) else (fp + 8, *fp, *(fp + 4))
+    /// Used as a fallback rule.
+    NoOpIfFirstFrameOtherwiseFp,
+    /// (sp, fp, lr) = (sp + 4x, fp, lr)
+    /// Only possible for the first frame. Subsequent frames must get the
+    /// return address from somewhere other than the lr register to avoid
+    /// infinite loops.
+    OffsetSp { sp_offset_by_4: u16 },
+    /// (sp, fp, lr) = (sp + 4x, fp, lr) if is_first_frame
+    /// This rule reflects an ambiguity in DWARF CFI information. When the
+    /// return address is "undefined" because it was omitted, it could mean
+    /// "same value", but this is only allowed for the first frame.
+    OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_4: u16 },
+    /// (sp, fp, lr) = (sp + 4x, fp, *(sp + 4y))
+    OffsetSpAndRestoreLr {
+        sp_offset_by_4: u16,
+        lr_storage_offset_from_sp_by_4: i16,
+    },
+    /// (sp, fp, lr) = (sp + 4x, *(sp + 4y), *(sp + 4z)), where fp is r7 or r11
+    OffsetSpAndRestoreFpAndLr {
+        fp: FramePointerArm,
+        sp_offset_by_4: u16,
+        fp_storage_offset_from_sp_by_4: i16,
+        lr_storage_offset_from_sp_by_4: i16,
+    },
+    /// (sp, fp, lr) = (fp + 8, *fp, *(fp + 4))
+    /// Both r7 and r11 are tried as the frame pointer, starting with r7 if pc is a Thumb
+    /// address, and with r11 otherwise.
+    UseFramePointer,
+    /// (sp, fp, lr) = (fp + 4x, *(fp + 4y), *(fp + 4z)), where fp is r7 or r11
+    UseFramepointerWithOffsets {
+        fp: FramePointerArm,
+        sp_offset_from_fp_by_4: i16,
+        fp_storage_offset_from_fp_by_4: i16,
+        lr_storage_offset_from_fp_by_4: i16,
+    },
+}
+
+const _: () = assert!(core::mem::size_of::<UnwindRuleArm>() <= 8);
+
+impl UnwindRuleArm {
+    /// The registers whose current values this rule reads, see [`RegisterSet`].
+    ///
+    /// Registers which the rule passes through to the caller unchanged are not included.
+    /// If the rule behaves differently for the first frame, the registers needed by either
+    /// case are included. This lets a caller which didn't capture all registers know in
+    /// advance whether a rule can be applied.
+    pub fn used_registers(&self) -> RegisterSet {
+        match self {
+            UnwindRuleArm::NoOp => RegisterSet::LR,
+            UnwindRuleArm::NoOpIfFirstFrameOtherwiseFp => {
+                RegisterSet::LR | RegisterSet::SP | RegisterSet::FP
+            }
+            UnwindRuleArm::OffsetSp { .. }
+            | UnwindRuleArm::OffsetSpIfFirstFrameOtherwiseStackEndsHere { .. } => {
+                RegisterSet::SP | RegisterSet::LR
+            }
+            UnwindRuleArm::OffsetSpAndRestoreLr { .. }
+            | UnwindRuleArm::OffsetSpAndRestoreFpAndLr { .. } => RegisterSet::SP,
+            UnwindRuleArm::UseFramepointerWithOffsets { .. } => RegisterSet::SP | RegisterSet::FP,
+            UnwindRuleArm::UseFramePointer => {
+                // The instruction set of pc decides which frame pointer register is tried first.
+                RegisterSet::SP | RegisterSet::FP | RegisterSet::PC
+            }
+        }
+    }
+}
+
+impl UnwindRule for UnwindRuleArm {
+    type UnwindRegs = UnwindRegsArm;
+
+    fn used_registers(&self) -> RegisterSet {
+        UnwindRuleArm::used_registers(self)
+    }
+
+    fn rule_for_stub_functions() -> Self {
+        UnwindRuleArm::NoOp
+    }
+    fn rule_for_function_start() -> Self {
+        UnwindRuleArm::NoOp
+    }
+    fn fallback_rule() -> Self {
+        UnwindRuleArm::UseFramePointer
+    }
+
+    async fn exec<F>(
+        self,
+        is_first_frame: bool,
+        regs: &mut UnwindRegsArm,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let lr = regs.lr();
+        let sp = regs.sp();
+
+        // The frame pointer register that was restored, if any, and its new value.
+        let (new_lr, new_sp, new_fp) = match self {
+            UnwindRuleArm::NoOp => {
+                if !is_first_frame {
+                    return Err(Error::DidNotAdvance);
+                }
+                (lr, sp, None)
+            }
+            UnwindRuleArm::NoOpIfFirstFrameOtherwiseFp => {
+                if is_first_frame {
+                    (lr, sp, None)
+                } else {
+                    let (fp_reg, new_lr, new_sp, new_fp) =
+                        frame_pointer_step(regs, read_stack).await?;
+                    (new_lr, new_sp, Some((fp_reg, new_fp)))
+                }
+            }
+            UnwindRuleArm::OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_4 } => {
+                if !is_first_frame {
+                    return Ok(None);
+                }
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (lr, new_sp, None)
+            }
+            UnwindRuleArm::OffsetSp { sp_offset_by_4 } => {
+                if !is_first_frame {
+                    return Err(Error::DidNotAdvance);
+                }
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (lr, new_sp, None)
+            }
+            UnwindRuleArm::OffsetSpAndRestoreLr {
+                sp_offset_by_4,
+                lr_storage_offset_from_sp_by_4,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_4) * 4;
+                let lr_location =
+                    checked_add_signed(sp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_lr = read_u32(read_stack, lr_location).await?;
+                (new_lr, new_sp, None)
+            }
+            UnwindRuleArm::OffsetSpAndRestoreFpAndLr {
+                fp,
+                sp_offset_by_4,
+                fp_storage_offset_from_sp_by_4,
+                lr_storage_offset_from_sp_by_4,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_4) * 4;
+                let lr_location =
+                    checked_add_signed(sp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_lr = read_u32(read_stack, lr_location).await?;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_4) * 4;
+                let fp_location =
+                    checked_add_signed(sp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_fp = read_u32(read_stack, fp_location).await?;
+                (new_lr, new_sp, Some((fp, new_fp)))
+            }
+            UnwindRuleArm::UseFramePointer => {
+                // Do a frame pointer stack walk. Per AAPCS, frame-based functions push a
+                // "frame record" of the caller's frame pointer and lr, and then set the frame
+                // pointer to the address of that record:
+                //
+                // push  {r7, lr}       ; Thumb; ARM code uses r11 instead of r7
+                // mov   r7, sp
+                //
+                // So: *fp is the caller's frame pointer, and *(fp + 4) is the return address.
+                //
+                // Which register is the frame pointer depends on the instruction set of the
+                // function, and a process can mix both, so we try both registers.
+                let (fp_reg, new_lr, new_sp, new_fp) = frame_pointer_step(regs, read_stack).await?;
+                if new_fp == 0 {
+                    return Ok(None);
+                }
+                (new_lr, new_sp, Some((fp_reg, new_fp)))
+            }
+            UnwindRuleArm::UseFramepointerWithOffsets {
+                fp,
+                sp_offset_from_fp_by_4,
+                fp_storage_offset_from_fp_by_4,
+                lr_storage_offset_from_fp_by_4,
+            } => {
+                let fp_reg = fp;
+                let fp = regs.fp(fp_reg);
+                let sp_offset_from_fp = i64::from(sp_offset_from_fp_by_4) * 4;
+                let new_sp =
+                    checked_add_signed(fp, sp_offset_from_fp).ok_or(Error::IntegerOverflow)?;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_fp_by_4) * 4;
+                let lr_location =
+                    checked_add_signed(fp, lr_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_lr = read_u32(read_stack, lr_location).await?;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_fp_by_4) * 4;
+                let fp_location =
+                    checked_add_signed(fp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_fp = read_u32(read_stack, fp_location).await?;
+
+                if new_fp == 0 {
+                    return Ok(None);
+                }
+                if new_fp <= fp || new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                (new_lr, new_sp, Some((fp_reg, new_fp)))
+            }
+        };
+        // Strip the Thumb bit. The lookup for the caller needs the code address.
+        let return_address = new_lr & !1;
+        if return_address == 0 {
+            return Ok(None);
+        }
+        if !is_first_frame && new_sp == sp {
+            return Err(Error::DidNotAdvance);
+        }
+        regs.set_pc(new_lr);
+        regs.set_lr(new_lr);
+        regs.set_sp(new_sp);
+        if let Some((fp_reg, new_fp)) = new_fp {
+            regs.set_fp(fp_reg, new_fp);
+        }
+
+        Ok(Some(return_address))
+    }
+}
+
+/// Read a 32-bit stack slot.
+async fn read_u32<F>(read_stack: &mut F, addr: u64) -> Result<u64, Error>
+where
+    F: AsyncMemoryAccess,
+{
+    let value = read_stack.read_u32(addr).await?;
+    Ok(u64::from(value))
+}
+
+/// Follow the frame record pointed to by r7 or r11, preferring r7 in Thumb code.
+/// Returns the register that was used, and the caller's lr, sp and frame pointer.
+async fn frame_pointer_step<F>(
+    regs: &UnwindRegsArm,
+    read_stack: &mut F,
+) -> Result<(FramePointerArm, u64, u64, u64), Error>
+where
+    F: AsyncMemoryAccess,
+{
+    let candidates = if regs.pc() & 1 != 0 {
+        [FramePointerArm::R7, FramePointerArm::R11]
+    } else {
+        [FramePointerArm::R11, FramePointerArm::R7]
+    };
+    let mut result = Err(Error::FramepointerUnwindingMovedBackwards);
+    for fp_reg in candidates {
+        result = follow_frame_record(regs.fp(fp_reg), regs.sp(), read_stack)
+            .await
+            .map(|(new_lr, new_sp, new_fp)| (fp_reg, new_lr, new_sp, new_fp));
+        if result.is_ok() {
+            break;
+        }
+    }
+    result
+}
+
+async fn follow_frame_record<F>(
+    fp: u64,
+    sp: u64,
+    read_stack: &mut F,
+) -> Result<(u64, u64, u64), Error>
+where
+    F: AsyncMemoryAccess,
+{
+    if fp & 3 != 0 || fp < sp {
+        return Err(Error::FramepointerUnwindingMovedBackwards);
+    }
+    let new_sp = fp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+    let new_lr = read_u32(read_stack, fp + 4).await?;
+    let new_fp = read_u32(read_stack, fp).await?;
+    if new_fp != 0 && new_fp <= fp {
+        return Err(Error::FramepointerUnwindingMovedBackwards);
+    }
+    Ok((new_lr, new_sp, new_fp))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+
+    #[test]
+    fn test_basic() {
+        // Thumb code with r7 frame records. r11 holds garbage.
+        let stack = [1, 2, 3, 4, 0x20, 0x10021, 5, 6, 0x30, 0x10011, 7, 8, 0, 0];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+        let mut regs = UnwindRegsArm::new(0x10041, 0x10031, 0x8, 0x10, 0x4);
+        let res = complete_sync(UnwindRuleArm::NoOp.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10030)));
+        assert_eq!(regs.sp(), 0x8);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10020)));
+        assert_eq!(regs.sp(), 0x18);
+        assert_eq!(regs.r7(), 0x20);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10010)));
+        assert_eq!(regs.sp(), 0x28);
+        assert_eq!(regs.r7(), 0x30);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(None));
+    }
+
+    #[test]
+    fn test_arm_mode_frame_pointer() {
+        // ARM code with r11 frame records. r7 is used as a general purpose register.
+        let stack = [1, 2, 0x18, 0x10020, 3, 4, 0, 0];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+        let mut regs = UnwindRegsArm::new(0x10040, 0x10030, 0x4, 0x3, 0x8);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10020)));
+        assert_eq!(regs.sp(), 0x10);
+        assert_eq!(regs.r11(), 0x18);
+        assert_eq!(regs.r7(), 0x3);
+    }
+
+    #[test]
+    fn test_frame_pointer_candidate_order() {
+        // Both r7 and r11 point at plausible frame records: the one at 0x10 returns to
+        // 0x10020, the one at 0x20 returns to 0x10030.
+        let stack = [1, 2, 3, 4, 0x30, 0x10021, 5, 6, 0x30, 0x10031, 7, 8];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+
+        // pc is a Thumb address, so r7 is tried first.
+        let mut regs = UnwindRegsArm::new(0x10041, 0x10041, 0x8, 0x10, 0x20);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10020)));
+        assert_eq!((regs.sp(), regs.r7(), regs.r11()), (0x18, 0x30, 0x20));
+
+        // pc is an ARM address, so r11 is tried first.
+        let mut regs = UnwindRegsArm::new(0x10040, 0x10040, 0x8, 0x10, 0x20);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10030)));
+        assert_eq!((regs.sp(), regs.r7(), regs.r11()), (0x28, 0x10, 0x30));
+    }
+
+    #[test]
+    fn test_frame_pointer_second_candidate() {
+        // The frame record at 0x10 points to a caller frame record at a lower address, so
+        // it is rejected. The one at 0x20 is valid.
+        let stack = [1, 2, 3, 4, 0x8, 0x10021, 5, 6, 0x30, 0x10031, 7, 8, 0, 0];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+
+        // Thumb: r7 is tried first and fails, r11 succeeds.
+        let mut regs = UnwindRegsArm::new(0x10041, 0x10041, 0x8, 0x10, 0x20);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10030)));
+        assert_eq!((regs.sp(), regs.r7(), regs.r11()), (0x28, 0x10, 0x30));
+
+        // ARM: r11 is tried first and fails, r7 succeeds.
+        let mut regs = UnwindRegsArm::new(0x10040, 0x10040, 0x8, 0x20, 0x10);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x10030)));
+        assert_eq!((regs.sp(), regs.r7(), regs.r11()), (0x28, 0x30, 0x10));
+
+        // If both candidates fail, the error of the second one is returned.
+        let mut read_stack = |addr| {
+            if addr < 0x20 {
+                Ok(stack[(addr / 4) as usize])
+            } else {
+                Err(())
+            }
+        };
+        let mut regs = UnwindRegsArm::new(0x10041, 0x10041, 0x8, 0x10, 0x20);
+        let res = complete_sync(UnwindRuleArm::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert!(matches!(res, Err(Error::CouldNotReadStack(0x24, _))));
+    }
+}
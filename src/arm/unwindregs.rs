@@ -0,0 +1,131 @@
+use core::fmt::Debug;
+
+use crate::display_utils::HexNum;
+
+/// The registers used for unwinding on 32-bit ARM. We need pc (r15), lr (r14), sp (r13),
+/// and both frame pointer candidates: r7, which is the frame pointer in Thumb code, and
+/// r11, which is the frame pointer in ARM code.
+///
+/// The values are stored as `u64` for consistency with the other architectures, but only
+/// the lower 32 bits are meaningful.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UnwindRegsArm {
+    pc: u64,
+    lr: u64,
+    sp: u64,
+    r7: u64,
+    r11: u64,
+}
+
+/// One of the two registers which can act as the frame pointer on 32-bit ARM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FramePointerArm {
+    /// r7, used as the frame pointer in Thumb code.
+    R7,
+    /// r11, used as the frame pointer in ARM code.
+    R11,
+}
+
+impl UnwindRegsArm {
+    /// Create a set of unwind register values.
+    pub fn new(pc: u64, lr: u64, sp: u64, r7: u64, r11: u64) -> Self {
+        Self {
+            pc,
+            lr,
+            sp,
+            r7,
+            r11,
+        }
+    }
+
+    /// Get the program counter value (r15).
+    #[inline(always)]
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    /// Set the program counter value (r15).
+    #[inline(always)]
+    pub fn set_pc(&mut self, pc: u64) {
+        self.pc = pc
+    }
+
+    /// Get the lr register value (r14).
+    #[inline(always)]
+    pub fn lr(&self) -> u64 {
+        self.lr
+    }
+
+    /// Set the lr register value (r14).
+    #[inline(always)]
+    pub fn set_lr(&mut self, lr: u64) {
+        self.lr = lr
+    }
+
+    /// Get the stack pointer value (r13).
+    #[inline(always)]
+    pub fn sp(&self) -> u64 {
+        self.sp
+    }
+
+    /// Set the stack pointer value (r13).
+    #[inline(always)]
+    pub fn set_sp(&mut self, sp: u64) {
+        self.sp = sp
+    }
+
+    /// Get the r7 value, the frame pointer in Thumb code.
+    #[inline(always)]
+    pub fn r7(&self) -> u64 {
+        self.r7
+    }
+
+    /// Set the r7 value, the frame pointer in Thumb code.
+    #[inline(always)]
+    pub fn set_r7(&mut self, r7: u64) {
+        self.r7 = r7
+    }
+
+    /// Get the r11 value, the frame pointer in ARM code.
+    #[inline(always)]
+    pub fn r11(&self) -> u64 {
+        self.r11
+    }
+
+    /// Set the r11 value, the frame pointer in ARM code.
+    #[inline(always)]
+    pub fn set_r11(&mut self, r11: u64) {
+        self.r11 = r11
+    }
+
+    /// Get the value of the given frame pointer register.
+    #[inline(always)]
+    pub fn fp(&self, reg: FramePointerArm) -> u64 {
+        match reg {
+            FramePointerArm::R7 => self.r7,
+            FramePointerArm::R11 => self.r11,
+        }
+    }
+
+    /// Set the value of the given frame pointer register.
+    #[inline(always)]
+    pub fn set_fp(&mut self, reg: FramePointerArm, fp: u64) {
+        match reg {
+            FramePointerArm::R7 => self.r7 = fp,
+            FramePointerArm::R11 => self.r11 = fp,
+        }
+    }
+}
+
+impl Debug for UnwindRegsArm {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UnwindRegsArm")
+            .field("pc", &HexNum(self.pc))
+            .field("lr", &HexNum(self.lr))
+            .field("sp", &HexNum(self.sp))
+            .field("r7", &HexNum(self.r7))
+            .field("r11", &HexNum(self.r11))
+            .finish()
+    }
+}
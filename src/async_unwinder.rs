@@ -0,0 +1,35 @@
+use core::future::Future;
+
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::unwinder::Unwinder;
+use crate::FrameAddress;
+
+/// Unwinding with an asynchronous stack memory reader.
+///
+/// This is useful if stack memory can't be read synchronously, for example when it has
+/// to be requested from a remote process over a pipe, or from a paused debuggee which
+/// is driven by an async runtime.
+///
+/// The unwinders use the same code for [`Unwinder::unwind_frame`] and for
+/// [`unwind_frame_async`](AsyncUnwinder::unwind_frame_async): unwind rules and DWARF
+/// CFI are evaluated against an [`AsyncMemoryAccess`], and the synchronous methods wrap
+/// their [`MemoryAccess`](crate::MemoryAccess) in one whose reads complete immediately.
+/// Looking up the rule, in the cache or in the unwind information, never waits. The
+/// returned future only waits for the stack reads which the rule needs, and each of
+/// them is requested once.
+pub trait AsyncUnwinder: Unwinder {
+    /// Unwind a single frame, to recover return address and caller register values,
+    /// awaiting the reads from `read_stack`.
+    ///
+    /// This behaves exactly like [`Unwinder::unwind_frame`] otherwise.
+    fn unwind_frame_async<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> impl Future<Output = Result<Option<u64>, Error>>
+    where
+        F: AsyncMemoryAccess;
+}
@@ -1,11 +1,19 @@
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use core::ops::Range;
 
 use crate::{rule_cache::RuleCache, unwind_rule::UnwindRule};
 
+pub(crate) use crate::rule_cache::DEFAULT_CACHE_ENTRY_COUNT;
+
 pub use crate::rule_cache::CacheStats;
 
 /// A trait which lets you opt into allocation-free unwinding. The two implementations of
 /// this trait are [`MustNotAllocateDuringUnwind`] and [`MayAllocateDuringUnwind`].
+///
+/// The policy selects the storage types which gimli uses for DWARF CFI evaluation. The
+/// unwind context storage is owned by the unwinder cache, so it is allocated once when
+/// the cache is created, and not during unwinding.
 pub trait AllocationPolicy {
     type GimliUnwindContextStorage<R: gimli::ReaderOffset>: gimli::UnwindContextStorage<R>;
     type GimliEvaluationStorage<R: gimli::Reader>: gimli::EvaluationStorage<R>;
@@ -18,6 +26,31 @@ pub trait AllocationPolicy {
 /// space for DWARF CFI unwind table row evaluation and for DWARF CFI expression evaluation.
 /// And because those preallocations are of a fixed size, it is possible that this fixed
 /// size is not large enough for certain DWARF unwinding tasks.
+///
+/// The limits are:
+///
+///  - Unwind table rows can have rules for up to 192 registers.
+///  - `DW_CFA_remember_state` can be nested up to 3 levels deep.
+///  - DWARF expressions can use up to 64 stack values, can be nested (e.g. with
+///    `DW_OP_call*` or `DW_OP_entry_value`) up to 4 levels deep, and can only describe
+///    a single location piece.
+///
+/// The limits for unwind table rows are the same with [`MayAllocateDuringUnwind`]. If a CIE
+/// or FDE exceeds them, gimli reports an error during evaluation, and the address is
+/// unwound as if no FDE covered it. DWARF expressions which exceed their limits can't be
+/// evaluated, and the unwinder uses the fallback rule for the address, which is based on
+/// frame pointers.
+///
+/// There are exceptions:
+///
+///  - If a module's `.eh_frame_hdr` has no entry for an address, the first such lookup
+///    builds an index of the module's `.eh_frame`, which allocates.
+///  - A module created with `Module::new_lazy` loads its
+///    unwind information when it's first needed, which locks a mutex and allocates.
+///    Call [`Module::load`](crate::Module::load) before unwinding to avoid this.
+///  - If FDE evaluations are recorded, see for example
+///    [`CacheX86_64::record_fde_evaluations`](crate::x86_64::CacheX86_64::record_fde_evaluations),
+///    counting an FDE for the first time allocates.
 pub struct MustNotAllocateDuringUnwind;
 
 /// This is only used in the implementation of [MustNotAllocateDuringUnwind] and
@@ -45,7 +78,7 @@ impl AllocationPolicy for MustNotAllocateDuringUnwind {
 /// implementations.
 ///
 /// This is the preferred policy because it saves memory and places no limitations on
-/// DWARF CFI evaluation.
+/// DWARF expression evaluation.
 pub struct MayAllocateDuringUnwind;
 impl AllocationPolicy for MayAllocateDuringUnwind {
     type GimliUnwindContextStorage<R: gimli::ReaderOffset> = gimli::StoreOnHeap;
@@ -58,20 +91,86 @@ impl AllocationPolicy for MayAllocateDuringUnwind {
 /// A single unwinder cache can be used with multiple unwinders alternatingly.
 ///
 /// The cache stores unwind rules for addresses it has seen before, and it stores the
-/// unwind context which gimli needs for DWARF CFI evaluation.
+/// unwind context which gimli needs for DWARF CFI evaluation. It also remembers the most
+/// recent address range which is not covered by any module, so that repeated lookups of
+/// addresses in that range fail quickly.
+///
+/// A cache must not be shared between the unwinders of different processes without
+/// calling `clear` in between, see for example
+/// [`CacheX86_64::clear`](crate::x86_64::CacheX86_64::clear).
 pub struct Cache<R: UnwindRule, P: AllocationPolicy = MayAllocateDuringUnwind> {
     pub(crate) gimli_unwind_context:
         Box<gimli::UnwindContext<usize, P::GimliUnwindContextStorage<usize>>>,
     pub(crate) rule_cache: RuleCache<R>,
+    pub(crate) no_module_range: Option<NoModuleRange>,
+    pub(crate) fde_evaluations: FdeEvaluations,
+}
+
+/// The FDE evaluation counts of a cache, see [`Cache::fde_evaluations`]. This is a separate
+/// field so that FDE evaluations can be counted while the gimli unwind context is borrowed.
+#[derive(Default)]
+pub(crate) struct FdeEvaluations {
+    pub recording: bool,
+    counts: BTreeMap<(u64, u32), u64>,
+}
+
+impl FdeEvaluations {
+    /// Count an evaluation of the FDE at `fde_offset` in the module starting at `module_start`,
+    /// if recording is enabled. This allocates the first time an FDE is counted.
+    pub fn count(&mut self, module_start: u64, fde_offset: u32) {
+        if self.recording {
+            *self.counts.entry((module_start, fde_offset)).or_insert(0) += 1;
+        }
+    }
+}
+
+/// An address range which contained no module for the given modules generation.
+pub(crate) struct NoModuleRange {
+    pub modules_generation: u16,
+    pub range: Range<u64>,
 }
 
 impl<R: UnwindRule, P: AllocationPolicy> Cache<R, P> {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CACHE_ENTRY_COUNT)
+    }
+
+    /// Create a cache which can store unwind rules for `entry_count` addresses.
+    pub fn with_capacity(entry_count: usize) -> Self {
         Self {
             gimli_unwind_context: Box::new(gimli::UnwindContext::new_in()),
-            rule_cache: RuleCache::new(),
+            rule_cache: RuleCache::with_capacity(entry_count),
+            no_module_range: None,
+            fde_evaluations: FdeEvaluations::default(),
         }
     }
+
+    /// The number of addresses for which this cache can store unwind rules.
+    pub fn capacity(&self) -> usize {
+        self.rule_cache.capacity()
+    }
+
+    /// Removes all cached unwind rules and the cached address range without modules.
+    pub fn clear(&mut self) {
+        self.rule_cache.clear();
+        self.no_module_range = None;
+    }
+
+    /// Start or stop counting how often each FDE is evaluated.
+    pub fn record_fde_evaluations(&mut self, record: bool) {
+        self.fde_evaluations.recording = record;
+    }
+
+    /// The number of evaluations of each FDE while recording was enabled, keyed by the
+    /// start of the module's address range and the FDE offset. Cache hits aren't counted.
+    pub fn fde_evaluations(&self) -> &BTreeMap<(u64, u32), u64> {
+        &self.fde_evaluations.counts
+    }
+
+    /// Forget the FDE evaluation counts, without changing whether they're recorded.
+    pub fn reset_fde_evaluations(&mut self) {
+        self.fde_evaluations.counts.clear();
+    }
 }
 
 impl<R: UnwindRule, P: AllocationPolicy> Default for Cache<R, P> {
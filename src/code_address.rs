@@ -72,4 +72,30 @@ impl FrameAddress {
             FrameAddress::ReturnAddress(_) => true,
         }
     }
+
+    /// Returns whether this address was taken from the instruction pointer, i.e. whether
+    /// this is the address of the first frame.
+    pub fn is_instruction_pointer(self) -> bool {
+        !self.is_return_address()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address_for_lookup() {
+        let ip = FrameAddress::from_instruction_pointer(0x1000);
+        assert!(ip.is_instruction_pointer());
+        assert_eq!(ip.address(), 0x1000);
+        assert_eq!(ip.address_for_lookup(), 0x1000);
+
+        let ra = FrameAddress::from_return_address(0x1000).unwrap();
+        assert!(ra.is_return_address());
+        assert_eq!(ra.address(), 0x1000);
+        assert_eq!(ra.address_for_lookup(), 0xfff);
+
+        assert_eq!(FrameAddress::from_return_address(0), None);
+    }
 }
@@ -0,0 +1,65 @@
+//! Opt-in diagnostic output.
+//!
+//! Framehop silently falls back to less precise unwind rules when something goes wrong,
+//! for example when a DWARF FDE does not cover the requested address or when a CFI row
+//! can't be translated into a cacheable rule. These events are interesting when
+//! debugging bad unwinding, but they happen far too often in normal profiling to be
+//! printed unconditionally. So they're only reported if a hook has been installed with
+//! [`set_diagnostics_hook`].
+//!
+//! Without the `std` feature, diagnostics are always discarded.
+
+/// Report a diagnostic message. The message is only formatted if a hook is installed.
+macro_rules! diagnostic {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "std")]
+        $crate::diagnostics::emit(format_args!($($arg)*));
+        #[cfg(not(feature = "std"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+pub(crate) use diagnostic;
+
+#[cfg(feature = "std")]
+mod hook {
+    use std::boxed::Box;
+    use std::sync::RwLock;
+
+    type Hook = Box<dyn Fn(&str) + Send + Sync>;
+
+    static DIAGNOSTICS_HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+    /// Install a function which receives framehop's diagnostic messages, replacing any
+    /// previously installed hook.
+    ///
+    /// By default no hook is installed and diagnostics are discarded without being
+    /// formatted. The hook is global, i.e. it receives messages from all unwinders.
+    ///
+    /// Messages are formatted into a `String` before the hook is called, so don't install
+    /// a hook if you rely on [`MustNotAllocateDuringUnwind`](crate::MustNotAllocateDuringUnwind).
+    pub fn set_diagnostics_hook(hook: impl Fn(&str) + Send + Sync + 'static) {
+        if let Ok(mut guard) = DIAGNOSTICS_HOOK.write() {
+            *guard = Some(Box::new(hook));
+        }
+    }
+
+    /// Remove the hook installed with [`set_diagnostics_hook`], so that diagnostics are
+    /// discarded again.
+    pub fn remove_diagnostics_hook() {
+        if let Ok(mut guard) = DIAGNOSTICS_HOOK.write() {
+            *guard = None;
+        }
+    }
+
+    pub(crate) fn emit(args: core::fmt::Arguments) {
+        if let Ok(guard) = DIAGNOSTICS_HOOK.read() {
+            if let Some(hook) = guard.as_ref() {
+                hook(&std::fmt::format(args));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use hook::*;
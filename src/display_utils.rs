@@ -1,4 +1,4 @@
-use core::fmt::{Binary, Debug, LowerHex};
+use core::fmt::{Binary, Debug, Display, LowerHex};
 
 pub struct HexNum<N: LowerHex>(pub N);
 
@@ -8,6 +8,20 @@ impl<N: LowerHex> Debug for HexNum<N> {
     }
 }
 
+/// A register value for the `Display` implementations of the unwind registers: `0x`
+/// and the value in hex, or `?` if the value is unknown.
+pub struct RegValue(pub Option<u64>);
+
+impl Display for RegValue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            Some(value) => write!(f, "{value:#x}"),
+            None => f.write_str("?"),
+        }
+    }
+}
+
+#[allow(unused)]
 pub struct BinNum<N: Binary>(pub N);
 
 impl<N: Binary> Debug for BinNum<N> {
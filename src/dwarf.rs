@@ -1,26 +1,36 @@
 use core::marker::PhantomData;
+use core::ops::Range;
 
+use alloc::boxed::Box;
 use alloc::vec::Vec;
 use gimli::{
-    CfaRule, CieOrFde, DebugFrame, EhFrame, EhFrameHdr, Encoding, EndianSlice, Evaluation,
-    EvaluationResult, EvaluationStorage, Expression, LittleEndian, Location, ParsedEhFrameHdr,
-    Reader, ReaderOffset, Register, RegisterRule, UnwindContext, UnwindContextStorage,
-    UnwindOffset, UnwindSection, UnwindTableRow, Value,
+    CfaRule, CieOrFde, DebugFrame, EhFrame, EhFrameHdr, Encoding, EndianSlice, Endianity,
+    Evaluation, EvaluationResult, EvaluationStorage, Expression, LittleEndian, Location,
+    ParsedEhFrameHdr, Reader, ReaderOffset, Register, RegisterRule, RunTimeEndian, StoreOnHeap,
+    UnwindContext, UnwindContextStorage, UnwindOffset, UnwindSection, UnwindTableRow, Value,
 };
 
 pub(crate) use gimli::BaseAddresses;
 
-use crate::{arch::Arch, unwind_result::UnwindResult, ModuleSectionInfo};
+use crate::add_signed::checked_add_signed;
+use crate::diagnostics::diagnostic;
+use crate::error::Error;
+use crate::memory::{AsyncMemoryAccess, TrackReadFailures};
+use crate::{arch::Arch, unwind_result::UnwindResult, FrameAddress, ModuleSectionInfo};
 
+/// An error from evaluating DWARF CFI, see [`Error::Dwarf`].
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
 #[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DwarfUnwinderError {
-    #[error("Could not get the FDE for the supplied offset: {0}")]
-    FdeFromOffsetFailed(#[source] gimli::Error),
+    /// The FDE at the given offset in the unwind section could not be parsed.
+    #[error("Could not get the FDE at offset 0x{0:x}: {1}")]
+    FdeFromOffsetFailed(u32, #[source] gimli::Error),
 
-    #[error("Could not find DWARF unwind info for the requested address: {0}")]
-    UnwindInfoForAddressFailed(#[source] gimli::Error),
+    /// The FDE has no unwind information for the given address, relative to the
+    /// module's stated base address.
+    #[error("Could not find DWARF unwind info for address 0x{0:x}: {1}")]
+    UnwindInfoForAddressFailed(u64, #[source] gimli::Error),
 
     #[error("Stack pointer moved backwards")]
     StackPointerMovedBackwards,
@@ -36,42 +46,320 @@ pub enum DwarfUnwinderError {
 
     #[error("Could not recover the frame pointer")]
     CouldNotRecoverFramePointer,
+
+    /// The CFA or the return address could not be recovered, even though no stack read
+    /// failed. This usually means that the CFI uses a rule which can't be evaluated, for
+    /// example a DWARF expression with an unsupported operation, or a rule which needs a
+    /// register that isn't tracked or is marked as unknown.
+    #[error("Unsupported DWARF CFI rule: {0}")]
+    UnsupportedRule(UnsupportedRuleDescription),
+}
+
+/// The DWARF CFI rules for the CFA and the return address of a frame which could not be
+/// unwound, see [`DwarfUnwinderError::UnsupportedRule`].
+///
+/// This is meant for bug reports: it's displayed like `CFA=expression, RA=register(lr)`.
+/// DWARF expressions are only described as such, without their operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedRuleDescription {
+    /// The rule for the CFA.
+    pub cfa: CfaRuleDescription,
+    /// The rule for the return address register.
+    pub return_address: RegisterRuleDescription,
+}
+
+impl core::fmt::Display for UnsupportedRuleDescription {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "CFA={}, RA={}", self.cfa, self.return_address)
+    }
+}
+
+/// A DWARF register, with its name if it's known for the architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterName {
+    /// The DWARF register number.
+    pub number: u16,
+    /// The register's name, e.g. `rsp`.
+    pub name: Option<&'static str>,
+}
+
+impl core::fmt::Display for RegisterName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{name}"),
+            None => write!(f, "reg{}", self.number),
+        }
+    }
+}
+
+/// The shape of a DWARF CFI rule for the CFA, see [`UnsupportedRuleDescription`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfaRuleDescription {
+    /// `CFA = register + offset`, displayed like `rsp+16`.
+    RegisterAndOffset { register: RegisterName, offset: i64 },
+    /// The CFA is computed with a DWARF expression.
+    Expression,
+}
+
+impl core::fmt::Display for CfaRuleDescription {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CfaRuleDescription::RegisterAndOffset { register, offset } => {
+                write!(f, "{register}{offset:+}")
+            }
+            CfaRuleDescription::Expression => write!(f, "expression"),
+        }
+    }
+}
+
+/// The shape of a DWARF CFI rule for a register, see [`UnsupportedRuleDescription`].
+/// Offsets are relative to the CFA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterRuleDescription {
+    Undefined,
+    SameValue,
+    Offset(i64),
+    ValOffset(i64),
+    Register(RegisterName),
+    Expression,
+    ValExpression,
+    Architectural,
+    Constant(u64),
+}
+
+impl core::fmt::Display for RegisterRuleDescription {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RegisterRuleDescription::Undefined => write!(f, "undefined"),
+            RegisterRuleDescription::SameValue => write!(f, "same-value"),
+            RegisterRuleDescription::Offset(offset) => write!(f, "offset({offset})"),
+            RegisterRuleDescription::ValOffset(offset) => write!(f, "val-offset({offset})"),
+            RegisterRuleDescription::Register(register) => write!(f, "register({register})"),
+            RegisterRuleDescription::Expression => write!(f, "expression"),
+            RegisterRuleDescription::ValExpression => write!(f, "val-expression"),
+            RegisterRuleDescription::Architectural => write!(f, "architectural"),
+            RegisterRuleDescription::Constant(value) => write!(f, "constant({value:#x})"),
+        }
+    }
+}
+
+impl UnsupportedRuleDescription {
+    /// Describe the rules of `row` for the CFA and for the return address register of
+    /// the architecture `A`.
+    fn for_row<A, RO, UCS>(row: &UnwindTableRow<RO, UCS>) -> Self
+    where
+        A: DwarfUnwinding,
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        let register_name = |register: Register| RegisterName {
+            number: register.0,
+            name: A::register_name(register),
+        };
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                CfaRuleDescription::RegisterAndOffset {
+                    register: register_name(*register),
+                    offset: *offset,
+                }
+            }
+            CfaRule::Expression(_) => CfaRuleDescription::Expression,
+        };
+        let return_address = match row.register(A::RETURN_ADDRESS_REGISTER) {
+            RegisterRule::Undefined => RegisterRuleDescription::Undefined,
+            RegisterRule::SameValue => RegisterRuleDescription::SameValue,
+            RegisterRule::Offset(offset) => RegisterRuleDescription::Offset(offset),
+            RegisterRule::ValOffset(offset) => RegisterRuleDescription::ValOffset(offset),
+            RegisterRule::Register(register) => {
+                RegisterRuleDescription::Register(register_name(register))
+            }
+            RegisterRule::Expression(_) => RegisterRuleDescription::Expression,
+            RegisterRule::ValExpression(_) => RegisterRuleDescription::ValExpression,
+            RegisterRule::Constant(value) => RegisterRuleDescription::Constant(value),
+            _ => RegisterRuleDescription::Architectural,
+        };
+        Self {
+            cfa,
+            return_address,
+        }
+    }
+}
+
+/// The error for a rule of `row` which the slow path couldn't evaluate: `err` if a read
+/// from `read_stack` failed, otherwise [`DwarfUnwinderError::UnsupportedRule`].
+pub fn eval_error<A, RO, UCS, M>(
+    row: &UnwindTableRow<RO, UCS>,
+    read_stack: &TrackReadFailures<M>,
+    err: DwarfUnwinderError,
+) -> DwarfUnwinderError
+where
+    A: DwarfUnwinding,
+    RO: ReaderOffset,
+    UCS: UnwindContextStorage<RO>,
+    M: AsyncMemoryAccess,
+{
+    if read_stack.read_failed {
+        err
+    } else {
+        DwarfUnwinderError::UnsupportedRule(UnsupportedRuleDescription::for_row::<A, _, _>(row))
+    }
 }
 
-#[derive(Clone, Debug)]
+/// The reason why a row of DWARF CFI could not be translated into an unwind rule.
+///
+/// Such rows are handled by evaluating the CFI for every unwound frame, which is slower
+/// than executing an unwind rule and whose result can't be cached.
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ConversionError {
+    #[error("The CFA is computed with a DWARF expression")]
     CfaIsExpression,
+
+    #[error(
+        "The CFA is an offset from a register other than the stack pointer or the frame pointer"
+    )]
     CfaIsOffsetFromUnknownRegister,
+
+    #[error("The return address is stored at an unexpected offset from the CFA")]
     ReturnAddressRuleWithUnexpectedOffset,
+
+    #[error("The return address rule is not supported")]
     ReturnAddressRuleWasWeird,
+
+    #[error("The stack pointer offset does not fit into the unwind rule")]
     SpOffsetDoesNotFit,
+
+    #[error("A register is not stored at an offset from the CFA")]
     RegisterNotStoredRelativeToCfa,
+
+    #[error("The frame pointer is restored but the link register is not")]
     RestoringFpButNotLr,
+
+    #[error("The link register storage offset does not fit into the unwind rule")]
     LrStorageOffsetDoesNotFit,
+
+    #[error("The frame pointer storage offset does not fit into the unwind rule")]
     FpStorageOffsetDoesNotFit,
+
+    #[error("The stack pointer offset from the frame pointer does not fit into the unwind rule")]
     SpOffsetFromFpDoesNotFit,
+
+    #[error("The CFA is based on the frame pointer, but the link register is not restored")]
     FramePointerRuleDoesNotRestoreLr,
+
+    #[error("The CFA is based on the frame pointer, but the frame pointer is not restored")]
     FramePointerRuleDoesNotRestoreFp,
+
+    #[error("The CFA is based on the frame pointer, but the frame pointer is not restored")]
     FramePointerRuleDoesNotRestoreBp,
+
+    #[error("The CFA is based on the frame pointer, but the frame pointer is stored at an unexpected offset")]
     FramePointerRuleHasStrangeBpOffset,
+
+    #[error("Both r7 and r11 are restored")]
+    RestoringBothFramePointers,
 }
 
 pub trait DwarfUnwinding: Arch {
-    fn unwind_frame<F, R, UCS, ES>(
+    /// The DWARF register which holds the return address.
+    const RETURN_ADDRESS_REGISTER: Register;
+
+    /// The name of a DWARF register, for error messages.
+    fn register_name(register: Register) -> Option<&'static str>;
+
+    async fn unwind_frame<F, R, UCS, ES>(
         section: &impl UnwindSection<R>,
         unwind_info: &UnwindTableRow<R::Offset, UCS>,
         encoding: Encoding,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
+        lookup_svma: u64,
         read_stack: &mut F,
     ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         R: Reader,
         UCS: UnwindContextStorage<R::Offset>,
         ES: EvaluationStorage<R>;
 
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule;
+
+    /// Translate a row of the unwind table into an unwind rule, for the fast path of
+    /// [`DwarfUnwinding::unwind_frame`].
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>;
+}
+
+/// The encoding of a DWARF FDE, as stated in its CIE, see
+/// [`Unwinder::debug_fde_encoding`](crate::Unwinder::debug_fde_encoding).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FdeEncoding {
+    /// The version of the CIE. This is 1 or 3 in `.eh_frame`, and 1, 3 or 4 in
+    /// `.debug_frame`.
+    pub version: u16,
+    /// The size of an address in bytes.
+    pub address_size: u8,
+    /// Whether the CIE uses the 64-bit DWARF format.
+    pub is_dwarf64: bool,
+}
+
+impl From<Encoding> for FdeEncoding {
+    fn from(encoding: Encoding) -> Self {
+        Self {
+            version: encoding.version,
+            address_size: encoding.address_size,
+            is_dwarf64: encoding.format == gimli::Format::Dwarf64,
+        }
+    }
+}
+
+/// Parse the FDE at `fde_offset` and return the encoding of its CIE.
+pub fn fde_encoding<R: Reader, US: UnwindSection<R>>(
+    unwind_section: &US,
+    bases: &BaseAddresses,
+    fde_offset: u32,
+) -> Option<Encoding> {
+    let fde = unwind_section
+        .fde_from_offset(
+            bases,
+            US::Offset::from(R::Offset::from_u32(fde_offset)),
+            US::cie_from_offset,
+        )
+        .ok()?;
+    Some(fde.cie().encoding())
+}
+
+/// Returns true if `unwind_section` contains a CIE. Fails if an entry before the first
+/// CIE, or the CIE itself, can't be parsed.
+pub fn contains_cie<R: Reader, US: UnwindSection<R>>(
+    unwind_section: &US,
+    bases: &BaseAddresses,
+) -> gimli::Result<bool> {
+    let mut entries = unwind_section.entries(bases);
+    while let Some(entry) = entries.next()? {
+        if let CieOrFde::Cie(_) = entry {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Divides `offset` by `unit` for storing it in an unwind rule. Fails with `err` if the
+/// offset is not a multiple of `unit`, or if the result doesn't fit into `T`.
+pub fn scaled_offset<T: TryFrom<i64>>(
+    offset: i64,
+    unit: i64,
+    err: ConversionError,
+) -> Result<T, ConversionError> {
+    if offset % unit != 0 {
+        return Err(err);
+    }
+    T::try_from(offset / unit).map_err(|_| err)
 }
 
 pub enum UnwindSectionType {
@@ -79,14 +367,19 @@ pub enum UnwindSectionType {
     DebugFrame,
 }
 
+/// The unwind section of a [`DwarfUnwinder`], with the address size already set.
+enum DwarfUnwindSection<R: Reader> {
+    EhFrame(EhFrame<R>),
+    DebugFrame(DebugFrame<R>),
+}
+
 pub struct DwarfUnwinder<'a, R, A, UCS>
 where
     R: Reader,
     A: DwarfUnwinding,
     UCS: UnwindContextStorage<R::Offset>,
 {
-    unwind_section_data: R,
-    unwind_section_type: UnwindSectionType,
+    unwind_section: DwarfUnwindSection<R>,
     eh_frame_hdr: Option<ParsedEhFrameHdr<EndianSlice<'a, R::Endian>>>,
     unwind_context: &'a mut UnwindContext<R::Offset, UCS>,
     base_svma: u64,
@@ -108,19 +401,30 @@ where
         bases: BaseAddresses,
         base_svma: u64,
     ) -> Self {
-        let eh_frame_hdr = match eh_frame_hdr_data {
-            Some(eh_frame_hdr_data) => {
-                let hdr = EhFrameHdr::new(eh_frame_hdr_data, unwind_section_data.endian());
-                match hdr.parse(&bases, 8) {
-                    Ok(hdr) => Some(hdr),
-                    Err(_) => None,
-                }
+        let eh_frame_hdr = eh_frame_hdr_data.and_then(|eh_frame_hdr_data| {
+            parse_eh_frame_hdr(
+                eh_frame_hdr_data,
+                unwind_section_data.endian(),
+                &bases,
+                A::ADDRESS_SIZE,
+            )
+        });
+        let unwind_section = match unwind_section_type {
+            UnwindSectionType::EhFrame => {
+                let mut eh_frame = EhFrame::from(unwind_section_data);
+                eh_frame.set_address_size(A::ADDRESS_SIZE);
+                eh_frame.set_vendor(A::VENDOR);
+                DwarfUnwindSection::EhFrame(eh_frame)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut debug_frame = DebugFrame::from(unwind_section_data);
+                debug_frame.set_address_size(A::ADDRESS_SIZE);
+                debug_frame.set_vendor(A::VENDOR);
+                DwarfUnwindSection::DebugFrame(debug_frame)
             }
-            None => None,
         };
         Self {
-            unwind_section_data,
-            unwind_section_type,
+            unwind_section,
             eh_frame_hdr,
             unwind_context,
             bases,
@@ -131,14 +435,10 @@ where
 
     pub fn get_fde_offset_for_relative_address(&self, rel_lookup_address: u32) -> Option<u32> {
         let lookup_svma = self.base_svma + rel_lookup_address as u64;
-        let eh_frame_hdr = self.eh_frame_hdr.as_ref()?;
-        let table = eh_frame_hdr.table()?;
-        let fde_ptr = table.lookup(lookup_svma, &self.bases).ok()?;
-        let fde_offset = table.pointer_to_offset(fde_ptr).ok()?;
-        fde_offset.0.into_u64().try_into().ok()
+        fde_offset_from_eh_frame_hdr(self.eh_frame_hdr.as_ref()?, &self.bases, lookup_svma)
     }
 
-    pub fn unwind_frame_with_fde<F, ES>(
+    pub async fn unwind_frame_with_fde<F, ES>(
         &mut self,
         regs: &mut A::UnwindRegs,
         is_first_frame: bool,
@@ -147,74 +447,236 @@ where
         read_stack: &mut F,
     ) -> Result<UnwindResult<A::UnwindRule>, DwarfUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         ES: EvaluationStorage<R>,
     {
         let lookup_svma = self.base_svma + rel_lookup_address as u64;
-        let unwind_section_data = self.unwind_section_data.clone();
-        match self.unwind_section_type {
-            UnwindSectionType::EhFrame => {
-                let mut eh_frame = EhFrame::from(unwind_section_data);
-                eh_frame.set_address_size(8);
-                let unwind_info = self.unwind_info_for_fde(&eh_frame, lookup_svma, fde_offset);
-                if let Err(DwarfUnwinderError::UnwindInfoForAddressFailed(_)) = unwind_info {
-                    return Ok(UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()));
-                }
-                let (unwind_info, encoding) = unwind_info?;
-                A::unwind_frame::<F, R, UCS, ES>(
-                    &eh_frame,
-                    unwind_info,
-                    encoding,
+        match &self.unwind_section {
+            DwarfUnwindSection::EhFrame(eh_frame) => {
+                unwind_frame_with_section::<A, _, _, _, _, ES>(
+                    eh_frame,
+                    &self.bases,
+                    self.unwind_context,
                     regs,
                     is_first_frame,
+                    lookup_svma,
+                    fde_offset,
                     read_stack,
                 )
+                .await
             }
-            UnwindSectionType::DebugFrame => {
-                let mut debug_frame = DebugFrame::from(unwind_section_data);
-                debug_frame.set_address_size(8);
-                let unwind_info = self.unwind_info_for_fde(&debug_frame, lookup_svma, fde_offset);
-                if let Err(DwarfUnwinderError::UnwindInfoForAddressFailed(_)) = unwind_info {
-                    return Ok(UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()));
-                }
-                let (unwind_info, encoding) = unwind_info?;
-                A::unwind_frame::<F, R, UCS, ES>(
-                    &debug_frame,
-                    unwind_info,
-                    encoding,
+            DwarfUnwindSection::DebugFrame(debug_frame) => {
+                unwind_frame_with_section::<A, _, _, _, _, ES>(
+                    debug_frame,
+                    &self.bases,
+                    self.unwind_context,
                     regs,
                     is_first_frame,
+                    lookup_svma,
+                    fde_offset,
                     read_stack,
                 )
+                .await
             }
         }
     }
+}
 
-    fn unwind_info_for_fde<US: UnwindSection<R>>(
-        &mut self,
-        unwind_section: &US,
-        lookup_svma: u64,
-        fde_offset: u32,
-    ) -> Result<(&UnwindTableRow<R::Offset, UCS>, Encoding), DwarfUnwinderError> {
-        let fde = unwind_section.fde_from_offset(
-            &self.bases,
+#[allow(clippy::too_many_arguments)]
+async fn unwind_frame_with_section<A, R, US, UCS, F, ES>(
+    unwind_section: &US,
+    bases: &BaseAddresses,
+    unwind_context: &mut UnwindContext<R::Offset, UCS>,
+    regs: &mut A::UnwindRegs,
+    is_first_frame: bool,
+    lookup_svma: u64,
+    fde_offset: u32,
+    read_stack: &mut F,
+) -> Result<UnwindResult<A::UnwindRule>, DwarfUnwinderError>
+where
+    A: DwarfUnwinding,
+    R: Reader,
+    US: UnwindSection<R>,
+    UCS: UnwindContextStorage<R::Offset>,
+    F: AsyncMemoryAccess,
+    ES: EvaluationStorage<R>,
+{
+    let fde = unwind_section
+        .fde_from_offset(
+            bases,
             US::Offset::from(R::Offset::from_u32(fde_offset)),
             US::cie_from_offset,
-        );
-        let fde = fde.map_err(DwarfUnwinderError::FdeFromOffsetFailed)?;
-        let encoding = fde.cie().encoding();
-        let unwind_info: &UnwindTableRow<_, _> = fde
-            .unwind_info_for_address(
-                unwind_section,
-                &self.bases,
-                self.unwind_context,
-                lookup_svma,
-            )
-            .map_err(DwarfUnwinderError::UnwindInfoForAddressFailed)?;
-        Ok((unwind_info, encoding))
+        )
+        .map_err(|err| DwarfUnwinderError::FdeFromOffsetFailed(fde_offset, err))?;
+    let encoding = fde.cie().encoding();
+    let unwind_info =
+        match fde.unwind_info_for_address(unwind_section, bases, unwind_context, lookup_svma) {
+            Ok(unwind_info) => unwind_info,
+            Err(err) => {
+                diagnostic!(
+                    "FDE at offset 0x{:x} has no unwind info for address 0x{:x}: {}",
+                    fde_offset,
+                    lookup_svma,
+                    err
+                );
+                return Ok(UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()));
+            }
+        };
+    A::unwind_frame::<F, R, UCS, ES>(
+        unwind_section,
+        unwind_info,
+        encoding,
+        regs,
+        is_first_frame,
+        lookup_svma,
+        read_stack,
+    )
+    .await
+}
+
+/// Parse the header of `.eh_frame_hdr`. The pointer encodings used by the header are
+/// resolved with `bases`, so the `.eh_frame_hdr` base needs to be set for `pcrel` and
+/// `datarel` encoded pointers, and `address_size` is the size of `absptr` encoded pointers.
+pub fn parse_eh_frame_hdr<'a, E: Endianity>(
+    eh_frame_hdr_data: &'a [u8],
+    endian: E,
+    bases: &BaseAddresses,
+    address_size: u8,
+) -> Option<ParsedEhFrameHdr<EndianSlice<'a, E>>> {
+    match EhFrameHdr::new(eh_frame_hdr_data, endian).parse(bases, address_size) {
+        Ok(hdr) => Some(hdr),
+        Err(err) => {
+            diagnostic!("Could not parse .eh_frame_hdr: {}", err);
+            None
+        }
+    }
+}
+
+/// Look up the offset of the FDE which covers `lookup_svma` in the binary search table
+/// of the parsed `.eh_frame_hdr`.
+pub fn fde_offset_from_eh_frame_hdr<R: Reader>(
+    eh_frame_hdr: &ParsedEhFrameHdr<R>,
+    bases: &BaseAddresses,
+    lookup_svma: u64,
+) -> Option<u32> {
+    let table = eh_frame_hdr.table()?;
+    let fde_ptr = table.lookup(lookup_svma, bases).ok()?;
+    let fde_offset = table.pointer_to_offset(fde_ptr).ok()?;
+    fde_offset.0.into_u64().try_into().ok()
+}
+
+/// Find the FDE which covers `lookup_svma` by parsing all entries of the unwind section.
+#[cfg(not(feature = "std"))]
+pub fn fde_offset_by_linear_scan<R: Reader, US: UnwindSection<R>>(
+    unwind_section: &US,
+    bases: &BaseAddresses,
+    lookup_svma: u64,
+) -> Option<u32> {
+    let mut entries = unwind_section.entries(bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial_fde) = entry else {
+            continue;
+        };
+        let Ok(fde) = partial_fde.parse(US::cie_from_offset) else {
+            continue;
+        };
+        if fde.contains(lookup_svma) {
+            return fde.offset().into_u64().try_into().ok();
+        }
+    }
+    None
+}
+
+/// Unwind a single frame with the FDE at `fde_offset` in the little-endian `.eh_frame`
+/// data `eh_frame`, whose section starts at `eh_frame_svma`. The code address of the
+/// frame is used as an SVMA.
+pub async fn unwind_frame_with_raw_eh_frame<A, F>(
+    eh_frame: &[u8],
+    eh_frame_svma: u64,
+    fde_offset: u32,
+    address: FrameAddress,
+    regs: &mut A::UnwindRegs,
+    read_stack: &mut F,
+) -> Result<Option<u64>, Error>
+where
+    A: DwarfUnwinding,
+    F: AsyncMemoryAccess,
+{
+    let lookup_svma = address.address_for_lookup();
+    let is_first_frame = address.is_instruction_pointer();
+    let mut unwind_context = Box::new(UnwindContext::new());
+    let mut unwinder = DwarfUnwinder::<_, A, StoreOnHeap>::new(
+        EndianSlice::new(eh_frame, LittleEndian),
+        UnwindSectionType::EhFrame,
+        None,
+        &mut unwind_context,
+        BaseAddresses::default().set_eh_frame(eh_frame_svma),
+        lookup_svma,
+    );
+    let result = unwinder
+        .unwind_frame_with_fde::<F, StoreOnHeap>(regs, is_first_frame, 0, fde_offset, read_stack)
+        .await
+        .map_err(Error::Dwarf)?;
+    match result {
+        UnwindResult::ExecRule(rule) => A::exec_rule(rule, is_first_frame, regs, read_stack).await,
+        UnwindResult::Uncacheable(0) => Ok(None),
+        UnwindResult::Uncacheable(return_address) => Ok(Some(return_address)),
+    }
+}
+
+/// Address ranges and the unwind rules which their DWARF CFI translates into.
+pub type TranslatedRules<R> = Vec<(Range<u64>, Result<R, ConversionError>)>;
+
+/// The unwind rules of all rows of all FDEs in `unwind_section`, sorted by address, with
+/// the SVMA ranges they apply to. Adjacent rows with the same result are merged.
+pub fn unwind_rules_by_linear_scan<A, R, US>(
+    unwind_section: &US,
+    bases: &BaseAddresses,
+) -> TranslatedRules<A::UnwindRule>
+where
+    A: DwarfUnwinding,
+    R: Reader,
+    US: UnwindSection<R>,
+{
+    let mut ctx = Box::new(UnwindContext::new());
+    let mut rules: TranslatedRules<A::UnwindRule> = Vec::new();
+    let mut entries = unwind_section.entries(bases);
+    while let Ok(Some(entry)) = entries.next() {
+        let CieOrFde::Fde(partial_fde) = entry else {
+            continue;
+        };
+        let Ok(fde) = partial_fde.parse(US::cie_from_offset) else {
+            continue;
+        };
+        let Ok(mut table) = fde.rows(unwind_section, bases, &mut ctx) else {
+            continue;
+        };
+        while let Ok(Some(row)) = table.next_row() {
+            let range = row.start_address()..row.end_address();
+            if range.is_empty() {
+                continue;
+            }
+            rules.push((range, A::rule_for_row(row)));
+        }
+    }
+    rules.sort_by_key(|(range, _)| range.start);
+    let mut merged: TranslatedRules<A::UnwindRule> = Vec::with_capacity(rules.len());
+    for (range, rule) in rules {
+        match merged.last_mut() {
+            Some((prev_range, prev_rule))
+                if prev_range.end == range.start && *prev_rule == rule =>
+            {
+                prev_range.end = range.end;
+            }
+            _ => merged.push((range, rule)),
+        }
     }
+    merged
 }
 
+/// The section addresses which gimli uses to resolve section-relative pointers in DWARF
+/// CFI. These are SVMAs, not file offsets or AVMAs, so that the resolved code addresses
+/// are SVMAs which can be compared to the module's relative addresses.
 pub(crate) fn base_addresses_for_sections<D>(
     section_info: &mut impl ModuleSectionInfo<D>,
 ) -> BaseAddresses {
@@ -234,6 +696,8 @@ pub(crate) fn base_addresses_for_sections<D>(
 
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
 #[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
+/// The reason why the FDE index of a DWARF CFI section could not be built, see
+/// [`AddModuleError::FdeIndex`](crate::AddModuleError::FdeIndex).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DwarfCfiIndexError {
     #[error("EhFrame processing failed: {0}")]
@@ -249,8 +713,23 @@ pub enum DwarfCfiIndexError {
     FdeOffsetTooBig,
 }
 
-/// A binary search table for eh_frame FDEs. We generate this whenever a module
-/// without eh_frame_hdr is added.
+/// A binary search table for eh_frame FDEs, with the address range of each FDE. We
+/// generate this whenever a module without eh_frame_hdr is added.
+///
+/// This type is exported as [`FdeIndex`](crate::FdeIndex). An index can be built ahead of
+/// time with [`FdeIndex::from_eh_frame`](crate::FdeIndex::from_eh_frame), stored, and
+/// passed to [`Module::new_with_fde_index`](crate::Module::new_with_fde_index) later, so
+/// that the FDEs don't need to be scanned when the module is added.
+///
+/// With the `serde` feature, the index implements `Serialize` and `Deserialize`. It is
+/// serialized as the list of [`entries`](Self::entries), and deserialized by sorting them
+/// by start address, so any list of entries gives a usable index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<(Range<u32>, u32)>", into = "Vec<(Range<u32>, u32)>")
+)]
 pub struct DwarfCfiIndex {
     /// Contains the initial address for every FDE, relative to the base address.
     /// This vector is sorted so that it can be used for binary search.
@@ -259,10 +738,12 @@ pub struct DwarfCfiIndex {
     /// Contains the FDE offset for every FDE. The FDE at offset `fde_offsets[i]`
     /// has a PC range which starts at `sorted_fde_pc_starts[i]`.
     fde_offsets: Vec<u32>,
+    /// Contains the end of the PC range for every FDE, relative to the base address.
+    fde_pc_ends: Vec<u32>,
 }
 
 impl DwarfCfiIndex {
-    pub fn try_new<R, US>(
+    pub(crate) fn try_new<R, US>(
         unwind_section: US,
         bases: BaseAddresses,
         base_svma: u64,
@@ -307,46 +788,128 @@ impl DwarfCfiIndex {
                 .map_err(|_| DwarfCfiIndexError::RelativeAddressTooBig)?;
             let fde_offset = <R::Offset as TryInto<u32>>::try_into(fde.offset())
                 .map_err(|_| DwarfCfiIndexError::FdeOffsetTooBig)?;
-            fde_pc_and_offset.push((relative_pc, fde_offset));
+            let relative_pc_end =
+                u32::try_from(u64::from(relative_pc) + fde.len()).unwrap_or(u32::MAX);
+            fde_pc_and_offset.push((relative_pc, relative_pc_end, fde_offset));
         }
-        fde_pc_and_offset.sort_by_key(|(pc, _)| *pc);
-        let sorted_fde_pc_starts = fde_pc_and_offset.iter().map(|(pc, _)| *pc).collect();
-        let fde_offsets = fde_pc_and_offset.into_iter().map(|(_, fde)| fde).collect();
-        Ok(Self {
+        Ok(Self::from_unsorted(fde_pc_and_offset))
+    }
+
+    fn from_unsorted(mut fde_pc_and_offset: Vec<(u32, u32, u32)>) -> Self {
+        fde_pc_and_offset.sort_by_key(|(pc, _, _)| *pc);
+        let sorted_fde_pc_starts = fde_pc_and_offset.iter().map(|(pc, _, _)| *pc).collect();
+        let fde_pc_ends = fde_pc_and_offset.iter().map(|(_, end, _)| *end).collect();
+        let fde_offsets = fde_pc_and_offset
+            .into_iter()
+            .map(|(_, _, fde)| fde)
+            .collect();
+        Self {
             sorted_fde_pc_starts,
             fde_offsets,
-        })
+            fde_pc_ends,
+        }
     }
 
-    pub fn try_new_eh_frame<D>(
+    pub(crate) fn try_new_eh_frame<D>(
         eh_frame_data: &[u8],
         section_info: &mut impl ModuleSectionInfo<D>,
     ) -> Result<Self, DwarfCfiIndexError> {
         let bases = base_addresses_for_sections(section_info);
-        let mut eh_frame = EhFrame::from(EndianSlice::new(eh_frame_data, LittleEndian));
-        eh_frame.set_address_size(8);
+        let mut eh_frame = EhFrame::from(EndianSlice::new(
+            eh_frame_data,
+            RunTimeEndian::from(section_info.endianness()),
+        ));
+        eh_frame.set_address_size(section_info.address_size().unwrap_or(8));
 
         Self::try_new(eh_frame, bases, section_info.base_svma())
     }
 
-    pub fn try_new_debug_frame<D>(
+    pub(crate) fn try_new_debug_frame<D>(
         debug_frame_data: &[u8],
         section_info: &mut impl ModuleSectionInfo<D>,
     ) -> Result<Self, DwarfCfiIndexError> {
         let bases = base_addresses_for_sections(section_info);
-        let mut debug_frame = DebugFrame::from(EndianSlice::new(debug_frame_data, LittleEndian));
-        debug_frame.set_address_size(8);
+        let mut debug_frame = DebugFrame::from(EndianSlice::new(
+            debug_frame_data,
+            RunTimeEndian::from(section_info.endianness()),
+        ));
+        debug_frame.set_address_size(section_info.address_size().unwrap_or(8));
 
         Self::try_new(debug_frame, bases, section_info.base_svma())
     }
 
+    /// Build an index of the FDEs in `eh_frame_data`, the contents of the module's
+    /// `.eh_frame` or `__eh_frame` section.
+    ///
+    /// `section_info` is only used for the section addresses, the base address, the
+    /// endianness and the address size; no section data is requested from it.
+    pub fn from_eh_frame<D>(
+        eh_frame_data: &[u8],
+        section_info: &mut impl ModuleSectionInfo<D>,
+    ) -> Option<Self> {
+        Self::try_new_eh_frame(eh_frame_data, section_info).ok()
+    }
+
+    /// Find the offset of the FDE for the given address, relative to the module's base
+    /// address. This is the last FDE whose start address is at or before the address.
     pub fn fde_offset_for_relative_address(&self, rel_lookup_address: u32) -> Option<u32> {
+        self.fde_for_relative_address(rel_lookup_address)
+            .map(|(_, fde_offset)| fde_offset)
+    }
+
+    /// Find the last FDE whose start address is at or before the given address, like
+    /// [`fde_offset_for_relative_address`](Self::fde_offset_for_relative_address), and
+    /// return its address range together with its offset.
+    ///
+    /// If the range doesn't contain the address, the address is in a gap between FDEs
+    /// and no FDE covers it.
+    pub fn fde_for_relative_address(&self, rel_lookup_address: u32) -> Option<(Range<u32>, u32)> {
         let i = match self.sorted_fde_pc_starts.binary_search(&rel_lookup_address) {
             Err(0) => return None,
             Ok(i) => i,
             Err(i) => i - 1,
         };
-        Some(self.fde_offsets[i])
+        Some((
+            self.sorted_fde_pc_starts[i]..self.fde_pc_ends[i],
+            self.fde_offsets[i],
+        ))
+    }
+
+    /// The address ranges of all FDEs in the index, relative to the module's base
+    /// address, with the FDE offsets. Sorted by start address.
+    pub fn entries(&self) -> impl Iterator<Item = (Range<u32>, u32)> + '_ {
+        self.sorted_fde_pc_starts
+            .iter()
+            .zip(&self.fde_pc_ends)
+            .zip(&self.fde_offsets)
+            .map(|((&start, &end), &fde_offset)| (start..end, fde_offset))
+    }
+
+    /// The number of FDEs in the index.
+    pub fn len(&self) -> usize {
+        self.fde_offsets.len()
+    }
+
+    /// Returns true if the index contains no FDEs.
+    pub fn is_empty(&self) -> bool {
+        self.fde_offsets.is_empty()
+    }
+}
+
+impl From<Vec<(Range<u32>, u32)>> for DwarfCfiIndex {
+    fn from(entries: Vec<(Range<u32>, u32)>) -> Self {
+        Self::from_unsorted(
+            entries
+                .into_iter()
+                .map(|(range, fde_offset)| (range.start, range.end, fde_offset))
+                .collect(),
+        )
+    }
+}
+
+impl From<DwarfCfiIndex> for Vec<(Range<u32>, u32)> {
+    fn from(index: DwarfCfiIndex) -> Self {
+        index.entries().collect()
     }
 }
 
@@ -354,30 +917,50 @@ pub trait DwarfUnwindRegs {
     fn get(&self, register: Register) -> Option<u64>;
 }
 
-pub fn eval_cfa_rule<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
+pub async fn eval_cfa_rule<R, F, UR, S>(
     section: &impl UnwindSection<R>,
     rule: &CfaRule<R::Offset>,
     encoding: Encoding,
     regs: &UR,
-) -> Option<u64> {
+    read_stack: &mut F,
+) -> Option<u64>
+where
+    R: Reader,
+    F: AsyncMemoryAccess,
+    UR: DwarfUnwindRegs,
+    S: EvaluationStorage<R>,
+{
     match rule {
         CfaRule::RegisterAndOffset { register, offset } => {
             let val = regs.get(*register)?;
-            u64::try_from(i64::try_from(val).ok()?.checked_add(*offset)?).ok()
+            checked_add_signed(val, *offset)
         }
         CfaRule::Expression(expr) => {
             let expr = expr.get(section).ok()?;
-            eval_expr::<R, UR, S>(expr, encoding, regs)
+            eval_expr::<R, F, UR, S>(expr, encoding, None, regs, read_stack).await
         }
     }
 }
 
-fn eval_expr<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
+/// Evaluates a DWARF expression which computes an address. If `initial_value` is
+/// given, it is pushed onto the stack before the evaluation starts.
+async fn eval_expr<R, F, UR, S>(
     expr: Expression<R>,
     encoding: Encoding,
+    initial_value: Option<u64>,
     regs: &UR,
-) -> Option<u64> {
+    read_stack: &mut F,
+) -> Option<u64>
+where
+    R: Reader,
+    F: AsyncMemoryAccess,
+    UR: DwarfUnwindRegs,
+    S: EvaluationStorage<R>,
+{
     let mut eval = Evaluation::<R, S>::new_in(expr.0, encoding);
+    if let Some(initial_value) = initial_value {
+        eval.set_initial_value(initial_value);
+    }
     let mut result = eval.evaluate().ok()?;
     loop {
         match result {
@@ -386,6 +969,23 @@ fn eval_expr<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
                 let value = regs.get(register)?;
                 result = eval.resume_with_register(Value::Generic(value as _)).ok()?;
             }
+            EvaluationResult::RequiresMemory { address, size, .. } => {
+                // Only read as many bytes as the expression asks for, e.g. for DW_OP_deref_size.
+                let value = match size {
+                    8 => read_stack.read_u64(address).await.ok()?,
+                    4 => u64::from(read_stack.read_u32(address).await.ok()?),
+                    1..=7 => {
+                        let mut buf = [0; 8];
+                        read_stack
+                            .read_bytes(address, &mut buf[..usize::from(size)])
+                            .await
+                            .ok()?;
+                        u64::from_le_bytes(buf)
+                    }
+                    _ => return None,
+                };
+                result = eval.resume_with_memory(Value::Generic(value)).ok()?;
+            }
             _ => return None,
         }
     }
@@ -397,7 +997,7 @@ fn eval_expr<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
     }
 }
 
-pub fn eval_register_rule<R, F, UR, S>(
+pub async fn eval_register_rule<R, F, UR, S>(
     section: &impl UnwindSection<R>,
     rule: RegisterRule<R::Offset>,
     cfa: u64,
@@ -408,7 +1008,7 @@ pub fn eval_register_rule<R, F, UR, S>(
 ) -> Option<u64>
 where
     R: Reader,
-    F: FnMut(u64) -> Result<u64, ()>,
+    F: AsyncMemoryAccess,
     UR: DwarfUnwindRegs,
     S: EvaluationStorage<R>,
 {
@@ -416,22 +1016,21 @@ where
         RegisterRule::Undefined => None,
         RegisterRule::SameValue => Some(val),
         RegisterRule::Offset(offset) => {
-            let cfa_plus_offset =
-                u64::try_from(i64::try_from(cfa).ok()?.checked_add(offset)?).ok()?;
-            read_stack(cfa_plus_offset).ok()
-        }
-        RegisterRule::ValOffset(offset) => {
-            u64::try_from(i64::try_from(cfa).ok()?.checked_add(offset)?).ok()
+            let cfa_plus_offset = checked_add_signed(cfa, offset)?;
+            read_stack.read_u64(cfa_plus_offset).await.ok()
         }
+        RegisterRule::ValOffset(offset) => checked_add_signed(cfa, offset),
         RegisterRule::Register(register) => regs.get(register),
+        // For DW_CFA_expression and DW_CFA_val_expression, the CFA is pushed onto the
+        // stack before the expression is evaluated.
         RegisterRule::Expression(expr) => {
             let expr = expr.get(section).ok()?;
-            let val = eval_expr::<R, UR, S>(expr, encoding, regs)?;
-            read_stack(val).ok()
+            let val = eval_expr::<R, F, UR, S>(expr, encoding, Some(cfa), regs, read_stack).await?;
+            read_stack.read_u64(val).await.ok()
         }
         RegisterRule::ValExpression(expr) => {
             let expr = expr.get(section).ok()?;
-            eval_expr::<R, UR, S>(expr, encoding, regs)
+            eval_expr::<R, F, UR, S>(expr, encoding, Some(cfa), regs, read_stack).await
         }
         RegisterRule::Architectural => {
             // Unimplemented
@@ -1,20 +1,32 @@
-use crate::dwarf::DwarfUnwinderError;
+use alloc::sync::Arc;
+
+use crate::dwarf::{DwarfCfiIndexError, DwarfUnwinderError};
 #[cfg(feature = "macho")]
 use crate::macho::CompactUnwindInfoUnwinderError;
 #[cfg(feature = "pe")]
 use crate::pe::PeUnwinderError;
+use crate::{ReadMemError, ReadMemErrorKind, RegisterSet};
 
 /// The error type used in this crate.
+///
+/// Errors from a module's broken DWARF CFI name the module, see [`Error::DwarfInModule`].
+/// For other errors, use [`Unwinder::module_for_address`](crate::Unwinder::module_for_address)
+/// with the address of the frame which failed to unwind, and
+/// [`Module::name`](crate::Module::name), to find out which module was involved.
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
 #[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
-    #[error("Could not read stack memory at 0x{0:x}")]
-    CouldNotReadStack(u64),
+    /// Stack memory at the given address could not be read, for the given reason.
+    #[error("Could not read stack memory at 0x{0:x}: {1}")]
+    CouldNotReadStack(u64, #[source] ReadMemErrorKind),
 
     #[error("Frame pointer unwinding moved backwards")]
     FramepointerUnwindingMovedBackwards,
 
+    /// The unwind information was most likely wrong. A null return address, or one of
+    /// the addresses set with `set_end_of_stack_return_addresses`, ends unwinding with
+    /// `Ok(None)` instead.
     #[error("Neither the code address nor the stack pointer changed, would loop")]
     DidNotAdvance,
 
@@ -23,6 +35,96 @@ pub enum Error {
 
     #[error("Return address is null")]
     ReturnAddressIsNull,
+
+    /// The address is not inside any of the modules that were added to the unwinder.
+    ///
+    /// If the address is inside a module but the module's unwind information doesn't
+    /// cover it, the unwinder falls back to frame pointer unwinding instead. Addresses
+    /// outside of all modules can also be unwound with the frame pointer, see for example
+    /// [`UnwinderX86_64::set_recover_with_frame_pointer_on_missing_info`](crate::x86_64::UnwinderX86_64::set_recover_with_frame_pointer_on_missing_info).
+    #[error("No module contains the address 0x{0:x}")]
+    NoModuleForAddress(u64),
+
+    /// The module could not be added because it overlaps with the existing module which
+    /// starts at the given address. See [`AddModulePolicy`](crate::AddModulePolicy).
+    #[error("The module overlaps with the existing module at 0x{0:x}")]
+    OverlappingModule(u64),
+
+    /// The recovered return address does not point just after a call instruction. This
+    /// is only checked for modules which were created with
+    /// [`Module::with_return_address_validation`](crate::Module::with_return_address_validation).
+    #[error("Return address 0x{0:x} does not point after a call instruction")]
+    ReturnAddressNotAfterCall(u64),
+
+    /// The maximum number of frames set with
+    /// [`UnwindIterator::with_limit`](crate::UnwindIterator::with_limit) was reached.
+    #[error("The maximum number of frames was reached")]
+    FrameLimitReached,
+
+    /// Unwinding arrived at the same return address and stack pointer a second time, and
+    /// would loop forever.
+    #[error("The same frame was found twice, unwinding would loop")]
+    CycleDetected,
+
+    /// Evaluating the DWARF CFI failed. This is only returned by functions which unwind
+    /// with a given FDE, such as
+    /// [`x86_64::unwind_frame_with_raw_eh_frame`](crate::x86_64::unwind_frame_with_raw_eh_frame).
+    /// The unwinder falls back to frame pointer unwinding instead.
+    #[error("DWARF unwinding failed: {0}")]
+    Dwarf(#[source] DwarfUnwinderError),
+
+    /// The DWARF CFI of the module named `module` could not be evaluated for the frame,
+    /// and the frame pointer based fallback rule, which the unwinder used instead, failed
+    /// as well. This usually means that the module's CFI is broken. If the fallback rule
+    /// succeeds, the frame is unwound without an error. If it fails because registers are
+    /// unknown, the error is [`Error::MissingRegisterForRule`] instead.
+    ///
+    /// The fallback rule is not cached in this case, so every unwind at the address
+    /// evaluates the CFI again and returns this error.
+    #[error("DWARF unwinding in module {module} failed: {source}")]
+    DwarfInModule {
+        /// The name of the module, see [`Module::name`](crate::Module::name).
+        module: Arc<str>,
+        #[source]
+        source: DwarfUnwinderError,
+    },
+
+    /// The unwind rule for this frame needs the values of the given registers, which
+    /// were marked as unknown, for example with
+    /// [`UnwindRegsX86_64::set_unknown_registers`](crate::x86_64::UnwindRegsX86_64::set_unknown_registers).
+    #[error("The unwind rule needs the values of the unknown registers {0:?}")]
+    MissingRegisterForRule(RegisterSet),
+}
+
+impl From<ReadMemError> for Error {
+    fn from(e: ReadMemError) -> Self {
+        Error::CouldNotReadStack(e.addr, e.kind)
+    }
+}
+
+/// The error type of [`Unwinder::try_add_module`](crate::Unwinder::try_add_module), for
+/// modules whose unwind information can't be parsed.
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddModuleError {
+    /// The index of the FDEs in `.eh_frame` or `.debug_frame` could not be built when the
+    /// module was created.
+    #[error("Could not build the FDE index: {0}")]
+    FdeIndex(#[source] DwarfCfiIndexError),
+
+    /// The `.eh_frame_hdr` section could not be parsed.
+    #[error("Could not parse .eh_frame_hdr: {0}")]
+    EhFrameHdr(#[source] gimli::Error),
+
+    /// The first CIE in `.eh_frame` or `.debug_frame`, or an entry before it, could not
+    /// be parsed.
+    #[error("Could not read a CIE: {0}")]
+    Cie(#[source] gimli::Error),
+
+    /// The `.eh_frame` or `.debug_frame` section does not contain any CIE.
+    #[error("The DWARF CFI section does not contain a CIE")]
+    NoCie,
 }
 
 #[cfg_attr(feature = "std", derive(thiserror::Error))]
@@ -17,4 +17,68 @@ pub trait InstructionAnalysis: Arch {
         Self::rule_from_prologue_analysis(text_bytes, pc_offset)
             .or_else(|| Self::rule_from_epilogue_analysis(text_bytes, pc_offset))
     }
+
+    /// Check whether the instruction which ends at `return_offset` is a call instruction,
+    /// i.e. whether `return_offset` is a plausible return address.
+    ///
+    /// Caller guarantees return_offset <= text_bytes.len()
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool;
+}
+
+/// Check for the x86 and x86_64 call instructions: `call rel32` (e8) and the indirect
+/// `call r/m` (ff /2) with all its addressing modes. Prefixes like REX are not checked,
+/// they come before the opcode.
+pub fn is_after_x86_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+    let bytes = &text_bytes[..return_offset];
+    if bytes.len() >= 5 && bytes[bytes.len() - 5] == 0xe8 {
+        return true;
+    }
+    (2..=7).any(|len| {
+        let Some(start) = bytes.len().checked_sub(len) else {
+            return false;
+        };
+        let (opcode, modrm) = (bytes[start], bytes[start + 1]);
+        if opcode != 0xff || (modrm >> 3) & 0b111 != 2 {
+            return false;
+        }
+        let (mode, rm) = (modrm >> 6, modrm & 0b111);
+        let expected_len = match (mode, rm) {
+            (0b11, _) => 2,
+            (0b00, 0b101) => 6,
+            (0b00, 0b100) if bytes.get(start + 2).is_some_and(|sib| sib & 0b111 == 0b101) => 7,
+            (0b00, 0b100) => 3,
+            (0b00, _) => 2,
+            (0b01, 0b100) => 4,
+            (0b01, _) => 3,
+            (0b10, 0b100) => 7,
+            _ => 6,
+        };
+        expected_len == len
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_x86_call_instructions() {
+        let is_call = |bytes: &[u8]| is_after_x86_call_instruction(bytes, bytes.len());
+        // call rel32
+        assert!(is_call(&[0x90, 0xe8, 0x10, 0x20, 0x30, 0x40]));
+        // call rax
+        assert!(is_call(&[0xff, 0xd0]));
+        // call r8
+        assert!(is_call(&[0x41, 0xff, 0xd0]));
+        // call qword [rip + 0x1234]
+        assert!(is_call(&[0xff, 0x15, 0x34, 0x12, 0x00, 0x00]));
+        // call qword [rax + 0x10]
+        assert!(is_call(&[0xff, 0x50, 0x10]));
+        // call qword [rsp + 0x10]
+        assert!(is_call(&[0xff, 0x54, 0x24, 0x10]));
+        // jmp rax, push rbp, and too little data
+        assert!(!is_call(&[0xff, 0xe0]));
+        assert!(!is_call(&[0x55]));
+        assert!(!is_call(&[]));
+    }
 }
@@ -2,7 +2,7 @@
 //!
 //! Framehop is a stack frame unwinder written in 100% Rust. It produces high quality stacks at high speed, on multiple platforms and architectures, without an expensive pre-processing step for unwind information. This makes it suitable for sampling profilers.
 //!
-//! It currently supports unwinding x86_64 and aarch64, with unwind information formats commonly used on Windows, macOS, Linux and Android.
+//! It currently supports unwinding x86_64, x86, aarch64, arm and riscv64, with unwind information formats commonly used on Windows, macOS, Linux and Android.
 //!
 //! You give framehop register values, stack memory and unwind data, and framehop produces a list of return addresses.
 //!
@@ -28,13 +28,17 @@
 //!    - DWARF CFI in `.debug_frame`
 //!    - PE unwind info in `.pdata`, `.rdata` and `.xdata` (for Windows x86_64)
 //!  - It supports correct unwinding even when the program is interrupted inside a function prologue or epilogue. On macOS, it has to analyze assembly instructions in order to do this.
-//!  - On x86_64 and aarch64, it falls back to frame pointer unwinding if it cannot find unwind information for an address.
+//!  - On x86_64, x86, aarch64, arm and riscv64, it falls back to frame pointer unwinding if a module has no unwind information for an address.
 //!  - It caches the unwind rule for each address in a fixed-size cache, so that repeated unwinding from the same address is even faster.
 //!  - It generates binary search indexes for unwind information formats which don't have them. Specifically, for `.debug_frame` and for `.eh_frame` without `.eh_frame_hdr`.
 //!  - It does a reasonable job of detecting the end of the stack, so that you can differentiate between properly terminated stacks and prematurely truncated stacks.
 //!
 //! Framehop is not suitable for debuggers or to implement exception handling. Debuggers usually need to recover all register values for every frame whereas framehop only cares about return addresses. And exception handling needs the ability to call destructors, which is also a non-goal for framehop.
 //!
+//! ## `no_std` support
+//!
+//! Framehop can be used in `no_std` environments which have an allocator, for example in embedded agents which unwind their own stacks. Disable the default features to remove the dependency on `std`, and enable the unwind information formats you need: `framehop = { version = "...", default-features = false, features = ["macho", "pe"] }`. The module list and the caches still use `alloc`. Without `std`, diagnostic messages are discarded and `set_diagnostics_hook` is not available.
+//!
 //! ## Speed
 //!
 //! Framehop achieves high speed in the following ways:
@@ -117,32 +121,63 @@ extern crate alloc;
 
 mod add_signed;
 mod arch;
+mod async_unwinder;
 mod cache;
 mod code_address;
+mod diagnostics;
 mod display_utils;
 mod dwarf;
 mod error;
 mod instruction_analysis;
 #[cfg(feature = "macho")]
 mod macho;
+mod memory;
 #[cfg(feature = "pe")]
 mod pe;
+mod register_set;
 mod rule_cache;
+mod rule_table;
+#[cfg(feature = "std")]
+mod shared_unwinder;
+#[cfg(feature = "test-util")]
+pub mod test_utils;
 mod unwind_result;
 mod unwind_rule;
 mod unwinder;
 
 /// Types for unwinding on the aarch64 CPU architecture.
 pub mod aarch64;
+/// Types for unwinding on the 32-bit ARM CPU architecture.
+pub mod arm;
+/// Types for unwinding on the RISC-V 64-bit CPU architecture.
+pub mod riscv64;
+/// Types for unwinding on the x86 (32-bit) CPU architecture.
+pub mod x86;
 /// Types for unwinding on the x86_64 CPU architecture.
 pub mod x86_64;
 
+pub use async_unwinder::AsyncUnwinder;
 pub use cache::{AllocationPolicy, MayAllocateDuringUnwind, MustNotAllocateDuringUnwind};
 pub use code_address::FrameAddress;
-pub use error::Error;
+#[cfg(feature = "std")]
+pub use diagnostics::{remove_diagnostics_hook, set_diagnostics_hook};
+pub use dwarf::{
+    CfaRuleDescription, ConversionError, DwarfCfiIndex as FdeIndex,
+    DwarfCfiIndexError as FdeIndexError, DwarfUnwinderError, FdeEncoding, RegisterName,
+    RegisterRuleDescription, UnsupportedRuleDescription,
+};
+pub use error::{AddModuleError, Error};
+pub use memory::{AsyncMemoryAccess, MemoryAccess, ReadMemError, ReadMemErrorKind, StackBytes};
+pub use register_set::RegisterSet;
 pub use rule_cache::CacheStats;
+pub use rule_table::UnwindRuleTable;
+#[cfg(feature = "std")]
+pub use shared_unwinder::SharedUnwinder;
 pub use unwinder::{
-    ExplicitModuleSectionInfo, Module, ModuleSectionInfo, UnwindIterator, Unwinder,
+    AddModulePolicy, CheckedUnwindFrameOutcome, Endianness, ExplicitModuleSectionInfo, Module,
+    ModuleSectionInfo, ModuleSectionInfoWithDebugFile, PrecomputedRules, RuleSource,
+    UnwindFrameOutcome, UnwindIterator, UnwindSourcePreference, UnwindStackOutcome, Unwinder,
+    UnwoundFrame,
 };
 
 /// The unwinder cache for the native CPU architecture.
@@ -155,6 +190,36 @@ pub type UnwindRegsNative = aarch64::UnwindRegsAarch64;
 #[cfg(target_arch = "aarch64")]
 pub type UnwinderNative<D, P> = aarch64::UnwinderAarch64<D, P>;
 
+/// The unwinder cache for the native CPU architecture.
+#[cfg(target_arch = "arm")]
+pub type CacheNative<P> = arm::CacheArm<P>;
+/// The unwind registers type for the native CPU architecture.
+#[cfg(target_arch = "arm")]
+pub type UnwindRegsNative = arm::UnwindRegsArm;
+/// The unwinder type for the native CPU architecture.
+#[cfg(target_arch = "arm")]
+pub type UnwinderNative<D, P> = arm::UnwinderArm<D, P>;
+
+/// The unwinder cache for the native CPU architecture.
+#[cfg(target_arch = "riscv64")]
+pub type CacheNative<P> = riscv64::CacheRiscV64<P>;
+/// The unwind registers type for the native CPU architecture.
+#[cfg(target_arch = "riscv64")]
+pub type UnwindRegsNative = riscv64::UnwindRegsRiscV64;
+/// The unwinder type for the native CPU architecture.
+#[cfg(target_arch = "riscv64")]
+pub type UnwinderNative<D, P> = riscv64::UnwinderRiscV64<D, P>;
+
+/// The unwinder cache for the native CPU architecture.
+#[cfg(target_arch = "x86")]
+pub type CacheNative<P> = x86::CacheX86<P>;
+/// The unwind registers type for the native CPU architecture.
+#[cfg(target_arch = "x86")]
+pub type UnwindRegsNative = x86::UnwindRegsX86;
+/// The unwinder type for the native CPU architecture.
+#[cfg(target_arch = "x86")]
+pub type UnwinderNative<D, P> = x86::UnwinderX86<D, P>;
+
 /// The unwinder cache for the native CPU architecture.
 #[cfg(target_arch = "x86_64")]
 pub type CacheNative<P> = x86_64::CacheX86_64<P>;
@@ -46,6 +46,15 @@ pub enum CompactUnwindInfoUnwinderError {
 
     #[error("Encountered invalid unwind entry")]
     InvalidFrameless,
+
+    #[error("Compact unwind info is not supported on RISC-V")]
+    RiscV64Unsupported,
+
+    #[error("Compact unwind info is not supported on 32-bit ARM")]
+    ArmUnsupported,
+
+    #[error("Compact unwind info is not yet supported on 32-bit x86")]
+    X86Unsupported,
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,337 @@
+use core::future::Future;
+use core::task::{Context, Poll, Waker};
+
+/// Read access to the stack memory of the unwound thread.
+///
+/// The unwinders call the narrowest read they need: 32-bit architectures use
+/// [`read_u32`](MemoryAccess::read_u32) for their stack slots, and DWARF expressions with
+/// `DW_OP_deref_size` use [`read_bytes`](MemoryAccess::read_bytes) for sizes other than
+/// 4 and 8 bytes. This matters if reads are expensive or if over-reading can cross into
+/// an unmapped page, for example when reading from a remote process.
+///
+/// Only [`read_u64`](MemoryAccess::read_u64) needs to be implemented. The other methods
+/// have default implementations which are built on top of `read_u64` and assume a
+/// little-endian target.
+///
+/// This trait is implemented for all `FnMut(u64) -> Result<u64, ()>` closures, which
+/// read the 8-byte value at the given address. Their errors are reported with
+/// [`ReadMemErrorKind::Unknown`]. Implement this trait for your own type to report why
+/// a read failed, for example to tell unmapped memory apart from an interrupted read.
+pub trait MemoryAccess {
+    /// Read the 8-byte value at `addr`.
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError>;
+
+    /// Read the 4-byte value at `addr`.
+    fn read_u32(&mut self, addr: u64) -> Result<u32, ReadMemError> {
+        Ok(self.read_u64(addr)? as u32)
+    }
+
+    /// Fill `buf` with the bytes starting at `addr`.
+    fn read_bytes(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ReadMemError> {
+        for (i, chunk) in buf.chunks_mut(8).enumerate() {
+            let chunk_addr = addr
+                .checked_add(i as u64 * 8)
+                .ok_or(ReadMemError::new(addr, ReadMemErrorKind::Unavailable))?;
+            let value = self.read_u64(chunk_addr)?;
+            chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+impl<F> MemoryAccess for F
+where
+    F: FnMut(u64) -> Result<u64, ()>,
+{
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        self(addr).map_err(|()| ReadMemError::new(addr, ReadMemErrorKind::Unknown))
+    }
+}
+
+/// The reason why a [`MemoryAccess`] read failed.
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadMemErrorKind {
+    /// The memory is not available, for example because it is unmapped or outside of
+    /// the captured stack bytes. Reading it again won't succeed.
+    #[error("memory is not available")]
+    Unavailable,
+
+    /// The read was interrupted or timed out, and may succeed if it is retried.
+    #[error("the read was interrupted")]
+    Interrupted,
+
+    /// The reader didn't report a reason, for example a closure which returned `Err(())`.
+    #[error("unknown reason")]
+    Unknown,
+}
+
+/// A failed read of stack memory: the address of the read and the reason.
+///
+/// Unwinding reports this as [`Error::CouldNotReadStack`](crate::Error::CouldNotReadStack).
+#[cfg_attr(feature = "std", derive(thiserror::Error))]
+#[cfg_attr(not(feature = "std"), derive(thiserror_no_std::Error))]
+#[error("Could not read memory at 0x{addr:x}: {kind}")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReadMemError {
+    /// The address which couldn't be read.
+    pub addr: u64,
+    /// Why the read failed.
+    #[source]
+    pub kind: ReadMemErrorKind,
+}
+
+impl ReadMemError {
+    /// Create an error for a failed read at `addr`.
+    pub fn new(addr: u64, kind: ReadMemErrorKind) -> Self {
+        Self { addr, kind }
+    }
+}
+
+/// A [`MemoryAccess`] implementation for a saved copy of stack memory, for example
+/// from a core dump or from a sample which captured the stack bytes.
+///
+/// `bytes` contains the memory starting at address `base`. Reads which aren't fully
+/// inside this range fail with [`ReadMemErrorKind::Unavailable`]. Reads don't need to be aligned. Values are read
+/// in little-endian byte order.
+#[derive(Debug, Clone, Copy)]
+pub struct StackBytes<'a> {
+    /// The address of the first byte in `bytes`.
+    pub base: u64,
+    /// The captured memory.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> StackBytes<'a> {
+    /// Create a reader for the memory in `bytes`, which starts at address `base`.
+    pub fn new(base: u64, bytes: &'a [u8]) -> Self {
+        Self { base, bytes }
+    }
+
+    fn slice(&self, addr: u64, len: usize) -> Result<&'a [u8], ReadMemError> {
+        let err = ReadMemError::new(addr, ReadMemErrorKind::Unavailable);
+        let offset = usize::try_from(addr.checked_sub(self.base).ok_or(err)?).map_err(|_| err)?;
+        let end = offset.checked_add(len).ok_or(err)?;
+        self.bytes.get(offset..end).ok_or(err)
+    }
+}
+
+impl MemoryAccess for StackBytes<'_> {
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        let bytes = self.slice(addr, 8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self, addr: u64) -> Result<u32, ReadMemError> {
+        let bytes = self.slice(addr, 4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_bytes(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ReadMemError> {
+        buf.copy_from_slice(self.slice(addr, buf.len())?);
+        Ok(())
+    }
+}
+
+/// Asynchronous read access to the stack memory of the unwound thread, for
+/// [`AsyncUnwinder`](crate::AsyncUnwinder).
+///
+/// This works like [`MemoryAccess`], except that the reads return futures. Only
+/// [`read_u64`](AsyncMemoryAccess::read_u64) needs to be implemented.
+///
+/// This trait is implemented for all `FnMut(u64) -> Fut` closures, where `Fut` is a
+/// `Future<Output = Result<u64, ()>>` which reads the 8-byte value at the given address.
+/// Their errors are reported with [`ReadMemErrorKind::Unknown`].
+pub trait AsyncMemoryAccess {
+    /// Read the 8-byte value at `addr`.
+    fn read_u64(&mut self, addr: u64) -> impl Future<Output = Result<u64, ReadMemError>>;
+
+    /// Read the 4-byte value at `addr`.
+    fn read_u32(&mut self, addr: u64) -> impl Future<Output = Result<u32, ReadMemError>> {
+        async move { Ok(self.read_u64(addr).await? as u32) }
+    }
+
+    /// Fill `buf` with the bytes starting at `addr`.
+    fn read_bytes(
+        &mut self,
+        addr: u64,
+        buf: &mut [u8],
+    ) -> impl Future<Output = Result<(), ReadMemError>> {
+        async move {
+            for (i, chunk) in buf.chunks_mut(8).enumerate() {
+                let chunk_addr = addr
+                    .checked_add(i as u64 * 8)
+                    .ok_or(ReadMemError::new(addr, ReadMemErrorKind::Unavailable))?;
+                let value = self.read_u64(chunk_addr).await?;
+                chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<F, Fut> AsyncMemoryAccess for F
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<u64, ()>>,
+{
+    async fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        self(addr)
+            .await
+            .map_err(|()| ReadMemError::new(addr, ReadMemErrorKind::Unknown))
+    }
+}
+
+/// Adapts a [`MemoryAccess`] for the unwinding code, which is written against
+/// [`AsyncMemoryAccess`]. Its reads complete immediately, so futures which only read
+/// through it can be run with [`complete_sync`].
+pub(crate) struct SyncMemory<'a, M: MemoryAccess>(pub &'a mut M);
+
+impl<M: MemoryAccess> AsyncMemoryAccess for SyncMemory<'_, M> {
+    fn read_u64(&mut self, addr: u64) -> impl Future<Output = Result<u64, ReadMemError>> {
+        core::future::ready(self.0.read_u64(addr))
+    }
+
+    fn read_u32(&mut self, addr: u64) -> impl Future<Output = Result<u32, ReadMemError>> {
+        core::future::ready(self.0.read_u32(addr))
+    }
+
+    fn read_bytes(
+        &mut self,
+        addr: u64,
+        buf: &mut [u8],
+    ) -> impl Future<Output = Result<(), ReadMemError>> {
+        core::future::ready(self.0.read_bytes(addr, buf))
+    }
+}
+
+/// Run a future which never waits, because all its reads go through [`SyncMemory`].
+#[inline(always)]
+pub(crate) fn complete_sync<T>(future: impl Future<Output = T>) -> T {
+    let future = core::pin::pin!(future);
+    match future.poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(value) => value,
+        Poll::Pending => unreachable!("reads from a MemoryAccess complete immediately"),
+    }
+}
+
+/// Adapts an [`AsyncMemoryAccess`] for 32-bit architectures: `read_u64` reads a 4-byte
+/// stack slot and zero-extends it.
+pub(crate) struct Memory32<'a, M: AsyncMemoryAccess>(pub &'a mut M);
+
+impl<M: AsyncMemoryAccess> AsyncMemoryAccess for Memory32<'_, M> {
+    async fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        self.0.read_u32(addr).await.map(u64::from)
+    }
+
+    async fn read_u32(&mut self, addr: u64) -> Result<u32, ReadMemError> {
+        self.0.read_u32(addr).await
+    }
+
+    async fn read_bytes(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ReadMemError> {
+        self.0.read_bytes(addr, buf).await
+    }
+}
+
+/// Remembers whether a read failed, so that failed stack reads can be told apart from
+/// unwind rules which can't be evaluated.
+pub(crate) struct TrackReadFailures<'a, M: AsyncMemoryAccess> {
+    memory: &'a mut M,
+    pub read_failed: bool,
+}
+
+impl<'a, M: AsyncMemoryAccess> TrackReadFailures<'a, M> {
+    pub fn new(memory: &'a mut M) -> Self {
+        Self {
+            memory,
+            read_failed: false,
+        }
+    }
+
+    fn track<T>(&mut self, result: Result<T, ReadMemError>) -> Result<T, ReadMemError> {
+        self.read_failed |= result.is_err();
+        result
+    }
+}
+
+impl<M: AsyncMemoryAccess> AsyncMemoryAccess for TrackReadFailures<'_, M> {
+    async fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        let result = self.memory.read_u64(addr).await;
+        self.track(result)
+    }
+
+    async fn read_u32(&mut self, addr: u64) -> Result<u32, ReadMemError> {
+        let result = self.memory.read_u32(addr).await;
+        self.track(result)
+    }
+
+    async fn read_bytes(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), ReadMemError> {
+        let result = self.memory.read_bytes(addr, buf).await;
+        self.track(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_closure_defaults() {
+        let mut read_stack = |addr: u64| Ok(0x0102030405060708 + (addr << 56));
+        assert_eq!(read_stack.read_u64(0x1), Ok(0x0102030405060708 + (1 << 56)));
+        assert_eq!(read_stack.read_u32(0x1), Ok(0x05060708));
+        let mut buf = [0; 10];
+        assert_eq!(read_stack.read_bytes(0x0, &mut buf), Ok(()));
+        assert_eq!(buf, [8, 7, 6, 5, 4, 3, 2, 1, 8, 7]);
+        assert_eq!(
+            complete_sync(Memory32(&mut SyncMemory(&mut read_stack)).read_u64(0x0)),
+            Ok(0x05060708)
+        );
+
+        let mut unit_error = |_| Err(());
+        assert_eq!(
+            unit_error.read_u32(0x20),
+            Err(ReadMemError::new(0x20, ReadMemErrorKind::Unknown))
+        );
+    }
+
+    #[test]
+    fn test_async_closure_defaults() {
+        let mut read_stack = |addr: u64| core::future::ready(Ok(0x0102030405060708 + (addr << 56)));
+        assert_eq!(complete_sync(read_stack.read_u32(0x1)), Ok(0x05060708));
+        let mut buf = [0; 10];
+        assert_eq!(complete_sync(read_stack.read_bytes(0x0, &mut buf)), Ok(()));
+        assert_eq!(buf, [8, 7, 6, 5, 4, 3, 2, 1, 8, 7]);
+
+        let mut unit_error = |_| core::future::ready(Err(()));
+        assert_eq!(
+            complete_sync(unit_error.read_u64(0x20)),
+            Err(ReadMemError::new(0x20, ReadMemErrorKind::Unknown))
+        );
+    }
+
+    #[test]
+    fn test_stack_bytes() {
+        let bytes = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        let mut stack = StackBytes::new(0x1000, &bytes);
+        assert_eq!(stack.read_u64(0x1000), Ok(0x0807060504030201));
+        assert_eq!(stack.read_u64(0x1002), Ok(0x0a09080706050403));
+        let unavailable = |addr| Err(ReadMemError::new(addr, ReadMemErrorKind::Unavailable));
+        assert_eq!(stack.read_u64(0x1003), unavailable(0x1003));
+        assert_eq!(stack.read_u64(0xfff), unavailable(0xfff));
+        assert_eq!(stack.read_u64(u64::MAX), unavailable(u64::MAX));
+        assert_eq!(stack.read_u32(0x1006), Ok(0x0a090807));
+        assert_eq!(
+            stack.read_u32(0x1007),
+            Err(ReadMemError::new(0x1007, ReadMemErrorKind::Unavailable))
+        );
+        let mut buf = [0; 3];
+        assert_eq!(stack.read_bytes(0x1007, &mut buf), Ok(()));
+        assert_eq!(buf, [8, 9, 10]);
+        assert_eq!(
+            stack.read_bytes(0x1008, &mut buf),
+            Err(ReadMemError::new(0x1008, ReadMemErrorKind::Unavailable))
+        );
+    }
+}
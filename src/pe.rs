@@ -1,5 +1,6 @@
 use alloc::format;
 
+use crate::memory::AsyncMemoryAccess;
 use crate::{arch::Arch, unwind_result::UnwindResult};
 use core::ops::Range;
 
@@ -17,6 +18,12 @@ pub enum PeUnwinderError {
     UnwindInfoParseError,
     #[error("AArch64 is not yet supported")]
     Aarch64Unsupported,
+    #[error("RISC-V is not supported")]
+    RiscV64Unsupported,
+    #[error("32-bit ARM is not supported")]
+    ArmUnsupported,
+    #[error("32-bit x86 is not yet supported")]
+    X86Unsupported,
 }
 
 /// Data and the related RVA range within the binary.
@@ -71,14 +78,14 @@ fn memory_at_rva<D: core::ops::Deref<Target = [u8]>>(
 }
 
 pub trait PeUnwinding: Arch {
-    fn unwind_frame<F, D>(
-        sections: PeSections<D>,
+    async fn unwind_frame<F, D>(
+        sections: PeSections<'_, D>,
         address: u32,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
         read_stack: &mut F,
     ) -> Result<UnwindResult<Self::UnwindRule>, PeUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         D: core::ops::Deref<Target = [u8]>;
 }
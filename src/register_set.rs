@@ -0,0 +1,116 @@
+use core::fmt::Debug;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+
+/// A small set of the registers which are relevant for unwinding, as returned by the
+/// `used_registers` method of the unwind rule types, for example
+/// [`UnwindRuleX86_64::used_registers`](crate::x86_64::UnwindRuleX86_64::used_registers).
+///
+/// The names are architecture-neutral:
+///
+///  - [`SP`](RegisterSet::SP) is the stack pointer.
+///  - [`FP`](RegisterSet::FP) is the frame pointer: rbp / ebp on x86_64 / x86, x29 on
+///    aarch64, s0 on riscv64, and r7 or r11 on arm.
+///  - [`LR`](RegisterSet::LR) is the register which holds the return address: lr on
+///    aarch64 and arm, ra on riscv64. It is never used on x86_64 and x86.
+///  - [`PC`](RegisterSet::PC) is the instruction pointer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RegisterSet(u8);
+
+impl RegisterSet {
+    /// The empty set.
+    pub const EMPTY: Self = Self(0);
+    /// The stack pointer.
+    pub const SP: Self = Self(1 << 0);
+    /// The frame pointer.
+    pub const FP: Self = Self(1 << 1);
+    /// The link register, which holds the return address.
+    pub const LR: Self = Self(1 << 2);
+    /// The instruction pointer.
+    pub const PC: Self = Self(1 << 3);
+
+    /// Returns true if all registers in `other` are also in `self`.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if the set contains no registers.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for RegisterSet {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for RegisterSet {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for RegisterSet {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitAndAssign for RegisterSet {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl Not for RegisterSet {
+    type Output = Self;
+
+    /// All registers which are not in `self`.
+    fn not(self) -> Self {
+        let all = Self::SP | Self::FP | Self::LR | Self::PC;
+        Self(!self.0 & all.0)
+    }
+}
+
+impl Debug for RegisterSet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let names = [
+            (Self::SP, "SP"),
+            (Self::FP, "FP"),
+            (Self::LR, "LR"),
+            (Self::PC, "PC"),
+        ];
+        let mut set = f.debug_set();
+        for (register, name) in names {
+            if self.contains(register) {
+                set.entry(&format_args!("{name}"));
+            }
+        }
+        set.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_register_set() {
+        let set = RegisterSet::SP | RegisterSet::FP;
+        assert!(set.contains(RegisterSet::SP));
+        assert!(set.contains(RegisterSet::SP | RegisterSet::FP));
+        assert!(!set.contains(RegisterSet::LR));
+        assert!(set.contains(RegisterSet::EMPTY));
+        assert!(RegisterSet::EMPTY.is_empty());
+        assert_eq!(set & RegisterSet::FP, RegisterSet::FP);
+        assert_eq!(!set, RegisterSet::LR | RegisterSet::PC);
+        assert!((set & !set).is_empty());
+        assert_eq!(format!("{set:?}"), "{SP, FP}");
+    }
+}
@@ -0,0 +1,15 @@
+use super::unwind_rule::UnwindRuleRiscV64;
+use super::unwindregs::UnwindRegsRiscV64;
+use crate::arch::Arch;
+
+/// The RISC-V 64-bit CPU architecture.
+pub struct ArchRiscV64;
+impl Arch for ArchRiscV64 {
+    type UnwindRule = UnwindRuleRiscV64;
+    type UnwindRegs = UnwindRegsRiscV64;
+    const ADDRESS_SIZE: u8 = 8;
+
+    fn placeholder_regs() -> UnwindRegsRiscV64 {
+        UnwindRegsRiscV64::new(0, 0, 0, 0)
+    }
+}
@@ -0,0 +1,400 @@
+use gimli::{
+    CfaRule, Encoding, EvaluationStorage, Reader, ReaderOffset, Register, RegisterRule, RiscV,
+    UnwindContextStorage, UnwindSection, UnwindTableRow,
+};
+
+use super::{arch::ArchRiscV64, unwind_rule::UnwindRuleRiscV64, unwindregs::UnwindRegsRiscV64};
+
+use crate::diagnostics::diagnostic;
+use crate::memory::{AsyncMemoryAccess, TrackReadFailures};
+use crate::unwind_result::UnwindResult;
+
+use crate::dwarf::{
+    eval_cfa_rule, eval_error, eval_register_rule, scaled_offset, ConversionError, DwarfUnwindRegs,
+    DwarfUnwinderError, DwarfUnwinding,
+};
+
+impl DwarfUnwindRegs for UnwindRegsRiscV64 {
+    fn get(&self, register: Register) -> Option<u64> {
+        match register {
+            RiscV::SP => Some(self.sp()),
+            RiscV::S0 => Some(self.fp()),
+            RiscV::RA => Some(self.ra()),
+            _ => None,
+        }
+    }
+}
+
+impl DwarfUnwinding for ArchRiscV64 {
+    const RETURN_ADDRESS_REGISTER: Register = RiscV::RA;
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        RiscV::register_name(register)
+    }
+
+    async fn unwind_frame<F, R, UCS, ES>(
+        section: &impl UnwindSection<R>,
+        unwind_info: &UnwindTableRow<R::Offset, UCS>,
+        encoding: Encoding,
+        regs: &mut Self::UnwindRegs,
+        is_first_frame: bool,
+        _lookup_svma: u64,
+        read_stack: &mut F,
+    ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
+    where
+        F: AsyncMemoryAccess,
+        R: Reader,
+        UCS: UnwindContextStorage<R::Offset>,
+        ES: EvaluationStorage<R>,
+    {
+        let cfa_rule = unwind_info.cfa();
+        let fp_rule = unwind_info.register(RiscV::S0);
+        let ra_rule = unwind_info.register(RiscV::RA);
+
+        match Self::rule_for_row(unwind_info) {
+            Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
+            Err(err) => {
+                // Could not translate into a cacheable unwind rule. Fall back to the generic path.
+                diagnostic!(
+                    "Unwind rule translation failed at pc 0x{:x}: {:?}",
+                    regs.pc(),
+                    err
+                );
+            }
+        }
+
+        let read_stack = &mut TrackReadFailures::new(read_stack);
+        let cfa = eval_cfa_rule::<R, _, _, ES>(section, cfa_rule, encoding, regs, read_stack)
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverCfa,
+                )
+            })?;
+
+        let ra = regs.ra();
+        let fp = regs.fp();
+        let sp = regs.sp();
+
+        let (fp, ra) = if !is_first_frame {
+            if cfa <= sp {
+                return Err(DwarfUnwinderError::StackPointerMovedBackwards);
+            }
+            let fp = eval_register_rule::<R, _, _, ES>(
+                section, fp_rule, cfa, encoding, fp, regs, read_stack,
+            )
+            .await
+            .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
+            let ra = eval_register_rule::<R, _, _, ES>(
+                section, ra_rule, cfa, encoding, ra, regs, read_stack,
+            )
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverReturnAddress,
+                )
+            })?;
+            (fp, ra)
+        } else {
+            // For the first frame, be more lenient when encountering errors.
+            let fp = eval_register_rule::<R, _, _, ES>(
+                section, fp_rule, cfa, encoding, fp, regs, read_stack,
+            )
+            .await
+            .unwrap_or(fp);
+            let ra = eval_register_rule::<R, _, _, ES>(
+                section, ra_rule, cfa, encoding, ra, regs, read_stack,
+            )
+            .await
+            .unwrap_or(ra);
+            (fp, ra)
+        };
+
+        regs.set_fp(fp);
+        regs.set_sp(cfa);
+        regs.set_ra(ra);
+        regs.set_pc(ra);
+
+        Ok(UnwindResult::Uncacheable(ra))
+    }
+
+    fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
+        UnwindRuleRiscV64::JustReturnIfFirstFrameOtherwiseFp
+    }
+
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        translate_into_unwind_rule(
+            row.cfa(),
+            &row.register(RiscV::S0),
+            &row.register(RiscV::RA),
+        )
+    }
+}
+
+fn register_rule_to_cfa_offset<RO: ReaderOffset>(
+    rule: &RegisterRule<RO>,
+) -> Result<Option<i64>, ConversionError> {
+    match *rule {
+        RegisterRule::Undefined | RegisterRule::SameValue => Ok(None),
+        RegisterRule::Offset(offset) => Ok(Some(offset)),
+        _ => Err(ConversionError::RegisterNotStoredRelativeToCfa),
+    }
+}
+
+fn translate_into_unwind_rule<RO: ReaderOffset>(
+    cfa_rule: &CfaRule<RO>,
+    fp_rule: &RegisterRule<RO>,
+    ra_rule: &RegisterRule<RO>,
+) -> Result<UnwindRuleRiscV64, ConversionError> {
+    match cfa_rule {
+        CfaRule::RegisterAndOffset { register, offset } => match *register {
+            RiscV::SP => {
+                let sp_offset_by_16 =
+                    scaled_offset(*offset, 16, ConversionError::SpOffsetDoesNotFit)?;
+                let ra_cfa_offset = register_rule_to_cfa_offset(ra_rule)?;
+                let fp_cfa_offset = register_rule_to_cfa_offset(fp_rule)?;
+                match (ra_cfa_offset, fp_cfa_offset) {
+                    (None, Some(_)) => Err(ConversionError::RestoringFpButNotLr),
+                    (None, None) => {
+                        if let RegisterRule::Undefined = ra_rule {
+                            // An undefined return address either marks the root of the stack,
+                            // or was omitted by a compiler which meant "same value". See the
+                            // equivalent case in the aarch64 DWARF translation.
+                            Ok(
+                                UnwindRuleRiscV64::OffsetSpIfFirstFrameOtherwiseStackEndsHere {
+                                    sp_offset_by_16,
+                                },
+                            )
+                        } else {
+                            Ok(UnwindRuleRiscV64::OffsetSp { sp_offset_by_16 })
+                        }
+                    }
+                    (Some(ra_cfa_offset), None) => {
+                        let ra_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + ra_cfa_offset,
+                            8,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
+                        Ok(UnwindRuleRiscV64::OffsetSpAndRestoreRa {
+                            sp_offset_by_16,
+                            ra_storage_offset_from_sp_by_8,
+                        })
+                    }
+                    (Some(ra_cfa_offset), Some(fp_cfa_offset)) => {
+                        let ra_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + ra_cfa_offset,
+                            8,
+                            ConversionError::LrStorageOffsetDoesNotFit,
+                        )?;
+                        let fp_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + fp_cfa_offset,
+                            8,
+                            ConversionError::FpStorageOffsetDoesNotFit,
+                        )?;
+                        Ok(UnwindRuleRiscV64::OffsetSpAndRestoreFpAndRa {
+                            sp_offset_by_16,
+                            fp_storage_offset_from_sp_by_8,
+                            ra_storage_offset_from_sp_by_8,
+                        })
+                    }
+                }
+            }
+            RiscV::S0 => {
+                let ra_cfa_offset = register_rule_to_cfa_offset(ra_rule)?
+                    .ok_or(ConversionError::FramePointerRuleDoesNotRestoreLr)?;
+                let fp_cfa_offset = register_rule_to_cfa_offset(fp_rule)?
+                    .ok_or(ConversionError::FramePointerRuleDoesNotRestoreFp)?;
+                if *offset == 0 && fp_cfa_offset == -16 && ra_cfa_offset == -8 {
+                    Ok(UnwindRuleRiscV64::UseFramePointer)
+                } else {
+                    let sp_offset_from_fp_by_8 =
+                        scaled_offset(*offset, 8, ConversionError::SpOffsetFromFpDoesNotFit)?;
+                    let ra_storage_offset_from_fp_by_8 = scaled_offset(
+                        offset + ra_cfa_offset,
+                        8,
+                        ConversionError::LrStorageOffsetDoesNotFit,
+                    )?;
+                    let fp_storage_offset_from_fp_by_8 = scaled_offset(
+                        offset + fp_cfa_offset,
+                        8,
+                        ConversionError::FpStorageOffsetDoesNotFit,
+                    )?;
+                    Ok(UnwindRuleRiscV64::UseFramepointerWithOffsets {
+                        sp_offset_from_fp_by_8,
+                        fp_storage_offset_from_fp_by_8,
+                        ra_storage_offset_from_fp_by_8,
+                    })
+                }
+            }
+            _ => Err(ConversionError::CfaIsOffsetFromUnknownRegister),
+        },
+        CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use gimli::{BaseAddresses, EhFrame, LittleEndian, UnwindContext};
+
+    #[test]
+    fn test_prologue_rules() {
+        // The CIE: CFA=sp+0, with ra as the return address register, code alignment 1
+        // and data alignment -8.
+        let mut eh_frame: Vec<u8> = Vec::new();
+        eh_frame.extend_from_slice(&20u32.to_le_bytes());
+        eh_frame.extend_from_slice(&0u32.to_le_bytes()); // CIE id
+        eh_frame.extend_from_slice(&[1, b'z', b'R', 0, 1, 0x78, 1, 1, 0x00]);
+        eh_frame.extend_from_slice(&[0x0c, 0x02, 0x00, 0, 0, 0, 0]);
+        // The FDE for a function at 0x100..0x120 which starts with this prologue:
+        //   0x100: addi sp, sp, -16
+        //   0x102: sd ra, 8(sp)
+        //   0x104: sd s0, 0(sp)
+        //   0x106: addi s0, sp, 16
+        //   0x108: ...
+        eh_frame.extend_from_slice(&36u32.to_le_bytes());
+        eh_frame.extend_from_slice(&28u32.to_le_bytes()); // CIE pointer
+        eh_frame.extend_from_slice(&0x100u64.to_le_bytes());
+        eh_frame.extend_from_slice(&0x20u64.to_le_bytes());
+        eh_frame.push(0); // augmentation data length
+        eh_frame.extend_from_slice(&[
+            0x42, // DW_CFA_advance_loc: 2
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: sp+16
+            0x44, // DW_CFA_advance_loc: 4
+            0x81, 0x01, // DW_CFA_offset: ra at CFA-8
+            0x88, 0x02, // DW_CFA_offset: s0 at CFA-16
+            0x42, // DW_CFA_advance_loc: 2
+            0x0c, 0x08, 0x00, // DW_CFA_def_cfa: s0+0
+            0, 0, 0, // DW_CFA_nop
+        ]);
+        eh_frame.extend_from_slice(&0u32.to_le_bytes()); // terminator
+
+        let eh_frame = EhFrame::new(&eh_frame, LittleEndian);
+        let bases = BaseAddresses::default();
+        let mut ctx = UnwindContext::new();
+        let fde = eh_frame
+            .fde_for_address(&bases, 0x100, EhFrame::cie_from_offset)
+            .unwrap();
+        let rule_at = |ctx: &mut UnwindContext<usize>, address| {
+            let row = fde
+                .unwind_info_for_address(&eh_frame, &bases, ctx, address)
+                .unwrap();
+            ArchRiscV64::rule_for_row(row).unwrap()
+        };
+
+        // The return address is still in ra, which the CIE leaves undefined.
+        assert_eq!(
+            rule_at(&mut ctx, 0x100),
+            UnwindRuleRiscV64::OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_16: 0 }
+        );
+        for address in 0x102..0x106 {
+            assert_eq!(
+                rule_at(&mut ctx, address),
+                UnwindRuleRiscV64::OffsetSpIfFirstFrameOtherwiseStackEndsHere {
+                    sp_offset_by_16: 1
+                }
+            );
+        }
+        for address in 0x106..0x108 {
+            assert_eq!(
+                rule_at(&mut ctx, address),
+                UnwindRuleRiscV64::OffsetSpAndRestoreFpAndRa {
+                    sp_offset_by_16: 1,
+                    fp_storage_offset_from_sp_by_8: 0,
+                    ra_storage_offset_from_sp_by_8: 1,
+                }
+            );
+        }
+        for address in 0x108..0x120 {
+            assert_eq!(
+                rule_at(&mut ctx, address),
+                UnwindRuleRiscV64::UseFramePointer
+            );
+        }
+    }
+
+    #[test]
+    fn test_translation() {
+        let cfa_rule = |register, offset| CfaRule::<usize>::RegisterAndOffset { register, offset };
+        let undefined = RegisterRule::<usize>::Undefined;
+
+        // A leaf function which saved ra, but not s0.
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(RiscV::SP, 32),
+                &undefined,
+                &RegisterRule::Offset(-8)
+            ),
+            Ok(UnwindRuleRiscV64::OffsetSpAndRestoreRa {
+                sp_offset_by_16: 2,
+                ra_storage_offset_from_sp_by_8: 3,
+            })
+        );
+        // s0 points below the CFA.
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(RiscV::S0, 16),
+                &RegisterRule::Offset(-32),
+                &RegisterRule::Offset(-24)
+            ),
+            Ok(UnwindRuleRiscV64::UseFramepointerWithOffsets {
+                sp_offset_from_fp_by_8: 2,
+                fp_storage_offset_from_fp_by_8: -2,
+                ra_storage_offset_from_fp_by_8: -1,
+            })
+        );
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(RiscV::S0, 0),
+                &RegisterRule::Offset(-16),
+                &undefined
+            ),
+            Err(ConversionError::FramePointerRuleDoesNotRestoreLr)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(RiscV::SP, 16),
+                &RegisterRule::Offset(-16),
+                &undefined
+            ),
+            Err(ConversionError::RestoringFpButNotLr)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(RiscV::SP, 16),
+                &undefined,
+                &RegisterRule::Register(RiscV::T0)
+            ),
+            Err(ConversionError::RegisterNotStoredRelativeToCfa)
+        );
+        // The stack pointer is always 16-byte aligned.
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(RiscV::SP, 8), &undefined, &undefined),
+            Err(ConversionError::SpOffsetDoesNotFit)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(RiscV::A0, 0), &undefined, &undefined),
+            Err(ConversionError::CfaIsOffsetFromUnknownRegister)
+        );
+    }
+
+    #[test]
+    fn test_dwarf_registers() {
+        let regs = UnwindRegsRiscV64::new(0x1000, 0x2000, 0x30, 0x40);
+        assert_eq!(regs.get(RiscV::RA), Some(0x2000));
+        assert_eq!(regs.get(RiscV::SP), Some(0x30));
+        assert_eq!(regs.get(RiscV::S0), Some(0x40));
+        assert_eq!(regs.get(RiscV::S1), None);
+    }
+}
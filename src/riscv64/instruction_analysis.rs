@@ -0,0 +1,39 @@
+use super::arch::ArchRiscV64;
+use crate::instruction_analysis::InstructionAnalysis;
+
+// Instruction analysis is only used for mach-O compact unwind info, which doesn't exist
+// for RISC-V. DWARF CFI on RISC-V Linux covers prologues and epilogues.
+impl InstructionAnalysis for ArchRiscV64 {
+    fn rule_from_prologue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn rule_from_epilogue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+        let bytes = &text_bytes[..return_offset];
+        if let [.., b0, b1, b2, b3] = *bytes {
+            let insn = u32::from_le_bytes([b0, b1, b2, b3]);
+            // jal ra, offset / jalr ra, offset(rs1)
+            if insn & 0xfff == 0x0ef || insn & 0x7fff == 0x00e7 {
+                return true;
+            }
+        }
+        if let [.., b0, b1] = *bytes {
+            // c.jalr rs1
+            let insn = u16::from_le_bytes([b0, b1]);
+            if insn & 0xf07f == 0x9002 && insn & 0x0f80 != 0 {
+                return true;
+            }
+        }
+        false
+    }
+}
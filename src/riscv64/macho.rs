@@ -0,0 +1,21 @@
+use super::arch::ArchRiscV64;
+use super::unwind_rule::UnwindRuleRiscV64;
+use crate::macho::{CompactUnwindInfoUnwinderError, CompactUnwindInfoUnwinding, CuiUnwindResult};
+use macho_unwind_info::Function;
+
+impl CompactUnwindInfoUnwinding for ArchRiscV64 {
+    fn unwind_frame(
+        _function: Function,
+        _is_first_frame: bool,
+        _address_offset_within_function: usize,
+        _function_bytes: Option<&[u8]>,
+    ) -> Result<CuiUnwindResult<UnwindRuleRiscV64>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::RiscV64Unsupported)
+    }
+
+    fn rule_for_stub_helper(
+        _offset: u32,
+    ) -> Result<CuiUnwindResult<UnwindRuleRiscV64>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::RiscV64Unsupported)
+    }
+}
@@ -0,0 +1,294 @@
+use super::unwindregs::UnwindRegsRiscV64;
+use crate::add_signed::checked_add_signed;
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
+
+use crate::unwind_rule::UnwindRule;
+
+/// Unwind rules are `Copy` and at most 8 bytes large, which is checked at compile time,
+/// so that they can be cached and stored in large numbers. The variants and their fields
+/// are public, so rules can also be built outside of framehop. The memory layout is not
+/// guaranteed; to persist rules, store their variants and fields, or enable the `serde`
+/// feature, which implements `Serialize` and `Deserialize` for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnwindRuleRiscV64 {
+    /// (sp, fp, ra) = (sp, fp, ra)
+    /// Only possible for the first frame. Subsequent frames must get the
+    /// return address from somewhere other than the ra register to avoid
+    /// infinite loops.
+    JustReturn,
+    /// (sp, fp, ra) = if is_first_frame (sp, fp, ra) else (fp, *(fp - 16), *(fp - 8))
+    /// Used as a fallback rule.
+    JustReturnIfFirstFrameOtherwiseFp,
+    /// (sp, fp, ra) = (sp + 16x, fp, ra)
+    /// Only possible for the first frame. Subsequent frames must get the
+    /// return address from somewhere other than the ra register to avoid
+    /// infinite loops.
+    OffsetSp { sp_offset_by_16: u16 },
+    /// (sp, fp, ra) = (sp + 16x, fp, ra) if is_first_frame
+    /// This rule reflects an ambiguity in DWARF CFI information. When the
+    /// return address is "undefined" because it was omitted, it could mean
+    /// "same value", but this is only allowed for the first frame.
+    OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_16: u16 },
+    /// (sp, fp, ra) = (sp + 16x, fp, *(sp + 8y))
+    OffsetSpAndRestoreRa {
+        sp_offset_by_16: u16,
+        ra_storage_offset_from_sp_by_8: i16,
+    },
+    /// (sp, fp, ra) = (sp + 16x, *(sp + 8y), *(sp + 8z))
+    OffsetSpAndRestoreFpAndRa {
+        sp_offset_by_16: u16,
+        fp_storage_offset_from_sp_by_8: i16,
+        ra_storage_offset_from_sp_by_8: i16,
+    },
+    /// (sp, fp, ra) = (fp, *(fp - 16), *(fp - 8))
+    UseFramePointer,
+    /// (sp, fp, ra) = (fp + 8x, *(fp + 8y), *(fp + 8z))
+    UseFramepointerWithOffsets {
+        sp_offset_from_fp_by_8: i16,
+        fp_storage_offset_from_fp_by_8: i16,
+        ra_storage_offset_from_fp_by_8: i16,
+    },
+}
+
+const _: () = assert!(core::mem::size_of::<UnwindRuleRiscV64>() <= 8);
+
+impl UnwindRuleRiscV64 {
+    /// The registers whose current values this rule reads, see [`RegisterSet`].
+    ///
+    /// Registers which the rule passes through to the caller unchanged are not included.
+    /// If the rule behaves differently for the first frame, the registers needed by either
+    /// case are included. This lets a caller which didn't capture all registers know in
+    /// advance whether a rule can be applied.
+    pub fn used_registers(&self) -> RegisterSet {
+        match self {
+            UnwindRuleRiscV64::JustReturn => RegisterSet::LR,
+            UnwindRuleRiscV64::JustReturnIfFirstFrameOtherwiseFp => {
+                RegisterSet::LR | RegisterSet::SP | RegisterSet::FP
+            }
+            UnwindRuleRiscV64::OffsetSp { .. }
+            | UnwindRuleRiscV64::OffsetSpIfFirstFrameOtherwiseStackEndsHere { .. } => {
+                RegisterSet::SP | RegisterSet::LR
+            }
+            UnwindRuleRiscV64::OffsetSpAndRestoreRa { .. }
+            | UnwindRuleRiscV64::OffsetSpAndRestoreFpAndRa { .. } => RegisterSet::SP,
+            UnwindRuleRiscV64::UseFramePointer
+            | UnwindRuleRiscV64::UseFramepointerWithOffsets { .. } => {
+                RegisterSet::SP | RegisterSet::FP
+            }
+        }
+    }
+}
+
+impl UnwindRule for UnwindRuleRiscV64 {
+    type UnwindRegs = UnwindRegsRiscV64;
+
+    fn used_registers(&self) -> RegisterSet {
+        UnwindRuleRiscV64::used_registers(self)
+    }
+
+    fn rule_for_stub_functions() -> Self {
+        UnwindRuleRiscV64::JustReturn
+    }
+    fn rule_for_function_start() -> Self {
+        UnwindRuleRiscV64::JustReturn
+    }
+    fn fallback_rule() -> Self {
+        UnwindRuleRiscV64::UseFramePointer
+    }
+
+    async fn exec<F>(
+        self,
+        is_first_frame: bool,
+        regs: &mut UnwindRegsRiscV64,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let ra = regs.ra();
+        let sp = regs.sp();
+        let fp = regs.fp();
+
+        let (new_ra, new_sp, new_fp) = match self {
+            UnwindRuleRiscV64::JustReturn => {
+                if !is_first_frame {
+                    return Err(Error::DidNotAdvance);
+                }
+                (ra, sp, fp)
+            }
+            UnwindRuleRiscV64::JustReturnIfFirstFrameOtherwiseFp => {
+                if is_first_frame {
+                    (ra, sp, fp)
+                } else {
+                    let (new_ra, new_sp, new_fp) = frame_pointer_step(fp, read_stack).await?;
+                    if new_sp <= sp {
+                        return Err(Error::FramepointerUnwindingMovedBackwards);
+                    }
+                    (new_ra, new_sp, new_fp)
+                }
+            }
+            UnwindRuleRiscV64::OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_16 } => {
+                if !is_first_frame {
+                    return Ok(None);
+                }
+                let sp_offset = u64::from(sp_offset_by_16) * 16;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (ra, new_sp, fp)
+            }
+            UnwindRuleRiscV64::OffsetSp { sp_offset_by_16 } => {
+                if !is_first_frame {
+                    return Err(Error::DidNotAdvance);
+                }
+                let sp_offset = u64::from(sp_offset_by_16) * 16;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (ra, new_sp, fp)
+            }
+            UnwindRuleRiscV64::OffsetSpAndRestoreRa {
+                sp_offset_by_16,
+                ra_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_16) * 16;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let ra_storage_offset = i64::from(ra_storage_offset_from_sp_by_8) * 8;
+                let ra_location =
+                    checked_add_signed(sp, ra_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_ra = read_stack.read_u64(ra_location).await?;
+                (new_ra, new_sp, fp)
+            }
+            UnwindRuleRiscV64::OffsetSpAndRestoreFpAndRa {
+                sp_offset_by_16,
+                fp_storage_offset_from_sp_by_8,
+                ra_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_16) * 16;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let ra_storage_offset = i64::from(ra_storage_offset_from_sp_by_8) * 8;
+                let ra_location =
+                    checked_add_signed(sp, ra_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_ra = read_stack.read_u64(ra_location).await?;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_8) * 8;
+                let fp_location =
+                    checked_add_signed(sp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_fp = read_stack.read_u64(fp_location).await?;
+                (new_ra, new_sp, new_fp)
+            }
+            UnwindRuleRiscV64::UseFramePointer => {
+                // Do a frame pointer stack walk. On RISC-V, frame-based functions store ra and
+                // the caller's fp right below the incoming sp, and then set fp to the incoming
+                // sp, i.e. to the CFA.
+                //
+                // Function prologue example:
+                // addi  sp, sp, -32
+                // sd    ra, 24(sp)     ; stores ra at the original sp - 8
+                // sd    s0, 16(sp)     ; stores the caller's fp at the original sp - 16
+                // addi  s0, sp, 32     ; sets fp to the original sp
+                //
+                // So: *(fp - 16) is the caller's frame pointer, and *(fp - 8) is the return address.
+                let (new_ra, new_sp, new_fp) = frame_pointer_step(fp, read_stack).await?;
+                if new_fp == 0 {
+                    return Ok(None);
+                }
+                if new_fp <= fp || new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                (new_ra, new_sp, new_fp)
+            }
+            UnwindRuleRiscV64::UseFramepointerWithOffsets {
+                sp_offset_from_fp_by_8,
+                fp_storage_offset_from_fp_by_8,
+                ra_storage_offset_from_fp_by_8,
+            } => {
+                let sp_offset_from_fp = i64::from(sp_offset_from_fp_by_8) * 8;
+                let new_sp =
+                    checked_add_signed(fp, sp_offset_from_fp).ok_or(Error::IntegerOverflow)?;
+                let ra_storage_offset = i64::from(ra_storage_offset_from_fp_by_8) * 8;
+                let ra_location =
+                    checked_add_signed(fp, ra_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_ra = read_stack.read_u64(ra_location).await?;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_fp_by_8) * 8;
+                let fp_location =
+                    checked_add_signed(fp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_fp = read_stack.read_u64(fp_location).await?;
+
+                if new_fp == 0 {
+                    return Ok(None);
+                }
+                if new_fp <= fp || new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                (new_ra, new_sp, new_fp)
+            }
+        };
+        if new_ra == 0 {
+            return Ok(None);
+        }
+        if !is_first_frame && new_sp == sp {
+            return Err(Error::DidNotAdvance);
+        }
+        regs.set_pc(new_ra);
+        regs.set_ra(new_ra);
+        regs.set_sp(new_sp);
+        regs.set_fp(new_fp);
+
+        Ok(Some(new_ra))
+    }
+}
+
+/// Returns (ra, sp, fp) of the caller, for a frame-based function whose fp points at the CFA.
+async fn frame_pointer_step<F>(fp: u64, read_stack: &mut F) -> Result<(u64, u64, u64), Error>
+where
+    F: AsyncMemoryAccess,
+{
+    let ra_location = fp.checked_sub(8).ok_or(Error::IntegerOverflow)?;
+    let fp_location = fp.checked_sub(16).ok_or(Error::IntegerOverflow)?;
+    let new_ra = read_stack.read_u64(ra_location).await?;
+    let new_fp = read_stack.read_u64(fp_location).await?;
+    Ok((new_ra, fp, new_fp))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+
+    #[test]
+    fn test_basic() {
+        let stack = [
+            1, 2, 0x40, 0x100200, 5, 6, 0x70, 0x100100, 7, 8, 9, 10, 0x0, 0x0,
+        ];
+        let mut read_stack = |addr| Ok(stack[(addr / 8) as usize]);
+        let mut regs = UnwindRegsRiscV64::new(0x100400, 0x100300, 0x10, 0x20);
+        let res = complete_sync(UnwindRuleRiscV64::JustReturn.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100300)));
+        assert_eq!(regs.sp(), 0x10);
+        let res = complete_sync(UnwindRuleRiscV64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x20);
+        assert_eq!(regs.fp(), 0x40);
+        let res = complete_sync(UnwindRuleRiscV64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100100)));
+        assert_eq!(regs.sp(), 0x40);
+        assert_eq!(regs.fp(), 0x70);
+        let res = complete_sync(UnwindRuleRiscV64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(None));
+    }
+}
@@ -0,0 +1,79 @@
+use core::fmt::Debug;
+
+use crate::display_utils::HexNum;
+
+/// The registers used for unwinding on RISC-V 64-bit. We need pc, ra (x1), sp (x2),
+/// and fp (s0 / x8).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UnwindRegsRiscV64 {
+    pc: u64,
+    ra: u64,
+    sp: u64,
+    fp: u64,
+}
+
+impl UnwindRegsRiscV64 {
+    /// Create a set of unwind register values.
+    pub fn new(pc: u64, ra: u64, sp: u64, fp: u64) -> Self {
+        Self { pc, ra, sp, fp }
+    }
+
+    /// Get the program counter value.
+    #[inline(always)]
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    /// Set the program counter value.
+    #[inline(always)]
+    pub fn set_pc(&mut self, pc: u64) {
+        self.pc = pc
+    }
+
+    /// Get the return address register value (x1).
+    #[inline(always)]
+    pub fn ra(&self) -> u64 {
+        self.ra
+    }
+
+    /// Set the return address register value (x1).
+    #[inline(always)]
+    pub fn set_ra(&mut self, ra: u64) {
+        self.ra = ra
+    }
+
+    /// Get the stack pointer value (x2).
+    #[inline(always)]
+    pub fn sp(&self) -> u64 {
+        self.sp
+    }
+
+    /// Set the stack pointer value (x2).
+    #[inline(always)]
+    pub fn set_sp(&mut self, sp: u64) {
+        self.sp = sp
+    }
+
+    /// Get the frame pointer value (s0 / x8).
+    #[inline(always)]
+    pub fn fp(&self) -> u64 {
+        self.fp
+    }
+
+    /// Set the frame pointer value (s0 / x8).
+    #[inline(always)]
+    pub fn set_fp(&mut self, fp: u64) {
+        self.fp = fp
+    }
+}
+
+impl Debug for UnwindRegsRiscV64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UnwindRegsRiscV64")
+            .field("pc", &HexNum(self.pc))
+            .field("ra", &HexNum(self.ra))
+            .field("sp", &HexNum(self.sp))
+            .field("fp", &HexNum(self.fp))
+            .finish()
+    }
+}
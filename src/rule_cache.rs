@@ -2,37 +2,44 @@ use alloc::boxed::Box;
 
 use crate::unwind_rule::UnwindRule;
 
-const CACHE_ENTRY_COUNT: usize = 509;
+use alloc::vec;
+
+pub const DEFAULT_CACHE_ENTRY_COUNT: usize = 509;
 
 pub struct RuleCache<R: UnwindRule> {
-    entries: Box<[Option<CacheEntry<R>>; CACHE_ENTRY_COUNT]>,
+    entries: Box<[Option<CacheEntry<R>>]>,
     stats: CacheStats,
 }
 
 impl<R: UnwindRule> RuleCache<R> {
-    pub fn new() -> Self {
+    /// A capacity of zero is treated as a capacity of one.
+    pub fn with_capacity(entry_count: usize) -> Self {
         Self {
-            entries: Box::new([None; CACHE_ENTRY_COUNT]),
+            entries: vec![None; entry_count.max(1)].into_boxed_slice(),
             stats: CacheStats::new(),
         }
     }
 
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
     pub fn lookup(&mut self, address: u64, modules_generation: u16) -> CacheResult<R> {
-        let slot = (address % (CACHE_ENTRY_COUNT as u64)) as u16;
-        match &self.entries[slot as usize] {
+        let slot = (address % (self.entries.len() as u64)) as usize;
+        match &self.entries[slot] {
             None => {
-                self.stats.miss_empty_slot_count += 1;
+                count(&mut self.stats.miss_empty_slot_count);
             }
             Some(entry) => {
                 if entry.modules_generation == modules_generation {
                     if entry.address == address {
-                        self.stats.hit_count += 1;
+                        count(&mut self.stats.hit_count);
                         return CacheResult::Hit(entry.unwind_rule);
                     } else {
-                        self.stats.miss_wrong_address_count += 1;
+                        count(&mut self.stats.miss_wrong_address_count);
                     }
                 } else {
-                    self.stats.miss_wrong_modules_count += 1;
+                    count(&mut self.stats.miss_wrong_modules_count);
                 }
             }
         }
@@ -49,7 +56,10 @@ impl<R: UnwindRule> RuleCache<R> {
             address,
             modules_generation,
         } = handle;
-        self.entries[slot as usize] = Some(CacheEntry {
+        if self.entries[slot].is_some() {
+            count(&mut self.stats.eviction_count);
+        }
+        self.entries[slot] = Some(CacheEntry {
             address,
             modules_generation,
             unwind_rule,
@@ -60,6 +70,33 @@ impl<R: UnwindRule> RuleCache<R> {
     pub fn stats(&self) -> CacheStats {
         self.stats
     }
+
+    /// Replaces the cache usage statistics, e.g. with an earlier snapshot from `stats`.
+    pub fn set_stats(&mut self, stats: CacheStats) {
+        self.stats = stats;
+    }
+
+    /// Resets all cache usage statistics to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::new();
+    }
+
+    /// Empties all slots, without changing the capacity or the statistics.
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+/// Increments a statistics counter. Without the `stats` feature, this does nothing and
+/// all statistics stay at zero.
+#[inline(always)]
+fn count(counter: &mut u64) {
+    #[cfg(feature = "stats")]
+    {
+        *counter += 1;
+    }
+    #[cfg(not(feature = "stats"))]
+    let _ = counter;
 }
 
 pub enum CacheResult<R: UnwindRule> {
@@ -68,16 +105,11 @@ pub enum CacheResult<R: UnwindRule> {
 }
 
 pub struct CacheHandle {
-    slot: u16,
+    slot: usize,
     address: u64,
     modules_generation: u16,
 }
 
-const _: () = assert!(
-    CACHE_ENTRY_COUNT as u64 <= u16::MAX as u64,
-    "u16 should be sufficient to store the cache slot index"
-);
-
 #[derive(Clone, Copy, Debug)]
 struct CacheEntry<R: UnwindRule> {
     address: u64,
@@ -85,7 +117,11 @@ struct CacheEntry<R: UnwindRule> {
     unwind_rule: R,
 }
 
-/// Statistics about the effectiveness of the rule cache.
+/// Statistics about the effectiveness of the rule cache, counted since the cache was
+/// created or since the last call to `reset_stats`.
+///
+/// The counters are only maintained if the `stats` feature is enabled, which it is by
+/// default. Otherwise they're always zero.
 #[derive(Default, Debug, Clone, Copy)]
 pub struct CacheStats {
     /// The number of successful cache hits.
@@ -101,6 +137,8 @@ pub struct CacheStats {
     /// The number of cache misses that were due to cache slot collisions of
     /// different addresses.
     pub miss_wrong_address_count: u64,
+    /// The number of times a new rule replaced an existing rule in a cache slot.
+    pub eviction_count: u64,
 }
 
 impl CacheStats {
@@ -123,14 +161,33 @@ impl CacheStats {
     pub fn misses(&self) -> u64 {
         self.miss_empty_slot_count + self.miss_wrong_modules_count + self.miss_wrong_address_count
     }
+
+    /// The number of total evictions.
+    pub fn evictions(&self) -> u64 {
+        self.eviction_count
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{aarch64::UnwindRuleAarch64, x86_64::UnwindRuleX86_64};
+    use crate::{
+        aarch64::UnwindRuleAarch64, arm::UnwindRuleArm, riscv64::UnwindRuleRiscV64,
+        x86::UnwindRuleX86, x86_64::UnwindRuleX86_64,
+    };
 
     use super::*;
 
+    // The rules themselves are checked to be at most 8 bytes at compile time. These are
+    // the exact sizes, so that a change is noticed.
+    #[test]
+    fn test_unwind_rule_size() {
+        assert_eq!(core::mem::size_of::<UnwindRuleX86_64>(), 6);
+        assert_eq!(core::mem::size_of::<UnwindRuleAarch64>(), 8);
+        assert_eq!(core::mem::size_of::<UnwindRuleArm>(), 8);
+        assert_eq!(core::mem::size_of::<UnwindRuleRiscV64>(), 8);
+        assert_eq!(core::mem::size_of::<UnwindRuleX86>(), 6);
+    }
+
     // Ensure that the size of Option<CacheEntry<UnwindRuleX86_64>> doesn't change by accident.
     #[test]
     fn test_cache_entry_size() {
@@ -142,5 +199,70 @@ mod tests {
             core::mem::size_of::<Option<CacheEntry<UnwindRuleAarch64>>>(),
             24 // <-- larger than we'd like
         );
+        assert_eq!(
+            core::mem::size_of::<Option<CacheEntry<UnwindRuleArm>>>(),
+            24
+        );
+        assert_eq!(
+            core::mem::size_of::<Option<CacheEntry<UnwindRuleRiscV64>>>(),
+            24
+        );
+        assert_eq!(
+            core::mem::size_of::<Option<CacheEntry<UnwindRuleX86>>>(),
+            16
+        );
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let mut cache = RuleCache::<UnwindRuleX86_64>::with_capacity(DEFAULT_CACHE_ENTRY_COUNT);
+        let insert = |cache: &mut RuleCache<_>, address| match cache.lookup(address, 0) {
+            CacheResult::Miss(handle) => cache.insert(handle, UnwindRuleX86_64::JustReturn),
+            CacheResult::Hit(_) => {}
+        };
+        insert(&mut cache, 0x1000);
+        insert(&mut cache, 0x1000);
+        insert(&mut cache, 0x1000 + DEFAULT_CACHE_ENTRY_COUNT as u64);
+        let stats = cache.stats();
+        assert_eq!(stats.hits(), 1);
+        assert_eq!(stats.miss_empty_slot_count, 1);
+        assert_eq!(stats.miss_wrong_address_count, 1);
+        assert_eq!(stats.evictions(), 1);
+        assert_eq!(stats.total(), 3);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats().total(), 0);
+        assert_eq!(cache.stats().evictions(), 0);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut cache = RuleCache::<UnwindRuleX86_64>::with_capacity(7);
+        assert_eq!(cache.capacity(), 7);
+        if let CacheResult::Miss(handle) = cache.lookup(0x1000, 0) {
+            cache.insert(handle, UnwindRuleX86_64::JustReturn);
+        }
+        assert!(matches!(cache.lookup(0x1000, 0), CacheResult::Hit(_)));
+        assert!(matches!(cache.lookup(0x1000 + 7, 0), CacheResult::Miss(_)));
+        assert_eq!(
+            RuleCache::<UnwindRuleX86_64>::with_capacity(0).capacity(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut cache = RuleCache::<UnwindRuleX86_64>::with_capacity(7);
+        for address in [0x1000, 0x1001] {
+            if let CacheResult::Miss(handle) = cache.lookup(address, 0) {
+                cache.insert(handle, UnwindRuleX86_64::JustReturn);
+            }
+        }
+        assert!(matches!(cache.lookup(0x1000, 0), CacheResult::Hit(_)));
+        cache.clear();
+        assert_eq!(cache.capacity(), 7);
+        assert!(matches!(cache.lookup(0x1000, 0), CacheResult::Miss(_)));
+        assert!(matches!(cache.lookup(0x1001, 0), CacheResult::Miss(_)));
     }
 }
@@ -0,0 +1,132 @@
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A table of precomputed unwind rules for the code of one module, keyed by address
+/// ranges which are relative to the start of the module's address range.
+///
+/// This lets you compute the unwind rules for a binary ahead of time, store them in
+/// your own format, and load them with `add_module_with_rules`, for example
+/// [`UnwinderX86_64::add_module_with_rules`](crate::x86_64::UnwinderX86_64::add_module_with_rules).
+/// Addresses in such a module are unwound with a binary search in the table, without
+/// parsing any unwind information.
+///
+/// With the `serde` feature, the table implements `Serialize` and `Deserialize`, so it can
+/// be computed once and shipped with the binary. It is serialized as the list of address
+/// ranges and rules, and deserialized with [`UnwindRuleTable::new`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        from = "Vec<(Range<u32>, R)>",
+        into = "Vec<(Range<u32>, R)>",
+        bound(serialize = "R: Copy + serde::Serialize"),
+        bound(deserialize = "R: Copy + serde::Deserialize<'de>")
+    )
+)]
+pub struct UnwindRuleTable<R> {
+    /// Sorted by start address. The ranges don't overlap and are not empty.
+    entries: Vec<(u32, u32, R)>,
+}
+
+impl<R: Copy> UnwindRuleTable<R> {
+    /// Create a table from the given address ranges and their rules.
+    ///
+    /// The ranges don't need to be sorted. Empty ranges are ignored. If ranges
+    /// overlap, the parts of a range which overlap with a range that starts earlier are
+    /// ignored.
+    pub fn new(mut rules: Vec<(Range<u32>, R)>) -> Self {
+        rules.retain(|(range, _)| !range.is_empty());
+        rules.sort_by_key(|(range, _)| range.start);
+        let mut entries: Vec<(u32, u32, R)> = Vec::with_capacity(rules.len());
+        for (range, rule) in rules {
+            let start = match entries.last() {
+                Some(&(_, prev_end, _)) => range.start.max(prev_end),
+                None => range.start,
+            };
+            if start < range.end {
+                entries.push((start, range.end, rule));
+            }
+        }
+        Self { entries }
+    }
+
+    /// Find the rule for `relative_address`, if a range in the table contains it.
+    pub fn lookup(&self, relative_address: u32) -> Option<R> {
+        let index = self
+            .entries
+            .partition_point(|(start, _, _)| *start <= relative_address)
+            .checked_sub(1)?;
+        let (_, end, rule) = self.entries[index];
+        (relative_address < end).then_some(rule)
+    }
+
+    /// The address ranges and rules in this table, sorted by address.
+    pub fn rules(&self) -> impl Iterator<Item = (Range<u32>, R)> + '_ {
+        self.entries
+            .iter()
+            .map(|&(start, end, rule)| (start..end, rule))
+    }
+
+    /// The number of address ranges in this table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the table has no rules.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<R: Copy> From<Vec<(Range<u32>, R)>> for UnwindRuleTable<R> {
+    fn from(rules: Vec<(Range<u32>, R)>) -> Self {
+        Self::new(rules)
+    }
+}
+
+impl<R: Copy> From<UnwindRuleTable<R>> for Vec<(Range<u32>, R)> {
+    fn from(table: UnwindRuleTable<R>) -> Self {
+        table.rules().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_rule_table() {
+        let table = UnwindRuleTable::new(vec![
+            (0x20..0x30, 'b'),
+            (0x10..0x20, 'a'),
+            (0x28..0x40, 'c'),
+            (0x50..0x50, 'd'),
+        ]);
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.lookup(0x0f), None);
+        assert_eq!(table.lookup(0x10), Some('a'));
+        assert_eq!(table.lookup(0x20), Some('b'));
+        assert_eq!(table.lookup(0x2f), Some('b'));
+        assert_eq!(table.lookup(0x30), Some('c'));
+        assert_eq!(table.lookup(0x40), None);
+        assert_eq!(table.rules().nth(2), Some((0x30..0x40, 'c')));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_rule_table() {
+        // Deserializing goes through `new`, so unsorted and overlapping input is fine.
+        let json = r#"[[{"start":32,"end":48},"b"],[{"start":16,"end":40},"a"]]"#;
+        let table: UnwindRuleTable<char> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            table,
+            UnwindRuleTable::new(vec![(0x10..0x28, 'a'), (0x28..0x30, 'b')])
+        );
+        assert_eq!(
+            serde_json::to_string(&table).unwrap(),
+            r#"[[{"start":16,"end":40},"a"],[{"start":40,"end":48},"b"]]"#
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use crate::error::AddModuleError;
+use crate::unwinder::Unwinder;
+
+/// An unwinder whose modules can be changed through a shared reference, while other
+/// threads are unwinding with it.
+///
+/// This is useful if samples are unwound on one thread and modules are added and removed
+/// on another, for example when handling `dlopen` and `dlclose` events, without a lock
+/// around the unwinder that the sampling thread would have to wait for.
+///
+/// Unwinding happens on a [`snapshot`](SharedUnwinder::snapshot), which is an immutable
+/// unwinder that keeps working while modules are changed. Changes are made to a clone of
+/// the current unwinder, which is cheap because the clone shares the modules' unwind
+/// information, and then the clone is published as the new snapshot. Getting a snapshot
+/// only waits for the pointer to the current snapshot to be replaced, never for a clone
+/// or a module to be parsed. Changes are applied one after another, so none of them get
+/// lost.
+///
+/// Use a new snapshot for each sample, so that the whole stack is unwound with the same
+/// modules. Every thread still needs its own cache. Caches can be used with consecutive
+/// snapshots, because every change to the modules invalidates cached rules.
+///
+/// ```
+/// use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+/// use framehop::{FrameAddress, Module, SharedUnwinder, Unwinder};
+///
+/// let shared = SharedUnwinder::new(UnwinderX86_64::new());
+///
+/// // On the thread which handles module events:
+/// shared.add_module(Module::<Vec<u8>>::new_framepointer_only(
+///     "libfoo.so".to_string(),
+///     0x10000..0x20000,
+///     0x10000,
+/// ));
+///
+/// // On the sampling thread:
+/// let mut cache = CacheX86_64::new();
+/// let stack = [0x30, 0x10800];
+/// let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+/// let unwinder = shared.snapshot();
+/// let mut iter = unwinder.iter_frames(
+///     0x10100,
+///     UnwindRegsX86_64::new(0x10100, 0x100, 0x0),
+///     &mut cache,
+///     &mut read_stack,
+/// );
+/// let mut frames = Vec::new();
+/// while let Ok(Some(frame)) = iter.next() {
+///     frames.push(frame);
+/// }
+/// assert_eq!(frames[0], FrameAddress::from_instruction_pointer(0x10100));
+/// ```
+pub struct SharedUnwinder<U> {
+    current: RwLock<Arc<U>>,
+    /// Held while a change is made, so that concurrent changes don't start from the
+    /// same snapshot and overwrite each other.
+    update_lock: Mutex<()>,
+}
+
+impl<U: Unwinder> SharedUnwinder<U> {
+    /// Start sharing `unwinder`, which becomes the first snapshot.
+    pub fn new(unwinder: U) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(unwinder)),
+            update_lock: Mutex::new(()),
+        }
+    }
+
+    /// The current unwinder. Changes which are made after this call don't affect the
+    /// returned unwinder.
+    pub fn snapshot(&self) -> Arc<U> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Change a clone of the current unwinder with `f`, and publish it as the new
+    /// snapshot. Use this for changes which need several calls, e.g. to add many modules
+    /// at once, or to change the unwinder's configuration.
+    pub fn update<R>(&self, f: impl FnOnce(&mut U) -> R) -> R {
+        let _update_guard = self
+            .update_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut unwinder = U::clone(&self.snapshot());
+        let result = f(&mut unwinder);
+        let previous = core::mem::replace(
+            &mut *self.current.write().unwrap_or_else(PoisonError::into_inner),
+            Arc::new(unwinder),
+        );
+        // Snapshots which are still in use keep their unwinder alive. Otherwise, it's
+        // dropped here, after the write lock was released.
+        drop(previous);
+        result
+    }
+
+    /// Add a module, see [`Unwinder::add_module`].
+    pub fn add_module(&self, module: U::Module) {
+        self.update(|unwinder| unwinder.add_module(module))
+    }
+
+    /// Add a module after checking its unwind information, see
+    /// [`Unwinder::try_add_module`]. If this fails, the modules stay the same.
+    pub fn try_add_module(&self, module: U::Module) -> Result<(), AddModuleError> {
+        self.update(|unwinder| unwinder.try_add_module(module))
+    }
+
+    /// Remove the module whose address range starts at `module_avma_range_start`, see
+    /// [`Unwinder::remove_module`].
+    pub fn remove_module(&self, module_avma_range_start: u64) {
+        self.update(|unwinder| unwinder.remove_module(module_avma_range_start))
+    }
+}
+
+impl<U: Unwinder + Default> Default for SharedUnwinder<U> {
+    fn default() -> Self {
+        Self::new(U::default())
+    }
+}
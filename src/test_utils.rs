@@ -0,0 +1,341 @@
+//! Helpers for unit-testing code which uses framehop, with synthetic unwind information
+//! instead of real binaries. Requires the `test-util` feature.
+//!
+//! [`EhFrameBuilder`] writes a synthetic `.eh_frame` section with the unwind rules you
+//! need, [`module_with_eh_frame`] wraps it in a [`Module`], and [`FakeMemory`] serves
+//! the stack values which the rules read.
+//!
+//! ```
+//! use framehop::test_utils::{module_with_eh_frame, CfiInstruction, EhFrameBuilder, FakeMemory};
+//! use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+//! use framehop::{FrameAddress, Unwinder};
+//!
+//! // A function at 0x100..0x120 in the module which pushes rbp in its first byte.
+//! let mut builder = EhFrameBuilder::x86_64();
+//! builder.add_fde(
+//!     0x100..0x120,
+//!     &[
+//!         CfiInstruction::AdvanceLoc(1),
+//!         CfiInstruction::DefCfaOffset(16),
+//!         CfiInstruction::Offset { register: 6, offset: -16 }, // rbp
+//!     ],
+//! );
+//! let mut unwinder = UnwinderX86_64::new();
+//! unwinder.add_module(module_with_eh_frame("lib", 0x10000..0x11000, builder.build()));
+//!
+//! let mut stack: FakeMemory = [(0x20, 0x30), (0x28, 0x10800)].into_iter().collect();
+//! let mut regs = UnwindRegsX86_64::new(0x10104, 0x20, 0x1234);
+//! let mut cache = CacheX86_64::<_>::new();
+//! let res = unwinder.unwind_frame(
+//!     FrameAddress::from_instruction_pointer(0x10104),
+//!     &mut regs,
+//!     &mut cache,
+//!     &mut stack,
+//! );
+//! assert_eq!(res, Ok(Some(0x10800)));
+//! assert_eq!((regs.sp(), regs.bp()), (0x30, 0x30));
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::string::ToString;
+use std::vec::Vec;
+
+use crate::memory::{MemoryAccess, ReadMemError, ReadMemErrorKind};
+use crate::unwinder::{ExplicitModuleSectionInfo, Module};
+
+/// A call frame instruction for [`EhFrameBuilder`]. Registers are DWARF register
+/// numbers, e.g. 7 for rsp, 6 for rbp and 16 for rip on x86_64, or 31 for sp, 29 for fp
+/// and 30 for lr on aarch64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfiInstruction {
+    /// `DW_CFA_advance_loc4`: the following instructions apply from `delta` bytes after
+    /// the current location.
+    AdvanceLoc(u32),
+    /// `DW_CFA_def_cfa`: CFA = `register` + `offset`.
+    DefCfa { register: u16, offset: u64 },
+    /// `DW_CFA_def_cfa_offset`: keep the CFA register, CFA = register + the given offset.
+    DefCfaOffset(u64),
+    /// `DW_CFA_def_cfa_expression`: CFA = the result of the given DWARF expression, e.g.
+    /// `&[0x77, 0x10]` (`DW_OP_breg7 16`) for rsp + 16 on x86_64.
+    DefCfaExpression(&'static [u8]),
+    /// `DW_CFA_offset_extended_sf`: `register` is saved at CFA + `offset`.
+    Offset { register: u16, offset: i64 },
+    /// `DW_CFA_val_offset_sf`: the value of `register` is CFA + `offset`.
+    ValOffset { register: u16, offset: i64 },
+    /// `DW_CFA_same_value`: `register` is unchanged from the callee.
+    SameValue(u16),
+    /// `DW_CFA_undefined`: `register` can't be recovered.
+    Undefined(u16),
+    /// `DW_CFA_remember_state`: push the current rules onto the state stack.
+    RememberState,
+    /// `DW_CFA_restore_state`: pop the rules from the state stack.
+    RestoreState,
+}
+
+impl CfiInstruction {
+    fn write(&self, out: &mut Vec<u8>) {
+        match *self {
+            CfiInstruction::AdvanceLoc(delta) => {
+                out.push(0x04);
+                out.extend_from_slice(&delta.to_le_bytes());
+            }
+            CfiInstruction::DefCfa { register, offset } => {
+                out.push(0x0c);
+                write_uleb128(out, register.into());
+                write_uleb128(out, offset);
+            }
+            CfiInstruction::DefCfaOffset(offset) => {
+                out.push(0x0e);
+                write_uleb128(out, offset);
+            }
+            CfiInstruction::DefCfaExpression(expression) => {
+                out.push(0x0f);
+                write_uleb128(out, expression.len() as u64);
+                out.extend_from_slice(expression);
+            }
+            CfiInstruction::Offset { register, offset } => {
+                out.push(0x11);
+                write_uleb128(out, register.into());
+                write_sleb128(out, offset);
+            }
+            CfiInstruction::ValOffset { register, offset } => {
+                out.push(0x15);
+                write_uleb128(out, register.into());
+                write_sleb128(out, offset);
+            }
+            CfiInstruction::SameValue(register) => {
+                out.push(0x08);
+                write_uleb128(out, register.into());
+            }
+            CfiInstruction::Undefined(register) => {
+                out.push(0x07);
+                write_uleb128(out, register.into());
+            }
+            CfiInstruction::RememberState => out.push(0x0a),
+            CfiInstruction::RestoreState => out.push(0x0b),
+        }
+    }
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let sign_bit_clear = byte & 0x40 == 0;
+        if (value == 0 && sign_bit_clear) || (value == -1 && !sign_bit_clear) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Builds a little-endian `.eh_frame` section for a 64-bit module, with one CIE and one
+/// FDE per function.
+///
+/// The code and data alignment factors are 1, so all offsets in [`CfiInstruction`] are
+/// in bytes. FDE addresses are relative to the start of the module, which matches the
+/// module created by [`module_with_eh_frame`].
+#[derive(Debug, Clone)]
+pub struct EhFrameBuilder {
+    return_address_register: u8,
+    cie_instructions: Vec<CfiInstruction>,
+    fdes: Vec<(Range<u64>, Vec<CfiInstruction>)>,
+}
+
+impl EhFrameBuilder {
+    /// Create a builder whose CIE sets up the initial rules with `cie_instructions`.
+    pub fn new(return_address_register: u8, cie_instructions: &[CfiInstruction]) -> Self {
+        Self {
+            return_address_register,
+            cie_instructions: cie_instructions.to_vec(),
+            fdes: Vec::new(),
+        }
+    }
+
+    /// A builder with the initial rules at the start of an x86_64 function:
+    /// `CFA=rsp+8: rip=[CFA-8]`.
+    pub fn x86_64() -> Self {
+        Self::new(
+            16,
+            &[
+                CfiInstruction::DefCfa {
+                    register: 7,
+                    offset: 8,
+                },
+                CfiInstruction::Offset {
+                    register: 16,
+                    offset: -8,
+                },
+            ],
+        )
+    }
+
+    /// A builder with the initial rules at the start of an aarch64 function: `CFA=sp`,
+    /// and the return address is in lr.
+    pub fn aarch64() -> Self {
+        Self::new(
+            30,
+            &[CfiInstruction::DefCfa {
+                register: 31,
+                offset: 0,
+            }],
+        )
+    }
+
+    /// Add an FDE for the function at `address_range`. `instructions` are applied after
+    /// the CIE's instructions, starting at the beginning of the function.
+    pub fn add_fde(&mut self, address_range: Range<u64>, instructions: &[CfiInstruction]) {
+        self.fdes.push((address_range, instructions.to_vec()));
+    }
+
+    /// Write the `.eh_frame` section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut cie = Vec::new();
+        cie.extend_from_slice(&0u32.to_le_bytes()); // CIE id
+        cie.push(1); // version
+        cie.extend_from_slice(b"zR\0"); // augmentation
+        write_uleb128(&mut cie, 1); // code alignment factor
+        write_sleb128(&mut cie, 1); // data alignment factor
+        cie.push(self.return_address_register);
+        cie.push(1); // augmentation data length
+        cie.push(0x00); // FDE pointer encoding: DW_EH_PE_absptr
+        for instruction in &self.cie_instructions {
+            instruction.write(&mut cie);
+        }
+
+        let mut eh_frame = Vec::new();
+        write_entry(&mut eh_frame, cie);
+        for (address_range, instructions) in &self.fdes {
+            let mut fde = Vec::new();
+            let cie_pointer = eh_frame.len() as u32 + 4;
+            fde.extend_from_slice(&cie_pointer.to_le_bytes());
+            fde.extend_from_slice(&address_range.start.to_le_bytes());
+            let len = address_range.end.saturating_sub(address_range.start);
+            fde.extend_from_slice(&len.to_le_bytes());
+            fde.push(0); // augmentation data length
+            for instruction in instructions {
+                instruction.write(&mut fde);
+            }
+            write_entry(&mut eh_frame, fde);
+        }
+        eh_frame.extend_from_slice(&0u32.to_le_bytes()); // terminator
+        eh_frame
+    }
+}
+
+/// Append a CIE or FDE with its length, padded with `DW_CFA_nop` to a multiple of 8 bytes.
+fn write_entry(eh_frame: &mut Vec<u8>, mut entry: Vec<u8>) {
+    while !(entry.len() + 4).is_multiple_of(8) {
+        entry.push(0); // DW_CFA_nop
+    }
+    eh_frame.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(&entry);
+}
+
+/// Create a module at `avma_range` whose only unwind information is `eh_frame`, for
+/// example from [`EhFrameBuilder::build`]. Addresses in the section are relative to
+/// `avma_range.start`.
+pub fn module_with_eh_frame(
+    name: &str,
+    avma_range: Range<u64>,
+    eh_frame: Vec<u8>,
+) -> Module<Vec<u8>> {
+    // The section's address only matters for pc-relative pointers, which the builder
+    // doesn't use. Put it after the code, as a linker would.
+    let module_size = avma_range.end - avma_range.start;
+    let eh_frame_svma = module_size..module_size + eh_frame.len() as u64;
+    let base_avma = avma_range.start;
+    Module::new(
+        name.to_string(),
+        avma_range,
+        base_avma,
+        ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(eh_frame_svma),
+            eh_frame: Some(eh_frame),
+            ..Default::default()
+        },
+    )
+}
+
+/// A [`MemoryAccess`] implementation backed by a map from addresses to 8-byte values.
+///
+/// Reads at addresses which have no value fail with [`ReadMemErrorKind::Unavailable`].
+/// `read_u32` returns the low 4 bytes of the value at the address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FakeMemory {
+    values: HashMap<u64, u64>,
+}
+
+impl FakeMemory {
+    /// Create an empty memory, where every read fails.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the 8-byte value at `addr`.
+    pub fn set(&mut self, addr: u64, value: u64) {
+        self.values.insert(addr, value);
+    }
+
+    /// The value at `addr`, if it was set.
+    pub fn get(&self, addr: u64) -> Option<u64> {
+        self.values.get(&addr).copied()
+    }
+}
+
+impl FromIterator<(u64, u64)> for FakeMemory {
+    fn from_iter<I: IntoIterator<Item = (u64, u64)>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl MemoryAccess for FakeMemory {
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        self.get(addr)
+            .ok_or(ReadMemError::new(addr, ReadMemErrorKind::Unavailable))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        let mut out = Vec::new();
+        write_uleb128(&mut out, 624485);
+        write_sleb128(&mut out, -123456);
+        write_sleb128(&mut out, 63);
+        write_sleb128(&mut out, 64);
+        assert_eq!(out, [0xe5, 0x8e, 0x26, 0xc0, 0xbb, 0x78, 0x3f, 0xc0, 0x00]);
+    }
+
+    #[test]
+    fn test_fake_memory() {
+        let mut memory = FakeMemory::new();
+        memory.set(0x10, 0x1122334455667788);
+        assert_eq!(memory.read_u64(0x10), Ok(0x1122334455667788));
+        assert_eq!(memory.read_u32(0x10), Ok(0x55667788));
+        assert_eq!(
+            memory.read_u64(0x18),
+            Err(ReadMemError::new(0x18, ReadMemErrorKind::Unavailable))
+        );
+    }
+}
@@ -1,16 +1,26 @@
 use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
 
-pub trait UnwindRule: Copy + core::fmt::Debug {
+// Only implemented and called inside this crate, which doesn't need `Send` bounds on the
+// futures.
+#[allow(async_fn_in_trait)]
+pub trait UnwindRule: Copy + core::fmt::Debug + PartialEq {
     type UnwindRegs;
 
-    fn exec<F>(
+    /// Unwind the frame with this rule. The returned future only waits while it reads
+    /// from `read_stack`.
+    async fn exec<F>(
         self,
         is_first_frame: bool,
         regs: &mut Self::UnwindRegs,
         read_stack: &mut F,
     ) -> Result<Option<u64>, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>;
+        F: AsyncMemoryAccess;
+
+    /// The registers whose current values `exec` reads.
+    fn used_registers(&self) -> RegisterSet;
 
     fn rule_for_stub_functions() -> Self;
     fn rule_for_function_start() -> Self;
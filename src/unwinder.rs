@@ -1,14 +1,21 @@
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use fallible_iterator::FallibleIterator;
-use gimli::{EndianSlice, LittleEndian};
+use gimli::{DebugFrame, EhFrame, EhFrameHdr, EndianSlice, RunTimeEndian};
 
 use crate::arch::Arch;
-use crate::cache::{AllocationPolicy, Cache};
-use crate::dwarf::{DwarfCfiIndex, DwarfUnwinder, DwarfUnwinding, UnwindSectionType};
-use crate::error::{Error, UnwinderError};
+use crate::cache::{AllocationPolicy, Cache, NoModuleRange};
+use crate::diagnostics::diagnostic;
+use crate::dwarf::{
+    contains_cie, fde_encoding, fde_offset_from_eh_frame_hdr, parse_eh_frame_hdr,
+    unwind_rules_by_linear_scan, DwarfCfiIndex, DwarfCfiIndexError, DwarfUnwinder, DwarfUnwinding,
+    FdeEncoding, TranslatedRules, UnwindSectionType,
+};
+use crate::error::{AddModuleError, Error, UnwinderError};
 use crate::instruction_analysis::InstructionAnalysis;
+use crate::memory::{complete_sync, AsyncMemoryAccess, MemoryAccess, SyncMemory};
 
 #[cfg(feature = "macho")]
 use crate::macho::{
@@ -16,7 +23,8 @@ use crate::macho::{
 };
 #[cfg(feature = "pe")]
 use crate::pe::{DataAtRvaRange, PeUnwinding};
-use crate::rule_cache::CacheResult;
+use crate::rule_cache::{CacheHandle, CacheResult};
+use crate::rule_table::UnwindRuleTable;
 use crate::unwind_result::UnwindResult;
 use crate::unwind_rule::UnwindRule;
 use crate::FrameAddress;
@@ -45,13 +53,75 @@ pub trait Unwinder: Clone {
     /// information and address ranges.
     ///
     /// This should be called whenever a new module is loaded into the process.
+    ///
+    /// Existing modules whose address range overlaps with the new module's range are
+    /// removed, because the new module has most likely been mapped over them. This is
+    /// the same as calling [`Unwinder::add_module_with_policy`] with
+    /// [`AddModulePolicy::ReplaceOverlapping`].
     fn add_module(&mut self, module: Self::Module);
 
+    /// Add a module, and decide what should happen if its address range overlaps with
+    /// the address range of existing modules.
+    ///
+    /// Returns [`Error::OverlappingModule`] if an overlap was found and the policy is
+    /// [`AddModulePolicy::RejectOverlapping`]. In that case the module is not added.
+    fn add_module_with_policy(
+        &mut self,
+        module: Self::Module,
+        policy: AddModulePolicy,
+    ) -> Result<(), Error>;
+
+    /// Add a module like [`Unwinder::add_module`], but check its DWARF unwind data first,
+    /// so that broken unwind information is reported now instead of making unwinding fail
+    /// later.
+    ///
+    /// This checks that the FDE index could be built when the module was created, that
+    /// `.eh_frame_hdr` can be parsed, and that the `.eh_frame` or `.debug_frame` section
+    /// contains a readable CIE. If a check fails, the module is not added. The unwind
+    /// information of a module created with `Module::new_lazy` is loaded by this call.
+    fn try_add_module(&mut self, module: Self::Module) -> Result<(), AddModuleError>;
+
     /// Remove a module that was added before using `add_module`, keyed by the start
     /// address of that module's address range. If no match is found, the call is ignored.
     /// This should be called whenever a module is unloaded from the process.
     fn remove_module(&mut self, module_avma_range_start: u64);
 
+    /// Remove all modules, for example when the process has called `exec` or when the
+    /// unwinder state is reset.
+    fn clear_modules(&mut self);
+
+    /// The modules which are currently known to the unwinder, sorted by the start of
+    /// their address range.
+    fn modules(&self) -> &[Self::Module];
+
+    /// Find the module whose address range contains `address`.
+    ///
+    /// Address ranges which were added with `add_module_with_rules` or
+    /// `add_framepointer_range` don't have a [`Module`] and are not returned here, see
+    /// [`Unwinder::covers_address`].
+    fn module_for_address(&self, address: u64) -> Option<&Self::Module>;
+
+    /// Returns true if `address` is in the address range of a module or of a range with
+    /// precomputed or frame pointer rules, i.e. if unwinding a frame at this address
+    /// won't fail with [`Error::NoModuleForAddress`].
+    ///
+    /// This can be used to skip samples in unknown code without attempting to unwind
+    /// them.
+    fn covers_address(&self, address: u64) -> bool;
+
+    /// Find the offset of the DWARF FDE which covers `address`, for debugging.
+    ///
+    /// The offset is relative to the start of the module's `.eh_frame` or `.debug_frame`
+    /// section, so it can be compared with the output of `readelf --debug-dump=frames`.
+    /// Returns `None` if no module contains the address, if the module has no DWARF CFI
+    /// index or `.eh_frame_hdr`, or if the lookup didn't find an FDE.
+    fn debug_fde_offset(&self, address: u64) -> Option<u32>;
+
+    /// Find the encoding of the DWARF FDE which covers `address`, i.e. the version of its
+    /// CIE, the address size and whether it uses the 64-bit DWARF format, for debugging.
+    /// Returns `None` in the same cases as [`Unwinder::debug_fde_offset`].
+    fn debug_fde_encoding(&self, address: u64) -> Option<FdeEncoding>;
+
     /// Returns the highest code address that is known in this process based on the module
     /// address ranges. Returns 0 if no modules have been added.
     ///
@@ -70,7 +140,128 @@ pub trait Unwinder: Clone {
         read_stack: &mut F,
     ) -> Result<Option<u64>, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>;
+        F: MemoryAccess,
+    {
+        let outcome = self.unwind_frame_detailed(address, regs, cache, read_stack)?;
+        Ok(outcome.next_pc)
+    }
+
+    /// Unwind a single frame like [`Unwinder::unwind_frame`], and also report where the
+    /// unwind rule for this frame came from, for example to measure the effectiveness of
+    /// the cache for individual samples.
+    fn unwind_frame_detailed<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess;
+
+    /// Unwind a single frame like [`Unwinder::unwind_frame`], with the unwind information
+    /// of `module`, which must contain the address. This skips the lookup of the module
+    /// by address, for callers who already know the module, for example because they
+    /// unwind samples which are sorted by address.
+    ///
+    /// `module` should be one of the modules of this unwinder, see
+    /// [`Unwinder::modules`], because the unwind rule which is computed from its unwind
+    /// information is cached for the address. If the address is outside the module's
+    /// address range, [`Error::NoModuleForAddress`] is returned.
+    fn unwind_frame_in_module<F>(
+        &self,
+        module: &Self::Module,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: MemoryAccess;
+
+    /// Compute the unwind rules for `addresses` and store them in `cache`, so that
+    /// unwinding frames at these addresses later hits the cache, for example for the
+    /// hottest functions of a previous profiling run.
+    ///
+    /// This only looks up the module and its unwind information; no stack memory is
+    /// read. Addresses whose unwind information can't be expressed as a cacheable rule,
+    /// for example because it contains DWARF expressions, or which aren't covered by
+    /// any module, count as failed. Precomputing doesn't change the cache statistics or
+    /// the recorded FDE evaluations, which only count the frames that were unwound.
+    fn precompute_rules(
+        &self,
+        addresses: &[FrameAddress],
+        cache: &mut Self::Cache,
+    ) -> PrecomputedRules;
+
+    /// Unwind a single frame like [`Unwinder::unwind_frame`], and also return the
+    /// canonical frame address (CFA) which was computed for this frame.
+    ///
+    /// The CFA is the value of the stack pointer in the caller, i.e. the stack pointer
+    /// value just before the call instruction. It can be used to compute the stack depth
+    /// of each frame, or to detect stack corruption.
+    fn unwind_frame_with_cfa<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<UnwoundFrame, Error>
+    where
+        F: MemoryAccess;
+
+    /// Unwind a single frame like [`Unwinder::unwind_frame_detailed`], and also compute
+    /// the return address with the frame pointer rule, for finding bugs in unwind
+    /// information.
+    ///
+    /// `regs` are updated with the result of the regular unwinding. If the frame
+    /// pointer rule succeeds and finds a different return address, it is reported in
+    /// [`CheckedUnwindFrameOutcome::disagreement`]. This is only meaningful for code
+    /// which maintains frame pointers. Even then, the first frame can legitimately
+    /// disagree if it is interrupted in a function prologue or epilogue, or in a leaf
+    /// function which doesn't set up a frame pointer.
+    fn unwind_frame_checked<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<CheckedUnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess;
+
+    /// Unwind a single frame like [`Unwinder::unwind_frame`], but only update `regs` if
+    /// unwinding succeeds. If an error is returned, `regs` is left untouched.
+    ///
+    /// `unwind_frame` can leave `regs` partially updated when it fails. All register
+    /// types are `Copy`, so callers which want to try an unwind and roll it back, for
+    /// example because the recovered return address doesn't look plausible, can also
+    /// save a copy of the registers before calling `unwind_frame` and restore it
+    /// afterwards:
+    ///
+    /// ```ignore
+    /// let saved_regs = regs;
+    /// let return_address = unwinder.unwind_frame(address, &mut regs, &mut cache, &mut read_stack)?;
+    /// if !is_plausible(return_address) {
+    ///     regs = saved_regs;
+    /// }
+    /// ```
+    fn try_unwind_frame<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        Self::UnwindRegs: Clone,
+        F: MemoryAccess,
+    {
+        let mut new_regs = regs.clone();
+        let return_address = self.unwind_frame(address, &mut new_regs, cache, read_stack)?;
+        *regs = new_regs;
+        Ok(return_address)
+    }
 
     /// Return an iterator that unwinds frame by frame until the end of the stack is found.
     fn iter_frames<'u, 'c, 'r, F>(
@@ -81,16 +272,274 @@ pub trait Unwinder: Clone {
         read_stack: &'r mut F,
     ) -> UnwindIterator<'u, 'c, 'r, Self, F>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: MemoryAccess,
     {
         UnwindIterator::new(self, pc, regs, cache, read_stack)
     }
+
+    /// Return an iterator like [`iter_frames`](Unwinder::iter_frames), which starts at
+    /// `address` instead of at an instruction pointer.
+    ///
+    /// This is useful to resume unwinding from a saved position in the middle of the
+    /// stack: pass the saved return address as [`FrameAddress::ReturnAddress`], together
+    /// with the register values of that frame, so that it is looked up like any other
+    /// return address, i.e. with the call instruction before it.
+    fn iter_frames_from<'u, 'c, 'r, F>(
+        &'u self,
+        address: FrameAddress,
+        regs: Self::UnwindRegs,
+        cache: &'c mut Self::Cache,
+        read_stack: &'r mut F,
+    ) -> UnwindIterator<'u, 'c, 'r, Self, F>
+    where
+        F: MemoryAccess,
+    {
+        UnwindIterator::new_from(self, address, regs, cache, read_stack)
+    }
+
+    /// Return an iterator which resumes unwinding from a state captured in the middle of
+    /// the stack, e.g. the pc, sp and frame pointer of an outer frame which were saved
+    /// during cooperative sampling.
+    ///
+    /// `pc` is treated as a return address: it is looked up as `pc - 1`, so that it's
+    /// attributed to the call instruction before it. `regs` are the register values of
+    /// that frame. The first item of the iterator is the frame at `pc`, and if `pc` is
+    /// zero, the iterator is empty. This is the same as [`iter_frames_from`](Unwinder::iter_frames_from)
+    /// with [`FrameAddress::from_return_address`].
+    ///
+    /// Use [`iter_frames`](Unwinder::iter_frames) instead if the state is from the
+    /// innermost frame, whose pc is the exact address of the next instruction.
+    fn resume_unwind<'u, 'c, 'r, F>(
+        &'u self,
+        pc: u64,
+        regs: Self::UnwindRegs,
+        cache: &'c mut Self::Cache,
+        read_stack: &'r mut F,
+    ) -> UnwindIterator<'u, 'c, 'r, Self, F>
+    where
+        F: MemoryAccess,
+    {
+        UnwindIterator::new_resumed(self, pc, regs, cache, read_stack)
+    }
+
+    /// Unwind the stack like [`iter_frames`](Unwinder::iter_frames), and write the
+    /// addresses of the frames to `out` instead of returning them one by one.
+    ///
+    /// The first address is `pc`, and the following ones are return addresses. This
+    /// doesn't allocate, so together with a [`MustNotAllocateDuringUnwind`](crate::MustNotAllocateDuringUnwind)
+    /// cache, the whole stack can be unwound without allocating. Unwinding stops when
+    /// `out` is full; if the stack has more frames, the outcome is truncated.
+    fn unwind_stack_into<F>(
+        &self,
+        pc: u64,
+        regs: Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+        out: &mut [u64],
+    ) -> UnwindStackOutcome
+    where
+        F: MemoryAccess,
+    {
+        let mut iter = self.iter_frames(pc, regs, cache, read_stack);
+        let mut frame_count = 0;
+        let error = loop {
+            match iter.next() {
+                Ok(Some(frame)) => {
+                    let Some(slot) = out.get_mut(frame_count) else {
+                        break Some(Error::FrameLimitReached);
+                    };
+                    *slot = frame.address();
+                    frame_count += 1;
+                }
+                Ok(None) => break None,
+                Err(err) => break Some(err),
+            }
+        };
+        UnwindStackOutcome { frame_count, error }
+    }
+
+    /// Unwind many samples with the same cache, for example when post-processing a
+    /// recorded profile.
+    ///
+    /// Each sample is the pc and the register values of its innermost frame, and all
+    /// samples read their stack memory from `read_stack`. Returns the frames of each
+    /// sample in the order of `samples`, as collected from
+    /// [`iter_frames`](Unwinder::iter_frames), together with the error which ended the
+    /// unwinding, like [`UnwindStackOutcome::error`]. If unwinding a sample fails, its
+    /// frames end with the last frame which could be unwound.
+    ///
+    /// The samples are unwound in the order of their pc, so that samples in the same
+    /// function are unwound one after the other and find their rules in the cache.
+    fn unwind_samples<F>(
+        &self,
+        samples: &[(u64, Self::UnwindRegs)],
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Vec<(Vec<FrameAddress>, Option<Error>)>
+    where
+        Self::UnwindRegs: Clone,
+        F: MemoryAccess,
+    {
+        let mut order: Vec<usize> = (0..samples.len()).collect();
+        order.sort_by_key(|&i| samples[i].0);
+        let mut stacks = vec![(Vec::new(), None); samples.len()];
+        for i in order {
+            let (pc, regs) = &samples[i];
+            let (frames, error) = &mut stacks[i];
+            let mut iter = self.iter_frames(*pc, regs.clone(), cache, read_stack);
+            *error = loop {
+                match iter.next() {
+                    Ok(Some(frame)) => frames.push(frame),
+                    Ok(None) => break None,
+                    Err(err) => break Some(err),
+                }
+            };
+        }
+        stacks
+    }
+}
+
+/// What [`Unwinder::add_module_with_policy`] should do when the new module's address range
+/// overlaps with the address range of a module that was added before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddModulePolicy {
+    /// Remove the existing overlapping modules, so that the last added module wins.
+    /// This is useful if a library was unmapped and another one was mapped at an
+    /// overlapping address before `remove_module` was called for the old one.
+    #[default]
+    ReplaceOverlapping,
+    /// Don't add the module and return [`Error::OverlappingModule`].
+    RejectOverlapping,
+    /// Keep all modules. Lookups for addresses in the overlapping range may find
+    /// either module.
+    AllowOverlapping,
+}
+
+/// Which unwind information to try first for modules which have both `__unwind_info`
+/// and `__eh_frame`, i.e. mach-O modules on macOS and iOS. See for example
+/// [`UnwinderX86_64::set_unwind_source_preference`](crate::x86_64::UnwinderX86_64::set_unwind_source_preference).
+///
+/// If the preferred source doesn't cover an address, or fails to unwind it, the other
+/// source is tried. Frames which were unwound with the other source are reported as
+/// [`RuleSource::DwarfFallback`] or [`RuleSource::CompactUnwindFallback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnwindSourcePreference {
+    /// Use `__unwind_info`, and the FDEs in `__eh_frame` which it refers to. This is
+    /// faster, because `__unwind_info` is a compact binary search table.
+    #[default]
+    PreferCompactUnwind,
+    /// Look up the address in `__eh_frame` first. DWARF CFI is more precise for some
+    /// functions, but looking up an address needs an index of all FDEs, which is built
+    /// when it's first needed.
+    PreferDwarf,
+}
+
+/// The result of [`Unwinder::unwind_frame_detailed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnwindFrameOutcome {
+    /// The return address of the frame, or `None` if the root of the stack was reached.
+    /// This is the same value that [`Unwinder::unwind_frame`] returns.
+    pub next_pc: Option<u64>,
+    /// Where the unwind rule for this frame came from.
+    pub source: RuleSource,
+    /// True if `next_pc` is the instruction pointer of interrupted code rather than a
+    /// return address, for example for a [`RuleSource::SignalFrame`]. The next frame
+    /// must then be unwound with [`FrameAddress::from_instruction_pointer`].
+    pub next_is_instruction_pointer: bool,
+}
+
+/// The result of [`Unwinder::unwind_stack_into`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwindStackOutcome {
+    /// The number of addresses which were written to the start of the buffer.
+    pub frame_count: usize,
+    /// `Some` if unwinding stopped before the root of the stack was reached: the error
+    /// which ended it, or [`Error::FrameLimitReached`] if the buffer was full.
+    pub error: Option<Error>,
+}
+
+impl UnwindStackOutcome {
+    /// Returns true if the stack had more frames than fit into the buffer.
+    pub fn is_truncated(&self) -> bool {
+        self.error == Some(Error::FrameLimitReached)
+    }
+}
+
+/// The result of [`Unwinder::unwind_frame_checked`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckedUnwindFrameOutcome {
+    /// The result of the regular unwinding, as returned by
+    /// [`Unwinder::unwind_frame_detailed`].
+    pub outcome: UnwindFrameOutcome,
+    /// `Some((return_address, frame_pointer_return_address))` if unwinding with the frame
+    /// pointer rule found a different return address. If the regular unwinding reached
+    /// the end of the stack, `return_address` is zero.
+    pub disagreement: Option<(u64, u64)>,
+}
+
+/// The result of [`Unwinder::precompute_rules`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrecomputedRules {
+    /// The number of addresses whose rule is now in the cache.
+    pub cached: usize,
+    /// The number of addresses for which no cacheable rule could be computed.
+    pub failed: usize,
+}
+
+/// Where the unwind rule for a frame came from, see [`UnwindFrameOutcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuleSource {
+    /// The rule was found in the unwinder cache, so no unwind information was consulted.
+    Cached,
+    /// The rule was computed from DWARF CFI, in `.eh_frame` or `.debug_frame`.
+    Dwarf,
+    /// The rule was computed from Apple's compact unwind info in `__unwind_info`.
+    CompactUnwind,
+    /// The rule was computed from DWARF CFI in `__eh_frame`, because `__unwind_info`
+    /// didn't cover the address or couldn't be used. See [`UnwindSourcePreference`].
+    DwarfFallback,
+    /// The rule was computed from `__unwind_info`, because [`UnwindSourcePreference::PreferDwarf`]
+    /// was set and `__eh_frame` didn't cover the address or couldn't be used.
+    CompactUnwindFallback,
+    /// The rule was computed from PE unwind info in `.pdata` and `.xdata`.
+    Pe,
+    /// The rule was found in a table of precomputed rules, which was added with
+    /// `add_module_with_rules`.
+    Precomputed,
+    /// The rule was found by analyzing the instructions around the address of the first
+    /// frame, see [`Module::with_prologue_analysis`].
+    InstructionAnalysis,
+    /// No usable unwind information was found, or it couldn't be used, so the frame was
+    /// unwound with the frame pointer based fallback rule.
+    FramePointer,
+    /// The frame was recognized as a signal return trampoline, and the registers were
+    /// restored from the signal frame on the stack.
+    SignalFrame,
+    /// The address isn't in any module, and the frame was unwound with the frame pointer
+    /// because recovery was enabled, see for example
+    /// [`UnwinderX86_64::set_recover_with_frame_pointer_on_missing_info`](crate::x86_64::UnwinderX86_64::set_recover_with_frame_pointer_on_missing_info).
+    /// The return address is less reliable than for the other sources, because nothing
+    /// is known about the code at the address.
+    FramePointerRecovery,
+}
+
+/// The result of [`Unwinder::unwind_frame_with_cfa`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnwoundFrame {
+    /// The return address of the frame, or `None` if the root of the stack was reached.
+    pub return_address: Option<u64>,
+    /// The canonical frame address, which is also the caller's stack pointer value.
+    /// If `return_address` is `None`, this is the stack pointer value of the unwound frame.
+    pub cfa: u64,
+    /// True if `return_address` is the instruction pointer of interrupted code, see
+    /// [`UnwindFrameOutcome::next_is_instruction_pointer`].
+    pub next_is_instruction_pointer: bool,
 }
 
 /// An iterator for unwinding the entire stack, starting from the initial register values.
 ///
-/// The first yielded frame is the instruction pointer. Subsequent addresses are return
-/// addresses.
+/// The first yielded frame is the instruction pointer, or the address which was given to
+/// [`Unwinder::iter_frames_from`]. Subsequent addresses are return addresses.
 ///
 /// This iterator attempts to detect if stack unwinding completed successfully, or if the
 /// stack was truncated prematurely. If it thinks that it successfully found the root
@@ -100,28 +549,72 @@ pub trait Unwinder: Clone {
 /// this iterator as a `FallibleIterator`, because you might lose the entire stack if the
 /// last iteration returns `Err(...)`.
 ///
+/// The iterator can also be used in a `for` loop, because it implements [`IntoIterator`]
+/// with `Result<FrameAddress, Error>` items. This takes care of passing the right
+/// [`FrameAddress`] kind to [`Unwinder::unwind_frame`] and stops after the first error.
+///
+/// An error doesn't discard the frames which were found before it: if unwinding the
+/// third frame fails, the items are `[Ok(frame0), Ok(frame1), Ok(frame2), Err(error)]`,
+/// and the iterator is done after the error. Profilers can keep the `Ok` frames as a
+/// truncated stack, and use the error to record why it was truncated.
+///
 /// Lifetimes:
 ///
 ///  - `'u`: The lifetime of the [`Unwinder`].
 ///  - `'c`: The lifetime of the unwinder cache.
 ///  - `'r`: The lifetime of the exclusive access to the `read_stack` callback.
-pub struct UnwindIterator<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> {
+pub struct UnwindIterator<'u, 'c, 'r, U: Unwinder, F: MemoryAccess> {
     unwinder: &'u U,
     state: UnwindIteratorState,
     regs: U::UnwindRegs,
     cache: &'c mut U::Cache,
     read_stack: &'r mut F,
+    frame_count: usize,
+    max_frames: Option<usize>,
+    cycle_detector: CycleDetector,
+}
+
+/// Detects if the same (return address, CFA) pair comes up again, using Brent's
+/// algorithm: The current pair is compared to a checkpoint, and the checkpoint is moved
+/// forward after 1, 2, 4, 8, ... frames. This finds cycles of any length without
+/// remembering all frames.
+struct CycleDetector {
+    checkpoint: Option<(u64, u64)>,
+    steps_since_checkpoint: usize,
+    steps_until_next_checkpoint: usize,
+}
+
+impl CycleDetector {
+    fn new() -> Self {
+        Self {
+            checkpoint: None,
+            steps_since_checkpoint: 0,
+            steps_until_next_checkpoint: 1,
+        }
+    }
+
+    /// Returns true if this frame has been seen before.
+    fn check(&mut self, frame: (u64, u64)) -> bool {
+        if self.checkpoint == Some(frame) {
+            return true;
+        }
+        self.steps_since_checkpoint += 1;
+        if self.steps_since_checkpoint == self.steps_until_next_checkpoint {
+            self.checkpoint = Some(frame);
+            self.steps_since_checkpoint = 0;
+            self.steps_until_next_checkpoint = self.steps_until_next_checkpoint.saturating_mul(2);
+        }
+        false
+    }
 }
 
 enum UnwindIteratorState {
-    Initial(u64),
+    Initial(FrameAddress),
     Unwinding(FrameAddress),
     Done,
 }
 
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
-    UnwindIterator<'u, 'c, 'r, U, F>
-{
+impl<'u, 'c, 'r, U: Unwinder, F: MemoryAccess> UnwindIterator<'u, 'c, 'r, U, F> {
     /// Create a new iterator. You'd usually use [`Unwinder::iter_frames`] instead.
     pub fn new(
         unwinder: &'u U,
@@ -129,46 +622,127 @@ impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
         regs: U::UnwindRegs,
         cache: &'c mut U::Cache,
         read_stack: &'r mut F,
+    ) -> Self {
+        Self::new_from(
+            unwinder,
+            FrameAddress::from_instruction_pointer(pc),
+            regs,
+            cache,
+            read_stack,
+        )
+    }
+
+    /// Create a new iterator whose first frame is `address`. You'd usually use
+    /// [`Unwinder::iter_frames_from`] instead.
+    pub fn new_from(
+        unwinder: &'u U,
+        address: FrameAddress,
+        regs: U::UnwindRegs,
+        cache: &'c mut U::Cache,
+        read_stack: &'r mut F,
     ) -> Self {
         Self {
             unwinder,
-            state: UnwindIteratorState::Initial(pc),
+            state: UnwindIteratorState::Initial(address),
             regs,
             cache,
             read_stack,
+            frame_count: 0,
+            max_frames: None,
+            cycle_detector: CycleDetector::new(),
         }
     }
+
+    /// Create a new iterator whose first frame is the return address `pc`, or an empty
+    /// iterator if `pc` is zero. You'd usually use [`Unwinder::resume_unwind`] instead.
+    pub fn new_resumed(
+        unwinder: &'u U,
+        pc: u64,
+        regs: U::UnwindRegs,
+        cache: &'c mut U::Cache,
+        read_stack: &'r mut F,
+    ) -> Self {
+        let mut iter = Self::new(unwinder, pc, regs, cache, read_stack);
+        iter.state = match FrameAddress::from_return_address(pc) {
+            Some(address) => UnwindIteratorState::Initial(address),
+            None => UnwindIteratorState::Done,
+        };
+        iter
+    }
+
+    /// Stop after `max_frames` frames, including the first frame. If the stack is deeper
+    /// than that, the next call to `next()` returns [`Error::FrameLimitReached`].
+    ///
+    /// This protects against very deep or endless unwinding with bad unwind information
+    /// which slips through the other sanity checks.
+    pub fn with_limit(mut self, max_frames: usize) -> Self {
+        self.max_frames = Some(max_frames);
+        self
+    }
 }
 
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
-    UnwindIterator<'u, 'c, 'r, U, F>
-{
+impl<'u, 'c, 'r, U: Unwinder, F: MemoryAccess> UnwindIterator<'u, 'c, 'r, U, F> {
     /// Yield the next frame in the stack.
     ///
-    /// The first frame is `Ok(Some(FrameAddress::InstructionPointer(...)))`.
+    /// The first frame is `Ok(Some(FrameAddress::InstructionPointer(...)))`, or the
+    /// address which was given to [`Unwinder::iter_frames_from`].
     /// Subsequent frames are `Ok(Some(FrameAddress::ReturnAddress(...)))`.
     ///
     /// If a root function has been reached, this iterator completes with `Ok(None)`.
     /// Otherwise it completes with `Err(...)`, usually indicating that a certain stack
-    /// address could not be read.
+    /// address could not be read. After `Err(...)`, all calls return `Ok(None)`.
+    ///
+    /// If the same return address and stack pointer come up a second time, unwinding is
+    /// stuck in a cycle and [`Error::CycleDetected`] is returned.
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<FrameAddress>, Error> {
+        if matches!(self.state, UnwindIteratorState::Done) {
+            return Ok(None);
+        }
+        if self.max_frames.is_some_and(|max| self.frame_count >= max) {
+            self.state = UnwindIteratorState::Done;
+            return Err(Error::FrameLimitReached);
+        }
         let next = match self.state {
-            UnwindIteratorState::Initial(pc) => {
-                self.state = UnwindIteratorState::Unwinding(FrameAddress::InstructionPointer(pc));
-                return Ok(Some(FrameAddress::InstructionPointer(pc)));
+            UnwindIteratorState::Initial(address) => {
+                self.state = UnwindIteratorState::Unwinding(address);
+                self.frame_count += 1;
+                return Ok(Some(address));
             }
             UnwindIteratorState::Unwinding(address) => {
-                self.unwinder
-                    .unwind_frame(address, &mut self.regs, self.cache, self.read_stack)?
+                match self.unwinder.unwind_frame_with_cfa(
+                    address,
+                    &mut self.regs,
+                    self.cache,
+                    self.read_stack,
+                ) {
+                    Ok(next) => next,
+                    Err(err) => {
+                        self.state = UnwindIteratorState::Done;
+                        return Err(err);
+                    }
+                }
             }
             UnwindIteratorState::Done => return Ok(None),
         };
-        match next {
+        match next.return_address {
             Some(return_address) => {
-                let return_address = FrameAddress::from_return_address(return_address)
-                    .ok_or(Error::ReturnAddressIsNull)?;
+                if self.cycle_detector.check((return_address, next.cfa)) {
+                    self.state = UnwindIteratorState::Done;
+                    return Err(Error::CycleDetected);
+                }
+                let return_address = if next.next_is_instruction_pointer {
+                    FrameAddress::from_instruction_pointer(return_address)
+                } else {
+                    let Some(return_address) = FrameAddress::from_return_address(return_address)
+                    else {
+                        self.state = UnwindIteratorState::Done;
+                        return Err(Error::ReturnAddressIsNull);
+                    };
+                    return_address
+                };
                 self.state = UnwindIteratorState::Unwinding(return_address);
+                self.frame_count += 1;
                 Ok(Some(return_address))
             }
             None => {
@@ -179,7 +753,7 @@ impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
     }
 }
 
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> FallibleIterator
+impl<'u, 'c, 'r, U: Unwinder, F: MemoryAccess> FallibleIterator
     for UnwindIterator<'u, 'c, 'r, U, F>
 {
     type Item = FrameAddress;
@@ -190,6 +764,15 @@ impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> Fallibl
     }
 }
 
+impl<'u, 'c, 'r, U: Unwinder, F: MemoryAccess> IntoIterator for UnwindIterator<'u, 'c, 'r, U, F> {
+    type Item = Result<FrameAddress, Error>;
+    type IntoIter = fallible_iterator::Iterator<Self>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iterator()
+    }
+}
+
 /// This global generation counter makes it so that the cache can be shared
 /// between multiple unwinders.
 /// This is a u16, so if you make it wrap around by adding / removing modules
@@ -222,52 +805,308 @@ cfg_if::cfg_if! {
     }
 }
 
-pub struct UnwinderInternal<D, A, P> {
+/// Steps which an architecture's unwinder adds to every frame that [`UnwinderInternal`]
+/// unwinds. `()` adds nothing.
+pub trait FrameHooks<A: Arch> {
+    /// Unwind the frame at `address` without looking up a rule, if it needs special
+    /// handling, for example because it's a signal frame. Returns `None` for regular
+    /// frames.
+    async fn unwind_special_frame<F: AsyncMemoryAccess>(
+        &self,
+        _address: FrameAddress,
+        _regs: &mut A::UnwindRegs,
+        _read_stack: &mut F,
+    ) -> Option<Result<UnwindFrameOutcome, Error>> {
+        None
+    }
+
+    /// Adjust the registers and return address which unwinding a frame produced, before
+    /// they're checked for the end of the stack and validated.
+    fn fix_up_unwound_frame(&self, _regs: &mut A::UnwindRegs, next_pc: Option<u64>) -> Option<u64> {
+        next_pc
+    }
+}
+
+impl<A: Arch> FrameHooks<A> for () {}
+
+pub struct UnwinderInternal<D, A: Arch, P> {
     /// sorted by avma_range.start
     modules: Vec<Module<D>>,
-    /// Incremented every time modules is changed.
+    /// Modules with precomputed unwind rules, sorted by avma_range.start.
+    rule_modules: Vec<RuleModule<A::UnwindRule>>,
+    /// Incremented every time modules or rule_modules is changed.
     modules_generation: u16,
+    /// Return addresses which mark the end of the stack, sorted.
+    end_of_stack_return_addresses: Vec<u64>,
+    /// Address ranges of functions which mark the end of the stack when a return address
+    /// points into them. Sorted and not overlapping.
+    end_of_stack_functions: Vec<Range<u64>>,
+    /// Return addresses below this address mark the end of the stack.
+    end_of_stack_below_address: u64,
+    unwind_source_preference: UnwindSourcePreference,
+    recover_with_frame_pointer_on_missing_info: bool,
     _arch: PhantomData<A>,
     _allocation_policy: PhantomData<P>,
 }
 
-impl<D, A, P> Default for UnwinderInternal<D, A, P> {
+/// A module which was added with `add_module_with_rules`.
+struct RuleModule<R> {
+    avma_range: Range<u64>,
+    rules: Arc<UnwindRuleTable<R>>,
+}
+
+/// The result of looking up the rule for a frame without reading unwind information.
+enum RuleLookup<'a, R, D> {
+    /// Unwind the frame with this rule.
+    Rule(R, RuleSource),
+    /// The address isn't in any module.
+    NoModule,
+    /// The rule needs to be computed from the unwind information of this module, at this
+    /// relative address. The cache handle is `None` if the rule cache is bypassed.
+    InModule(&'a Module<D>, u32, Option<CacheHandle>),
+}
+
+impl<R> Clone for RuleModule<R> {
+    fn clone(&self) -> Self {
+        Self {
+            avma_range: self.avma_range.clone(),
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+impl<D, A: Arch, P> Default for UnwinderInternal<D, A, P> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<D, A, P> Clone for UnwinderInternal<D, A, P> {
+impl<D, A: Arch, P> Clone for UnwinderInternal<D, A, P> {
     fn clone(&self) -> Self {
         Self {
             modules: self.modules.clone(),
+            rule_modules: self.rule_modules.clone(),
             modules_generation: self.modules_generation,
+            end_of_stack_return_addresses: self.end_of_stack_return_addresses.clone(),
+            end_of_stack_functions: self.end_of_stack_functions.clone(),
+            end_of_stack_below_address: self.end_of_stack_below_address,
+            unwind_source_preference: self.unwind_source_preference,
+            recover_with_frame_pointer_on_missing_info: self
+                .recover_with_frame_pointer_on_missing_info,
             _arch: PhantomData,
             _allocation_policy: PhantomData,
         }
     }
 }
 
-impl<D, A, P> UnwinderInternal<D, A, P> {
+impl<D, A: Arch, P> UnwinderInternal<D, A, P> {
     pub fn new() -> Self {
         Self {
             modules: Vec::new(),
+            rule_modules: Vec::new(),
             modules_generation: next_global_modules_generation(),
+            end_of_stack_return_addresses: Vec::new(),
+            end_of_stack_functions: Vec::new(),
+            end_of_stack_below_address: 0,
+            unwind_source_preference: UnwindSourcePreference::default(),
+            recover_with_frame_pointer_on_missing_info: false,
             _arch: PhantomData,
             _allocation_policy: PhantomData,
         }
     }
+
+    pub fn add_module_with_rules(
+        &mut self,
+        avma_range: Range<u64>,
+        rules: UnwindRuleTable<A::UnwindRule>,
+    ) {
+        self.rule_modules.retain(|m| {
+            m.avma_range.start >= avma_range.end || avma_range.start >= m.avma_range.end
+        });
+        let insertion_index = self
+            .rule_modules
+            .partition_point(|m| m.avma_range.start < avma_range.start);
+        self.rule_modules.insert(
+            insertion_index,
+            RuleModule {
+                avma_range,
+                rules: Arc::new(rules),
+            },
+        );
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn set_end_of_stack_return_addresses(&mut self, mut addresses: Vec<u64>) {
+        addresses.sort_unstable();
+        addresses.dedup();
+        self.end_of_stack_return_addresses = addresses;
+    }
+
+    pub fn set_unwind_source_preference(&mut self, preference: UnwindSourcePreference) {
+        self.unwind_source_preference = preference;
+        // Cached rules may have come from the other source.
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn set_recover_with_frame_pointer_on_missing_info(&mut self, recover: bool) {
+        self.recover_with_frame_pointer_on_missing_info = recover;
+    }
+
+    pub fn set_end_of_stack_functions(&mut self, mut function_ranges: Vec<Range<u64>>) {
+        function_ranges.retain(|range| !range.is_empty());
+        function_ranges.sort_unstable_by_key(|range| range.start);
+        let mut merged: Vec<Range<u64>> = Vec::with_capacity(function_ranges.len());
+        for range in function_ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        self.end_of_stack_functions = merged;
+    }
+
+    pub async fn unwind_frame_with_rule<F: AsyncMemoryAccess>(
+        &self,
+        rule: A::UnwindRule,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<Option<u64>, Error> {
+        let return_address = match hooks.unwind_special_frame(address, regs, read_stack).await {
+            Some(outcome) => outcome?.next_pc,
+            None => A::exec_rule(rule, address.is_instruction_pointer(), regs, read_stack).await?,
+        };
+        let return_address = hooks.fix_up_unwound_frame(regs, return_address);
+        Ok(return_address.filter(|ra| !self.is_end_of_stack(*ra, regs)))
+    }
+
+    pub fn set_end_of_stack_below_address(&mut self, address: u64) {
+        self.end_of_stack_below_address = address;
+    }
+
+    /// Returns true if the frame with `return_address` and the unwound `regs` is past the
+    /// end of the stack, so that unwinding should stop.
+    fn is_end_of_stack(&self, return_address: u64, regs: &A::UnwindRegs) -> bool {
+        A::is_past_stack_end(regs) || self.is_end_of_stack_return_address(return_address)
+    }
+
+    fn is_end_of_stack_return_address(&self, return_address: u64) -> bool {
+        if return_address < self.end_of_stack_below_address {
+            return true;
+        }
+        if self
+            .end_of_stack_return_addresses
+            .binary_search(&return_address)
+            .is_ok()
+        {
+            return true;
+        }
+        // Look up the call instruction, like for return addresses in modules.
+        let Some(lookup_address) = return_address.checked_sub(1) else {
+            return false;
+        };
+        let index = self
+            .end_of_stack_functions
+            .partition_point(|range| range.start <= lookup_address);
+        index > 0 && lookup_address < self.end_of_stack_functions[index - 1].end
+    }
+
+    pub fn add_framepointer_range(&mut self, avma_range: Range<u64>) {
+        // A rule module without rules uses the fallback rule for its entire range.
+        self.add_module_with_rules(avma_range, UnwindRuleTable::new(Vec::new()));
+    }
 }
 
 impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInternal<D, A, P> {
     pub fn add_module(&mut self, module: Module<D>) {
+        let _ = self.add_module_with_policy(module, AddModulePolicy::ReplaceOverlapping);
+    }
+
+    pub fn try_add_module(&mut self, module: Module<D>) -> Result<(), AddModuleError> {
+        Self::validate_module(&module)?;
+        self.add_module(module);
+        Ok(())
+    }
+
+    /// Check that the DWARF unwind data of `module` can be parsed.
+    fn validate_module(module: &Module<D>) -> Result<(), AddModuleError> {
+        let unwind_info = module.unwind_info();
+        if let Some(e) = unwind_info.index_error {
+            return Err(AddModuleError::FdeIndex(e));
+        }
+        if let ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
+            eh_frame_hdr,
+            base_addresses,
+            ..
+        } = &unwind_info.data
+        {
+            EhFrameHdr::new(&eh_frame_hdr[..], unwind_info.endian)
+                .parse(base_addresses, A::ADDRESS_SIZE)
+                .map_err(AddModuleError::EhFrameHdr)?;
+        }
+        let Some((data, base_addresses, section_type)) = Self::dwarf_section(module) else {
+            return Ok(());
+        };
+        let data = EndianSlice::new(&data[..], unwind_info.endian);
+        let has_cie = match section_type {
+            UnwindSectionType::EhFrame => {
+                let mut section = EhFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                contains_cie(&section, base_addresses)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut section = DebugFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                contains_cie(&section, base_addresses)
+            }
+        };
+        match has_cie {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(AddModuleError::NoCie),
+            Err(e) => Err(AddModuleError::Cie(e)),
+        }
+    }
+
+    pub fn add_module_with_policy(
+        &mut self,
+        module: Module<D>,
+        policy: AddModulePolicy,
+    ) -> Result<(), Error> {
+        let new_range = module.avma_range.clone();
+        let overlaps = |m: &Module<D>| {
+            m.avma_range.start < new_range.end && new_range.start < m.avma_range.end
+        };
+        match policy {
+            AddModulePolicy::ReplaceOverlapping => {
+                self.modules.retain(|m| {
+                    let overlapping = overlaps(m);
+                    if overlapping {
+                        diagnostic!(
+                            "Module {} at 0x{:x} overlaps with new module {}, removing it",
+                            m.name,
+                            m.avma_range.start,
+                            module.name
+                        );
+                    }
+                    !overlapping
+                });
+            }
+            AddModulePolicy::RejectOverlapping => {
+                if let Some(m) = self.modules.iter().find(|m| overlaps(m)) {
+                    return Err(Error::OverlappingModule(m.avma_range.start));
+                }
+            }
+            AddModulePolicy::AllowOverlapping => {}
+        }
         let insertion_index = match self
             .modules
             .binary_search_by_key(&module.avma_range.start, |module| module.avma_range.start)
         {
             Ok(i) => {
-                #[cfg(feature = "std")]
-                eprintln!(
+                diagnostic!(
                     "Now we have two modules at the same start address 0x{:x}. This can't be good.",
                     module.avma_range.start
                 );
@@ -276,7 +1115,10 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
             Err(i) => i,
         };
         self.modules.insert(insertion_index, module);
+        // Changing the generation also invalidates all cached rules, including the ones
+        // for addresses in the range of the removed modules.
         self.modules_generation = next_global_modules_generation();
+        Ok(())
     }
 
     pub fn remove_module(&mut self, module_address_range_start: u64) {
@@ -289,10 +1131,189 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
             self.modules.remove(index);
             self.modules_generation = next_global_modules_generation();
         };
+        if let Ok(index) = self
+            .rule_modules
+            .binary_search_by_key(&module_address_range_start, |m| m.avma_range.start)
+        {
+            self.rule_modules.remove(index);
+            self.modules_generation = next_global_modules_generation();
+        };
+    }
+
+    pub fn clear_modules(&mut self) {
+        self.modules.clear();
+        self.rule_modules.clear();
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn modules(&self) -> &[Module<D>] {
+        &self.modules
+    }
+
+    pub fn module_for_address(&self, address: u64) -> Option<&Module<D>> {
+        let (module_index, _) = self.find_module_for_address(address)?;
+        Some(&self.modules[module_index])
+    }
+
+    pub fn covers_address(&self, address: u64) -> bool {
+        self.rule_module_for_address(address).is_some()
+            || self.find_module_for_address(address).is_some()
     }
 
     pub fn max_known_code_address(&self) -> u64 {
-        self.modules.last().map_or(0, |m| m.avma_range.end)
+        let modules_end = self.modules.last().map_or(0, |m| m.avma_range.end);
+        let rule_modules_end = self.rule_modules.last().map_or(0, |m| m.avma_range.end);
+        modules_end.max(rule_modules_end)
+    }
+
+    fn rule_module_for_address(&self, address: u64) -> Option<&RuleModule<A::UnwindRule>> {
+        let index = self
+            .rule_modules
+            .partition_point(|m| m.avma_range.start <= address)
+            .checked_sub(1)?;
+        let rule_module = &self.rule_modules[index];
+        rule_module
+            .avma_range
+            .contains(&address)
+            .then_some(rule_module)
+    }
+
+    /// The module's DWARF CFI section, its base addresses, and which kind of section it is.
+    fn dwarf_section(
+        module: &Module<D>,
+    ) -> Option<(&D, &crate::dwarf::BaseAddresses, UnwindSectionType)> {
+        match &module.unwind_info().data {
+            #[cfg(feature = "macho")]
+            ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame {
+                eh_frame: Some(eh_frame),
+                base_addresses,
+                ..
+            } => Some((eh_frame, base_addresses, UnwindSectionType::EhFrame)),
+            ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
+                eh_frame,
+                base_addresses,
+                ..
+            }
+            | ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame {
+                eh_frame,
+                base_addresses,
+                ..
+            } => Some((eh_frame, base_addresses, UnwindSectionType::EhFrame)),
+            ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame {
+                debug_frame,
+                base_addresses,
+                ..
+            } => Some((debug_frame, base_addresses, UnwindSectionType::DebugFrame)),
+            _ => None,
+        }
+    }
+
+    /// The unwind rules which the DWARF CFI of `module` translates into, with the AVMA
+    /// ranges they apply to.
+    pub fn dwarf_rules(module: &Module<D>) -> TranslatedRules<A::UnwindRule> {
+        let Some((data, base_addresses, section_type)) = Self::dwarf_section(module) else {
+            return Vec::new();
+        };
+        let data = EndianSlice::new(&data[..], module.unwind_info().endian);
+        let mut rules = match section_type {
+            UnwindSectionType::EhFrame => {
+                let mut section = EhFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                unwind_rules_by_linear_scan::<A, _, _>(&section, base_addresses)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut section = DebugFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                unwind_rules_by_linear_scan::<A, _, _>(&section, base_addresses)
+            }
+        };
+        for (range, _) in &mut rules {
+            *range = module.svma_to_avma(range.start)..module.svma_to_avma(range.end);
+        }
+        rules
+    }
+
+    pub fn debug_fde_offset(&self, address: u64) -> Option<u32> {
+        let (module_index, rel_address) = self.find_module_for_address(address)?;
+        let module = &self.modules[module_index];
+        match &module.unwind_info().data {
+            ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
+                eh_frame_hdr,
+                base_addresses,
+                ..
+            } => {
+                let hdr = parse_eh_frame_hdr(
+                    &eh_frame_hdr[..],
+                    module.unwind_info().endian,
+                    base_addresses,
+                    A::ADDRESS_SIZE,
+                )?;
+                let lookup_svma = module.unwind_info().base_svma + u64::from(rel_address);
+                fde_offset_from_eh_frame_hdr(&hdr, base_addresses, lookup_svma)
+                    .or_else(|| Self::fde_offset_without_eh_frame_hdr(module, rel_address))
+            }
+            ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame { index, .. }
+            | ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame { index, .. } => {
+                index.fde_offset_for_relative_address(rel_address)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn debug_fde_encoding(&self, address: u64) -> Option<FdeEncoding> {
+        let fde_offset = self.debug_fde_offset(address)?;
+        let (module_index, _) = self.find_module_for_address(address)?;
+        let module = &self.modules[module_index];
+        let (data, base_addresses, section_type) = Self::dwarf_section(module)?;
+        let data = EndianSlice::new(&data[..], module.unwind_info().endian);
+        let encoding = match section_type {
+            UnwindSectionType::EhFrame => {
+                let mut section = EhFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                fde_encoding(&section, base_addresses, fde_offset)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut section = DebugFrame::from(data);
+                section.set_address_size(A::ADDRESS_SIZE);
+                section.set_vendor(A::VENDOR);
+                fde_encoding(&section, base_addresses, fde_offset)
+            }
+        };
+        encoding.map(FdeEncoding::from)
+    }
+
+    /// The range between the modules around `address`, for an address for which
+    /// `find_module_for_address` returned `None`.
+    fn no_module_range_around_address(&self, address: u64) -> Range<u64> {
+        let next_index = self
+            .modules
+            .partition_point(|m| m.avma_range.start <= address);
+        let mut start = match next_index.checked_sub(1) {
+            Some(i) if self.modules[i].avma_range.end > address => {
+                // The address is inside this module, but the module's base address is
+                // unusable for it. Only remember this address.
+                return address..address.saturating_add(1);
+            }
+            Some(i) => self.modules[i].avma_range.end,
+            None => 0,
+        };
+        let mut end = self
+            .modules
+            .get(next_index)
+            .map_or(u64::MAX, |m| m.avma_range.start);
+        // The address is not in a module with precomputed rules either, so these can
+        // only narrow down the range.
+        for rule_module in &self.rule_modules {
+            if rule_module.avma_range.end <= address {
+                start = start.max(rule_module.avma_range.end);
+            } else if rule_module.avma_range.start > address {
+                end = end.min(rule_module.avma_range.start);
+            }
+        }
+        start..end
     }
 
     fn find_module_for_address(&self, address: u64) -> Option<(usize, u32)> {
@@ -323,90 +1344,662 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
         Some((module_index, relative_address))
     }
 
-    fn with_cache<F, G>(
+    /// Unwind a frame with the rule which [`lookup_rule`](Self::lookup_rule) found, or
+    /// compute the rule from the module's unwind information.
+    async fn unwind_frame_with_lookup<F>(
         &self,
+        lookup: RuleLookup<'_, A::UnwindRule, D>,
         address: FrameAddress,
         regs: &mut A::UnwindRegs,
         cache: &mut Cache<A::UnwindRule, P>,
         read_stack: &mut F,
-        callback: G,
-    ) -> Result<Option<u64>, Error>
+    ) -> Result<UnwindFrameOutcome, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
-        G: FnOnce(
-            &Module<D>,
-            FrameAddress,
-            u32,
-            &mut A::UnwindRegs,
-            &mut Cache<A::UnwindRule, P>,
-            &mut F,
-        ) -> Result<UnwindResult<A::UnwindRule>, UnwinderError>,
+        F: AsyncMemoryAccess,
     {
-        let lookup_address = address.address_for_lookup();
-        let is_first_frame = !address.is_return_address();
-        let cache_handle = match cache
-            .rule_cache
-            .lookup(lookup_address, self.modules_generation)
-        {
-            CacheResult::Hit(unwind_rule) => {
-                return unwind_rule.exec(is_first_frame, regs, read_stack);
+        match lookup {
+            RuleLookup::Rule(unwind_rule, source) => {
+                let next_pc = A::exec_rule(
+                    unwind_rule,
+                    address.is_instruction_pointer(),
+                    regs,
+                    read_stack,
+                )
+                .await?;
+                Ok(UnwindFrameOutcome {
+                    next_pc,
+                    source,
+                    next_is_instruction_pointer: false,
+                })
             }
-            CacheResult::Miss(handle) => handle,
-        };
-
-        let unwind_rule = match self.find_module_for_address(lookup_address) {
-            None => A::UnwindRule::fallback_rule(),
-            Some((module_index, relative_lookup_address)) => {
-                let module = &self.modules[module_index];
-                match callback(
+            RuleLookup::NoModule => {
+                self.unwind_frame_without_module(address, regs, read_stack)
+                    .await
+            }
+            RuleLookup::InModule(module, relative_lookup_address, cache_handle) => {
+                self.unwind_frame_with_unwind_info(
                     module,
                     address,
                     relative_lookup_address,
+                    cache_handle,
                     regs,
                     cache,
                     read_stack,
-                ) {
-                    Ok(UnwindResult::ExecRule(rule)) => rule,
-                    Ok(UnwindResult::Uncacheable(return_address)) => {
-                        return Ok(Some(return_address))
-                    }
-                    Err(_err) => {
-                        // eprintln!("Unwinder error: {}", err);
-                        A::UnwindRule::fallback_rule()
+                )
+                .await
+            }
+        }
+    }
+
+    /// Like [`unwind_frame_with_lookup`](Self::unwind_frame_with_lookup), for a
+    /// synchronous `read_stack`. Found rules are executed without creating the future
+    /// which can evaluate unwind information, because that future is large and
+    /// cached rules are the common case.
+    fn unwind_frame_with_lookup_sync<F>(
+        &self,
+        lookup: RuleLookup<'_, A::UnwindRule, D>,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess,
+    {
+        let read_stack = &mut SyncMemory(read_stack);
+        match lookup {
+            RuleLookup::Rule(unwind_rule, source) => {
+                let next_pc = complete_sync(A::exec_rule(
+                    unwind_rule,
+                    address.is_instruction_pointer(),
+                    regs,
+                    read_stack,
+                ))?;
+                Ok(UnwindFrameOutcome {
+                    next_pc,
+                    source,
+                    next_is_instruction_pointer: false,
+                })
+            }
+            lookup => complete_sync(
+                self.unwind_frame_with_lookup(lookup, address, regs, cache, read_stack),
+            ),
+        }
+    }
+
+    /// Find the rule for `address` in the cache or in the rule modules, without reading
+    /// any unwind information. If `known_module` is `None`, the module is looked up by
+    /// address.
+    fn lookup_rule<'a>(
+        &'a self,
+        address: FrameAddress,
+        known_module: Option<&'a Module<D>>,
+        regs: &A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+    ) -> Result<RuleLookup<'a, A::UnwindRule, D>, Error> {
+        let lookup_address = address.address_for_lookup();
+        let known_module = match known_module {
+            Some(module) => {
+                let relative_lookup_address = module
+                    .avma_range
+                    .contains(&lookup_address)
+                    .then(|| lookup_address.checked_sub(module.base_avma))
+                    .flatten()
+                    .and_then(|address| u32::try_from(address).ok())
+                    .ok_or(Error::NoModuleForAddress(address.address()))?;
+                Some((module, relative_lookup_address))
+            }
+            None => {
+                if let Some(no_module) = &cache.no_module_range {
+                    if no_module.modules_generation == self.modules_generation
+                        && no_module.range.contains(&lookup_address)
+                    {
+                        return Ok(RuleLookup::NoModule);
                     }
                 }
+                None
+            }
+        };
+        let cache_handle = if A::bypasses_rule_cache(regs) {
+            None
+        } else {
+            match cache
+                .rule_cache
+                .lookup(lookup_address, self.modules_generation)
+            {
+                CacheResult::Hit(unwind_rule) => {
+                    return Ok(RuleLookup::Rule(unwind_rule, RuleSource::Cached));
+                }
+                CacheResult::Miss(handle) => Some(handle),
             }
         };
-        cache.rule_cache.insert(cache_handle, unwind_rule);
-        unwind_rule.exec(is_first_frame, regs, read_stack)
+
+        let rule_module = match known_module {
+            Some(_) => None,
+            None => self.rule_module_for_address(lookup_address),
+        };
+        if let Some(rule_module) = rule_module {
+            let relative_address = u32::try_from(lookup_address - rule_module.avma_range.start);
+            let (unwind_rule, source) = match relative_address
+                .ok()
+                .and_then(|a| rule_module.rules.lookup(a))
+            {
+                Some(rule) => (rule, RuleSource::Precomputed),
+                None => (A::UnwindRule::fallback_rule(), RuleSource::FramePointer),
+            };
+            if let Some(cache_handle) = cache_handle {
+                cache.rule_cache.insert(cache_handle, unwind_rule);
+            }
+            return Ok(RuleLookup::Rule(unwind_rule, source));
+        }
+
+        let module_and_address = known_module.or_else(|| {
+            let (module_index, relative_lookup_address) =
+                self.find_module_for_address(lookup_address)?;
+            Some((&self.modules[module_index], relative_lookup_address))
+        });
+        match module_and_address {
+            None => {
+                cache.no_module_range = Some(NoModuleRange {
+                    modules_generation: self.modules_generation,
+                    range: self.no_module_range_around_address(lookup_address),
+                });
+                Ok(RuleLookup::NoModule)
+            }
+            Some((module, relative_lookup_address)) => Ok(RuleLookup::InModule(
+                module,
+                relative_lookup_address,
+                cache_handle,
+            )),
+        }
+    }
+
+    /// Unwind a frame in `module` with a rule computed from the module's unwind
+    /// information, and cache the rule if possible.
+    #[allow(clippy::too_many_arguments)]
+    async fn unwind_frame_with_unwind_info<F>(
+        &self,
+        module: &Module<D>,
+        address: FrameAddress,
+        relative_lookup_address: u32,
+        cache_handle: Option<CacheHandle>,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let lookup_address = address.address_for_lookup();
+        let is_first_frame = address.is_instruction_pointer();
+        let result = Self::unwind_frame_impl(
+            module,
+            address,
+            relative_lookup_address,
+            self.unwind_source_preference,
+            regs,
+            cache,
+            read_stack,
+        )
+        .await;
+        let (unwind_rule, source, dwarf_failure) = match result {
+            Ok((UnwindResult::ExecRule(rule), source)) => (rule, source, None),
+            Ok((UnwindResult::Uncacheable(return_address), source)) => {
+                // A null return address marks the end of the stack.
+                return Ok(UnwindFrameOutcome {
+                    next_pc: Some(return_address).filter(|ra| *ra != 0),
+                    source,
+                    next_is_instruction_pointer: false,
+                });
+            }
+            Err(err) => {
+                diagnostic!(
+                    "Unwinding 0x{:x} in module {} failed, using the fallback rule: {}",
+                    lookup_address,
+                    module.name,
+                    err
+                );
+                let dwarf_failure = match err {
+                    UnwinderError::Dwarf(err) => Some((&module.name, err)),
+                    _ => None,
+                };
+                (
+                    A::UnwindRule::fallback_rule(),
+                    RuleSource::FramePointer,
+                    dwarf_failure,
+                )
+            }
+        };
+        if is_first_frame && source == RuleSource::FramePointer {
+            if let Some(rule) =
+                Self::rule_from_instruction_analysis(module, relative_lookup_address)
+            {
+                // The rule may only be valid for the first frame, so don't cache it.
+                let next_pc = A::exec_rule(rule, is_first_frame, regs, read_stack).await?;
+                return Ok(UnwindFrameOutcome {
+                    next_pc,
+                    source: RuleSource::InstructionAnalysis,
+                    next_is_instruction_pointer: false,
+                });
+            }
+        }
+        let Some((module_name, dwarf_error)) = dwarf_failure else {
+            if let Some(cache_handle) = cache_handle {
+                cache.rule_cache.insert(cache_handle, unwind_rule);
+            }
+            let next_pc = A::exec_rule(unwind_rule, is_first_frame, regs, read_stack).await?;
+            return Ok(UnwindFrameOutcome {
+                next_pc,
+                source,
+                next_is_instruction_pointer: false,
+            });
+        };
+        // The module's CFI couldn't be evaluated here. The fallback rule isn't cached, so
+        // that the error names the module on every unwind at this address, not just the
+        // first. Unknown registers are the caller's doing, so that error is kept.
+        let next_pc = A::exec_rule(unwind_rule, is_first_frame, regs, read_stack)
+            .await
+            .map_err(|err| match err {
+                Error::MissingRegisterForRule(_) => err,
+                _ => Error::DwarfInModule {
+                    module: module_name.clone(),
+                    source: dwarf_error,
+                },
+            })?;
+        Ok(UnwindFrameOutcome {
+            next_pc,
+            source,
+            next_is_instruction_pointer: false,
+        })
+    }
+
+    pub async fn unwind_frame_detailed<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let saved_regs = regs.clone();
+        if let Some(outcome) = hooks.unwind_special_frame(address, regs, read_stack).await {
+            return self.finish_outcome(outcome?, regs, saved_regs, hooks);
+        }
+        let lookup = self.lookup_rule(address, None, regs, cache)?;
+        let outcome = self
+            .unwind_frame_with_lookup(lookup, address, regs, cache, read_stack)
+            .await?;
+        self.finish_outcome(outcome, regs, saved_regs, hooks)
+    }
+
+    /// Like [`unwind_frame_detailed`](Self::unwind_frame_detailed), for a synchronous
+    /// `read_stack`.
+    pub fn unwind_frame_detailed_sync<F: MemoryAccess>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<UnwindFrameOutcome, Error> {
+        self.unwind_frame_sync(address, None, regs, cache, read_stack, hooks)
+    }
+
+    pub async fn unwind_frame_checked<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<CheckedUnwindFrameOutcome, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let mut framepointer_regs = regs.clone();
+        let outcome = self
+            .unwind_frame_detailed(address, regs, cache, read_stack, hooks)
+            .await?;
+        let framepointer_result = A::exec_rule(
+            A::UnwindRule::fallback_rule(),
+            address.is_instruction_pointer(),
+            &mut framepointer_regs,
+            read_stack,
+        )
+        .await
+        .map(|return_address| hooks.fix_up_unwound_frame(&mut framepointer_regs, return_address));
+        let disagreement = match framepointer_result {
+            Ok(Some(framepointer_return_address))
+                if outcome.next_pc != Some(framepointer_return_address) =>
+            {
+                Some((outcome.next_pc.unwrap_or(0), framepointer_return_address))
+            }
+            _ => None,
+        };
+        Ok(CheckedUnwindFrameOutcome {
+            outcome,
+            disagreement,
+        })
+    }
+
+    pub fn unwind_frame_in_module<F: MemoryAccess>(
+        &self,
+        module: &Module<D>,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<UnwindFrameOutcome, Error> {
+        self.unwind_frame_sync(address, Some(module), regs, cache, read_stack, hooks)
+    }
+
+    /// Unwind a frame with a synchronous `read_stack`, in `known_module` if it's given.
+    fn unwind_frame_sync<F: MemoryAccess>(
+        &self,
+        address: FrameAddress,
+        known_module: Option<&Module<D>>,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<A::UnwindRule, P>,
+        read_stack: &mut F,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<UnwindFrameOutcome, Error> {
+        let saved_regs = regs.clone();
+        if let Some(outcome) =
+            complete_sync(hooks.unwind_special_frame(address, regs, &mut SyncMemory(read_stack)))
+        {
+            return self.finish_outcome(outcome?, regs, saved_regs, hooks);
+        }
+        let lookup = self.lookup_rule(address, known_module, regs, cache)?;
+        let outcome =
+            self.unwind_frame_with_lookup_sync(lookup, address, regs, cache, read_stack)?;
+        self.finish_outcome(outcome, regs, saved_regs, hooks)
+    }
+
+    /// Unwind a frame whose address isn't in any module: with the frame pointer if
+    /// recovery is enabled, otherwise this fails with [`Error::NoModuleForAddress`]. The
+    /// rule isn't cached, so that addresses outside of modules don't take up cache slots.
+    async fn unwind_frame_without_module<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        if !self.recover_with_frame_pointer_on_missing_info {
+            return Err(Error::NoModuleForAddress(address.address()));
+        }
+        let next_pc = A::exec_rule(
+            A::UnwindRule::fallback_rule(),
+            address.is_instruction_pointer(),
+            regs,
+            read_stack,
+        )
+        .await?;
+        Ok(UnwindFrameOutcome {
+            next_pc,
+            source: RuleSource::FramePointerRecovery,
+            next_is_instruction_pointer: false,
+        })
+    }
+
+    /// Check the unwound frame for the end of the stack and validate its return address.
+    /// If the return address is invalid, `regs` are reset to `saved_regs`, their values
+    /// before the frame was unwound.
+    fn finish_outcome(
+        &self,
+        mut outcome: UnwindFrameOutcome,
+        regs: &mut A::UnwindRegs,
+        saved_regs: A::UnwindRegs,
+        hooks: &impl FrameHooks<A>,
+    ) -> Result<UnwindFrameOutcome, Error> {
+        outcome.next_pc = hooks.fix_up_unwound_frame(regs, outcome.next_pc);
+        if let Some(return_address) = outcome.next_pc {
+            if self.is_end_of_stack(return_address, regs) {
+                outcome.next_pc = None;
+            } else if !outcome.next_is_instruction_pointer {
+                if let Err(err) = self.validate_return_address(return_address) {
+                    *regs = saved_regs;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    pub fn precompute_rules(
+        &self,
+        addresses: &[FrameAddress],
+        cache: &mut Cache<A::UnwindRule, P>,
+    ) -> PrecomputedRules {
+        let stats = cache.rule_cache.stats();
+        let recording = core::mem::replace(&mut cache.fde_evaluations.recording, false);
+        let mut result = PrecomputedRules::default();
+        for address in addresses {
+            if self.precompute_rule(*address, cache) {
+                result.cached += 1;
+            } else {
+                result.failed += 1;
+            }
+        }
+        cache.rule_cache.set_stats(stats);
+        cache.fde_evaluations.recording = recording;
+        result
+    }
+
+    /// Put the rule for `address` into the cache. Returns false if there is no
+    /// cacheable rule for the address.
+    fn precompute_rule(&self, address: FrameAddress, cache: &mut Cache<A::UnwindRule, P>) -> bool {
+        let lookup_address = address.address_for_lookup();
+        let cache_handle = match cache
+            .rule_cache
+            .lookup(lookup_address, self.modules_generation)
+        {
+            CacheResult::Hit(_) => return true,
+            CacheResult::Miss(handle) => handle,
+        };
+        let unwind_rule = if let Some(rule_module) = self.rule_module_for_address(lookup_address) {
+            u32::try_from(lookup_address - rule_module.avma_range.start)
+                .ok()
+                .and_then(|a| rule_module.rules.lookup(a))
+                .unwrap_or_else(A::UnwindRule::fallback_rule)
+        } else {
+            let Some((module_index, relative_lookup_address)) =
+                self.find_module_for_address(lookup_address)
+            else {
+                return false;
+            };
+            // Rules which can be cached are computed without looking at register values
+            // or stack memory.
+            let mut regs = A::placeholder_regs();
+            let mut read_stack = |_| Err(());
+            match complete_sync(Self::unwind_frame_impl(
+                &self.modules[module_index],
+                address,
+                relative_lookup_address,
+                self.unwind_source_preference,
+                &mut regs,
+                cache,
+                &mut SyncMemory(&mut read_stack),
+            )) {
+                Ok((UnwindResult::ExecRule(rule), _)) => rule,
+                _ => return false,
+            }
+        };
+        cache.rule_cache.insert(cache_handle, unwind_rule);
+        true
+    }
+
+    /// Find the rule for the first frame at `relative_address` by analyzing the
+    /// instructions around it, if the module has code bytes for prologue analysis.
+    fn rule_from_instruction_analysis(
+        module: &Module<D>,
+        relative_address: u32,
+    ) -> Option<A::UnwindRule> {
+        let text = module.prologue_text.as_ref()?;
+        let svma = module.unwind_info().base_svma + u64::from(relative_address);
+        let offset = usize::try_from(svma.checked_sub(text.svma_range.start)?).ok()?;
+        if offset >= text.bytes.len() {
+            return None;
+        }
+        A::rule_from_instruction_analysis(&text.bytes, offset)
+    }
+
+    /// Check that the return address points after a call instruction, if the module
+    /// containing it has return address validation enabled.
+    fn validate_return_address(&self, return_address: u64) -> Result<(), Error> {
+        // The call instruction ends at the return address, so look up the byte before it.
+        let Some((module_index, relative_address)) =
+            FrameAddress::from_return_address(return_address)
+                .and_then(|address| self.find_module_for_address(address.address_for_lookup()))
+        else {
+            return Ok(());
+        };
+        let module = &self.modules[module_index];
+        let Some(text) = &module.call_site_text else {
+            return Ok(());
+        };
+        let svma = module.unwind_info().base_svma + u64::from(relative_address) + 1;
+        let Some(offset) = svma
+            .checked_sub(text.svma_range.start)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .filter(|offset| *offset <= text.bytes.len())
+        else {
+            return Ok(());
+        };
+        if !A::is_after_call_instruction(&text.bytes, offset) {
+            diagnostic!(
+                "Return address 0x{:x} in module {} is not after a call instruction",
+                return_address,
+                module.name
+            );
+            return Err(Error::ReturnAddressNotAfterCall(return_address));
+        }
+        Ok(())
     }
 
-    pub fn unwind_frame<F>(
-        &self,
-        address: FrameAddress,
-        regs: &mut A::UnwindRegs,
-        cache: &mut Cache<A::UnwindRule, P>,
-        read_stack: &mut F,
-    ) -> Result<Option<u64>, Error>
-    where
-        F: FnMut(u64) -> Result<u64, ()>,
-    {
-        self.with_cache(address, regs, cache, read_stack, Self::unwind_frame_impl)
+    /// Find the FDE for an address in a module with `.eh_frame_hdr`, for which the
+    /// `.eh_frame_hdr` table lookup failed, by looking at all FDEs in `.eh_frame`.
+    ///
+    /// With the `std` feature, the FDEs are scanned once and the result is kept in an
+    /// index on the module. Otherwise, the FDEs are scanned on every call; the unwind
+    /// rule cache avoids most repeated lookups.
+    fn fde_offset_without_eh_frame_hdr(module: &Module<D>, rel_lookup_address: u32) -> Option<u32> {
+        let ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
+            eh_frame,
+            base_addresses,
+            #[cfg(feature = "std")]
+            fallback_index,
+            ..
+        } = &module.unwind_info().data
+        else {
+            return None;
+        };
+        let mut eh_frame_section =
+            EhFrame::from(EndianSlice::new(&eh_frame[..], module.unwind_info().endian));
+        eh_frame_section.set_address_size(A::ADDRESS_SIZE);
+        eh_frame_section.set_vendor(A::VENDOR);
+        #[cfg(feature = "std")]
+        {
+            let index = fallback_index.get_or_init(|| {
+                diagnostic!(
+                    "Building an FDE index for module {} because its .eh_frame_hdr is incomplete",
+                    module.name
+                );
+                DwarfCfiIndex::try_new(
+                    eh_frame_section,
+                    base_addresses.clone(),
+                    module.unwind_info().base_svma,
+                )
+                .ok()
+            });
+            index
+                .as_ref()?
+                .fde_offset_for_relative_address(rel_lookup_address)
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            crate::dwarf::fde_offset_by_linear_scan(
+                &eh_frame_section,
+                base_addresses,
+                module.unwind_info().base_svma + u64::from(rel_lookup_address),
+            )
+        }
+    }
+
+    /// Find the FDE which covers `rel_lookup_address` in the `__eh_frame` of a mach-O
+    /// module, without going through `__unwind_info`. There's no search table for these
+    /// FDEs, so this works like [`Self::fde_offset_without_eh_frame_hdr`].
+    #[cfg(feature = "macho")]
+    fn fde_offset_in_macho_eh_frame(module: &Module<D>, rel_lookup_address: u32) -> Option<u32> {
+        let ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame {
+            eh_frame: Some(eh_frame),
+            base_addresses,
+            #[cfg(feature = "std")]
+            eh_frame_index,
+            ..
+        } = &module.unwind_info().data
+        else {
+            return None;
+        };
+        let mut eh_frame_section =
+            EhFrame::from(EndianSlice::new(&eh_frame[..], module.unwind_info().endian));
+        eh_frame_section.set_address_size(A::ADDRESS_SIZE);
+        eh_frame_section.set_vendor(A::VENDOR);
+        #[cfg(feature = "std")]
+        {
+            let index = eh_frame_index.get_or_init(|| {
+                diagnostic!(
+                    "Building an FDE index for the __eh_frame of module {}",
+                    module.name
+                );
+                DwarfCfiIndex::try_new(
+                    eh_frame_section,
+                    base_addresses.clone(),
+                    module.unwind_info().base_svma,
+                )
+                .ok()
+            });
+            let (fde_range, fde_offset) = index
+                .as_ref()?
+                .fde_for_relative_address(rel_lookup_address)?;
+            fde_range
+                .contains(&rel_lookup_address)
+                .then_some(fde_offset)
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            crate::dwarf::fde_offset_by_linear_scan(
+                &eh_frame_section,
+                base_addresses,
+                module.unwind_info().base_svma + u64::from(rel_lookup_address),
+            )
+        }
     }
 
-    fn unwind_frame_impl<F>(
+    async fn unwind_frame_impl<F>(
         module: &Module<D>,
         address: FrameAddress,
         rel_lookup_address: u32,
+        preference: UnwindSourcePreference,
         regs: &mut A::UnwindRegs,
         cache: &mut Cache<A::UnwindRule, P>,
         read_stack: &mut F,
-    ) -> Result<UnwindResult<A::UnwindRule>, UnwinderError>
+    ) -> Result<(UnwindResult<A::UnwindRule>, RuleSource), UnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
     {
-        let is_first_frame = !address.is_return_address();
-        let unwind_result = match &*module.unwind_data {
+        // Only mach-O modules have more than one source of unwind information.
+        #[cfg(not(feature = "macho"))]
+        let _ = preference;
+        let is_first_frame = address.is_instruction_pointer();
+        let info = module.unwind_info();
+        let (unwind_result, source) = match &info.data {
             #[cfg(feature = "macho")]
             ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame {
                 unwind_info,
@@ -415,57 +2008,125 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
                 stub_helper_svma: stub_helper,
                 base_addresses,
                 text_data,
+                ..
             } => {
-                // eprintln!("unwinding with cui and eh_frame in module {}", module.name);
-                let text_bytes = text_data.as_ref().and_then(|data| {
-                    let offset_from_base =
-                        u32::try_from(data.svma_range.start.checked_sub(module.base_svma)?).ok()?;
-                    Some(TextBytes::new(offset_from_base, &data.bytes[..]))
-                });
-                let stubs_range = if let Some(stubs_range) = stubs {
-                    (
-                        (stubs_range.start - module.base_svma) as u32,
-                        (stubs_range.end - module.base_svma) as u32,
-                    )
-                } else {
-                    (0, 0)
-                };
-                let stub_helper_range = if let Some(stub_helper_range) = stub_helper {
-                    (
-                        (stub_helper_range.start - module.base_svma) as u32,
-                        (stub_helper_range.end - module.base_svma) as u32,
-                    )
-                } else {
-                    (0, 0)
-                };
-                let mut unwinder = CompactUnwindInfoUnwinder::<A>::new(
-                    &unwind_info[..],
-                    text_bytes,
-                    stubs_range,
-                    stub_helper_range,
-                );
-
-                let unwind_result = unwinder.unwind_frame(rel_lookup_address, is_first_frame)?;
-                match unwind_result {
-                    CuiUnwindResult::ExecRule(rule) => UnwindResult::ExecRule(rule),
-                    CuiUnwindResult::NeedDwarf(fde_offset) => {
-                        let eh_frame_data =
-                            eh_frame.as_deref().ok_or(UnwinderError::NoDwarfData)?;
-                        let mut dwarf_unwinder = DwarfUnwinder::<_, A, _>::new(
-                            EndianSlice::new(eh_frame_data, LittleEndian),
-                            UnwindSectionType::EhFrame,
-                            None,
-                            &mut cache.gimli_unwind_context,
-                            base_addresses.clone(),
-                            module.base_svma,
-                        );
-                        dwarf_unwinder.unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
+                let unwind_with_fde = async |fde_offset: u32,
+                                             regs: &mut A::UnwindRegs,
+                                             cache: &mut Cache<A::UnwindRule, P>,
+                                             read_stack: &mut F|
+                       -> Result<
+                    UnwindResult<A::UnwindRule>,
+                    UnwinderError,
+                > {
+                    let eh_frame_data = eh_frame.as_deref().ok_or(UnwinderError::NoDwarfData)?;
+                    cache
+                        .fde_evaluations
+                        .count(module.avma_range.start, fde_offset);
+                    let mut dwarf_unwinder = DwarfUnwinder::<_, A, _>::new(
+                        EndianSlice::new(eh_frame_data, info.endian),
+                        UnwindSectionType::EhFrame,
+                        None,
+                        &mut cache.gimli_unwind_context,
+                        base_addresses.clone(),
+                        info.base_svma,
+                    );
+                    Ok(dwarf_unwinder
+                        .unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
                             regs,
                             is_first_frame,
                             rel_lookup_address,
                             fde_offset,
                             read_stack,
-                        )?
+                        )
+                        .await?)
+                };
+                let unwind_with_compact_unwind_info = async |regs: &mut A::UnwindRegs,
+                                                             cache: &mut Cache<
+                    A::UnwindRule,
+                    P,
+                >,
+                                                             read_stack: &mut F|
+                       -> Result<
+                    (UnwindResult<A::UnwindRule>, RuleSource),
+                    UnwinderError,
+                > {
+                    let text_bytes = text_data.as_ref().and_then(|data| {
+                        let offset_from_base =
+                            u32::try_from(data.svma_range.start.checked_sub(info.base_svma)?)
+                                .ok()?;
+                        Some(TextBytes::new(offset_from_base, &data.bytes[..]))
+                    });
+                    let stubs_range = if let Some(stubs_range) = stubs {
+                        (
+                            (stubs_range.start - info.base_svma) as u32,
+                            (stubs_range.end - info.base_svma) as u32,
+                        )
+                    } else {
+                        (0, 0)
+                    };
+                    let stub_helper_range = if let Some(stub_helper_range) = stub_helper {
+                        (
+                            (stub_helper_range.start - info.base_svma) as u32,
+                            (stub_helper_range.end - info.base_svma) as u32,
+                        )
+                    } else {
+                        (0, 0)
+                    };
+                    let mut unwinder = CompactUnwindInfoUnwinder::<A>::new(
+                        &unwind_info[..],
+                        text_bytes,
+                        stubs_range,
+                        stub_helper_range,
+                    );
+
+                    let unwind_result =
+                        unwinder.unwind_frame(rel_lookup_address, is_first_frame)?;
+                    match unwind_result {
+                        CuiUnwindResult::ExecRule(rule) => {
+                            Ok((UnwindResult::ExecRule(rule), RuleSource::CompactUnwind))
+                        }
+                        CuiUnwindResult::NeedDwarf(fde_offset) => Ok((
+                            unwind_with_fde(fde_offset, regs, cache, read_stack).await?,
+                            RuleSource::Dwarf,
+                        )),
+                    }
+                };
+                let unwind_with_dwarf = async |regs: &mut A::UnwindRegs,
+                                               cache: &mut Cache<A::UnwindRule, P>,
+                                               read_stack: &mut F|
+                       -> Result<
+                    UnwindResult<A::UnwindRule>,
+                    UnwinderError,
+                > {
+                    let fde_offset = Self::fde_offset_in_macho_eh_frame(module, rel_lookup_address)
+                        .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
+                    unwind_with_fde(fde_offset, regs, cache, read_stack).await
+                };
+
+                match preference {
+                    UnwindSourcePreference::PreferCompactUnwind => {
+                        match unwind_with_compact_unwind_info(regs, cache, read_stack).await {
+                            Ok(result) => result,
+                            Err(err) => match unwind_with_dwarf(regs, cache, read_stack).await {
+                                Ok(unwind_result) => (unwind_result, RuleSource::DwarfFallback),
+                                Err(_) => return Err(err),
+                            },
+                        }
+                    }
+                    UnwindSourcePreference::PreferDwarf => {
+                        match unwind_with_dwarf(regs, cache, read_stack).await {
+                            Ok(unwind_result) => (unwind_result, RuleSource::Dwarf),
+                            Err(err) => {
+                                match unwind_with_compact_unwind_info(regs, cache, read_stack).await
+                                {
+                                    Ok((unwind_result, RuleSource::CompactUnwind)) => {
+                                        (unwind_result, RuleSource::CompactUnwindFallback)
+                                    }
+                                    Ok(result) => result,
+                                    Err(_) => return Err(err),
+                                }
+                            }
+                        }
                     }
                 }
             }
@@ -473,26 +2134,34 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
                 eh_frame_hdr,
                 eh_frame,
                 base_addresses,
+                ..
             } => {
                 let eh_frame_hdr_data = &eh_frame_hdr[..];
                 let mut dwarf_unwinder = DwarfUnwinder::<_, A, _>::new(
-                    EndianSlice::new(eh_frame, LittleEndian),
+                    EndianSlice::new(eh_frame, info.endian),
                     UnwindSectionType::EhFrame,
                     Some(eh_frame_hdr_data),
                     &mut cache.gimli_unwind_context,
                     base_addresses.clone(),
-                    module.base_svma,
+                    info.base_svma,
                 );
                 let fde_offset = dwarf_unwinder
                     .get_fde_offset_for_relative_address(rel_lookup_address)
+                    .or_else(|| Self::fde_offset_without_eh_frame_hdr(module, rel_lookup_address))
                     .ok_or(UnwinderError::EhFrameHdrCouldNotFindAddress)?;
-                dwarf_unwinder.unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
-                    regs,
-                    is_first_frame,
-                    rel_lookup_address,
-                    fde_offset,
-                    read_stack,
-                )?
+                cache
+                    .fde_evaluations
+                    .count(module.avma_range.start, fde_offset);
+                let unwind_result = dwarf_unwinder
+                    .unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
+                        regs,
+                        is_first_frame,
+                        rel_lookup_address,
+                        fde_offset,
+                        read_stack,
+                    )
+                    .await?;
+                (unwind_result, RuleSource::Dwarf)
             }
             ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame {
                 index,
@@ -500,23 +2169,37 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
                 base_addresses,
             } => {
                 let mut dwarf_unwinder = DwarfUnwinder::<_, A, _>::new(
-                    EndianSlice::new(eh_frame, LittleEndian),
+                    EndianSlice::new(eh_frame, info.endian),
                     UnwindSectionType::EhFrame,
                     None,
                     &mut cache.gimli_unwind_context,
                     base_addresses.clone(),
-                    module.base_svma,
+                    info.base_svma,
                 );
-                let fde_offset = index
-                    .fde_offset_for_relative_address(rel_lookup_address)
+                let (fde_range, fde_offset) = index
+                    .fde_for_relative_address(rel_lookup_address)
                     .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
-                dwarf_unwinder.unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
-                    regs,
-                    is_first_frame,
-                    rel_lookup_address,
-                    fde_offset,
-                    read_stack,
-                )?
+                if !fde_range.contains(&rel_lookup_address) {
+                    // The address is in a gap between FDEs. This is what evaluating the
+                    // FDE would find, without parsing it.
+                    return Ok((
+                        UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()),
+                        RuleSource::Dwarf,
+                    ));
+                }
+                cache
+                    .fde_evaluations
+                    .count(module.avma_range.start, fde_offset);
+                let unwind_result = dwarf_unwinder
+                    .unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
+                        regs,
+                        is_first_frame,
+                        rel_lookup_address,
+                        fde_offset,
+                        read_stack,
+                    )
+                    .await?;
+                (unwind_result, RuleSource::Dwarf)
             }
             ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame {
                 index,
@@ -524,23 +2207,37 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
                 base_addresses,
             } => {
                 let mut dwarf_unwinder = DwarfUnwinder::<_, A, _>::new(
-                    EndianSlice::new(debug_frame, LittleEndian),
+                    EndianSlice::new(debug_frame, info.endian),
                     UnwindSectionType::DebugFrame,
                     None,
                     &mut cache.gimli_unwind_context,
                     base_addresses.clone(),
-                    module.base_svma,
+                    info.base_svma,
                 );
-                let fde_offset = index
-                    .fde_offset_for_relative_address(rel_lookup_address)
+                let (fde_range, fde_offset) = index
+                    .fde_for_relative_address(rel_lookup_address)
                     .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
-                dwarf_unwinder.unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
-                    regs,
-                    is_first_frame,
-                    rel_lookup_address,
-                    fde_offset,
-                    read_stack,
-                )?
+                if !fde_range.contains(&rel_lookup_address) {
+                    // The address is in a gap between FDEs. This is what evaluating the
+                    // FDE would find, without parsing it.
+                    return Ok((
+                        UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()),
+                        RuleSource::Dwarf,
+                    ));
+                }
+                cache
+                    .fde_evaluations
+                    .count(module.avma_range.start, fde_offset);
+                let unwind_result = dwarf_unwinder
+                    .unwind_frame_with_fde::<_, P::GimliEvaluationStorage<_>>(
+                        regs,
+                        is_first_frame,
+                        rel_lookup_address,
+                        fde_offset,
+                        read_stack,
+                    )
+                    .await?;
+                (unwind_result, RuleSource::Dwarf)
             }
             #[cfg(feature = "pe")]
             ModuleUnwindDataInternal::PeUnwindInfo {
@@ -548,21 +2245,29 @@ impl<D: Deref<Target = [u8]>, A: Unwinding, P: AllocationPolicy> UnwinderInterna
                 rdata,
                 xdata,
                 text,
-            } => <A as PeUnwinding>::unwind_frame(
-                crate::pe::PeSections {
-                    pdata,
-                    rdata: rdata.as_ref(),
-                    xdata: xdata.as_ref(),
-                    text: text.as_ref(),
-                },
-                rel_lookup_address,
-                regs,
-                is_first_frame,
-                read_stack,
-            )?,
+            } => {
+                let unwind_result = <A as PeUnwinding>::unwind_frame(
+                    crate::pe::PeSections {
+                        pdata,
+                        rdata: rdata.as_ref(),
+                        xdata: xdata.as_ref(),
+                        text: text.as_ref(),
+                    },
+                    rel_lookup_address,
+                    regs,
+                    is_first_frame,
+                    read_stack,
+                )
+                .await?;
+                (unwind_result, RuleSource::Pe)
+            }
+            ModuleUnwindDataInternal::FramePointerOnly => (
+                UnwindResult::ExecRule(A::UnwindRule::fallback_rule()),
+                RuleSource::FramePointer,
+            ),
             ModuleUnwindDataInternal::None => return Err(UnwinderError::NoModuleUnwindData),
         };
-        Ok(unwind_result)
+        Ok((unwind_result, source))
     }
 }
 
@@ -590,6 +2295,10 @@ enum ModuleUnwindDataInternal<D> {
         stub_helper_svma: Option<Range<u64>>,
         base_addresses: crate::dwarf::BaseAddresses,
         text_data: Option<TextByteData<D>>,
+        /// An index of all FDEs in `eh_frame`, for looking up addresses without
+        /// `__unwind_info`. It is created on first use.
+        #[cfg(feature = "std")]
+        eh_frame_index: std::sync::OnceLock<Option<DwarfCfiIndex>>,
     },
     /// Used with ELF binaries (Linux and friends), in the `.eh_frame_hdr` and `.eh_frame`
     /// sections. Contains an index and DWARF CFI.
@@ -597,6 +2306,10 @@ enum ModuleUnwindDataInternal<D> {
         eh_frame_hdr: D,
         eh_frame: D,
         base_addresses: crate::dwarf::BaseAddresses,
+        /// An index of all FDEs in `eh_frame`, for addresses which the `.eh_frame_hdr`
+        /// table doesn't find. It is created on first use.
+        #[cfg(feature = "std")]
+        fallback_index: std::sync::OnceLock<Option<DwarfCfiIndex>>,
     },
     /// Used with ELF binaries (Linux and friends), in the `.eh_frame` section. Contains
     /// DWARF CFI. We create a binary index for the FDEs when a module with this unwind
@@ -622,13 +2335,19 @@ enum ModuleUnwindDataInternal<D> {
         xdata: Option<DataAtRvaRange<D>>,
         text: Option<DataAtRvaRange<D>>,
     },
+    /// The module was explicitly added as frame pointer only, see
+    /// [`Module::new_framepointer_only`]. Unwinding in this module always uses the
+    /// architecture's frame pointer rule.
+    FramePointerOnly,
     /// No unwind information is used. Unwinding in this module will use a fallback rule
     /// (usually frame pointer unwinding).
     None,
 }
 
 impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
-    fn new(section_info: &mut impl ModuleSectionInfo<D>) -> Self {
+    /// Find the module's unwind data, and build an FDE index if needed. Fails if the
+    /// index could not be built, in which case the module has no usable unwind data.
+    fn new(section_info: &mut impl ModuleSectionInfo<D>) -> Result<Self, DwarfCfiIndexError> {
         use crate::dwarf::base_addresses_for_sections;
 
         #[cfg(feature = "macho")]
@@ -655,14 +2374,16 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
             } else {
                 None
             };
-            return ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame {
+            return Ok(ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame {
                 unwind_info,
                 eh_frame,
                 stubs_svma: stubs,
                 stub_helper_svma: stub_helper,
                 base_addresses: base_addresses_for_sections(section_info),
                 text_data,
-            };
+                #[cfg(feature = "std")]
+                eh_frame_index: std::sync::OnceLock::new(),
+            });
         }
 
         #[cfg(feature = "pe")]
@@ -677,12 +2398,12 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
                 let data = section_info.section_data(name)?;
                 Some(DataAtRvaRange { data, rva_range })
             };
-            return ModuleUnwindDataInternal::PeUnwindInfo {
+            return Ok(ModuleUnwindDataInternal::PeUnwindInfo {
                 pdata,
                 rdata: range_and_data(b".rdata"),
                 xdata: range_and_data(b".xdata"),
                 text: range_and_data(b".text"),
-            };
+            });
         }
 
         if let Some(eh_frame) = section_info
@@ -693,32 +2414,33 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
                 .section_data(b".eh_frame_hdr")
                 .or_else(|| section_info.section_data(b"__eh_frame_hdr"))
             {
-                ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
+                Ok(ModuleUnwindDataInternal::EhFrameHdrAndEhFrame {
                     eh_frame_hdr,
                     eh_frame,
                     base_addresses: base_addresses_for_sections(section_info),
-                }
+                    #[cfg(feature = "std")]
+                    fallback_index: std::sync::OnceLock::new(),
+                })
             } else {
-                match DwarfCfiIndex::try_new_eh_frame(&eh_frame, section_info) {
-                    Ok(index) => ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame {
-                        index,
-                        eh_frame,
-                        base_addresses: base_addresses_for_sections(section_info),
-                    },
-                    Err(_) => ModuleUnwindDataInternal::None,
-                }
-            }
-        } else if let Some(debug_frame) = section_info.section_data(b".debug_frame") {
-            match DwarfCfiIndex::try_new_debug_frame(&debug_frame, section_info) {
-                Ok(index) => ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame {
+                let index = DwarfCfiIndex::try_new_eh_frame(&eh_frame, section_info)?;
+                Ok(ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame {
                     index,
-                    debug_frame,
+                    eh_frame,
                     base_addresses: base_addresses_for_sections(section_info),
-                },
-                Err(_) => ModuleUnwindDataInternal::None,
+                })
             }
+        } else if let Some(debug_frame) = section_info
+            .section_data(b".debug_frame")
+            .or_else(|| section_info.section_data(b"__debug_frame"))
+        {
+            let index = DwarfCfiIndex::try_new_debug_frame(&debug_frame, section_info)?;
+            Ok(ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame {
+                index,
+                debug_frame,
+                base_addresses: base_addresses_for_sections(section_info),
+            })
         } else {
-            ModuleUnwindDataInternal::None
+            Ok(ModuleUnwindDataInternal::None)
         }
     }
 }
@@ -740,7 +2462,6 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
 ///    module, e.g. `Vec<u8>`. But it could also be a wrapper around mapped memory from
 ///    a file or a different process, for example. It just needs to provide a slice of
 ///    bytes via its `Deref` implementation.
-#[cfg(feature = "macho")]
 struct TextByteData<D> {
     pub bytes: D,
     pub svma_range: Range<u64>,
@@ -759,29 +2480,111 @@ struct TextByteData<D> {
 ///    module, e.g. `Vec<u8>`. But it could also be a wrapper around mapped memory from
 ///    a file or a different process, for example. It just needs to provide a slice of
 ///    bytes via its `Deref` implementation.
+///
+/// Cloning a module is cheap: the section data and the FDE index which was built when the
+/// module was created are shared between the clones. If the same binary, e.g. identified
+/// by its build ID, is loaded in multiple processes, create its module once and use
+/// [`Module::relocated`] to get a module for each process's load address.
 pub struct Module<D> {
-    /// The name or file path of the module. Unused, it's just there for easier debugging.
-    #[allow(unused)]
-    name: String,
+    /// The name or file path of the module, for diagnostics and debugging. This is an
+    /// `Arc` so that errors can name the module without allocating.
+    name: Arc<str>,
     /// The address range where this module is mapped into the process.
     avma_range: Range<u64>,
     /// The base address of this module, in the process's address space. On Linux, the base
     /// address can sometimes be different from the start address of the mapped range.
     base_avma: u64,
+    /// The unwind information for addresses in this module, which may only be loaded on
+    /// first use.
+    unwind_info: Arc<ModuleUnwindInfoSlot<D>>,
+    /// The code bytes which are used to check return addresses in this module, if
+    /// return address validation was enabled.
+    call_site_text: Option<Arc<TextByteData<D>>>,
+    /// The code bytes which are used for prologue and epilogue analysis of the first
+    /// frame, if it was enabled.
+    prologue_text: Option<Arc<TextByteData<D>>>,
+}
+
+/// The unwind data of a module, together with the module properties which are needed to
+/// interpret it.
+struct ModuleUnwindInfo<D> {
     /// The base address of this module, according to the module.
     base_svma: u64,
+    /// The byte order of the module's DWARF unwind data.
+    endian: RunTimeEndian,
     /// The unwind data that should be used for unwinding addresses from this module.
-    unwind_data: Arc<ModuleUnwindDataInternal<D>>,
+    data: ModuleUnwindDataInternal<D>,
+    /// The error from building the FDE index, if that failed. `data` is `None` then.
+    index_error: Option<DwarfCfiIndexError>,
+}
+
+impl<D: Deref<Target = [u8]>> ModuleUnwindInfo<D> {
+    fn new(section_info: &mut impl ModuleSectionInfo<D>) -> Self {
+        let (data, index_error) = match ModuleUnwindDataInternal::new(section_info) {
+            Ok(data) => (data, None),
+            Err(e) => (ModuleUnwindDataInternal::None, Some(e)),
+        };
+        Self {
+            base_svma: section_info.base_svma(),
+            endian: section_info.endianness().into(),
+            data,
+            index_error,
+        }
+    }
+}
+
+/// The unwind information of a module, or a way to load it.
+enum ModuleUnwindInfoSlot<D> {
+    Loaded(ModuleUnwindInfo<D>),
+    /// Used for modules created with [`Module::new_lazy`]. The closure is called, and
+    /// taken out of the mutex, when the unwind information is needed for the first time.
+    /// If it panics, the module has no unwind information.
+    #[cfg(feature = "std")]
+    Lazy {
+        info: std::sync::OnceLock<ModuleUnwindInfo<D>>,
+        load: std::sync::Mutex<Option<LoadModuleUnwindInfo<D>>>,
+    },
 }
 
+/// The closure which loads the unwind information of a lazily-created module.
+#[cfg(feature = "std")]
+type LoadModuleUnwindInfo<D> = Box<dyn FnOnce() -> ModuleUnwindInfo<D> + Send>;
+
 impl<D> Clone for Module<D> {
     fn clone(&self) -> Self {
         Self {
             name: self.name.clone(),
             avma_range: self.avma_range.clone(),
             base_avma: self.base_avma,
-            base_svma: self.base_svma,
-            unwind_data: self.unwind_data.clone(),
+            unwind_info: self.unwind_info.clone(),
+            call_site_text: self.call_site_text.clone(),
+            prologue_text: self.prologue_text.clone(),
+        }
+    }
+}
+
+impl<D> Module<D> {
+    /// The unwind information of this module. For a module created with
+    /// [`Module::new_lazy`], this loads it if it hasn't been loaded yet.
+    fn unwind_info(&self) -> &ModuleUnwindInfo<D> {
+        match &*self.unwind_info {
+            ModuleUnwindInfoSlot::Loaded(info) => info,
+            #[cfg(feature = "std")]
+            ModuleUnwindInfoSlot::Lazy { info, load } => info.get_or_init(|| {
+                let load = load
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .take();
+                let loaded = load.and_then(|load| {
+                    std::panic::catch_unwind(std::panic::AssertUnwindSafe(load)).ok()
+                });
+                loaded.unwrap_or(ModuleUnwindInfo {
+                    base_svma: 0,
+                    endian: RunTimeEndian::Little,
+                    data: ModuleUnwindDataInternal::None,
+                    index_error: None,
+                })
+            }),
         }
     }
 }
@@ -806,10 +2609,14 @@ pub trait ModuleSectionInfo<D> {
     /// This is used to convert between SVMAs and relative addresses.
     fn base_svma(&self) -> u64;
 
-    /// Get the given section's memory range, as stated in the module.
+    /// Get the given section's memory range, as stated in the module. This is the
+    /// section's address, e.g. `sh_addr` for ELF sections, and not its file offset.
     fn section_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>>;
 
     /// Get the given section's data. This will only be called once per section.
+    ///
+    /// The data doesn't need to come from mapped memory: for a binary on disk, it can be
+    /// read from the section's file offset.
     fn section_data(&mut self, name: &[u8]) -> Option<D>;
 
     /// Get the given segment's memory range, as stated in the module.
@@ -821,6 +2628,42 @@ pub trait ModuleSectionInfo<D> {
     fn segment_data(&mut self, _name: &[u8]) -> Option<D> {
         None
     }
+
+    /// Return the byte order of the module's unwind sections. The default implementation
+    /// returns [`Endianness::Little`].
+    fn endianness(&self) -> Endianness {
+        Endianness::Little
+    }
+
+    /// Return the size of a pointer in the module, in bytes, if known. This is used to
+    /// read absolute addresses in DWARF CFI when the module is added. If `None` is
+    /// returned, 8-byte addresses are assumed.
+    fn address_size(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// The byte order of a module's DWARF unwind sections (`.eh_frame`, `.eh_frame_hdr` and
+/// `.debug_frame`).
+///
+/// Big-endian data is only found on big-endian targets, or when unwinding with data from
+/// a big-endian core dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Endianness {
+    /// Little-endian, used by all architectures which are supported by this crate.
+    #[default]
+    Little,
+    /// Big-endian.
+    Big,
+}
+
+impl From<Endianness> for RunTimeEndian {
+    fn from(endianness: Endianness) -> Self {
+        match endianness {
+            Endianness::Little => RunTimeEndian::Little,
+            Endianness::Big => RunTimeEndian::Big,
+        }
+    }
 }
 
 /// Explicit addresses and data of various sections in the module. This implements
@@ -834,6 +2677,14 @@ pub trait ModuleSectionInfo<D> {
 /// or as relative addresses. For example, DWARF CFI can have code addresses expressed as
 /// relative-to-.text addresses or as absolute SVMAs. And mach-O compact unwind info
 /// contains addresses relative to the image base address.
+///
+/// The section data doesn't need to be read from the process's memory. For a binary on
+/// disk, read each section's data at its file offset, but still pass its SVMA, e.g.
+/// `sh_addr` for ELF sections, which usually differs from the file offset. Pointers in
+/// DWARF CFI which are relative to a section, e.g. `DW_EH_PE_pcrel` or `DW_EH_PE_datarel`
+/// pointers, are resolved with these SVMAs, and the resulting code addresses are
+/// translated into AVMAs with the module's `base_avma`, i.e. the load bias for ELF
+/// binaries. So a binary can be unwound without mapping it at its runtime address.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct ExplicitModuleSectionInfo<D> {
     /// The image base address, as stated in the object. For mach-O objects, this is the
@@ -891,6 +2742,10 @@ pub struct ExplicitModuleSectionInfo<D> {
     pub text_segment_svma: Option<Range<u64>>,
     /// The data of the `__TEXT` segment of mach-O binaries, if available.
     pub text_segment: Option<D>,
+    /// The byte order of the unwind sections.
+    pub endianness: Endianness,
+    /// The size of a pointer in bytes, e.g. 4 for 32-bit modules. 8 if `None`.
+    pub address_size: Option<u8>,
 }
 
 impl<D> ModuleSectionInfo<D> for ExplicitModuleSectionInfo<D>
@@ -934,11 +2789,104 @@ where
             _ => None,
         }
     }
+    fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+    fn address_size(&self) -> Option<u8> {
+        self.address_size
+    }
+}
+
+/// A [`ModuleSectionInfo`] for a module whose unwind sections are in a separate file, for
+/// example a stripped binary whose `.debug_frame` is in a debug file found via
+/// `.gnu_debuglink` or a build ID.
+///
+/// The unwind sections (`.eh_frame`, `.eh_frame_hdr`, `.debug_frame` and `__unwind_info`)
+/// are taken from `debug_file` if it has them with non-empty data, and from `image`
+/// otherwise. Sections of separate debug files which were stripped from them, such as
+/// an `SHT_NOBITS` `.eh_frame`, are empty, so the image's data is used for them. All
+/// other sections and segments, the base address, the byte order and the address size
+/// are taken from `image`, which is the file that is mapped into the process.
+///
+/// Addresses in the debug file must be the same SVMAs as in the image, because the code
+/// addresses in its unwind information are converted into AVMAs with the image's base
+/// address. This is the case for debug files created with `objcopy --only-keep-debug`.
+pub struct ModuleSectionInfoWithDebugFile<I, F> {
+    image: I,
+    debug_file: F,
+}
+
+impl<I, F> ModuleSectionInfoWithDebugFile<I, F> {
+    /// Combine the section info of the mapped `image` with that of its `debug_file`.
+    pub fn new(image: I, debug_file: F) -> Self {
+        Self { image, debug_file }
+    }
+
+    fn is_unwind_section(name: &[u8]) -> bool {
+        matches!(
+            name,
+            b".eh_frame"
+                | b"__eh_frame"
+                | b".eh_frame_hdr"
+                | b"__eh_frame_hdr"
+                | b".debug_frame"
+                | b"__debug_frame"
+                | b"__unwind_info"
+        )
+    }
+}
+
+impl<D, I, F> ModuleSectionInfo<D> for ModuleSectionInfoWithDebugFile<I, F>
+where
+    D: Deref<Target = [u8]>,
+    I: ModuleSectionInfo<D>,
+    F: ModuleSectionInfo<D>,
+{
+    fn base_svma(&self) -> u64 {
+        self.image.base_svma()
+    }
+
+    fn section_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>> {
+        if Self::is_unwind_section(name) {
+            if let Some(range) = self.debug_file.section_svma_range(name) {
+                return Some(range);
+            }
+        }
+        self.image.section_svma_range(name)
+    }
+
+    fn section_data(&mut self, name: &[u8]) -> Option<D> {
+        if Self::is_unwind_section(name) {
+            if let Some(data) = self.debug_file.section_data(name) {
+                if !data.is_empty() {
+                    return Some(data);
+                }
+            }
+        }
+        self.image.section_data(name)
+    }
+
+    fn segment_svma_range(&mut self, name: &[u8]) -> Option<Range<u64>> {
+        self.image.segment_svma_range(name)
+    }
+
+    fn segment_data(&mut self, name: &[u8]) -> Option<D> {
+        self.image.segment_data(name)
+    }
+
+    fn endianness(&self) -> Endianness {
+        self.image.endianness()
+    }
+
+    fn address_size(&self) -> Option<u8> {
+        self.image.address_size()
+    }
 }
 
 #[cfg(feature = "object")]
 mod object {
-    use super::{ModuleSectionInfo, Range};
+    use super::{Endianness, ModuleSectionInfo, Range};
+    use alloc::string::String;
     use object::read::{Object, ObjectSection, ObjectSegment};
 
     impl<'data: 'file, 'file, O, D> ModuleSectionInfo<D> for &'file O
@@ -978,6 +2926,48 @@ mod object {
             let segment = self.segments().find(|s| s.name_bytes() == Ok(Some(name)))?;
             segment.data().ok().map(|data| data.into())
         }
+
+        fn endianness(&self) -> Endianness {
+            if self.is_little_endian() {
+                Endianness::Little
+            } else {
+                Endianness::Big
+            }
+        }
+
+        fn address_size(&self) -> Option<u8> {
+            Some(if self.is_64() { 8 } else { 4 })
+        }
+    }
+
+    impl<D: core::ops::Deref<Target = [u8]>> super::Module<D> {
+        /// Create a module from a parsed object file, which has been loaded into the process
+        /// at `base_avma`.
+        ///
+        /// The unwind sections (`.eh_frame`, `.eh_frame_hdr`, `.debug_frame`, `.text`, `.got`, and
+        /// the mach-O `__unwind_info` and stub sections) are looked up automatically. The module's
+        /// address range is computed from the object's segments, by translating their SVMAs
+        /// into AVMAs with `base_avma + svma - base_svma`, where `base_svma` is the image base
+        /// address stated in the object.
+        ///
+        /// `base_avma` is the address at which the image base was loaded: for ELF objects this is
+        /// the load bias, and for mach-O objects it's the address of the `__TEXT` segment.
+        pub fn from_object<'data, O>(name: String, file: &O, base_avma: u64) -> Self
+        where
+            O: Object<'data>,
+            D: From<&'data [u8]>,
+        {
+            let base_svma = ModuleSectionInfo::<D>::base_svma(&file);
+            let svma_range = file
+                .segments()
+                .filter(|segment| segment.size() != 0 && segment.name() != Ok(Some("__PAGEZERO")))
+                .map(|segment| segment.address()..segment.address() + segment.size())
+                .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+                .unwrap_or(base_svma..base_svma);
+            let avma_range = base_avma.wrapping_add(svma_range.start.wrapping_sub(base_svma))
+                ..base_avma.wrapping_add(svma_range.end.wrapping_sub(base_svma));
+            Self::new(name, avma_range, base_avma, file)
+        }
     }
 }
 
@@ -988,26 +2978,256 @@ impl<D: Deref<Target = [u8]>> Module<D> {
         base_avma: u64,
         mut section_info: impl ModuleSectionInfo<D>,
     ) -> Self {
-        let unwind_data = ModuleUnwindDataInternal::new(&mut section_info);
+        let unwind_info = ModuleUnwindInfo::new(&mut section_info);
 
         Self {
-            name,
+            name: name.into(),
             avma_range,
             base_avma,
-            base_svma: section_info.base_svma(),
-            unwind_data: Arc::new(unwind_data),
+            unwind_info: Arc::new(ModuleUnwindInfoSlot::Loaded(unwind_info)),
+            call_site_text: None,
+            prologue_text: None,
+        }
+    }
+
+    /// Create a module like [`Module::new`], but only get its section information and
+    /// parse its unwind information when it's needed for the first time, i.e. when an
+    /// address in `avma_range` is unwound.
+    ///
+    /// This makes adding modules cheap, which helps with processes that have hundreds of
+    /// libraries of which only a few end up being sampled. `load_section_info` is called
+    /// at most once, even if the module is cloned or relocated; the clones share the
+    /// loaded information. If it panics, the panic is caught and the module is treated as
+    /// having no unwind information.
+    ///
+    /// Loading happens inside the first unwind call which needs the module: it calls
+    /// `load_section_info`, locks a mutex and allocates, even with
+    /// [`MustNotAllocateDuringUnwind`](crate::MustNotAllocateDuringUnwind). Call
+    /// [`load`](Self::load), or add the module with
+    /// [`try_add_module`](Unwinder::try_add_module), to load it ahead of time.
+    #[cfg(feature = "std")]
+    pub fn new_lazy<S, F>(
+        name: String,
+        avma_range: core::ops::Range<u64>,
+        base_avma: u64,
+        load_section_info: F,
+    ) -> Self
+    where
+        S: ModuleSectionInfo<D>,
+        F: FnOnce() -> S + Send + 'static,
+    {
+        let load = Box::new(move || ModuleUnwindInfo::new(&mut load_section_info()));
+
+        Self {
+            name: name.into(),
+            avma_range,
+            base_avma,
+            unwind_info: Arc::new(ModuleUnwindInfoSlot::Lazy {
+                info: std::sync::OnceLock::new(),
+                load: std::sync::Mutex::new(Some(load)),
+            }),
+            call_site_text: None,
+            prologue_text: None,
         }
     }
 
+    /// Create a module like [`Module::new`], but use a prebuilt index of the FDEs in its
+    /// `.eh_frame` section instead of the module's `.eh_frame_hdr`, or instead of
+    /// scanning `.eh_frame` when the module is added.
+    ///
+    /// The index needs to have been built from the same `.eh_frame` data, see
+    /// [`FdeIndex::from_eh_frame`](crate::FdeIndex::from_eh_frame). If the module has no
+    /// `.eh_frame` section, the index is ignored.
+    pub fn new_with_fde_index(
+        name: String,
+        avma_range: core::ops::Range<u64>,
+        base_avma: u64,
+        mut section_info: impl ModuleSectionInfo<D>,
+        index: DwarfCfiIndex,
+    ) -> Self {
+        let unwind_info = match section_info
+            .section_data(b".eh_frame")
+            .or_else(|| section_info.section_data(b"__eh_frame"))
+        {
+            Some(eh_frame) => ModuleUnwindInfo {
+                base_svma: section_info.base_svma(),
+                endian: section_info.endianness().into(),
+                data: ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame {
+                    index,
+                    eh_frame,
+                    base_addresses: crate::dwarf::base_addresses_for_sections(&mut section_info),
+                },
+                index_error: None,
+            },
+            None => ModuleUnwindInfo::new(&mut section_info),
+        };
+
+        Self {
+            name: name.into(),
+            avma_range,
+            base_avma,
+            unwind_info: Arc::new(ModuleUnwindInfoSlot::Loaded(unwind_info)),
+            call_site_text: None,
+            prologue_text: None,
+        }
+    }
+
+    /// Create a module which has no unwind information, for code that is known to be
+    /// compiled with frame pointers, e.g. JIT code or stripped binaries.
+    ///
+    /// Addresses in this module are always unwound by walking the frame pointer chain,
+    /// with the architecture's `UseFramePointer` rule. The caller's frame pointer and the
+    /// return address are read from:
+    ///
+    /// - x86_64: `[rbp]` and `[rbp + 8]`, see [`UnwindRuleX86_64::UseFramePointer`](crate::x86_64::UnwindRuleX86_64::UseFramePointer).
+    /// - x86: `[ebp]` and `[ebp + 4]`, as 32-bit values, see [`UnwindRuleX86::UseFramePointer`](crate::x86::UnwindRuleX86::UseFramePointer).
+    /// - aarch64: `[fp]` and `[fp + 8]`, see [`UnwindRuleAarch64::UseFramePointer`](crate::aarch64::UnwindRuleAarch64::UseFramePointer).
+    /// - arm: `[fp]` and `[fp + 4]`, as 32-bit values, where fp is r7 for Thumb code and
+    ///   r11 for ARM code; both are tried, starting with the one matching the Thumb bit of
+    ///   pc. See [`UnwindRuleArm::UseFramePointer`](crate::arm::UnwindRuleArm::UseFramePointer).
+    /// - riscv64: `[fp - 16]` and `[fp - 8]`, because fp points to the top of the frame
+    ///   record, see [`UnwindRuleRiscV64::UseFramePointer`](crate::riscv64::UnwindRuleRiscV64::UseFramePointer).
+    pub fn new_framepointer_only(
+        name: String,
+        avma_range: core::ops::Range<u64>,
+        base_avma: u64,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            avma_range,
+            base_avma,
+            unwind_info: Arc::new(ModuleUnwindInfoSlot::Loaded(ModuleUnwindInfo {
+                base_svma: 0,
+                endian: RunTimeEndian::Little,
+                data: ModuleUnwindDataInternal::FramePointerOnly,
+                index_error: None,
+            })),
+            call_site_text: None,
+            prologue_text: None,
+        }
+    }
+
+    /// Create a module with the same unwind information as this one, which is loaded at a
+    /// different address, for example in another process with a different ASLR slide.
+    ///
+    /// This doesn't copy or parse any unwind information; the returned module shares it
+    /// with this module. `avma_range` and `base_avma` have the same meaning as in
+    /// [`Module::new`].
+    pub fn relocated(&self, avma_range: Range<u64>, base_avma: u64) -> Self {
+        Self {
+            avma_range,
+            base_avma,
+            ..self.clone()
+        }
+    }
+
+    /// Enable return address validation for this module.
+    ///
+    /// When a frame is unwound and the recovered return address lies in this module, the
+    /// unwinder checks that the instruction just before the return address is a call
+    /// instruction (e.g. `call` on x86_64, `bl` / `blr` on aarch64). If it isn't, the
+    /// unwind info was most likely wrong or the stack was corrupted, and
+    /// [`Error::ReturnAddressNotAfterCall`] is returned instead of the return address. The
+    /// registers are left as they were before the frame was unwound.
+    ///
+    /// `text` contains the code bytes of the module at the SVMAs `text_svma_range`, for
+    /// example the `.text` section. Return addresses outside of this range are not checked.
+    pub fn with_return_address_validation(mut self, text_svma_range: Range<u64>, text: D) -> Self {
+        self.call_site_text = Some(Arc::new(TextByteData {
+            bytes: text,
+            svma_range: text_svma_range,
+        }));
+        self
+    }
+
+    /// Enable prologue and epilogue analysis for the first frame in this module.
+    ///
+    /// If the first frame is interrupted in a function prologue or epilogue, the return
+    /// address may still be in the link register (aarch64), or on the stack without a
+    /// frame pointer that points to it (x86_64). When the module's unwind information
+    /// doesn't cover the address of the first frame, or can't be used, the unwinder
+    /// usually falls back to the frame pointer, which gives a wrong return address in
+    /// that case. With prologue analysis, it first looks at the instructions around the
+    /// address and uses a matching rule if it detects a prologue or an epilogue.
+    ///
+    /// `text` contains the code bytes of the module at the SVMAs `text_svma_range`, for
+    /// example the `.text` section.
+    pub fn with_prologue_analysis(mut self, text_svma_range: Range<u64>, text: D) -> Self {
+        self.prologue_text = Some(Arc::new(TextByteData {
+            bytes: text,
+            svma_range: text_svma_range,
+        }));
+        self
+    }
+
     pub fn avma_range(&self) -> core::ops::Range<u64> {
         self.avma_range.clone()
     }
 
+    /// Returns true if `address` is in this module's address range.
+    pub fn contains_address(&self, address: u64) -> bool {
+        self.avma_range.contains(&address)
+    }
+
     pub fn base_avma(&self) -> u64 {
         self.base_avma
     }
 
+    /// Load the unwind information of a module created with `Module::new_lazy` now,
+    /// instead of during the first unwind call which needs it, for example before
+    /// unwinding from a context which must not allocate. Other modules are already
+    /// loaded, so this does nothing for them.
+    pub fn load(&self) {
+        self.unwind_info();
+    }
+
+    /// Convert an address in the process, e.g. a return address from unwinding, into
+    /// the corresponding address in the module's file (an SVMA), as used by symbol tables
+    /// and debug information.
+    ///
+    /// For a module created with `Module::new_lazy`, this loads the section
+    /// information if it hasn't been loaded yet, because the module's base SVMA is part
+    /// of it.
+    pub fn avma_to_svma(&self, avma: u64) -> u64 {
+        self.unwind_info()
+            .base_svma
+            .wrapping_add(avma.wrapping_sub(self.base_avma))
+    }
+
+    /// Convert an address in the module's file (an SVMA) into the corresponding address
+    /// in the process. This is the inverse of [`avma_to_svma`](Self::avma_to_svma).
+    pub fn svma_to_avma(&self, svma: u64) -> u64 {
+        self.base_avma
+            .wrapping_add(svma.wrapping_sub(self.unwind_info().base_svma))
+    }
+
+    /// The name or file path which was given when the module was created.
+    ///
+    /// If a frame can't be unwound because this module's DWARF CFI is broken, the error is
+    /// an [`Error::DwarfInModule`] with this name.
     pub fn name(&self) -> &str {
         &self.name
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cycle_detector() {
+        let mut detector = CycleDetector::new();
+        assert!(!detector.check((0x10, 0x100)));
+        assert!(!detector.check((0x20, 0x110)));
+        assert!(!detector.check((0x30, 0x120)));
+
+        // A cycle of length 3 which starts after a few frames is found eventually.
+        let cycle = [(0x40, 0x130), (0x50, 0x140), (0x60, 0x150)];
+        let found_after = cycle
+            .iter()
+            .cycle()
+            .take(100)
+            .position(|frame| detector.check(*frame));
+        assert!(found_after.is_some());
+    }
+}
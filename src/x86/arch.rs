@@ -0,0 +1,15 @@
+use super::unwind_rule::UnwindRuleX86;
+use super::unwindregs::UnwindRegsX86;
+use crate::arch::Arch;
+
+/// The x86 (32-bit) CPU architecture.
+pub struct ArchX86;
+impl Arch for ArchX86 {
+    type UnwindRule = UnwindRuleX86;
+    type UnwindRegs = UnwindRegsX86;
+    const ADDRESS_SIZE: u8 = 4;
+
+    fn placeholder_regs() -> UnwindRegsX86 {
+        UnwindRegsX86::new(0, 0, 0)
+    }
+}
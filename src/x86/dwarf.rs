@@ -0,0 +1,200 @@
+use gimli::{
+    CfaRule, Encoding, EvaluationStorage, Reader, ReaderOffset, Register, RegisterRule,
+    UnwindContextStorage, UnwindSection, UnwindTableRow, X86,
+};
+
+use super::{arch::ArchX86, unwind_rule::UnwindRuleX86, unwindregs::UnwindRegsX86};
+use crate::diagnostics::diagnostic;
+use crate::dwarf::{
+    eval_cfa_rule, eval_error, eval_register_rule, scaled_offset, ConversionError, DwarfUnwindRegs,
+    DwarfUnwinderError, DwarfUnwinding,
+};
+use crate::memory::{AsyncMemoryAccess, Memory32, TrackReadFailures};
+use crate::unwind_result::UnwindResult;
+
+impl DwarfUnwindRegs for UnwindRegsX86 {
+    fn get(&self, register: Register) -> Option<u64> {
+        match register {
+            X86::RA => Some(self.ip()),
+            X86::ESP => Some(self.sp()),
+            X86::EBP => Some(self.bp()),
+            _ => None,
+        }
+    }
+}
+
+impl DwarfUnwinding for ArchX86 {
+    const RETURN_ADDRESS_REGISTER: Register = X86::RA;
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        X86::register_name(register)
+    }
+
+    async fn unwind_frame<F, R, UCS, ES>(
+        section: &impl UnwindSection<R>,
+        unwind_info: &UnwindTableRow<R::Offset, UCS>,
+        encoding: Encoding,
+        regs: &mut Self::UnwindRegs,
+        is_first_frame: bool,
+        _lookup_svma: u64,
+        read_stack: &mut F,
+    ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
+    where
+        F: AsyncMemoryAccess,
+        R: Reader,
+        UCS: UnwindContextStorage<R::Offset>,
+        ES: EvaluationStorage<R>,
+    {
+        let cfa_rule = unwind_info.cfa();
+        let bp_rule = unwind_info.register(X86::EBP);
+        let ra_rule = unwind_info.register(X86::RA);
+
+        match Self::rule_for_row(unwind_info) {
+            Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
+            Err(err) => {
+                // Could not translate into a cacheable unwind rule. Fall back to the generic path.
+                diagnostic!(
+                    "Unwind rule translation failed at eip 0x{:x}: {:?}",
+                    regs.ip(),
+                    err
+                );
+            }
+        }
+
+        // Stack slots are 32 bits wide.
+        let read_stack = &mut Memory32(read_stack);
+        let read_stack = &mut TrackReadFailures::new(read_stack);
+
+        let cfa = eval_cfa_rule::<R, _, _, ES>(section, cfa_rule, encoding, regs, read_stack)
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverCfa,
+                )
+            })?;
+
+        let ip = regs.ip();
+        let bp = regs.bp();
+        let sp = regs.sp();
+
+        let new_bp = eval_register_rule::<R, _, _, ES>(
+            section, bp_rule, cfa, encoding, bp, regs, read_stack,
+        )
+        .await
+        .unwrap_or(bp);
+
+        let return_address = match eval_register_rule::<R, _, _, ES>(
+            section, ra_rule, cfa, encoding, ip, regs, read_stack,
+        )
+        .await
+        {
+            Some(ra) => ra,
+            None => read_stack
+                .read_u64(cfa - 4)
+                .await
+                .map_err(|_| DwarfUnwinderError::CouldNotRecoverReturnAddress)?,
+        };
+
+        if return_address == 0 {
+            // The outermost frame, e.g. _start, has a null return address.
+            return Ok(UnwindResult::Uncacheable(0));
+        }
+        if cfa == sp && return_address == ip {
+            return Err(DwarfUnwinderError::DidNotAdvance);
+        }
+        if !is_first_frame && cfa < regs.sp() {
+            return Err(DwarfUnwinderError::StackPointerMovedBackwards);
+        }
+
+        regs.set_ip(return_address);
+        regs.set_bp(new_bp);
+        regs.set_sp(cfa);
+
+        Ok(UnwindResult::Uncacheable(return_address))
+    }
+
+    fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
+        UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp
+    }
+
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        translate_into_unwind_rule(row.cfa(), &row.register(X86::EBP), &row.register(X86::RA))
+    }
+}
+
+fn register_rule_to_cfa_offset<RO: ReaderOffset>(
+    rule: &RegisterRule<RO>,
+) -> Result<Option<i64>, ConversionError> {
+    match *rule {
+        RegisterRule::Undefined | RegisterRule::SameValue => Ok(None),
+        RegisterRule::Offset(offset) => Ok(Some(offset)),
+        _ => Err(ConversionError::RegisterNotStoredRelativeToCfa),
+    }
+}
+
+fn translate_into_unwind_rule<RO: ReaderOffset>(
+    cfa_rule: &CfaRule<RO>,
+    bp_rule: &RegisterRule<RO>,
+    ra_rule: &RegisterRule<RO>,
+) -> Result<UnwindRuleX86, ConversionError> {
+    match ra_rule {
+        RegisterRule::Undefined => {
+            // No return address. This means that we've reached the end of the stack.
+            return Ok(UnwindRuleX86::EndOfStack);
+        }
+        RegisterRule::Offset(offset) if *offset == -4 => {
+            // This is normal case. Return address is [CFA-4].
+        }
+        RegisterRule::Offset(_) => {
+            // Unsupported, will have to use the slow path.
+            return Err(ConversionError::ReturnAddressRuleWithUnexpectedOffset);
+        }
+        _ => {
+            // Unsupported, will have to use the slow path.
+            return Err(ConversionError::ReturnAddressRuleWasWeird);
+        }
+    }
+
+    match cfa_rule {
+        CfaRule::RegisterAndOffset { register, offset } => match *register {
+            X86::ESP => {
+                let sp_offset_by_4 =
+                    scaled_offset(*offset, 4, ConversionError::SpOffsetDoesNotFit)?;
+                let fp_cfa_offset = register_rule_to_cfa_offset(bp_rule)?;
+                match fp_cfa_offset {
+                    None => Ok(UnwindRuleX86::OffsetSp { sp_offset_by_4 }),
+                    Some(bp_cfa_offset) => {
+                        let bp_storage_offset_from_sp_by_4 = scaled_offset(
+                            offset + bp_cfa_offset,
+                            4,
+                            ConversionError::FpStorageOffsetDoesNotFit,
+                        )?;
+                        Ok(UnwindRuleX86::OffsetSpAndRestoreBp {
+                            sp_offset_by_4,
+                            bp_storage_offset_from_sp_by_4,
+                        })
+                    }
+                }
+            }
+            X86::EBP => {
+                let bp_cfa_offset = register_rule_to_cfa_offset(bp_rule)?
+                    .ok_or(ConversionError::FramePointerRuleDoesNotRestoreBp)?;
+                if *offset == 8 && bp_cfa_offset == -8 {
+                    Ok(UnwindRuleX86::UseFramePointer)
+                } else {
+                    Err(ConversionError::FramePointerRuleHasStrangeBpOffset)
+                }
+            }
+            _ => Err(ConversionError::CfaIsOffsetFromUnknownRegister),
+        },
+        CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
+    }
+}
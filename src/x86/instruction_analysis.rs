@@ -0,0 +1,24 @@
+use super::arch::ArchX86;
+use crate::instruction_analysis::{is_after_x86_call_instruction, InstructionAnalysis};
+
+// Instruction analysis is only used for mach-O compact unwind info, which framehop
+// doesn't support for 32-bit x86 binaries yet.
+impl InstructionAnalysis for ArchX86 {
+    fn rule_from_prologue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn rule_from_epilogue_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+        is_after_x86_call_instruction(text_bytes, return_offset)
+    }
+}
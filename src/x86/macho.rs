@@ -0,0 +1,21 @@
+use super::arch::ArchX86;
+use super::unwind_rule::UnwindRuleX86;
+use crate::macho::{CompactUnwindInfoUnwinderError, CompactUnwindInfoUnwinding, CuiUnwindResult};
+use macho_unwind_info::Function;
+
+impl CompactUnwindInfoUnwinding for ArchX86 {
+    fn unwind_frame(
+        _function: Function,
+        _is_first_frame: bool,
+        _address_offset_within_function: usize,
+        _function_bytes: Option<&[u8]>,
+    ) -> Result<CuiUnwindResult<UnwindRuleX86>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::X86Unsupported)
+    }
+
+    fn rule_for_stub_helper(
+        _offset: u32,
+    ) -> Result<CuiUnwindResult<UnwindRuleX86>, CompactUnwindInfoUnwinderError> {
+        Err(CompactUnwindInfoUnwinderError::X86Unsupported)
+    }
+}
@@ -0,0 +1,20 @@
+use super::arch::ArchX86;
+use crate::memory::AsyncMemoryAccess;
+use crate::pe::{PeSections, PeUnwinderError, PeUnwinding};
+use crate::unwind_result::UnwindResult;
+
+impl PeUnwinding for ArchX86 {
+    async fn unwind_frame<F, D>(
+        _sections: PeSections<'_, D>,
+        _address: u32,
+        _regs: &mut Self::UnwindRegs,
+        _is_first_frame: bool,
+        _read_stack: &mut F,
+    ) -> Result<UnwindResult<Self::UnwindRule>, PeUnwinderError>
+    where
+        F: AsyncMemoryAccess,
+        D: core::ops::Deref<Target = [u8]>,
+    {
+        Err(PeUnwinderError::X86Unsupported)
+    }
+}
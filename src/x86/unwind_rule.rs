@@ -0,0 +1,215 @@
+use super::unwindregs::UnwindRegsX86;
+use crate::add_signed::checked_add_signed;
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
+use crate::unwind_rule::UnwindRule;
+
+/// For all of these: return address is *(new_sp - 4)
+///
+/// Unwind rules are `Copy` and at most 8 bytes large, which is checked at compile time,
+/// so that they can be cached and stored in large numbers. The variants and their fields
+/// are public, so rules can also be built outside of framehop. The memory layout is not
+/// guaranteed; to persist rules, store their variants and fields, or enable the `serde`
+/// feature, which implements `Serialize` and `Deserialize` for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UnwindRuleX86 {
+    EndOfStack,
+    /// (sp, bp) = (sp + 4, bp)
+    JustReturn,
+    /// (sp, bp) = if is_first_frame (sp + 4, bp) else (bp + 8, *bp)
+    JustReturnIfFirstFrameOtherwiseFp,
+    /// (sp, bp) = (sp + 4x, bp)
+    OffsetSp {
+        sp_offset_by_4: u16,
+    },
+    /// (sp, bp) = (sp + 4x, *(sp + 4y))
+    OffsetSpAndRestoreBp {
+        sp_offset_by_4: u16,
+        bp_storage_offset_from_sp_by_4: i16,
+    },
+    /// (sp, bp) = (bp + 8, *bp)
+    UseFramePointer,
+}
+
+const _: () = assert!(core::mem::size_of::<UnwindRuleX86>() <= 8);
+
+impl UnwindRuleX86 {
+    /// The registers whose current values this rule reads, see [`RegisterSet`].
+    ///
+    /// Registers which the rule passes through to the caller unchanged are not included.
+    /// If the rule behaves differently for the first frame, the registers needed by either
+    /// case are included. This lets a caller which didn't capture all registers know in
+    /// advance whether a rule can be applied.
+    pub fn used_registers(&self) -> RegisterSet {
+        match self {
+            UnwindRuleX86::EndOfStack => RegisterSet::EMPTY,
+            UnwindRuleX86::JustReturn
+            | UnwindRuleX86::OffsetSp { .. }
+            | UnwindRuleX86::OffsetSpAndRestoreBp { .. } => RegisterSet::SP,
+            UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp | UnwindRuleX86::UseFramePointer => {
+                RegisterSet::SP | RegisterSet::FP
+            }
+        }
+    }
+}
+
+impl UnwindRule for UnwindRuleX86 {
+    type UnwindRegs = UnwindRegsX86;
+
+    fn used_registers(&self) -> RegisterSet {
+        UnwindRuleX86::used_registers(self)
+    }
+
+    fn rule_for_stub_functions() -> Self {
+        UnwindRuleX86::JustReturn
+    }
+    fn rule_for_function_start() -> Self {
+        UnwindRuleX86::JustReturn
+    }
+    fn fallback_rule() -> Self {
+        UnwindRuleX86::UseFramePointer
+    }
+
+    async fn exec<F>(
+        self,
+        is_first_frame: bool,
+        regs: &mut UnwindRegsX86,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let sp = regs.sp();
+        let (new_sp, new_bp) = match self {
+            UnwindRuleX86::EndOfStack => return Ok(None),
+            UnwindRuleX86::JustReturn => {
+                let new_sp = sp.checked_add(4).ok_or(Error::IntegerOverflow)?;
+                (new_sp, regs.bp())
+            }
+            UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp => {
+                if is_first_frame {
+                    let new_sp = sp.checked_add(4).ok_or(Error::IntegerOverflow)?;
+                    (new_sp, regs.bp())
+                } else {
+                    let bp = regs.bp();
+                    let new_sp = bp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+                    if new_sp <= sp {
+                        return Err(Error::FramepointerUnwindingMovedBackwards);
+                    }
+                    let new_bp = read_u32(read_stack, bp).await?;
+                    (new_sp, new_bp)
+                }
+            }
+            UnwindRuleX86::OffsetSp { sp_offset_by_4 } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (new_sp, regs.bp())
+            }
+            UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4,
+                bp_storage_offset_from_sp_by_4,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let bp_storage_offset_from_sp = i64::from(bp_storage_offset_from_sp_by_4) * 4;
+                let bp_location = checked_add_signed(sp, bp_storage_offset_from_sp)
+                    .ok_or(Error::IntegerOverflow)?;
+                let new_bp = match read_u32(read_stack, bp_location).await {
+                    Ok(new_bp) => new_bp,
+                    // Same as on x86_64: epilogues can describe registers which were already
+                    // popped, and read_stack may refuse to read below the stack pointer.
+                    Err(_) if is_first_frame && bp_location < sp => regs.bp(),
+                    Err(err) => return Err(err),
+                };
+                (new_sp, new_bp)
+            }
+            UnwindRuleX86::UseFramePointer => {
+                // Do a frame pointer stack walk. This works just like on x86_64, but with
+                // 4-byte stack slots:
+                //
+                // pushl  %ebp
+                // movl   %esp, %ebp
+                //
+                // So: *ebp is the caller's frame pointer, and *(ebp + 4) is the return address.
+                let bp = regs.bp();
+                if bp == 0 {
+                    return Ok(None);
+                }
+                let new_sp = bp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+                if new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                let new_bp = read_u32(read_stack, bp).await?;
+                (new_sp, new_bp)
+            }
+        };
+        let return_address = read_u32(read_stack, new_sp - 4).await?;
+        if return_address == 0 {
+            return Ok(None);
+        }
+        if new_sp == sp && return_address == regs.ip() {
+            return Err(Error::DidNotAdvance);
+        }
+        regs.set_ip(return_address);
+        regs.set_sp(new_sp);
+        regs.set_bp(new_bp);
+        Ok(Some(return_address))
+    }
+}
+
+/// Read a 32-bit stack slot.
+async fn read_u32<F>(read_stack: &mut F, addr: u64) -> Result<u64, Error>
+where
+    F: AsyncMemoryAccess,
+{
+    let value = read_stack.read_u32(addr).await?;
+    Ok(u64::from(value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+
+    #[test]
+    fn test_basic() {
+        let stack = [
+            1, 2, 0x100300, 4, 0x20, 0x100200, 5, 6, 0x38, 0x100100, 7, 8, 9, 10, 0x0, 0x0,
+        ];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+        let mut regs = UnwindRegsX86::new(0x100400, 0x8, 0x10);
+        let res = complete_sync(UnwindRuleX86::JustReturn.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100300)));
+        assert_eq!(regs.ip(), 0x100300);
+        assert_eq!(regs.sp(), 0xc);
+        assert_eq!(regs.bp(), 0x10);
+        let res = complete_sync(UnwindRuleX86::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x18);
+        assert_eq!(regs.bp(), 0x20);
+        let res = complete_sync(UnwindRuleX86::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x100100)));
+        assert_eq!(regs.sp(), 0x28);
+        assert_eq!(regs.bp(), 0x38);
+        let res = complete_sync(UnwindRuleX86::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(None));
+    }
+}
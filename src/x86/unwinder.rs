@@ -0,0 +1,297 @@
+use alloc::vec::Vec;
+use core::ops::{Deref, Range};
+
+use crate::memory::{complete_sync, AsyncMemoryAccess, MemoryAccess, SyncMemory};
+use crate::{
+    unwinder::UnwinderInternal, AddModuleError, AddModulePolicy, AllocationPolicy, AsyncUnwinder,
+    CheckedUnwindFrameOutcome, ConversionError, Error, FdeEncoding, FrameAddress,
+    MayAllocateDuringUnwind, Module, PrecomputedRules, UnwindFrameOutcome, Unwinder, UnwoundFrame,
+};
+
+use super::{ArchX86, CacheX86, UnwindRegsX86, UnwindRuleX86};
+
+/// The unwinder for the x86 (32-bit) CPU architecture. Use the [`Unwinder`] trait for unwinding.
+///
+/// Type arguments:
+///
+///  - `D`: The type for unwind section data in the modules. See [`Module`].
+/// -  `P`: The [`AllocationPolicy`].
+///
+/// Cloning an unwinder is cheap and works for any `D`: the clones share the modules and
+/// their parsed unwind information, see [`Module`]. For example, to unwind on several
+/// threads, give each thread a clone of the unwinder and its own cache.
+pub struct UnwinderX86<D, P = MayAllocateDuringUnwind>(UnwinderInternal<D, ArchX86, P>);
+
+impl<D, P> Default for UnwinderX86<D, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, P> Clone for UnwinderX86<D, P> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<D, P> UnwinderX86<D, P> {
+    /// Create an unwinder for a process.
+    pub fn new() -> Self {
+        Self(UnwinderInternal::new())
+    }
+
+    /// Add an address range whose code has no unwind information and is known to
+    /// maintain the frame pointer chain, for example JIT code.
+    ///
+    /// Addresses in `avma_range` are always unwound with the frame pointer rule, without
+    /// looking for a module or parsing any unwind information. Existing ranges and
+    /// modules with precomputed rules which overlap with `avma_range` are removed. The
+    /// range can be removed with [`remove_module`](Unwinder::remove_module) and
+    /// `avma_range.start`.
+    ///
+    /// Unlike [`Module::new_framepointer_only`], this doesn't need a [`Module`].
+    pub fn add_framepointer_range(&mut self, avma_range: Range<u64>) {
+        self.0.add_framepointer_range(avma_range);
+    }
+
+    /// Unwind a frame with a rule which is already known to be correct for its address,
+    /// for example one which was found for the same pc in an earlier sample, such as
+    /// [`UnwindRuleX86::UseFramePointer`](UnwindRuleX86::UseFramePointer).
+    ///
+    /// This doesn't look up a module, a cached rule or any unwind information, so it's
+    /// the cheapest way to unwind a frame; the caller is responsible for `rule` matching
+    /// the code at `address`. `address` only tells the rule whether this is the first
+    /// frame. Returns the same values as [`unwind_frame`](Unwinder::unwind_frame).
+    /// Return addresses which mark the end of the stack are respected, but return
+    /// addresses are not validated.
+    pub fn unwind_frame_with_rule<F: MemoryAccess>(
+        &self,
+        rule: UnwindRuleX86,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error> {
+        complete_sync(self.0.unwind_frame_with_rule(
+            rule,
+            address,
+            regs,
+            &mut SyncMemory(read_stack),
+            &(),
+        ))
+    }
+
+    /// Treat the given return addresses as the end of the stack.
+    ///
+    /// When a frame is unwound and its return address is one of `addresses`, for example
+    /// the address after the call in a thread entry point, unwinding stops successfully:
+    /// [`unwind_frame`](Unwinder::unwind_frame) returns `Ok(None)`, as it does for a
+    /// null return address. Replaces the previously set addresses.
+    pub fn set_end_of_stack_return_addresses(&mut self, addresses: Vec<u64>) {
+        self.0.set_end_of_stack_return_addresses(addresses);
+    }
+
+    /// Treat return addresses into the given functions as the end of the stack, like
+    /// [`set_end_of_stack_return_addresses`](Self::set_end_of_stack_return_addresses).
+    ///
+    /// A return address ends unwinding if the address of the call before it, i.e.
+    /// `return_address - 1`, is inside one of `function_ranges`. For example, goroutine
+    /// stacks in Go programs end in a frame whose return address points into
+    /// `runtime.goexit`; pass the address range of that function from the symbol table
+    /// so that unwinding stops there instead of producing bogus frames. Replaces the
+    /// previously set ranges.
+    pub fn set_end_of_stack_functions(&mut self, function_ranges: Vec<Range<u64>>) {
+        self.0.set_end_of_stack_functions(function_ranges);
+    }
+
+    /// Treat return addresses below `address` as the end of the stack, like
+    /// [`set_end_of_stack_return_addresses`](Self::set_end_of_stack_return_addresses).
+    ///
+    /// A null return address always ends the stack. Some runtimes end the stack with
+    /// other small values instead, such as 1; pass e.g. the page size to treat every
+    /// return address in the first page, which is never mapped, as the end of the
+    /// stack. The default is 0, i.e. only the null return address.
+    pub fn set_end_of_stack_below_address(&mut self, address: u64) {
+        self.0.set_end_of_stack_below_address(address);
+    }
+
+    /// Unwind addresses which aren't in any module with the frame pointer, instead of
+    /// failing with [`Error::NoModuleForAddress`](crate::Error::NoModuleForAddress).
+    /// Off by default.
+    ///
+    /// This bridges gaps in otherwise well-described stacks, for example a JIT stub
+    /// between two modules with unwind information: the frame in the gap is unwound with
+    /// a single frame pointer step, and its caller is unwound with its module's unwind
+    /// information again. [`unwind_frame_detailed`](Unwinder::unwind_frame_detailed)
+    /// reports these frames as [`RuleSource::FramePointerRecovery`](crate::RuleSource::FramePointerRecovery),
+    /// because their return addresses are less reliable.
+    pub fn set_recover_with_frame_pointer_on_missing_info(&mut self, recover: bool) {
+        self.0
+            .set_recover_with_frame_pointer_on_missing_info(recover);
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy> UnwinderX86<D, P> {
+    /// The unwind rules which the DWARF CFI of `module` translates into, sorted by
+    /// address, with the address ranges (AVMAs) they apply to.
+    ///
+    /// This covers every row of every FDE in the module's `.eh_frame` or `.debug_frame`
+    /// section. Adjacent rows with the same result are merged. An `Err` means that the
+    /// CFI for this range can't be expressed as an unwind rule, so that unwinding
+    /// evaluates it for every frame, without caching. This is meant for testing and
+    /// debugging, and it doesn't need the module to be added to this unwinder.
+    pub fn dwarf_rules(
+        &self,
+        module: &Module<D>,
+    ) -> Vec<(Range<u64>, Result<UnwindRuleX86, ConversionError>)> {
+        UnwinderInternal::<D, ArchX86, P>::dwarf_rules(module)
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy> Unwinder for UnwinderX86<D, P> {
+    type UnwindRegs = UnwindRegsX86;
+    type Cache = CacheX86<P>;
+    type Module = Module<D>;
+
+    fn add_module(&mut self, module: Module<D>) {
+        self.0.add_module(module);
+    }
+
+    fn add_module_with_policy(
+        &mut self,
+        module: Module<D>,
+        policy: AddModulePolicy,
+    ) -> Result<(), Error> {
+        self.0.add_module_with_policy(module, policy)
+    }
+
+    fn try_add_module(&mut self, module: Module<D>) -> Result<(), AddModuleError> {
+        self.0.try_add_module(module)
+    }
+
+    fn remove_module(&mut self, module_address_range_start: u64) {
+        self.0.remove_module(module_address_range_start);
+    }
+
+    fn clear_modules(&mut self) {
+        self.0.clear_modules();
+    }
+
+    fn modules(&self) -> &[Module<D>] {
+        self.0.modules()
+    }
+
+    fn module_for_address(&self, address: u64) -> Option<&Module<D>> {
+        self.0.module_for_address(address)
+    }
+
+    fn covers_address(&self, address: u64) -> bool {
+        self.0.covers_address(address)
+    }
+
+    fn debug_fde_offset(&self, address: u64) -> Option<u32> {
+        self.0.debug_fde_offset(address)
+    }
+
+    fn debug_fde_encoding(&self, address: u64) -> Option<FdeEncoding> {
+        self.0.debug_fde_encoding(address)
+    }
+
+    fn max_known_code_address(&self) -> u64 {
+        self.0.max_known_code_address()
+    }
+
+    fn unwind_frame_detailed<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        cache: &mut CacheX86<P>,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess,
+    {
+        self.0
+            .unwind_frame_detailed_sync(address, regs, &mut cache.0, read_stack, &())
+    }
+
+    fn unwind_frame_in_module<F>(
+        &self,
+        module: &Module<D>,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        cache: &mut CacheX86<P>,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: MemoryAccess,
+    {
+        let outcome =
+            self.0
+                .unwind_frame_in_module(module, address, regs, &mut cache.0, read_stack, &())?;
+        Ok(outcome.next_pc)
+    }
+
+    fn precompute_rules(
+        &self,
+        addresses: &[FrameAddress],
+        cache: &mut CacheX86<P>,
+    ) -> PrecomputedRules {
+        self.0.precompute_rules(addresses, &mut cache.0)
+    }
+
+    fn unwind_frame_checked<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        cache: &mut CacheX86<P>,
+        read_stack: &mut F,
+    ) -> Result<CheckedUnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess,
+    {
+        complete_sync(self.0.unwind_frame_checked(
+            address,
+            regs,
+            &mut cache.0,
+            &mut SyncMemory(read_stack),
+            &(),
+        ))
+    }
+
+    fn unwind_frame_with_cfa<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        cache: &mut CacheX86<P>,
+        read_stack: &mut F,
+    ) -> Result<UnwoundFrame, Error>
+    where
+        F: MemoryAccess,
+    {
+        let outcome = self.unwind_frame_detailed(address, regs, cache, read_stack)?;
+        Ok(UnwoundFrame {
+            return_address: outcome.next_pc,
+            cfa: regs.sp(),
+            next_is_instruction_pointer: outcome.next_is_instruction_pointer,
+        })
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy> AsyncUnwinder for UnwinderX86<D, P> {
+    async fn unwind_frame_async<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86,
+        cache: &mut CacheX86<P>,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: AsyncMemoryAccess,
+    {
+        let outcome = self
+            .0
+            .unwind_frame_detailed(address, regs, &mut cache.0, read_stack, &())
+            .await?;
+        Ok(outcome.next_pc)
+    }
+}
@@ -0,0 +1,67 @@
+use core::fmt::Debug;
+
+use crate::display_utils::HexNum;
+
+/// The registers used for unwinding on 32-bit x86. We only need eip, esp and ebp.
+///
+/// The values are stored as `u64` for consistency with the other architectures, but only
+/// the lower 32 bits are meaningful.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UnwindRegsX86 {
+    ip: u64,
+    sp: u64,
+    bp: u64,
+}
+
+impl UnwindRegsX86 {
+    /// Create a set of unwind register values.
+    pub fn new(ip: u64, sp: u64, bp: u64) -> Self {
+        Self { ip, sp, bp }
+    }
+
+    /// Get the instruction pointer value (eip).
+    #[inline(always)]
+    pub fn ip(&self) -> u64 {
+        self.ip
+    }
+
+    /// Set the instruction pointer value (eip).
+    #[inline(always)]
+    pub fn set_ip(&mut self, ip: u64) {
+        self.ip = ip
+    }
+
+    /// Get the stack pointer value (esp).
+    #[inline(always)]
+    pub fn sp(&self) -> u64 {
+        self.sp
+    }
+
+    /// Set the stack pointer value (esp).
+    #[inline(always)]
+    pub fn set_sp(&mut self, sp: u64) {
+        self.sp = sp
+    }
+
+    /// Get the frame pointer value (ebp).
+    #[inline(always)]
+    pub fn bp(&self) -> u64 {
+        self.bp
+    }
+
+    /// Set the frame pointer value (ebp).
+    #[inline(always)]
+    pub fn set_bp(&mut self, bp: u64) {
+        self.bp = bp
+    }
+}
+
+impl Debug for UnwindRegsX86 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("UnwindRegsX86")
+            .field("eip", &HexNum(self.ip))
+            .field("esp", &HexNum(self.sp))
+            .field("ebp", &HexNum(self.bp))
+            .finish()
+    }
+}
@@ -1,10 +1,29 @@
 use super::unwind_rule::UnwindRuleX86_64;
 use super::unwindregs::UnwindRegsX86_64;
 use crate::arch::Arch;
+use crate::register_set::RegisterSet;
 
 /// The x86_64 CPU architecture.
 pub struct ArchX86_64;
 impl Arch for ArchX86_64 {
     type UnwindRule = UnwindRuleX86_64;
     type UnwindRegs = UnwindRegsX86_64;
+    const ADDRESS_SIZE: u8 = 8;
+
+    fn placeholder_regs() -> UnwindRegsX86_64 {
+        UnwindRegsX86_64::new(0, 0, 0)
+    }
+
+    fn bypasses_rule_cache(regs: &UnwindRegsX86_64) -> bool {
+        regs.callee_saved().is_some()
+    }
+
+    fn unknown_registers(regs: &UnwindRegsX86_64) -> RegisterSet {
+        regs.unknown_registers()
+    }
+
+    fn is_past_stack_end(regs: &UnwindRegsX86_64) -> bool {
+        regs.stack_end()
+            .is_some_and(|stack_end| regs.sp() >= stack_end)
+    }
 }
@@ -1,3 +1,5 @@
+use alloc::collections::BTreeMap;
+
 use super::unwind_rule::*;
 use crate::cache::*;
 
@@ -11,6 +13,14 @@ impl CacheX86_64<MayAllocateDuringUnwind> {
     pub fn new() -> Self {
         Self(Cache::new())
     }
+
+    /// Create a new cache with room for `entry_count` cached unwind rules.
+    ///
+    /// The default is 509. Each entry takes 16 bytes. Prime numbers spread
+    /// the cached addresses most evenly across the entries.
+    pub fn with_capacity(entry_count: usize) -> Self {
+        Self(Cache::with_capacity(entry_count))
+    }
 }
 
 impl<P: AllocationPolicy> CacheX86_64<P> {
@@ -19,10 +29,65 @@ impl<P: AllocationPolicy> CacheX86_64<P> {
         Self(Cache::new())
     }
 
+    /// Create a new cache with room for `entry_count` cached unwind rules. See
+    /// [`with_capacity`](CacheX86_64::with_capacity).
+    pub fn with_capacity_in(entry_count: usize) -> Self {
+        Self(Cache::with_capacity(entry_count))
+    }
+
+    /// The number of cached unwind rules this cache has room for.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
     /// Returns a snapshot of the cache usage statistics.
     pub fn stats(&self) -> CacheStats {
         self.0.rule_cache.stats()
     }
+
+    /// Resets the cache usage statistics to zero.
+    pub fn reset_stats(&mut self) {
+        self.0.rule_cache.reset_stats()
+    }
+
+    /// Start or stop counting how often each FDE is evaluated to unwind a frame, for
+    /// example to find the functions whose unwind information is broken. Recording is
+    /// off by default, and allocates when an FDE is counted for the first time, even
+    /// with [`MustNotAllocateDuringUnwind`](crate::MustNotAllocateDuringUnwind).
+    pub fn record_fde_evaluations(&mut self, record: bool) {
+        self.0.record_fde_evaluations(record)
+    }
+
+    /// How often each FDE was evaluated while recording was enabled with
+    /// [`record_fde_evaluations`](Self::record_fde_evaluations).
+    ///
+    /// The keys are the start address of the module's address range, which also
+    /// identifies the module for `remove_module`, and the offset of the FDE in the
+    /// module's `.eh_frame` or `.debug_frame` section, as returned by `debug_fde_offset`.
+    /// An FDE is counted when its unwind information is evaluated, even if unwinding the
+    /// frame then fails. This only happens on cache misses: frames whose unwind rule was
+    /// found in the cache don't look at an FDE, so the counts are not the number of
+    /// frames which each FDE covers.
+    pub fn fde_evaluations(&self) -> &BTreeMap<(u64, u32), u64> {
+        self.0.fde_evaluations()
+    }
+
+    /// Forget the counts returned by [`fde_evaluations`](CacheX86_64::fde_evaluations).
+    pub fn reset_fde_evaluations(&mut self) {
+        self.0.reset_fde_evaluations()
+    }
+
+    /// Removes all cached unwind rules and the cached address range without modules,
+    /// but keeps the allocated memory and the usage statistics.
+    ///
+    /// Cached rules are keyed by address. They are discarded automatically when modules
+    /// are added to or removed from the unwinder, but not if the cache is used with a
+    /// different unwinder, for example one for another process in which the same
+    /// modules are mapped at different addresses. Clear the cache, or use a new one,
+    /// whenever the address space it is used for changes.
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
 }
 
 impl<P: AllocationPolicy> Default for CacheX86_64<P> {
@@ -3,35 +3,84 @@ use gimli::{
     UnwindContextStorage, UnwindSection, UnwindTableRow, X86_64,
 };
 
-use super::{arch::ArchX86_64, unwind_rule::UnwindRuleX86_64, unwindregs::UnwindRegsX86_64};
+use super::{
+    arch::ArchX86_64,
+    unwind_rule::UnwindRuleX86_64,
+    unwindregs::{Reg, UnwindRegsX86_64},
+};
+use crate::diagnostics::diagnostic;
 use crate::dwarf::{
-    eval_cfa_rule, eval_register_rule, ConversionError, DwarfUnwindRegs, DwarfUnwinderError,
-    DwarfUnwinding,
+    eval_cfa_rule, eval_error, eval_register_rule, scaled_offset, ConversionError, DwarfUnwindRegs,
+    DwarfUnwinderError, DwarfUnwinding,
+};
+use crate::error::Error;
+use crate::memory::{
+    complete_sync, AsyncMemoryAccess, MemoryAccess, SyncMemory, TrackReadFailures,
 };
+use crate::register_set::RegisterSet;
 use crate::unwind_result::UnwindResult;
+use crate::FrameAddress;
 
 impl DwarfUnwindRegs for UnwindRegsX86_64 {
     fn get(&self, register: Register) -> Option<u64> {
-        match register {
-            X86_64::RA => Some(self.ip()),
-            X86_64::RSP => Some(self.sp()),
-            X86_64::RBP => Some(self.bp()),
-            _ => None,
-        }
+        // All general purpose registers are needed for DW_CFA_register rules, which
+        // say that a register's caller value is held in a different register.
+        let unknown = self.unknown_registers();
+        let reg = match register {
+            X86_64::RA if unknown.contains(RegisterSet::PC) => return None,
+            X86_64::RSP if unknown.contains(RegisterSet::SP) => return None,
+            X86_64::RBP if unknown.contains(RegisterSet::FP) => return None,
+            X86_64::RA => return Some(self.ip()),
+            X86_64::RAX => Reg::RAX,
+            X86_64::RDX => Reg::RDX,
+            X86_64::RCX => Reg::RCX,
+            X86_64::RBX => Reg::RBX,
+            X86_64::RSI => Reg::RSI,
+            X86_64::RDI => Reg::RDI,
+            X86_64::RBP => Reg::RBP,
+            X86_64::RSP => Reg::RSP,
+            X86_64::R8 => Reg::R8,
+            X86_64::R9 => Reg::R9,
+            X86_64::R10 => Reg::R10,
+            X86_64::R11 => Reg::R11,
+            X86_64::R12 => Reg::R12,
+            X86_64::R13 => Reg::R13,
+            X86_64::R14 => Reg::R14,
+            X86_64::R15 => Reg::R15,
+            _ => return None,
+        };
+        Some(self.get(reg))
     }
 }
 
+/// The callee-saved registers which are restored if
+/// [`UnwindRegsX86_64::track_callee_saved`] was called. rbp is always restored.
+const CALLEE_SAVED_REGS: [(Register, Reg); 5] = [
+    (X86_64::RBX, Reg::RBX),
+    (X86_64::R12, Reg::R12),
+    (X86_64::R13, Reg::R13),
+    (X86_64::R14, Reg::R14),
+    (X86_64::R15, Reg::R15),
+];
+
 impl DwarfUnwinding for ArchX86_64 {
-    fn unwind_frame<F, R, UCS, ES>(
+    const RETURN_ADDRESS_REGISTER: Register = X86_64::RA;
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        X86_64::register_name(register)
+    }
+
+    async fn unwind_frame<F, R, UCS, ES>(
         section: &impl UnwindSection<R>,
         unwind_info: &UnwindTableRow<R::Offset, UCS>,
         encoding: Encoding,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
+        _lookup_svma: u64,
         read_stack: &mut F,
     ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         R: Reader,
         UCS: UnwindContextStorage<R::Offset>,
         ES: EvaluationStorage<R>,
@@ -40,35 +89,59 @@ impl DwarfUnwinding for ArchX86_64 {
         let bp_rule = unwind_info.register(X86_64::RBP);
         let ra_rule = unwind_info.register(X86_64::RA);
 
-        match translate_into_unwind_rule(cfa_rule, &bp_rule, &ra_rule) {
-            Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
-            Err(_err) => {
-                // Could not translate into a cacheable unwind rule. Fall back to the generic path.
-                // eprintln!("Unwind rule translation failed: {:?}", err);
+        // Unwind rules only restore rbp, so they can't be used if the callee-saved
+        // registers are tracked.
+        if regs.callee_saved().is_none() {
+            match Self::rule_for_row(unwind_info) {
+                Ok(unwind_rule) => return Ok(UnwindResult::ExecRule(unwind_rule)),
+                Err(err) => {
+                    // Could not translate into a cacheable unwind rule. Fall back to the generic path.
+                    diagnostic!(
+                        "Unwind rule translation failed at ip 0x{:x}: {:?}",
+                        regs.ip(),
+                        err
+                    );
+                }
             }
         }
 
-        let cfa = eval_cfa_rule::<R, _, ES>(section, cfa_rule, encoding, regs)
-            .ok_or(DwarfUnwinderError::CouldNotRecoverCfa)?;
+        let read_stack = &mut TrackReadFailures::new(read_stack);
+        let cfa = eval_cfa_rule::<R, _, _, ES>(section, cfa_rule, encoding, regs, read_stack)
+            .await
+            .ok_or_else(|| {
+                eval_error::<Self, _, _, _>(
+                    unwind_info,
+                    read_stack,
+                    DwarfUnwinderError::CouldNotRecoverCfa,
+                )
+            })?;
 
         let ip = regs.ip();
         let bp = regs.bp();
         let sp = regs.sp();
 
-        let new_bp = eval_register_rule::<R, F, _, ES>(
+        let new_bp = eval_register_rule::<R, _, _, ES>(
             section, bp_rule, cfa, encoding, bp, regs, read_stack,
         )
+        .await
         .unwrap_or(bp);
 
-        let return_address = match eval_register_rule::<R, F, _, ES>(
+        let return_address = match eval_register_rule::<R, _, _, ES>(
             section, ra_rule, cfa, encoding, ip, regs, read_stack,
-        ) {
+        )
+        .await
+        {
             Some(ra) => ra,
-            None => {
-                read_stack(cfa - 8).map_err(|_| DwarfUnwinderError::CouldNotRecoverReturnAddress)?
-            }
+            None => read_stack
+                .read_u64(cfa - 8)
+                .await
+                .map_err(|_| DwarfUnwinderError::CouldNotRecoverReturnAddress)?,
         };
 
+        if return_address == 0 {
+            // The outermost frame, e.g. _start, has a null return address.
+            return Ok(UnwindResult::Uncacheable(0));
+        }
         if cfa == sp && return_address == ip {
             return Err(DwarfUnwinderError::DidNotAdvance);
         }
@@ -76,9 +149,35 @@ impl DwarfUnwinding for ArchX86_64 {
             return Err(DwarfUnwinderError::StackPointerMovedBackwards);
         }
 
+        // All rules need to be evaluated with the register values of this frame, so the
+        // callee-saved registers are only updated at the end.
+        let tracks_callee_saved = regs.callee_saved().is_some();
+        let mut callee_saved = [0; 5];
+        if tracks_callee_saved {
+            for ((register, reg), value) in CALLEE_SAVED_REGS.iter().zip(&mut callee_saved) {
+                let old_value = regs.get(*reg);
+                *value = eval_register_rule::<R, _, _, ES>(
+                    section,
+                    unwind_info.register(*register),
+                    cfa,
+                    encoding,
+                    old_value,
+                    regs,
+                    read_stack,
+                )
+                .await
+                .unwrap_or(old_value);
+            }
+        }
+
         regs.set_ip(return_address);
         regs.set_bp(new_bp);
         regs.set_sp(cfa);
+        if tracks_callee_saved {
+            for ((_, reg), value) in CALLEE_SAVED_REGS.iter().zip(callee_saved) {
+                regs.set(*reg, value);
+            }
+        }
 
         Ok(UnwindResult::Uncacheable(return_address))
     }
@@ -86,6 +185,20 @@ impl DwarfUnwinding for ArchX86_64 {
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
         UnwindRuleX86_64::JustReturnIfFirstFrameOtherwiseFp
     }
+
+    fn rule_for_row<RO, UCS>(
+        row: &UnwindTableRow<RO, UCS>,
+    ) -> Result<Self::UnwindRule, ConversionError>
+    where
+        RO: ReaderOffset,
+        UCS: UnwindContextStorage<RO>,
+    {
+        translate_into_unwind_rule(
+            row.cfa(),
+            &row.register(X86_64::RBP),
+            &row.register(X86_64::RA),
+        )
+    }
 }
 
 fn register_rule_to_cfa_offset<RO: ReaderOffset>(
@@ -125,14 +238,16 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
         CfaRule::RegisterAndOffset { register, offset } => match *register {
             X86_64::RSP => {
                 let sp_offset_by_8 =
-                    u16::try_from(offset / 8).map_err(|_| ConversionError::SpOffsetDoesNotFit)?;
+                    scaled_offset(*offset, 8, ConversionError::SpOffsetDoesNotFit)?;
                 let fp_cfa_offset = register_rule_to_cfa_offset(bp_rule)?;
                 match fp_cfa_offset {
                     None => Ok(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 }),
                     Some(bp_cfa_offset) => {
-                        let bp_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + bp_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                        let bp_storage_offset_from_sp_by_8 = scaled_offset(
+                            offset + bp_cfa_offset,
+                            8,
+                            ConversionError::FpStorageOffsetDoesNotFit,
+                        )?;
                         Ok(UnwindRuleX86_64::OffsetSpAndRestoreBp {
                             sp_offset_by_8,
                             bp_storage_offset_from_sp_by_8,
@@ -162,3 +277,139 @@ fn translate_into_unwind_rule<RO: ReaderOffset>(
         CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
     }
 }
+
+/// Unwind a single frame with the FDE at `fde_offset` in the given `.eh_frame` data,
+/// without creating a [`Module`](crate::Module) and an unwinder. This is meant for
+/// fuzzing and testing the DWARF CFI evaluation.
+///
+/// `eh_frame_svma` is the address of the `.eh_frame` section, which is needed for
+/// pc-relative pointers, and the code address in `address` is used as an SVMA. The data
+/// needs to be little-endian. If the FDE doesn't cover the address, the frame pointer
+/// is used, like during normal unwinding. Unlike during normal unwinding, evaluation
+/// errors are returned as [`Error::Dwarf`](crate::Error::Dwarf).
+pub fn unwind_frame_with_raw_eh_frame<F: MemoryAccess>(
+    eh_frame: &[u8],
+    eh_frame_svma: u64,
+    fde_offset: u32,
+    address: FrameAddress,
+    regs: &mut UnwindRegsX86_64,
+    read_stack: &mut F,
+) -> Result<Option<u64>, Error> {
+    complete_sync(
+        crate::dwarf::unwind_frame_with_raw_eh_frame::<ArchX86_64, _>(
+            eh_frame,
+            eh_frame_svma,
+            fde_offset,
+            address,
+            regs,
+            &mut SyncMemory(read_stack),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::vec::Vec;
+    use gimli::{BaseAddresses, EhFrame, LittleEndian, UnwindContext};
+
+    #[test]
+    fn test_prologue_rules() {
+        // The CIE: CFA=rsp+8: rip=[CFA-8], with code alignment 1 and data alignment -8.
+        let mut eh_frame: Vec<u8> = Vec::new();
+        eh_frame.extend_from_slice(&20u32.to_le_bytes());
+        eh_frame.extend_from_slice(&0u32.to_le_bytes()); // CIE id
+        eh_frame.extend_from_slice(&[1, b'z', b'R', 0, 1, 0x78, 16, 1, 0x00]);
+        eh_frame.extend_from_slice(&[0x0c, 0x07, 0x08, 0x90, 0x01, 0, 0]);
+        // The FDE for a function at 0x100..0x120 which starts with this prologue:
+        //   0x100: push rbp
+        //   0x101: mov rbp, rsp
+        //   0x104: ...
+        eh_frame.extend_from_slice(&36u32.to_le_bytes());
+        eh_frame.extend_from_slice(&28u32.to_le_bytes()); // CIE pointer
+        eh_frame.extend_from_slice(&0x100u64.to_le_bytes());
+        eh_frame.extend_from_slice(&0x20u64.to_le_bytes());
+        eh_frame.push(0); // augmentation data length
+        eh_frame.extend_from_slice(&[
+            0x41, // DW_CFA_advance_loc: 1
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: rsp+16
+            0x86, 0x02, // DW_CFA_offset: rbp at CFA-16
+            0x43, // DW_CFA_advance_loc: 3
+            0x0d, 0x06, // DW_CFA_def_cfa_register: rbp
+            0, 0, 0, 0, 0, 0, 0, // DW_CFA_nop
+        ]);
+        eh_frame.extend_from_slice(&0u32.to_le_bytes()); // terminator
+
+        let eh_frame = EhFrame::new(&eh_frame, LittleEndian);
+        let bases = BaseAddresses::default();
+        let mut ctx = UnwindContext::new();
+        let fde = eh_frame
+            .fde_for_address(&bases, 0x100, EhFrame::cie_from_offset)
+            .unwrap();
+        let rule_at = |ctx: &mut UnwindContext<usize>, address| {
+            let row = fde
+                .unwind_info_for_address(&eh_frame, &bases, ctx, address)
+                .unwrap();
+            ArchX86_64::rule_for_row(row).unwrap()
+        };
+
+        assert_eq!(
+            rule_at(&mut ctx, 0x100),
+            UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 }
+        );
+        for address in 0x101..0x104 {
+            assert_eq!(
+                rule_at(&mut ctx, address),
+                UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                    sp_offset_by_8: 2,
+                    bp_storage_offset_from_sp_by_8: 0,
+                }
+            );
+        }
+        for address in 0x104..0x120 {
+            assert_eq!(
+                rule_at(&mut ctx, address),
+                UnwindRuleX86_64::UseFramePointer
+            );
+        }
+    }
+
+    #[test]
+    fn test_offsets_which_dont_fit() {
+        let ra_rule = RegisterRule::<usize>::Offset(-8);
+        let cfa_rule = |offset| CfaRule::<usize>::RegisterAndOffset {
+            register: X86_64::RSP,
+            offset,
+        };
+        // A 1 MiB stack frame.
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(0x100008), &RegisterRule::Undefined, &ra_rule),
+            Err(ConversionError::SpOffsetDoesNotFit)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(12), &RegisterRule::Undefined, &ra_rule),
+            Err(ConversionError::SpOffsetDoesNotFit)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(-8), &RegisterRule::Undefined, &ra_rule),
+            Err(ConversionError::SpOffsetDoesNotFit)
+        );
+        // A 256 KiB frame with rbp saved right below the return address, too far from
+        // the stack pointer for an i16.
+        assert_eq!(
+            translate_into_unwind_rule(&cfa_rule(0x40010), &RegisterRule::Offset(-0x10), &ra_rule),
+            Err(ConversionError::FpStorageOffsetDoesNotFit)
+        );
+        assert_eq!(
+            translate_into_unwind_rule(
+                &cfa_rule(0x40010),
+                &RegisterRule::Offset(-0x8010),
+                &ra_rule
+            ),
+            Ok(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 0x8002,
+                bp_storage_offset_from_sp_by_8: 0x7000,
+            })
+        );
+    }
+}
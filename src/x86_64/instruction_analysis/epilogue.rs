@@ -6,9 +6,17 @@ pub fn unwind_rule_from_detected_epilogue(
 ) -> Option<UnwindRuleX86_64> {
     let (slice_from_start, slice_to_end) = text_bytes.split_at(pc_offset);
 
-    let mut sp_offset_by_8 = 0;
+    let mut sp_offset_by_8: u16 = 0;
     let mut bp_offset_by_8 = None;
+    let mut has_popped = false;
     let mut bytes = slice_to_end;
+
+    // Detect leave; ret and mov rsp, rbp; pop rbp; ret. Both restore rsp and rbp
+    // from the frame pointer.
+    if bytes.starts_with(&[0xc9, 0xc3]) || bytes.starts_with(&[0x48, 0x89, 0xec, 0x5d, 0xc3]) {
+        return Some(UnwindRuleX86_64::UseFramePointer);
+    }
+
     loop {
         if bytes.is_empty() {
             return None;
@@ -26,7 +34,7 @@ pub fn unwind_rule_from_detected_epilogue(
             // starts and ends.
             // For now, we instead use the following heuristic: Any jmp that directly follows
             // a `pop` instruction is treated as a tail call.
-            if sp_offset_by_8 != 0 {
+            if has_popped {
                 // We have detected a pop in the previous loop iteration.
                 break;
             }
@@ -45,23 +53,40 @@ pub fn unwind_rule_from_detected_epilogue(
         }
         // Detect pop rbp
         if bytes[0] == 0x5d {
-            bp_offset_by_8 = Some(sp_offset_by_8 as i16);
-            sp_offset_by_8 += 1;
+            bp_offset_by_8 = Some(i16::try_from(sp_offset_by_8).ok()?);
+            sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
+            has_popped = true;
             bytes = &bytes[1..];
             continue;
         }
         // Detect pop rXX
         if (0x58..=0x5f).contains(&bytes[0]) {
-            sp_offset_by_8 += 1;
+            sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
+            has_popped = true;
             bytes = &bytes[1..];
             continue;
         }
         // Detect pop rXX with prefix
         if bytes.len() >= 2 && bytes[0] & 0xfe == 0x40 && bytes[1] & 0xf8 == 0x58 {
-            sp_offset_by_8 += 1;
+            sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
+            has_popped = true;
             bytes = &bytes[2..];
             continue;
         }
+        // Detect add rsp, 0xXX (8-bit immediate operand), before the pops
+        if !has_popped && bytes.len() >= 4 && bytes[0..3] == [0x48, 0x83, 0xc4] {
+            sp_offset_by_8 =
+                sp_offset_by_8.checked_add(stack_adjustment_by_8(bytes[3] as i8 as i64)?)?;
+            bytes = &bytes[4..];
+            continue;
+        }
+        // Detect add rsp, 0xXX (32-bit immediate operand), before the pops
+        if !has_popped && bytes.len() >= 7 && bytes[0..3] == [0x48, 0x81, 0xc4] {
+            let imm = i32::from_le_bytes([bytes[3], bytes[4], bytes[5], bytes[6]]);
+            sp_offset_by_8 = sp_offset_by_8.checked_add(stack_adjustment_by_8(imm as i64)?)?;
+            bytes = &bytes[7..];
+            continue;
+        }
         // Unexpected instruction.
         // This probably means that we weren't in an epilogue after all.
         return None;
@@ -71,7 +96,7 @@ pub fn unwind_rule_from_detected_epilogue(
     let rule = if sp_offset_by_8 == 0 {
         UnwindRuleX86_64::JustReturn
     } else {
-        sp_offset_by_8 += 1; // Add one for popping the return address.
+        sp_offset_by_8 = sp_offset_by_8.checked_add(1)?; // Add one for popping the return address.
         if let Some(bp_storage_offset_from_sp_by_8) = bp_offset_by_8 {
             UnwindRuleX86_64::OffsetSpAndRestoreBp {
                 sp_offset_by_8,
@@ -83,3 +108,94 @@ pub fn unwind_rule_from_detected_epilogue(
     };
     Some(rule)
 }
+
+/// Convert the immediate operand of `add rsp, imm` into a number of 8-byte stack slots.
+fn stack_adjustment_by_8(imm: i64) -> Option<u16> {
+    if imm <= 0 || imm % 8 != 0 {
+        return None;
+    }
+    u16::try_from(imm / 8).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_epilogue_with_stack_adjustment() {
+        // 48 83 c4 18     add  rsp, 0x18
+        // 5b              pop  rbx
+        // 5d              pop  rbp
+        // c3              ret
+        let bytes = &[0x48, 0x83, 0xc4, 0x18, 0x5b, 0x5d, 0xc3];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 0),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 6,
+                bp_storage_offset_from_sp_by_8: 4,
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 4),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 3,
+                bp_storage_offset_from_sp_by_8: 1,
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 6),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+
+        // 48 81 c4 00 01 00 00     add  rsp, 0x100
+        // c3                       ret
+        let bytes = &[0x48, 0x81, 0xc4, 0x00, 0x01, 0x00, 0x00, 0xc3];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 0),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 33 })
+        );
+
+        // add rsp, 0x4 is not a whole stack slot.
+        let bytes = &[0x48, 0x83, 0xc4, 0x04, 0xc3];
+        assert_eq!(unwind_rule_from_detected_epilogue(bytes, 0), None);
+    }
+
+    #[test]
+    fn test_epilogue_with_frame_pointer() {
+        // c9      leave
+        // c3      ret
+        let bytes = &[0xc9, 0xc3];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 0),
+            Some(UnwindRuleX86_64::UseFramePointer)
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 1),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+
+        // 48 89 ec     mov  rsp, rbp
+        // 5d           pop  rbp
+        // c3           ret
+        let bytes = &[0x48, 0x89, 0xec, 0x5d, 0xc3];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 0),
+            Some(UnwindRuleX86_64::UseFramePointer)
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(bytes, 3),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 2,
+                bp_storage_offset_from_sp_by_8: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_jmp_after_stack_adjustment_is_not_a_tail_call() {
+        // 48 83 c4 08     add  rsp, 0x8
+        // eb 10           jmp  +0x10
+        let bytes = &[0x48, 0x83, 0xc4, 0x08, 0xeb, 0x10];
+        assert_eq!(unwind_rule_from_detected_epilogue(bytes, 0), None);
+    }
+}
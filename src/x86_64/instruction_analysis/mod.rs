@@ -1,5 +1,5 @@
 use super::arch::ArchX86_64;
-use crate::instruction_analysis::InstructionAnalysis;
+use crate::instruction_analysis::{is_after_x86_call_instruction, InstructionAnalysis};
 
 mod epilogue;
 mod prologue;
@@ -21,4 +21,8 @@ impl InstructionAnalysis for ArchX86_64 {
     ) -> Option<Self::UnwindRule> {
         unwind_rule_from_detected_epilogue(text_bytes, pc_offset)
     }
+
+    fn is_after_call_instruction(text_bytes: &[u8], return_offset: usize) -> bool {
+        is_after_x86_call_instruction(text_bytes, return_offset)
+    }
 }
@@ -98,3 +98,58 @@ fn is_next_instruction_expected_in_prologue(bytes: &[u8]) -> bool {
 // 442405  53           push  rbx
 // 442406  48 83 EC 18  sub  rsp, 0x18
 // 44240a  48 8B 07     mov  rax, qword [rdi]
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prologue() {
+        // 55              push  rbp
+        // 48 89 e5        mov   rbp, rsp
+        // 41 57           push  r15
+        // 53              push  rbx
+        // 48 83 ec 18     sub   rsp, 0x18
+        // 48 8b 07        mov   rax, qword [rdi]
+        let bytes = &[
+            0x55, 0x48, 0x89, 0xe5, 0x41, 0x57, 0x53, 0x48, 0x83, 0xec, 0x18, 0x48, 0x8b, 0x07,
+        ];
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 0),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 1),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 4),
+            Some(UnwindRuleX86_64::UseFramePointer)
+        );
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 7),
+            Some(UnwindRuleX86_64::UseFramePointer)
+        );
+        // In the function body.
+        assert_eq!(unwind_rule_from_detected_prologue(bytes, 11), None);
+    }
+
+    #[test]
+    fn test_frameless_prologue() {
+        // 41 57                   push  r15
+        // 53                      push  rbx
+        // 48 81 ec 80 00 00 00    sub   rsp, 0x80
+        // 48 89 f3                mov   rbx, rsi
+        let bytes = &[
+            0x41, 0x57, 0x53, 0x48, 0x81, 0xec, 0x80, 0x00, 0x00, 0x00, 0x48, 0x89, 0xf3, 0x90,
+        ];
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 2),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_prologue(bytes, 3),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 3 })
+        );
+    }
+}
@@ -7,12 +7,15 @@ mod macho;
 #[cfg(feature = "pe")]
 mod pe;
 mod register_ordering;
+mod signal;
 mod unwind_rule;
 mod unwinder;
 mod unwindregs;
 
 pub use arch::*;
 pub use cache::*;
+pub use dwarf::unwind_frame_with_raw_eh_frame;
+pub use signal::is_sigreturn_trampoline;
 pub use unwind_rule::*;
 pub use unwinder::*;
 pub use unwindregs::*;
@@ -4,6 +4,8 @@ use super::{
     unwindregs::Reg,
 };
 use crate::arch::Arch;
+use crate::diagnostics::diagnostic;
+use crate::memory::AsyncMemoryAccess;
 use crate::pe::{PeSections, PeUnwinderError, PeUnwinding};
 use crate::unwind_result::UnwindResult;
 use core::ops::ControlFlow;
@@ -11,33 +13,63 @@ use core::ops::ControlFlow;
 use alloc::vec::Vec;
 use pe_unwind_info::x86_64::{
     FunctionEpilogInstruction, FunctionTableEntries, Register, UnwindInfo, UnwindInfoTrailer,
-    UnwindOperation, UnwindState,
+    UnwindOperation,
 };
 
-struct State<'a, F> {
-    regs: &'a mut <ArchX86_64 as Arch>::UnwindRegs,
-    read_stack: &'a mut F,
-}
-
-impl<F> UnwindState for State<'_, F>
+/// Perform `op` on `regs`, like [`UnwindInfo::resolve_operation`], but reading the stack
+/// through an [`AsyncMemoryAccess`]. XMM registers aren't tracked, but they're still
+/// read so that unreadable ones fail the same way.
+///
+/// Returns `None` when reading the stack fails.
+async fn resolve_operation<F>(
+    unwind_info: &UnwindInfo<'_>,
+    regs: &mut <ArchX86_64 as Arch>::UnwindRegs,
+    read_stack: &mut F,
+    op: &UnwindOperation,
+) -> Option<ControlFlow<u64>>
 where
-    F: FnMut(u64) -> Result<u64, ()>,
+    F: AsyncMemoryAccess,
 {
-    fn read_register(&mut self, register: Register) -> u64 {
-        self.regs.get(convert_pe_register(register))
-    }
-
-    fn read_stack(&mut self, addr: u64) -> Option<u64> {
-        (self.read_stack)(addr).ok()
-    }
-
-    fn write_register(&mut self, register: Register, value: u64) {
-        self.regs.set(convert_pe_register(register), value)
-    }
-
-    fn write_xmm_register(&mut self, _register: pe_unwind_info::x86_64::XmmRegister, _value: u128) {
-        // Ignore
+    match op {
+        UnwindOperation::PopNonVolatile(reg) => {
+            let rsp = regs.get(Reg::RSP);
+            let value = read_stack.read_u64(rsp).await.ok()?;
+            regs.set(convert_pe_register(*reg), value);
+            regs.set(Reg::RSP, rsp + 8);
+        }
+        UnwindOperation::UnStackAlloc(bytes) => {
+            let rsp = regs.get(Reg::RSP);
+            regs.set(Reg::RSP, rsp + *bytes as u64);
+        }
+        UnwindOperation::RestoreSPFromFP => {
+            if let Some(reg) = unwind_info.frame_register() {
+                let value =
+                    regs.get(convert_pe_register(reg)) - unwind_info.frame_register_offset() as u64;
+                regs.set(Reg::RSP, value);
+            }
+        }
+        UnwindOperation::ReadNonVolatile(reg, offset) => {
+            let addr =
+                unwind_info.resolve_offset(|reg| regs.get(convert_pe_register(reg)), *offset);
+            let value = read_stack.read_u64(addr).await.ok()?;
+            regs.set(convert_pe_register(*reg), value);
+        }
+        UnwindOperation::ReadXMM(_reg, offset) => {
+            let addr =
+                unwind_info.resolve_offset(|reg| regs.get(convert_pe_register(reg)), *offset);
+            read_stack.read_u64(addr).await.ok()?;
+            read_stack.read_u64(addr + 8).await.ok()?;
+        }
+        UnwindOperation::PopMachineFrame { error_code } => {
+            let offset = if *error_code { 8 } else { 0 };
+            let rsp = regs.get(Reg::RSP);
+            let return_address = read_stack.read_u64(rsp + offset).await.ok()?;
+            let rsp = read_stack.read_u64(rsp + offset + 24).await.ok()?;
+            regs.set(Reg::RSP, rsp);
+            return Some(ControlFlow::Break(return_address));
+        }
     }
+    Some(ControlFlow::Continue(()))
 }
 
 fn convert_pe_register(r: Register) -> Reg {
@@ -94,15 +126,15 @@ impl From<&'_ UnwindOperation> for OffsetOrPop {
 }
 
 impl PeUnwinding for ArchX86_64 {
-    fn unwind_frame<F, D>(
-        sections: PeSections<D>,
+    async fn unwind_frame<F, D>(
+        sections: PeSections<'_, D>,
         address: u32,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
         read_stack: &mut F,
     ) -> Result<UnwindResult<Self::UnwindRule>, PeUnwinderError>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
         D: core::ops::Deref<Target = [u8]>,
     {
         let entries = FunctionTableEntries::parse(sections.pdata);
@@ -110,8 +142,11 @@ impl PeUnwinding for ArchX86_64 {
             return Ok(UnwindResult::ExecRule(UnwindRuleX86_64::JustReturn));
         };
 
-        let read_stack_err = |read_stack: &mut F, addr| {
-            read_stack(addr).map_err(|()| PeUnwinderError::MissingStackData(Some(addr)))
+        let read_stack_err = async |read_stack: &mut F, addr| {
+            read_stack
+                .read_u64(addr)
+                .await
+                .map_err(|_| PeUnwinderError::MissingStackData(Some(addr)))
         };
 
         let unwind_info_address = function.unwind_info_address.get();
@@ -124,11 +159,19 @@ impl PeUnwinding for ArchX86_64 {
             // simulate the remaining epilog instructions (unwind codes don't account for
             // unwinding from the epilog). We only need to check this for the first unwind info (if
             // there are chained infos).
+            // If the function's code isn't available, we can't detect epilogs, but the
+            // unwind codes are still correct for the function body.
             let bytes = (function.end_address.get() - address) as usize;
-            let instruction = &sections.text_memory_at_rva(address)?[..bytes];
-            if let Ok(epilog_instructions) =
+            let instruction = match sections.text_memory_at_rva(address) {
+                Ok(text) => text.get(..bytes),
+                Err(err) => {
+                    diagnostic!("Skipping epilog detection: {}", err);
+                    None
+                }
+            };
+            if let Some(Ok(epilog_instructions)) = instruction.map(|instruction| {
                 FunctionEpilogInstruction::parse_sequence(instruction, unwind_info.frame_register())
-            {
+            }) {
                 // If the epilog is an optional AddSP followed by Pops, we can return a cache
                 // rule.
                 if let Some(rule) =
@@ -153,7 +196,7 @@ impl PeUnwinding for ArchX86_64 {
                         }
                         FunctionEpilogInstruction::Pop(reg) => {
                             let rsp = regs.get(Reg::RSP);
-                            let val = read_stack_err(read_stack, rsp)?;
+                            let val = read_stack_err(read_stack, rsp).await?;
                             regs.set(convert_pe_register(*reg), val);
                             regs.set(Reg::RSP, rsp + 8);
                         }
@@ -161,7 +204,7 @@ impl PeUnwinding for ArchX86_64 {
                 }
 
                 let rsp = regs.get(Reg::RSP);
-                let ra = read_stack_err(read_stack, rsp)?;
+                let ra = read_stack_err(read_stack, rsp).await?;
                 regs.set(Reg::RSP, rsp + 8);
 
                 return Ok(UnwindResult::Uncacheable(ra));
@@ -205,10 +248,9 @@ impl PeUnwinding for ArchX86_64 {
         }
 
         // Resolve operations to get the return address.
-        let mut state = State { regs, read_stack };
         for op in operations {
-            if let ControlFlow::Break(ra) = unwind_info
-                .resolve_operation(&mut state, &op)
+            if let ControlFlow::Break(ra) = resolve_operation(&unwind_info, regs, read_stack, &op)
+                .await
                 .ok_or(PeUnwinderError::MissingStackData(None))?
             {
                 return Ok(UnwindResult::Uncacheable(ra));
@@ -216,9 +258,100 @@ impl PeUnwinding for ArchX86_64 {
         }
 
         let rsp = regs.get(Reg::RSP);
-        let ra = read_stack_err(read_stack, rsp)?;
+        let ra = read_stack_err(read_stack, rsp).await?;
         regs.set(Reg::RSP, rsp + 8);
 
         Ok(UnwindResult::Uncacheable(ra))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+    use crate::pe::DataAtRvaRange;
+    use crate::x86_64::UnwindRegsX86_64;
+    use alloc::vec;
+
+    // A function at RVA 0x1000..0x100e with the following code:
+    //
+    // 0x1000  53            push rbx
+    // 0x1001  48 83 ec 20   sub  rsp, 0x20
+    // 0x1005  90 90 90 90   nop (function body)
+    // 0x1009  48 83 c4 20   add  rsp, 0x20
+    // 0x100d  5b            pop  rbx
+    // 0x100e  c3            ret
+    const TEXT: [u8; 15] = [
+        0x53, 0x48, 0x83, 0xec, 0x20, 0x90, 0x90, 0x90, 0x90, 0x48, 0x83, 0xc4, 0x20, 0x5b, 0xc3,
+    ];
+
+    fn pdata() -> Vec<u8> {
+        [0x1000u32, 0x100f, 0x2000]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect()
+    }
+
+    fn xdata() -> DataAtRvaRange<Vec<u8>> {
+        DataAtRvaRange {
+            // Version 1, prolog size 5, 2 unwind codes, no frame register.
+            // UWOP_ALLOC_SMALL 0x20 at offset 5, UWOP_PUSH_NONVOL rbx at offset 1.
+            data: vec![0x01, 0x05, 0x02, 0x00, 0x05, 0x32, 0x01, 0x30],
+            rva_range: 0x2000..0x2008,
+        }
+    }
+
+    fn unwind(
+        address: u32,
+        text: Option<&DataAtRvaRange<Vec<u8>>>,
+    ) -> Result<UnwindResult<UnwindRuleX86_64>, PeUnwinderError> {
+        let pdata = pdata();
+        let xdata = xdata();
+        let sections = PeSections {
+            pdata: &pdata,
+            rdata: None,
+            xdata: Some(&xdata),
+            text,
+        };
+        let mut regs = UnwindRegsX86_64::new(0x1000 + address as u64, 0x100, 0x200);
+        let mut read_stack = |_| Err(());
+        complete_sync(ArchX86_64::unwind_frame(
+            sections,
+            address,
+            &mut regs,
+            true,
+            &mut SyncMemory(&mut read_stack),
+        ))
+    }
+
+    #[test]
+    fn test_epilog_detection() {
+        let full_text = DataAtRvaRange {
+            data: TEXT.to_vec(),
+            rva_range: 0x1000..0x100f,
+        };
+        let truncated_text = DataAtRvaRange {
+            data: TEXT[..0xb].to_vec(),
+            rva_range: 0x1000..0x100b,
+        };
+        let body_rule = UnwindRuleX86_64::for_sequence_of_offset_or_pop(
+            [OffsetOrPop::OffsetBy8(4), OffsetOrPop::Pop(Reg::RBX)].into_iter(),
+        );
+        let pop_rule = UnwindRuleX86_64::for_sequence_of_offset_or_pop(
+            [OffsetOrPop::Pop(Reg::RBX)].into_iter(),
+        );
+
+        for (address, text, expected) in [
+            (0x1006, Some(&full_text), body_rule),
+            (0x100d, Some(&full_text), pop_rule),
+            // Without code bytes, or with too few of them, the unwind codes are used.
+            (0x1006, None, body_rule),
+            (0x1006, Some(&truncated_text), body_rule),
+        ] {
+            match unwind(address, text) {
+                Ok(UnwindResult::ExecRule(rule)) => assert_eq!(Some(rule), expected),
+                other => panic!("unexpected result {other:?} at 0x{address:x}"),
+            }
+        }
+    }
+}
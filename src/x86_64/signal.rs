@@ -0,0 +1,116 @@
+use super::unwindregs::{Reg, UnwindRegsX86_64};
+use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+
+/// The code of the Linux signal return trampoline (`__restore_rt` in glibc and musl):
+///
+/// ```text
+/// mov    $0xf, %rax    ; __NR_rt_sigreturn
+/// syscall
+/// ```
+const SIGRETURN_CODE: [u8; 9] = [0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+
+/// Returns whether `code` starts with the x86_64 Linux signal return sequence
+/// `mov $15, %rax; syscall`.
+///
+/// This can be used to implement a recognizer for
+/// [`UnwinderX86_64::set_signal_trampoline_recognizer`](super::UnwinderX86_64::set_signal_trampoline_recognizer),
+/// by reading the code bytes at the address passed to the recognizer.
+pub fn is_sigreturn_trampoline(code: &[u8]) -> bool {
+    code.starts_with(&SIGRETURN_CODE)
+}
+
+/// The offset of `uc_mcontext` in `struct ucontext`: `uc_flags`, `uc_link` and the
+/// 24 byte `uc_stack` come first.
+const UC_MCONTEXT_OFFSET: u64 = 40;
+
+/// The registers in `struct sigcontext`, in the order in which they're stored.
+const SIGCONTEXT_REGS: [Reg; 15] = [
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+    Reg::RDI,
+    Reg::RSI,
+    Reg::RBP,
+    Reg::RBX,
+    Reg::RDX,
+    Reg::RAX,
+    Reg::RCX,
+];
+
+/// Restore the registers of the interrupted code from the signal frame.
+///
+/// When the signal handler returns into the trampoline, the return address of the
+/// handler (`pretcode`) has been popped off the stack and `rsp` points at the
+/// `struct ucontext` which the kernel pushed when delivering the signal. Its
+/// `uc_mcontext` contains all general purpose registers, followed by `rsp` and `rip`.
+pub(crate) async fn unwind_signal_frame<F>(
+    regs: &mut UnwindRegsX86_64,
+    read_stack: &mut F,
+) -> Result<Option<u64>, Error>
+where
+    F: AsyncMemoryAccess,
+{
+    let mcontext = regs
+        .sp()
+        .checked_add(UC_MCONTEXT_OFFSET)
+        .ok_or(Error::IntegerOverflow)?;
+    let mut read = async |index: u64| {
+        let addr = mcontext
+            .checked_add(index * 8)
+            .ok_or(Error::IntegerOverflow)?;
+        read_stack.read_u64(addr).await.map_err(Error::from)
+    };
+    let mut new_regs = *regs;
+    for (index, reg) in SIGCONTEXT_REGS.iter().enumerate() {
+        new_regs.set(*reg, read(index as u64).await?);
+    }
+    let new_sp = read(15).await?;
+    let new_ip = read(16).await?;
+    if new_ip == 0 {
+        return Ok(None);
+    }
+    new_regs.set_sp(new_sp);
+    new_regs.set_ip(new_ip);
+    *regs = new_regs;
+    Ok(Some(new_ip))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+
+    #[test]
+    fn test_unwind_signal_frame() {
+        assert!(is_sigreturn_trampoline(&[
+            0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05, 0x90
+        ]));
+        assert!(!is_sigreturn_trampoline(&[0x48, 0xc7, 0xc0, 0x0f]));
+
+        // The ucontext starts at 0x10, so uc_mcontext starts at 0x38.
+        let mut stack = [0u64; 32];
+        for (i, slot) in stack[7..22].iter_mut().enumerate() {
+            *slot = 0x100 + i as u64;
+        }
+        stack[22] = 0x1234; // rsp
+        stack[23] = 0x5678; // rip
+        let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+        let mut regs = UnwindRegsX86_64::new(0x7f00, 0x10, 0x20);
+        let res = complete_sync(unwind_signal_frame(
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
+        assert_eq!(res, Ok(Some(0x5678)));
+        assert_eq!(regs.ip(), 0x5678);
+        assert_eq!(regs.sp(), 0x1234);
+        assert_eq!(regs.bp(), 0x10a);
+        assert_eq!(regs.get(Reg::R8), 0x100);
+        assert_eq!(regs.get(Reg::RCX), 0x10e);
+    }
+}
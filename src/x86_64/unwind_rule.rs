@@ -2,11 +2,21 @@ use super::register_ordering;
 use super::unwindregs::{Reg, UnwindRegsX86_64};
 use crate::add_signed::checked_add_signed;
 use crate::error::Error;
+use crate::memory::AsyncMemoryAccess;
+use crate::register_set::RegisterSet;
 use crate::unwind_rule::UnwindRule;
 use arrayvec::ArrayVec;
 
 /// For all of these: return address is *(new_sp - 8)
+///
+/// Unwind rules are `Copy` and at most 8 bytes large, which is checked at compile time,
+/// so that they can be cached and stored in large numbers. The variants and their fields
+/// are public, so rules can also be built outside of framehop, for example for
+/// [`UnwinderX86_64::add_module_with_rules`](crate::x86_64::UnwinderX86_64::add_module_with_rules). The
+/// memory layout is not guaranteed; to persist rules, store their variants and fields, or
+/// enable the `serde` feature, which implements `Serialize` and `Deserialize` for them.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnwindRuleX86_64 {
     EndOfStack,
     /// (sp, bp) = (sp + 8, bp)
@@ -41,6 +51,8 @@ pub enum UnwindRuleX86_64 {
     },
 }
 
+const _: () = assert!(core::mem::size_of::<UnwindRuleX86_64>() <= 8);
+
 pub enum OffsetOrPop {
     None,
     OffsetBy8(u16),
@@ -48,6 +60,24 @@ pub enum OffsetOrPop {
 }
 
 impl UnwindRuleX86_64 {
+    /// The registers whose current values this rule reads, see [`RegisterSet`].
+    ///
+    /// Registers which the rule passes through to the caller unchanged are not included.
+    /// If the rule behaves differently for the first frame, the registers needed by either
+    /// case are included. This lets a caller which didn't capture all registers know in
+    /// advance whether a rule can be applied.
+    pub fn used_registers(&self) -> RegisterSet {
+        match self {
+            UnwindRuleX86_64::EndOfStack => RegisterSet::EMPTY,
+            UnwindRuleX86_64::JustReturn
+            | UnwindRuleX86_64::OffsetSp { .. }
+            | UnwindRuleX86_64::OffsetSpAndRestoreBp { .. }
+            | UnwindRuleX86_64::OffsetSpAndPopRegisters { .. } => RegisterSet::SP,
+            UnwindRuleX86_64::JustReturnIfFirstFrameOtherwiseFp
+            | UnwindRuleX86_64::UseFramePointer => RegisterSet::SP | RegisterSet::FP,
+        }
+    }
+
     /// Get the rule which represents the given operations, if possible.
     pub fn for_sequence_of_offset_or_pop<I, T>(iter: I) -> Option<Self>
     where
@@ -89,6 +119,10 @@ impl UnwindRuleX86_64 {
 impl UnwindRule for UnwindRuleX86_64 {
     type UnwindRegs = UnwindRegsX86_64;
 
+    fn used_registers(&self) -> RegisterSet {
+        UnwindRuleX86_64::used_registers(self)
+    }
+
     fn rule_for_stub_functions() -> Self {
         UnwindRuleX86_64::JustReturn
     }
@@ -99,14 +133,14 @@ impl UnwindRule for UnwindRuleX86_64 {
         UnwindRuleX86_64::UseFramePointer
     }
 
-    fn exec<F>(
+    async fn exec<F>(
         self,
         is_first_frame: bool,
         regs: &mut UnwindRegsX86_64,
         read_stack: &mut F,
     ) -> Result<Option<u64>, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
     {
         let sp = regs.sp();
         let (new_sp, new_bp) = match self {
@@ -126,7 +160,7 @@ impl UnwindRule for UnwindRuleX86_64 {
                     if new_sp <= sp {
                         return Err(Error::FramepointerUnwindingMovedBackwards);
                     }
-                    let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                    let new_bp = read_stack.read_u64(bp).await?;
                     (new_sp, new_bp)
                 }
             }
@@ -144,9 +178,9 @@ impl UnwindRule for UnwindRuleX86_64 {
                 let bp_storage_offset_from_sp = i64::from(bp_storage_offset_from_sp_by_8) * 8;
                 let bp_location = checked_add_signed(sp, bp_storage_offset_from_sp)
                     .ok_or(Error::IntegerOverflow)?;
-                let new_bp = match read_stack(bp_location) {
+                let new_bp = match read_stack.read_u64(bp_location).await {
                     Ok(new_bp) => new_bp,
-                    Err(()) if is_first_frame && bp_location < sp => {
+                    Err(_) if is_first_frame && bp_location < sp => {
                         // Ignore errors when reading beyond the stack pointer in the first frame.
                         // These negative offsets are sometimes seen in x86_64 epilogues, where
                         // a bunch of registers are popped one after the other, and the compiler
@@ -157,7 +191,7 @@ impl UnwindRule for UnwindRuleX86_64 {
                         // sample record, where the ustack bytes are copied starting from sp.
                         regs.bp()
                     }
-                    Err(()) => return Err(Error::CouldNotReadStack(bp_location)),
+                    Err(err) => return Err(err.into()),
                 };
                 (new_sp, new_bp)
             }
@@ -210,7 +244,7 @@ impl UnwindRule for UnwindRuleX86_64 {
                 if new_sp <= sp {
                     return Err(Error::FramepointerUnwindingMovedBackwards);
                 }
-                let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                let new_bp = read_stack.read_u64(bp).await?;
                 // new_bp is the caller's bp. If the caller uses frame pointers, then bp should be
                 // a valid frame pointer and we could do a coherency check on new_bp to make sure
                 // it's moving in the right direction. But if the caller is using bp as a general
@@ -229,15 +263,14 @@ impl UnwindRule for UnwindRuleX86_64 {
                     .checked_add(sp_offset_by_8 as u64 * 8)
                     .ok_or(Error::IntegerOverflow)?;
                 for reg in register_ordering::decode(register_count, encoded_registers_to_pop) {
-                    let value = read_stack(sp).map_err(|_| Error::CouldNotReadStack(sp))?;
+                    let value = read_stack.read_u64(sp).await?;
                     sp = sp.checked_add(8).ok_or(Error::IntegerOverflow)?;
                     regs.set(reg, value);
                 }
                 (sp.checked_add(8).ok_or(Error::IntegerOverflow)?, regs.bp())
             }
         };
-        let return_address =
-            read_stack(new_sp - 8).map_err(|_| Error::CouldNotReadStack(new_sp - 8))?;
+        let return_address = read_stack.read_u64(new_sp - 8).await?;
         if return_address == 0 {
             return Ok(None);
         }
@@ -254,6 +287,17 @@ impl UnwindRule for UnwindRuleX86_64 {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::memory::{complete_sync, SyncMemory};
+
+    #[test]
+    fn test_used_registers() {
+        assert!(UnwindRuleX86_64::EndOfStack.used_registers().is_empty());
+        let rule = UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 };
+        assert_eq!(rule.used_registers(), RegisterSet::SP);
+        let rule = UnwindRuleX86_64::UseFramePointer;
+        assert!(rule.used_registers().contains(RegisterSet::FP));
+        assert!(!(RegisterSet::SP).contains(rule.used_registers()));
+    }
 
     #[test]
     fn test_basic() {
@@ -262,23 +306,38 @@ mod test {
         ];
         let mut read_stack = |addr| Ok(stack[(addr / 8) as usize]);
         let mut regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
-        let res =
-            UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 }.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 }.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100300)));
         assert_eq!(regs.ip(), 0x100300);
         assert_eq!(regs.sp(), 0x18);
         assert_eq!(regs.bp(), 0x20);
-        let res = UnwindRuleX86_64::UseFramePointer.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::UseFramePointer.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100200)));
         assert_eq!(regs.ip(), 0x100200);
         assert_eq!(regs.sp(), 0x30);
         assert_eq!(regs.bp(), 0x40);
-        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(Some(0x100100)));
         assert_eq!(regs.ip(), 0x100100);
         assert_eq!(regs.sp(), 0x50);
         assert_eq!(regs.bp(), 0x70);
-        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::UseFramePointer.exec(
+            false,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Ok(None));
     }
 
@@ -292,18 +351,31 @@ mod test {
         ];
         let mut read_stack = |addr| Ok(stack[(addr / 8) as usize]);
         let mut regs = UnwindRegsX86_64::new(0x100400, u64::MAX / 8 * 8, u64::MAX);
-        let res = UnwindRuleX86_64::JustReturn.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::JustReturn.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Err(Error::IntegerOverflow));
-        let res =
-            UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 }.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 }.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Err(Error::IntegerOverflow));
-        let res = UnwindRuleX86_64::OffsetSpAndRestoreBp {
-            sp_offset_by_8: 1,
-            bp_storage_offset_from_sp_by_8: 2,
-        }
-        .exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(
+            UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 1,
+                bp_storage_offset_from_sp_by_8: 2,
+            }
+            .exec(true, &mut regs, &mut SyncMemory(&mut read_stack)),
+        );
         assert_eq!(res, Err(Error::IntegerOverflow));
-        let res = UnwindRuleX86_64::UseFramePointer.exec(true, &mut regs, &mut read_stack);
+        let res = complete_sync(UnwindRuleX86_64::UseFramePointer.exec(
+            true,
+            &mut regs,
+            &mut SyncMemory(&mut read_stack),
+        ));
         assert_eq!(res, Err(Error::IntegerOverflow));
     }
 }
@@ -1,12 +1,23 @@
-use core::ops::Deref;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::{Deref, Range};
 
 use super::arch::ArchX86_64;
 use super::cache::CacheX86_64;
+use super::signal::unwind_signal_frame;
+use super::unwind_rule::UnwindRuleX86_64;
 use super::unwindregs::UnwindRegsX86_64;
+use crate::async_unwinder::AsyncUnwinder;
 use crate::cache::{AllocationPolicy, MayAllocateDuringUnwind};
-use crate::error::Error;
-use crate::unwinder::UnwinderInternal;
-use crate::unwinder::{Module, Unwinder};
+use crate::dwarf::{ConversionError, FdeEncoding};
+use crate::error::{AddModuleError, Error};
+use crate::memory::{complete_sync, AsyncMemoryAccess, MemoryAccess, SyncMemory};
+use crate::rule_table::UnwindRuleTable;
+use crate::unwinder::{
+    AddModulePolicy, CheckedUnwindFrameOutcome, Module, PrecomputedRules, RuleSource,
+    UnwindFrameOutcome, UnwindSourcePreference, Unwinder, UnwoundFrame,
+};
+use crate::unwinder::{FrameHooks, UnwinderInternal};
 use crate::FrameAddress;
 
 /// The unwinder for the x86_64 CPU architecture. Use the [`Unwinder`] trait for unwinding.
@@ -15,7 +26,46 @@ use crate::FrameAddress;
 ///
 ///  - `D`: The type for unwind section data in the modules. See [`Module`].
 /// -  `P`: The [`AllocationPolicy`].
-pub struct UnwinderX86_64<D, P = MayAllocateDuringUnwind>(UnwinderInternal<D, ArchX86_64, P>);
+///
+/// Cloning an unwinder is cheap and works for any `D`: the clones share the modules and
+/// their parsed unwind information, see [`Module`]. For example, to unwind on several
+/// threads, give each thread a clone of the unwinder and its own cache.
+pub struct UnwinderX86_64<D, P = MayAllocateDuringUnwind>(
+    UnwinderInternal<D, ArchX86_64, P>,
+    Option<SignalTrampolineRecognizer>,
+);
+
+type SignalTrampolineRecognizer = Arc<dyn Fn(u64) -> bool + Send + Sync>;
+
+/// Unwinds the frames which the recognizer set with
+/// [`UnwinderX86_64::set_signal_trampoline_recognizer`] accepts as signal frames.
+///
+/// This is implemented for any lifetime of the recognizer, not just for the `'static`
+/// one it has, because checking that the future of
+/// [`unwind_frame_async`](crate::AsyncUnwinder::unwind_frame_async) is `Send` needs the
+/// impl to hold for all lifetimes.
+impl FrameHooks<ArchX86_64> for Option<Arc<dyn Fn(u64) -> bool + Send + Sync + '_>> {
+    async fn unwind_special_frame<F: AsyncMemoryAccess>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        read_stack: &mut F,
+    ) -> Option<Result<UnwindFrameOutcome, Error>> {
+        let recognizer = self.as_ref()?;
+        if !recognizer(address.address()) {
+            return None;
+        }
+        let outcome =
+            unwind_signal_frame(regs, read_stack)
+                .await
+                .map(|next_pc| UnwindFrameOutcome {
+                    next_pc,
+                    source: RuleSource::SignalFrame,
+                    next_is_instruction_pointer: true,
+                });
+        Some(outcome)
+    }
+}
 
 impl<D, P> Default for UnwinderX86_64<D, P> {
     fn default() -> Self {
@@ -25,14 +75,181 @@ impl<D, P> Default for UnwinderX86_64<D, P> {
 
 impl<D, P> Clone for UnwinderX86_64<D, P> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0.clone(), self.1.clone())
     }
 }
 
 impl<D, P> UnwinderX86_64<D, P> {
     /// Create an unwinder for a process.
     pub fn new() -> Self {
-        Self(UnwinderInternal::new())
+        Self(UnwinderInternal::new(), None)
+    }
+
+    /// Recognize signal return trampolines, such as `__restore_rt` on Linux.
+    ///
+    /// When a signal handler returns, it returns into a trampoline which calls
+    /// `rt_sigreturn`. The registers of the interrupted code are saved in the
+    /// `ucontext` on the stack, and can't be recovered with CFA rules.
+    ///
+    /// `recognizer` is called with the address of each frame before it's unwound. If it
+    /// returns true, the address is treated as the start of a signal trampoline and
+    /// `rip`, `rsp`, `rbp` and all other general purpose registers are restored from the
+    /// `sigcontext` on the stack. The returned address is the instruction pointer of the
+    /// interrupted code, not a return address, so the next frame must be unwound with
+    /// [`FrameAddress::from_instruction_pointer`]. [`iter_frames`](Unwinder::iter_frames)
+    /// takes care of this; callers of [`unwind_frame_detailed`](Unwinder::unwind_frame_detailed)
+    /// can check [`UnwindFrameOutcome::next_is_instruction_pointer`].
+    ///
+    /// [`is_sigreturn_trampoline`](super::is_sigreturn_trampoline) can be used to check
+    /// the code bytes at the address for the `mov $15, %rax; syscall` sequence.
+    pub fn set_signal_trampoline_recognizer(
+        &mut self,
+        recognizer: impl Fn(u64) -> bool + Send + Sync + 'static,
+    ) {
+        self.1 = Some(Arc::new(recognizer));
+    }
+
+    /// Stop recognizing signal return trampolines.
+    pub fn clear_signal_trampoline_recognizer(&mut self) {
+        self.1 = None;
+    }
+
+    /// Add a module with precomputed unwind rules, for example rules which were
+    /// computed from the module's unwind information ahead of time.
+    ///
+    /// The ranges in `rules` are relative to `avma_range.start`. Addresses in
+    /// `avma_range` are unwound by looking up their rule in these ranges, before any
+    /// module which was added with [`add_module`](Unwinder::add_module) is consulted.
+    /// Addresses which aren't covered by any range use the frame pointer based fallback
+    /// rule. `rules` can also be a prebuilt [`UnwindRuleTable`](crate::UnwindRuleTable),
+    /// for example one which was deserialized with the `serde` feature.
+    ///
+    /// Existing modules with precomputed rules whose address range overlaps with
+    /// `avma_range` are removed. The module can be removed with
+    /// [`remove_module`](Unwinder::remove_module) and `avma_range.start`.
+    pub fn add_module_with_rules(
+        &mut self,
+        avma_range: Range<u64>,
+        rules: impl Into<UnwindRuleTable<UnwindRuleX86_64>>,
+    ) {
+        self.0.add_module_with_rules(avma_range, rules.into());
+    }
+
+    /// Add an address range whose code has no unwind information and is known to
+    /// maintain the frame pointer chain, for example JIT code.
+    ///
+    /// Addresses in `avma_range` are always unwound with the frame pointer rule, without
+    /// looking for a module or parsing any unwind information. Existing ranges and
+    /// modules with precomputed rules which overlap with `avma_range` are removed. The
+    /// range can be removed with [`remove_module`](Unwinder::remove_module) and
+    /// `avma_range.start`.
+    ///
+    /// Unlike [`Module::new_framepointer_only`], this doesn't need a [`Module`].
+    pub fn add_framepointer_range(&mut self, avma_range: Range<u64>) {
+        self.0.add_framepointer_range(avma_range);
+    }
+
+    /// Unwind a frame with a rule which is already known to be correct for its address,
+    /// for example one which was found for the same pc in an earlier sample, such as
+    /// [`UnwindRuleX86_64::UseFramePointer`](UnwindRuleX86_64::UseFramePointer).
+    ///
+    /// This doesn't look up a module, a cached rule or any unwind information, so it's
+    /// the cheapest way to unwind a frame; the caller is responsible for `rule` matching
+    /// the code at `address`. `address` only tells the rule whether this is the first
+    /// frame. Returns the same values as [`unwind_frame`](Unwinder::unwind_frame).
+    /// Return addresses which mark the end of the stack are respected, but return
+    /// addresses are not validated. Signal trampolines are recognized, see
+    /// [`set_signal_trampoline_recognizer`](Self::set_signal_trampoline_recognizer).
+    pub fn unwind_frame_with_rule<F: MemoryAccess>(
+        &self,
+        rule: UnwindRuleX86_64,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error> {
+        complete_sync(self.0.unwind_frame_with_rule(
+            rule,
+            address,
+            regs,
+            &mut SyncMemory(read_stack),
+            &self.1,
+        ))
+    }
+
+    /// Treat the given return addresses as the end of the stack.
+    ///
+    /// When a frame is unwound and its return address is one of `addresses`, for example
+    /// the address after the call in a thread entry point, unwinding stops successfully:
+    /// [`unwind_frame`](Unwinder::unwind_frame) returns `Ok(None)`, as it does for a
+    /// null return address. Replaces the previously set addresses.
+    pub fn set_end_of_stack_return_addresses(&mut self, addresses: Vec<u64>) {
+        self.0.set_end_of_stack_return_addresses(addresses);
+    }
+
+    /// Treat return addresses into the given functions as the end of the stack, like
+    /// [`set_end_of_stack_return_addresses`](Self::set_end_of_stack_return_addresses).
+    ///
+    /// A return address ends unwinding if the address of the call before it, i.e.
+    /// `return_address - 1`, is inside one of `function_ranges`. For example, goroutine
+    /// stacks in Go programs end in a frame whose return address points into
+    /// `runtime.goexit`; pass the address range of that function from the symbol table
+    /// so that unwinding stops there instead of producing bogus frames. Replaces the
+    /// previously set ranges.
+    pub fn set_end_of_stack_functions(&mut self, function_ranges: Vec<Range<u64>>) {
+        self.0.set_end_of_stack_functions(function_ranges);
+    }
+
+    /// Treat return addresses below `address` as the end of the stack, like
+    /// [`set_end_of_stack_return_addresses`](Self::set_end_of_stack_return_addresses).
+    ///
+    /// A null return address always ends the stack. Some runtimes end the stack with
+    /// other small values instead, such as 1; pass e.g. the page size to treat every
+    /// return address in the first page, which is never mapped, as the end of the
+    /// stack. The default is 0, i.e. only the null return address.
+    pub fn set_end_of_stack_below_address(&mut self, address: u64) {
+        self.0.set_end_of_stack_below_address(address);
+    }
+
+    /// Unwind addresses which aren't in any module with the frame pointer, instead of
+    /// failing with [`Error::NoModuleForAddress`](crate::Error::NoModuleForAddress).
+    /// Off by default.
+    ///
+    /// This bridges gaps in otherwise well-described stacks, for example a JIT stub
+    /// between two modules with unwind information: the frame in the gap is unwound with
+    /// a single frame pointer step, and its caller is unwound with its module's unwind
+    /// information again. [`unwind_frame_detailed`](Unwinder::unwind_frame_detailed)
+    /// reports these frames as [`RuleSource::FramePointerRecovery`](crate::RuleSource::FramePointerRecovery),
+    /// because their return addresses are less reliable.
+    pub fn set_recover_with_frame_pointer_on_missing_info(&mut self, recover: bool) {
+        self.0
+            .set_recover_with_frame_pointer_on_missing_info(recover);
+    }
+
+    /// Choose whether `__unwind_info` or `__eh_frame` is tried first for mach-O modules
+    /// which have both. The default is [`UnwindSourcePreference::PreferCompactUnwind`].
+    ///
+    /// Either way, the other source is used for addresses which the preferred source
+    /// doesn't cover or can't unwind. Changing the preference invalidates the rules in
+    /// existing caches.
+    pub fn set_unwind_source_preference(&mut self, preference: UnwindSourcePreference) {
+        self.0.set_unwind_source_preference(preference);
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy> UnwinderX86_64<D, P> {
+    /// The unwind rules which the DWARF CFI of `module` translates into, sorted by
+    /// address, with the address ranges (AVMAs) they apply to.
+    ///
+    /// This covers every row of every FDE in the module's `.eh_frame` or `.debug_frame`
+    /// section. Adjacent rows with the same result are merged. An `Err` means that the
+    /// CFI for this range can't be expressed as an unwind rule, so that unwinding
+    /// evaluates it for every frame, without caching. This is meant for testing and
+    /// debugging, and it doesn't need the module to be added to this unwinder.
+    pub fn dwarf_rules(
+        &self,
+        module: &Module<D>,
+    ) -> Vec<(Range<u64>, Result<UnwindRuleX86_64, ConversionError>)> {
+        UnwinderInternal::<D, ArchX86_64, P>::dwarf_rules(module)
     }
 }
 
@@ -45,15 +262,134 @@ impl<D: Deref<Target = [u8]>, P: AllocationPolicy> Unwinder for UnwinderX86_64<D
         self.0.add_module(module);
     }
 
+    fn add_module_with_policy(
+        &mut self,
+        module: Module<D>,
+        policy: AddModulePolicy,
+    ) -> Result<(), Error> {
+        self.0.add_module_with_policy(module, policy)
+    }
+
+    fn try_add_module(&mut self, module: Module<D>) -> Result<(), AddModuleError> {
+        self.0.try_add_module(module)
+    }
+
     fn remove_module(&mut self, module_address_range_start: u64) {
         self.0.remove_module(module_address_range_start);
     }
 
+    fn clear_modules(&mut self) {
+        self.0.clear_modules();
+    }
+
+    fn modules(&self) -> &[Module<D>] {
+        self.0.modules()
+    }
+
+    fn module_for_address(&self, address: u64) -> Option<&Module<D>> {
+        self.0.module_for_address(address)
+    }
+
+    fn covers_address(&self, address: u64) -> bool {
+        self.0.covers_address(address)
+    }
+
+    fn debug_fde_offset(&self, address: u64) -> Option<u32> {
+        self.0.debug_fde_offset(address)
+    }
+
+    fn debug_fde_encoding(&self, address: u64) -> Option<FdeEncoding> {
+        self.0.debug_fde_encoding(address)
+    }
+
     fn max_known_code_address(&self) -> u64 {
         self.0.max_known_code_address()
     }
 
-    fn unwind_frame<F>(
+    fn unwind_frame_detailed<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<P>,
+        read_stack: &mut F,
+    ) -> Result<UnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess,
+    {
+        self.0
+            .unwind_frame_detailed_sync(address, regs, &mut cache.0, read_stack, &self.1)
+    }
+
+    fn unwind_frame_in_module<F>(
+        &self,
+        module: &Module<D>,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<P>,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: MemoryAccess,
+    {
+        let outcome = self.0.unwind_frame_in_module(
+            module,
+            address,
+            regs,
+            &mut cache.0,
+            read_stack,
+            &self.1,
+        )?;
+        Ok(outcome.next_pc)
+    }
+
+    fn precompute_rules(
+        &self,
+        addresses: &[FrameAddress],
+        cache: &mut CacheX86_64<P>,
+    ) -> PrecomputedRules {
+        self.0.precompute_rules(addresses, &mut cache.0)
+    }
+
+    fn unwind_frame_checked<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<P>,
+        read_stack: &mut F,
+    ) -> Result<CheckedUnwindFrameOutcome, Error>
+    where
+        F: MemoryAccess,
+    {
+        complete_sync(self.0.unwind_frame_checked(
+            address,
+            regs,
+            &mut cache.0,
+            &mut SyncMemory(read_stack),
+            &self.1,
+        ))
+    }
+
+    fn unwind_frame_with_cfa<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<P>,
+        read_stack: &mut F,
+    ) -> Result<UnwoundFrame, Error>
+    where
+        F: MemoryAccess,
+    {
+        let outcome = self.unwind_frame_detailed(address, regs, cache, read_stack)?;
+        Ok(UnwoundFrame {
+            return_address: outcome.next_pc,
+            cfa: regs.sp(),
+            next_is_instruction_pointer: outcome.next_is_instruction_pointer,
+        })
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy> AsyncUnwinder for UnwinderX86_64<D, P> {
+    async fn unwind_frame_async<F>(
         &self,
         address: FrameAddress,
         regs: &mut UnwindRegsX86_64,
@@ -61,8 +397,12 @@ impl<D: Deref<Target = [u8]>, P: AllocationPolicy> Unwinder for UnwinderX86_64<D
         read_stack: &mut F,
     ) -> Result<Option<u64>, Error>
     where
-        F: FnMut(u64) -> Result<u64, ()>,
+        F: AsyncMemoryAccess,
     {
-        self.0.unwind_frame(address, regs, &mut cache.0, read_stack)
+        let outcome = self
+            .0
+            .unwind_frame_detailed(address, regs, &mut cache.0, read_stack, &self.1)
+            .await?;
+        Ok(outcome.next_pc)
     }
 }
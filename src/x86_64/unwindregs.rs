@@ -1,11 +1,26 @@
-use core::fmt::Debug;
+use core::fmt::{Debug, Display};
 
-use crate::display_utils::HexNum;
+use crate::display_utils::{HexNum, RegValue};
+use crate::register_set::RegisterSet;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct UnwindRegsX86_64 {
     ip: u64,
     regs: [u64; 16],
+    tracks_callee_saved: bool,
+    unknown: RegisterSet,
+    stack_end: Option<u64>,
+}
+
+/// The values of the callee-saved registers rbx and r12 to r15, see
+/// [`UnwindRegsX86_64::track_callee_saved`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CalleeSavedRegsX86_64 {
+    pub rbx: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -29,23 +44,101 @@ pub enum Reg {
     R15,
 }
 
+/// The general purpose registers in Linux's `struct user_regs_struct`, as returned by
+/// `ptrace(PTRACE_GETREGS)`, with their indexes. `rip` is at index 16.
+const USER_REGS_STRUCT_REGS: [(Reg, usize); 16] = [
+    (Reg::R15, 0),
+    (Reg::R14, 1),
+    (Reg::R13, 2),
+    (Reg::R12, 3),
+    (Reg::RBP, 4),
+    (Reg::RBX, 5),
+    (Reg::R11, 6),
+    (Reg::R10, 7),
+    (Reg::R9, 8),
+    (Reg::R8, 9),
+    (Reg::RAX, 10),
+    (Reg::RCX, 11),
+    (Reg::RDX, 12),
+    (Reg::RSI, 13),
+    (Reg::RDI, 14),
+    (Reg::RSP, 19),
+];
+
+/// The general purpose registers in Mach's `x86_thread_state64_t`, in the order in
+/// which they're stored. `rip` follows them, at index 16.
+const THREAD_STATE64_REGS: [Reg; 16] = [
+    Reg::RAX,
+    Reg::RBX,
+    Reg::RCX,
+    Reg::RDX,
+    Reg::RDI,
+    Reg::RSI,
+    Reg::RBP,
+    Reg::RSP,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
 impl UnwindRegsX86_64 {
     pub fn new(ip: u64, sp: u64, bp: u64) -> Self {
         let mut r = Self {
             ip,
             regs: Default::default(),
+            tracks_callee_saved: false,
+            unknown: RegisterSet::EMPTY,
+            stack_end: None,
         };
         r.set_sp(sp);
         r.set_bp(bp);
         r
     }
 
+    /// Create the registers from a Linux `struct user_regs_struct`, as returned by
+    /// `ptrace(PTRACE_GETREGS)` or in the `NT_PRSTATUS` note of a core dump, given as
+    /// its 27 64-bit words.
+    ///
+    /// All general purpose registers are set, so [`track_callee_saved`](Self::track_callee_saved)
+    /// can be called without setting the callee-saved registers separately.
+    pub fn from_user_regs_struct(words: &[u64; 27]) -> Self {
+        let mut r = Self::new(words[16], 0, 0);
+        for (reg, index) in USER_REGS_STRUCT_REGS {
+            r.set(reg, words[index]);
+        }
+        r
+    }
+
+    /// Create the registers from a Mach `x86_thread_state64_t`, as returned by
+    /// `thread_get_state` with `x86_THREAD_STATE64`, given as its 21 64-bit words.
+    ///
+    /// All general purpose registers are set, like in [`from_user_regs_struct`](Self::from_user_regs_struct).
+    pub fn from_x86_thread_state64(words: &[u64; 21]) -> Self {
+        let mut r = Self::new(words[16], 0, 0);
+        for (index, reg) in THREAD_STATE64_REGS.into_iter().enumerate() {
+            r.set(reg, words[index]);
+        }
+        r
+    }
+
     #[inline(always)]
     pub fn get(&self, reg: Reg) -> u64 {
         self.regs[reg as usize]
     }
     #[inline(always)]
     pub fn set(&mut self, reg: Reg, value: u64) {
+        if value != self.regs[reg as usize] {
+            match reg {
+                Reg::RSP => self.unknown &= !RegisterSet::SP,
+                Reg::RBP => self.unknown &= !RegisterSet::FP,
+                _ => {}
+            }
+        }
         self.regs[reg as usize] = value;
     }
 
@@ -55,6 +148,9 @@ impl UnwindRegsX86_64 {
     }
     #[inline(always)]
     pub fn set_ip(&mut self, ip: u64) {
+        if ip != self.ip {
+            self.unknown &= !RegisterSet::PC;
+        }
         self.ip = ip
     }
 
@@ -75,12 +171,102 @@ impl UnwindRegsX86_64 {
     pub fn set_bp(&mut self, bp: u64) {
         self.set(Reg::RBP, bp)
     }
+
+    /// Start restoring the callee-saved registers rbx and r12 to r15 during unwinding.
+    /// Their values for the current frame need to be set with [`set`](Self::set).
+    ///
+    /// When these registers are tracked, unwinding with DWARF CFI restores them from the
+    /// register rules in the CFI, so that their values are the caller's values after each
+    /// unwound frame. Registers without a rule keep their value. Tracking is off by
+    /// default because it makes unwinding slower: unwind rules can't be cached, and all
+    /// register rules need to be evaluated. Unwind information other than DWARF CFI, and
+    /// the frame pointer fallback, doesn't update these registers.
+    pub fn track_callee_saved(&mut self) {
+        self.tracks_callee_saved = true;
+    }
+
+    /// Mark the values of some of ip, rsp and rbp as unknown, for example because the
+    /// sampler didn't capture rbp. Registers which are not in `unknown` are known.
+    ///
+    /// Unwinding a frame whose unwind rule needs an unknown register fails with
+    /// [`Error::MissingRegisterForRule`](crate::Error::MissingRegisterForRule), instead
+    /// of using the placeholder value. Rules which only need rsp still work. A register
+    /// stays unknown in the caller's frame as long as unwinding passes it through
+    /// unchanged, and becomes known when it is set to a different value, for example
+    /// when the unwind rule restores rbp from the stack.
+    pub fn set_unknown_registers(&mut self, unknown: RegisterSet) {
+        self.unknown = unknown;
+    }
+
+    /// The registers which are marked as unknown, see
+    /// [`set_unknown_registers`](Self::set_unknown_registers).
+    #[inline(always)]
+    pub fn unknown_registers(&self) -> RegisterSet {
+        self.unknown
+    }
+
+    /// Set the end of the thread's stack, i.e. the address just above its highest byte,
+    /// for example the stack address plus the stack size from `pthread_getattr_np`.
+    ///
+    /// Unwinding stops when the caller's rsp is at or above this address, because its
+    /// frame can't be on the stack: the unwinder returns `Ok(None)` instead of the
+    /// return address, which was read from outside the stack. The value is kept for
+    /// the caller's frame, so it only needs to be set for the first frame.
+    pub fn set_stack_end(&mut self, stack_end: u64) {
+        self.stack_end = Some(stack_end);
+    }
+
+    /// The end of the stack, if it was set with [`set_stack_end`](Self::set_stack_end).
+    #[inline(always)]
+    pub fn stack_end(&self) -> Option<u64> {
+        self.stack_end
+    }
+
+    /// Stop restoring the callee-saved registers rbx and r12 to r15.
+    pub fn stop_tracking_callee_saved(&mut self) {
+        self.tracks_callee_saved = false;
+    }
+
+    /// The values of the callee-saved registers rbx and r12 to r15, if they're tracked.
+    pub fn callee_saved(&self) -> Option<CalleeSavedRegsX86_64> {
+        if !self.tracks_callee_saved {
+            return None;
+        }
+        Some(CalleeSavedRegsX86_64 {
+            rbx: self.get(Reg::RBX),
+            r12: self.get(Reg::R12),
+            r13: self.get(Reg::R13),
+            r14: self.get(Reg::R14),
+            r15: self.get(Reg::R15),
+        })
+    }
+
+    /// Return a copy of these register values with `ip` replaced.
+    #[inline(always)]
+    pub fn with_ip(mut self, ip: u64) -> Self {
+        self.set_ip(ip);
+        self
+    }
+
+    /// Return a copy of these register values with `sp` replaced.
+    #[inline(always)]
+    pub fn with_sp(mut self, sp: u64) -> Self {
+        self.set_sp(sp);
+        self
+    }
+
+    /// Return a copy of these register values with `bp` replaced.
+    #[inline(always)]
+    pub fn with_bp(mut self, bp: u64) -> Self {
+        self.set_bp(bp);
+        self
+    }
 }
 
 impl Debug for UnwindRegsX86_64 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("UnwindRegsX86_64")
-            .field("ip", &HexNum(self.ip()))
+        let mut s = f.debug_struct("UnwindRegsX86_64");
+        s.field("ip", &HexNum(self.ip()))
             .field("rax", &HexNum(self.get(Reg::RAX)))
             .field("rdx", &HexNum(self.get(Reg::RDX)))
             .field("rcx", &HexNum(self.get(Reg::RCX)))
@@ -96,7 +282,75 @@ impl Debug for UnwindRegsX86_64 {
             .field("r12", &HexNum(self.get(Reg::R12)))
             .field("r13", &HexNum(self.get(Reg::R13)))
             .field("r14", &HexNum(self.get(Reg::R14)))
-            .field("r15", &HexNum(self.get(Reg::R15)))
-            .finish()
+            .field("r15", &HexNum(self.get(Reg::R15)));
+        if !self.unknown.is_empty() {
+            s.field("unknown", &self.unknown);
+        }
+        if let Some(stack_end) = self.stack_end {
+            s.field("stack_end", &HexNum(stack_end));
+        }
+        s.finish()
+    }
+}
+
+/// Formats the registers which are used for unwinding on one line, e.g.
+/// `ip=0x1000 sp=0x28 bp=0x40`, with `?` for unknown values, see
+/// [`UnwindRegsX86_64::set_unknown_registers`]. Use `Debug` to see all registers.
+impl Display for UnwindRegsX86_64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let value = |register, value| (!self.unknown.contains(register)).then_some(value);
+        write!(
+            f,
+            "ip={} sp={} bp={}",
+            RegValue(value(RegisterSet::PC, self.ip())),
+            RegValue(value(RegisterSet::SP, self.sp())),
+            RegValue(value(RegisterSet::FP, self.bp())),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::format;
+
+    #[test]
+    fn test_with_accessors() {
+        let regs = UnwindRegsX86_64::new(0x1000, 0x20, 0x30);
+        let adjusted = regs.with_ip(0xfff).with_sp(0x28).with_bp(0x40);
+        assert_eq!(regs.ip(), 0x1000);
+        assert_eq!(adjusted.ip(), 0xfff);
+        assert_eq!(adjusted.sp(), 0x28);
+        assert_eq!(adjusted.bp(), 0x40);
+        assert_eq!(regs.with_ip(0x1000), regs);
+        assert!(format!("{adjusted:?}").contains("ip: fff, "));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut regs = UnwindRegsX86_64::new(0x1000, 0x28, 0x40);
+        assert_eq!(format!("{regs}"), "ip=0x1000 sp=0x28 bp=0x40");
+        regs.set_unknown_registers(RegisterSet::FP);
+        assert_eq!(format!("{regs}"), "ip=0x1000 sp=0x28 bp=?");
+        assert_ne!(regs, UnwindRegsX86_64::new(0x1000, 0x28, 0x40));
+    }
+
+    #[test]
+    fn test_from_kernel_register_layouts() {
+        let words: [u64; 27] = core::array::from_fn(|i| 0x100 + i as u64);
+        let regs = UnwindRegsX86_64::from_user_regs_struct(&words);
+        assert_eq!(regs.ip(), 0x110);
+        assert_eq!(regs.sp(), 0x113);
+        assert_eq!(regs.bp(), 0x104);
+        assert_eq!(regs.get(Reg::RBX), 0x105);
+        assert_eq!(regs.get(Reg::R15), 0x100);
+
+        let words: [u64; 21] = core::array::from_fn(|i| 0x100 + i as u64);
+        let regs = UnwindRegsX86_64::from_x86_thread_state64(&words);
+        assert_eq!(regs.ip(), 0x110);
+        assert_eq!(regs.sp(), 0x107);
+        assert_eq!(regs.bp(), 0x106);
+        assert_eq!(regs.get(Reg::RBX), 0x101);
+        assert_eq!(regs.get(Reg::R15), 0x10f);
     }
 }
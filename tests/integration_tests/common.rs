@@ -136,3 +136,14 @@ pub fn relative_address_base<'data>(object_file: &impl object::Object<'data>) ->
     // which is what we want.
     object_file.relative_address_base()
 }
+
+/// Drive a future to completion on the current thread, for testing [`AsyncUnwinder`].
+pub fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+    let mut future = std::pin::pin!(future);
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    loop {
+        if let std::task::Poll::Ready(value) = future.as_mut().poll(&mut context) {
+            return value;
+        }
+    }
+}
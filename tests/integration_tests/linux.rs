@@ -2,8 +2,11 @@ use std::path::Path;
 
 use framehop::aarch64::*;
 use framehop::x86_64::*;
+use framehop::AsyncUnwinder;
 use framehop::FrameAddress;
+use framehop::MemoryAccess;
 use framehop::Unwinder;
+use framehop::{ReadMemError, ReadMemErrorKind};
 
 use super::common;
 
@@ -61,6 +64,3029 @@ fn test_plt_cfa_expr() {
     }
 }
 
+#[test]
+fn test_plt_cfa_expr_async() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/fp/nightly-firefox-bin"),
+        0x1000000,
+    );
+
+    // Same as test_plt_cfa_expr, but the stack is read asynchronously.
+    let stack = [1, 2, 3, 4, 5, 0xa, 0x123456, 6, 7, 8, 9];
+    let mut requested = Vec::new();
+    let mut read_stack = |addr| {
+        requested.push(addr);
+        let value = stack.get((addr / 8) as usize).cloned().ok_or(());
+        async move { value }
+    };
+
+    for (sp, rel_pc) in [(0x28, 0xc0db), (0x30, 0xc0e0)].iter() {
+        let mut regs = UnwindRegsX86_64::new(0x1000000 + rel_pc, *sp, 0x345);
+        let res = common::block_on(unwinder.unwind_frame_async(
+            FrameAddress::from_instruction_pointer(0x1000000 + rel_pc),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        ));
+        assert_eq!(res, Ok(Some(0x123456)));
+        assert_eq!(regs.sp(), 0x38);
+        assert_eq!(regs.bp(), 0x345);
+    }
+
+    // Each frame only needs the return address, and it's only requested once.
+    assert_eq!(requested, vec![0x30, 0x30]);
+}
+
+#[test]
+fn test_plt_cfa_expr_with_cfa() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/fp/nightly-firefox-bin"),
+        0x1000000,
+    );
+
+    // Same PLT stubs as in test_plt_cfa_expr. The CFA is the stack pointer just above
+    // the return address at stack location 0x30.
+    let stack = [1, 2, 3, 4, 5, 0xa, 0x123456, 6, 7, 8, 9];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    for (sp, rel_pc) in [(0x28, 0xc0db), (0x30, 0xc0e0)].iter() {
+        let mut regs = UnwindRegsX86_64::new(0x1000000 + rel_pc, *sp, 0x345);
+        let res = unwinder.unwind_frame_with_cfa(
+            FrameAddress::from_instruction_pointer(0x1000000 + rel_pc),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(
+            res,
+            Ok(framehop::UnwoundFrame {
+                return_address: Some(0x123456),
+                cfa: 0x38,
+                next_is_instruction_pointer: false,
+            })
+        );
+    }
+}
+
+#[test]
+fn test_framepointer_only_module() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x1000000..0x1010000,
+        0x1000000,
+    ));
+
+    let stack = [1, 2, 0x40, 0x1000400, 5, 6, 7, 8, 0x0, 0x1000300];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000800, 0x8, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x40);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000300)));
+    assert_eq!(regs.bp(), 0x0);
+}
+
+#[test]
+fn test_return_address_validation() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    let mut text = vec![0x90; 0x1000];
+    // 0x3fb: call 0x800
+    text[0x3fb..0x400].copy_from_slice(&[0xe8, 0x00, 0x04, 0x00, 0x00]);
+    unwinder.add_module(
+        framehop::Module::new_framepointer_only("jit".to_string(), 0x1000000..0x1010000, 0x1000000)
+            .with_return_address_validation(0x0..0x1000, text),
+    );
+
+    let stack = [1, 2, 0x40, 0x1000400, 5, 6, 7, 8, 0x0, 0x1000300];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000800, 0x8, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+
+    // The byte before 0x1000300 is a nop, not the end of a call instruction.
+    let regs_before = regs;
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::ReturnAddressNotAfterCall(0x1000300))
+    );
+    assert_eq!(regs, regs_before);
+}
+
+/// Build an `.eh_frame` section with one CIE and one FDE for the function at
+/// `pc_begin..pc_begin + pc_range`. The CIE sets up `CFA=rsp+8: rip=[CFA-8]`.
+pub fn synthetic_eh_frame(
+    pc_begin: u64,
+    pc_range: u64,
+    fde_instructions: &[u8],
+    big_endian: bool,
+) -> Vec<u8> {
+    synthetic_eh_frame_with_fdes(&[(pc_begin, pc_range, fde_instructions)], big_endian)
+}
+
+/// Like `synthetic_eh_frame`, but with an FDE for each `(pc_begin, pc_range,
+/// fde_instructions)`.
+fn synthetic_eh_frame_with_fdes(fdes: &[(u64, u64, &[u8])], big_endian: bool) -> Vec<u8> {
+    synthetic_eh_frame_with_cie_and_fdes(
+        1,
+        16, // rip
+        &[
+            0x0c, 0x07, 0x08, // DW_CFA_def_cfa: rsp+8
+            0x90, 0x01, // DW_CFA_offset: rip at CFA-8
+        ],
+        fdes,
+        big_endian,
+    )
+}
+
+/// A module at `0x1000000..0x1001000` with the given `.eh_frame` section at SVMA 0x800.
+fn synthetic_module(eh_frame: Vec<u8>) -> framehop::Module<Vec<u8>> {
+    framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            ..Default::default()
+        },
+    )
+}
+
+/// Like `synthetic_eh_frame`, but with the given code alignment factor, return address
+/// register and initial instructions in the CIE. The data alignment factor is -8.
+fn synthetic_eh_frame_with_cie(
+    code_alignment_factor: u8,
+    return_address_register: u8,
+    cie_instructions: &[u8],
+    pc_begin: u64,
+    pc_range: u64,
+    fde_instructions: &[u8],
+    big_endian: bool,
+) -> Vec<u8> {
+    synthetic_eh_frame_with_cie_and_fdes(
+        code_alignment_factor,
+        return_address_register,
+        cie_instructions,
+        &[(pc_begin, pc_range, fde_instructions)],
+        big_endian,
+    )
+}
+
+fn synthetic_eh_frame_with_cie_and_fdes(
+    code_alignment_factor: u8,
+    return_address_register: u8,
+    cie_instructions: &[u8],
+    fdes: &[(u64, u64, &[u8])],
+    big_endian: bool,
+) -> Vec<u8> {
+    let u32_bytes = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let u64_bytes = |v: u64| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+
+    let mut cie = vec![];
+    cie.extend_from_slice(&u32_bytes(0)); // CIE id
+    cie.push(1); // version
+    cie.extend_from_slice(b"zR\0"); // augmentation
+    cie.push(code_alignment_factor);
+    cie.push(0x78); // data alignment factor: -8
+    cie.push(return_address_register);
+    cie.push(1); // augmentation data length
+    cie.push(0x00); // FDE pointer encoding: DW_EH_PE_absptr
+    cie.extend_from_slice(cie_instructions);
+    while (cie.len() + 4) % 8 != 0 {
+        cie.push(0); // DW_CFA_nop
+    }
+
+    let mut eh_frame = vec![];
+    eh_frame.extend_from_slice(&u32_bytes(cie.len() as u32));
+    eh_frame.extend_from_slice(&cie);
+
+    for &(pc_begin, pc_range, fde_instructions) in fdes {
+        let fde_start = eh_frame.len();
+        let mut fde = vec![];
+        fde.extend_from_slice(&u32_bytes((fde_start + 4) as u32)); // CIE pointer
+        fde.extend_from_slice(&u64_bytes(pc_begin));
+        fde.extend_from_slice(&u64_bytes(pc_range));
+        fde.push(0); // augmentation data length
+        fde.extend_from_slice(fde_instructions);
+        while (fde.len() + 4) % 8 != 0 {
+            fde.push(0); // DW_CFA_nop
+        }
+        eh_frame.extend_from_slice(&u32_bytes(fde.len() as u32));
+        eh_frame.extend_from_slice(&fde);
+    }
+    eh_frame.extend_from_slice(&u32_bytes(0)); // terminator
+    eh_frame
+}
+
+#[test]
+fn test_val_offset_and_register_rules() {
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x14, 0x06, 0x02, // DW_CFA_val_offset: rbp=CFA-16
+            0x50, // DW_CFA_advance_loc: 0x10
+            0x09, 0x06, 0x03, // DW_CFA_register: rbp is in rbx
+        ],
+        false,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x10);
+
+    let mut regs = UnwindRegsX86_64::new(0x1000114, 0x10, 0x1234);
+    regs.set(Reg::RBX, 0x5678);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000114),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x5678);
+}
+
+#[test]
+fn test_val_offset_return_address() {
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x15, 0x10, 0x80, 0x7f, // DW_CFA_val_offset_sf: rip=CFA+0x400
+        ],
+        false,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    // The return address is computed from the CFA, without reading the stack.
+    let mut read_stack = |_| Err(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x1000c00, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1001010)));
+    assert_eq!(regs.sp(), 0x1000c10);
+    assert_eq!(regs.bp(), 0x1234);
+}
+
+#[test]
+fn test_restore_to_cie_rule_in_epilogue() {
+    // rbp is saved in the prologue and restored in the epilogue, where it goes back to
+    // the rule from the CIE, in which rbp has the same value as in the callee.
+    //   0x100: push rbp
+    //   0x101: ...
+    //   0x110: pop rbp
+    //   0x111: ret
+    for restore in [
+        &[0xc6][..],       // DW_CFA_restore: rbp
+        &[0x06, 0x06][..], // DW_CFA_restore_extended: rbp
+    ] {
+        let mut fde_instructions = vec![
+            0x41, // DW_CFA_advance_loc: 1
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x86, 0x02, // DW_CFA_offset: rbp at CFA-16
+            0x4f, // DW_CFA_advance_loc: 15
+            0x0e, 0x08, // DW_CFA_def_cfa_offset: CFA=rsp+8
+        ];
+        fde_instructions.extend_from_slice(restore);
+        let eh_frame = synthetic_eh_frame(0x100, 0x20, &fde_instructions, false);
+        let mut cache = CacheX86_64::new();
+        let mut unwinder = UnwinderX86_64::new();
+        unwinder.add_module(synthetic_module(eh_frame));
+
+        // The stack slot below sp still contains the popped rbp, which must not be used.
+        let stack = [0x5555, 0x1000400];
+        let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+        for track_callee_saved in [false, true] {
+            let mut regs = UnwindRegsX86_64::new(0x1000111, 0x8, 0x1234);
+            if track_callee_saved {
+                regs.track_callee_saved();
+            }
+            let res = unwinder.unwind_frame(
+                FrameAddress::from_instruction_pointer(0x1000111),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            );
+            assert_eq!(res, Ok(Some(0x1000400)));
+            assert_eq!((regs.sp(), regs.bp()), (0x10, 0x1234));
+        }
+    }
+
+    // The same with a CIE in which the caller's rbp is in rbx.
+    let eh_frame = synthetic_eh_frame_with_cie(
+        1,
+        16, // rip
+        &[
+            0x0c, 0x07, 0x08, // DW_CFA_def_cfa: rsp+8
+            0x90, 0x01, // DW_CFA_offset: rip at CFA-8
+            0x09, 0x06, 0x03, // DW_CFA_register: rbp is in rbx
+        ],
+        0x100,
+        0x20,
+        &[
+            0x41, // DW_CFA_advance_loc: 1
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x86, 0x02, // DW_CFA_offset: rbp at CFA-16
+            0x4f, // DW_CFA_advance_loc: 15
+            0x0e, 0x08, // DW_CFA_def_cfa_offset: CFA=rsp+8
+            0xc6, // DW_CFA_restore: rbp
+        ],
+        false,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let stack = [0x5555, 0x1000400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000111, 0x8, 0x1234);
+    regs.set(Reg::RBX, 0x7777);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000111),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.sp(), regs.bp()), (0x10, 0x7777));
+}
+
+#[test]
+fn test_remember_and_restore_state() {
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x44, // DW_CFA_advance_loc: 4
+            0x0a, // DW_CFA_remember_state
+            0x0e, 0x08, // DW_CFA_def_cfa_offset: CFA=rsp+8
+            0x42, // DW_CFA_advance_loc: 2
+            0x0b, // DW_CFA_restore_state: CFA=rsp+16
+        ],
+        false,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [0x1000300, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // Each address is unwound twice, the second time with the cached rule, which must
+    // be the rule of the row that covers the address and not of another row in the FDE.
+    for pc in [
+        0x1000102, 0x1000105, 0x1000110, 0x1000102, 0x1000105, 0x1000110,
+    ] {
+        let mut regs = UnwindRegsX86_64::new(pc, 0x0, 0x1234);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(pc),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        if (0x1000104..0x1000106).contains(&pc) {
+            assert_eq!(res, Ok(Some(0x1000300)));
+            assert_eq!(regs.sp(), 0x8);
+        } else {
+            assert_eq!(res, Ok(Some(0x1000400)));
+            assert_eq!(regs.sp(), 0x10);
+        }
+    }
+}
+
+#[test]
+fn test_gnu_args_size() {
+    // A caller which passes 48 bytes of arguments on the stack:
+    //
+    //   0x100: sub rsp, 8
+    //   0x104: push (six 8-byte arguments)
+    //   0x110: call callee
+    //   0x115: add rsp, 48
+    //   0x119: ...
+    //
+    // The CFA offset already includes the pushed arguments. DW_CFA_GNU_args_size only
+    // says how many bytes to pop when resuming at a landing pad in this frame, so it must
+    // not be added to the CFA a second time when unwinding through the call.
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x44, // DW_CFA_advance_loc: 4
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x4c, // DW_CFA_advance_loc: 12
+            0x0e, 0x40, // DW_CFA_def_cfa_offset: CFA=rsp+64
+            0x2e, 0x30, // DW_CFA_GNU_args_size: 48
+            0x49, // DW_CFA_advance_loc: 9
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=rsp+16
+            0x2e, 0x00, // DW_CFA_GNU_args_size: 0
+        ],
+        false,
+    );
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::new();
+    let stack = [1, 2, 3, 4, 5, 6, 7, 0x1000400, 0x1000500];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // The caller's frame, after the callee returned: the arguments are still on the stack.
+    let mut regs = UnwindRegsX86_64::new(0x1000115, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000115).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x40);
+
+    // After the arguments were popped, the args size is zero again.
+    let mut regs = UnwindRegsX86_64::new(0x1000119, 0x38, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000119),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000500)));
+    assert_eq!(regs.sp(), 0x48);
+}
+
+#[test]
+fn test_aarch64_callee_saved_registers() {
+    let eh_frame = synthetic_eh_frame_with_cie(
+        4,
+        30,                  // lr
+        &[0x0c, 0x1f, 0x00], // DW_CFA_def_cfa: sp+0
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x20, // DW_CFA_def_cfa_offset: CFA=sp+32
+            0x9d, 0x04, // DW_CFA_offset: x29 at CFA-32
+            0x9e, 0x03, // DW_CFA_offset: x30 at CFA-24
+            0x93, 0x02, // DW_CFA_offset: x19 at CFA-16
+            0x94, 0x01, // DW_CFA_offset: x20 at CFA-8
+        ],
+        false,
+    );
+    let mut cache = CacheAarch64::new();
+    let mut unwinder = UnwinderAarch64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [0x40, 0x1000400, 0x1919, 0x2020];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // Unwind without tracking first, so that the rule for this address is cached.
+    let mut regs = UnwindRegsAarch64::new(0x1000108, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.callee_saved(), None);
+
+    let mut regs = UnwindRegsAarch64::new(0x1000108, 0x0, 0x1234);
+    regs.track_callee_saved([19, 20, 21, 22, 23, 24, 25, 26, 27, 28]);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.fp(), 0x40);
+    assert_eq!(regs.lr(), 0x1000400);
+    assert_eq!(
+        regs.callee_saved(),
+        Some([0x1919, 0x2020, 21, 22, 23, 24, 25, 26, 27, 28])
+    );
+}
+
+#[test]
+fn test_aarch64_negate_ra_state() {
+    // A function which signs its return address with paciasp before storing it, and
+    // authenticates it with autiasp after loading it again.
+    let eh_frame = synthetic_eh_frame_with_cie(
+        4,
+        30,                  // lr
+        &[0x0c, 0x1f, 0x00], // DW_CFA_def_cfa: sp+0
+        0x100,
+        0x20,
+        &[
+            0x2d, // DW_CFA_AARCH64_negate_ra_state
+            0x41, // DW_CFA_advance_loc: 4
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=sp+16
+            0x9d, 0x02, // DW_CFA_offset: x29 at CFA-16
+            0x9e, 0x01, // DW_CFA_offset: x30 at CFA-8
+            0x44, // DW_CFA_advance_loc: 16
+            0x0e, 0x00, // DW_CFA_def_cfa_offset: CFA=sp+0
+            0xdd, // DW_CFA_restore: x29
+            0xde, // DW_CFA_restore: x30
+            0x2d, // DW_CFA_AARCH64_negate_ra_state
+        ],
+        false,
+    );
+    let mut cache = CacheAarch64::new();
+    let mut unwinder = UnwinderAarch64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    // The saved return address is signed.
+    let stack = [0x40, 0x002a_0000_0100_0400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mask = PtrAuthMask::new_24_40();
+
+    let mut regs = UnwindRegsAarch64::new_with_ptr_auth_mask(mask, 0x1000200, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000108),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.sp(), regs.fp(), regs.lr()), (0x10, 0x40, 0x1000400));
+
+    // The same with DWARF evaluation instead of a cached unwind rule.
+    let mut regs = UnwindRegsAarch64::new_with_ptr_auth_mask(mask, 0x1000200, 0x0, 0x1234);
+    regs.track_callee_saved([0; 10]);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000108),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.sp(), regs.fp(), regs.lr()), (0x10, 0x40, 0x1000400));
+
+    // After autiasp, lr holds the unsigned return address again.
+    let mut regs = UnwindRegsAarch64::new_with_ptr_auth_mask(mask, 0x1000400, 0x10, 0x40);
+    regs.track_callee_saved([0; 10]);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000118),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.sp(), regs.fp()), (0x10, 0x40));
+}
+
+#[test]
+fn test_aarch64_cfa_from_fp_in_realigned_frame() {
+    // A function which realigns sp, so that the CFA can only be computed from fp, and
+    // which doesn't store fp and lr at [fp] and [fp + 8].
+    let eh_frame = synthetic_eh_frame_with_cie(
+        4,
+        30,                  // lr
+        &[0x0c, 0x1f, 0x00], // DW_CFA_def_cfa: sp+0
+        0x100,
+        0x20,
+        &[
+            0x0c, 0x1d, 0x20, // DW_CFA_def_cfa: CFA=x29+32
+            0x9d, 0x02, // DW_CFA_offset: x29 at CFA-16
+            0x9e, 0x01, // DW_CFA_offset: x30 at CFA-8
+            0x41, // DW_CFA_advance_loc: 4
+            0x0e, 0x14, // DW_CFA_def_cfa_offset: CFA=x29+20
+        ],
+        false,
+    );
+    let mut cache = CacheAarch64::new();
+    let mut unwinder = UnwinderAarch64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let mut read_stack = |addr| match addr {
+        0x1104 => Ok(0x1300),
+        0x110c => Ok(0x1000500),
+        0x1110 => Ok(0x1200),
+        0x1118 => Ok(0x1000400),
+        _ => Err(()),
+    };
+
+    // Unwind twice, so that the second unwind uses the cached rule, if there is one.
+    for _ in 0..2 {
+        // CFA = fp + 32, which can be expressed as an unwind rule.
+        let mut regs = UnwindRegsAarch64::new(0x1234, 0x1000, 0x1100);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_return_address(0x1000104).unwrap(),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x1000400)));
+        assert_eq!(regs.sp(), 0x1120);
+        assert_eq!(regs.fp(), 0x1200);
+
+        // CFA = fp + 20, which is evaluated exactly by the DWARF slow path.
+        let mut regs = UnwindRegsAarch64::new(0x1234, 0x1000, 0x1100);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_return_address(0x1000108).unwrap(),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x1000500)));
+        assert_eq!(regs.sp(), 0x1114);
+        assert_eq!(regs.fp(), 0x1300);
+    }
+}
+
+#[test]
+fn test_x86_64_callee_saved_registers() {
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x20, // DW_CFA_def_cfa_offset: CFA=rsp+32
+            0x83, 0x03, // DW_CFA_offset: rbx at CFA-24
+            0x8c, 0x02, // DW_CFA_offset: r12 at CFA-16
+        ],
+        false,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [0, 0x3333, 0x1212, 0x1000400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // Unwind without tracking first, so that the rule for this address is cached.
+    let mut regs = UnwindRegsX86_64::new(0x1000108, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.callee_saved(), None);
+    assert_eq!(regs.get(Reg::RBX), 0);
+
+    let mut regs = UnwindRegsX86_64::new(0x1000108, 0x0, 0x1234);
+    regs.track_callee_saved();
+    regs.set(Reg::RBX, 3);
+    regs.set(Reg::R12, 12);
+    regs.set(Reg::R13, 13);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x1234);
+    assert_eq!(
+        regs.callee_saved(),
+        Some(CalleeSavedRegsX86_64 {
+            rbx: 0x3333,
+            r12: 0x1212,
+            r13: 13,
+            r14: 0,
+            r15: 0,
+        })
+    );
+}
+
+#[test]
+fn test_eh_frame_hdr_without_matching_entry() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    // An .eh_frame_hdr at 0x700 whose search table is empty, so it can't find any FDE.
+    let mut eh_frame_hdr = vec![
+        1,    // version
+        0x1b, // eh_frame_ptr encoding: DW_EH_PE_pcrel | DW_EH_PE_sdata4
+        0x03, // fde_count encoding: DW_EH_PE_udata4
+        0x3b, // table encoding: DW_EH_PE_datarel | DW_EH_PE_sdata4
+    ];
+    eh_frame_hdr.extend_from_slice(&(0x800i32 - 0x704).to_le_bytes()); // eh_frame_ptr
+    eh_frame_hdr.extend_from_slice(&0u32.to_le_bytes()); // fde_count
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_hdr_svma: Some(0x700..0x700 + eh_frame_hdr.len() as u64),
+            eh_frame_hdr: Some(eh_frame_hdr),
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            ..Default::default()
+        },
+    ));
+
+    // The FDE is found by scanning .eh_frame instead.
+    assert_eq!(unwinder.debug_fde_offset(0x1000104), Some(24));
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+}
+
+#[test]
+fn test_prebuilt_fde_index() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let section_info = framehop::ExplicitModuleSectionInfo {
+        base_svma: 0,
+        eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+        eh_frame: Some(eh_frame.clone()),
+        ..Default::default()
+    };
+    let index = framehop::FdeIndex::from_eh_frame(&eh_frame, &mut section_info.clone()).unwrap();
+    #[cfg(feature = "serde")]
+    let index = {
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(json, r#"[[{"start":256,"end":288},24]]"#);
+        serde_json::from_str::<framehop::FdeIndex>(&json).unwrap()
+    };
+    assert_eq!(index.fde_offset_for_relative_address(0x104), Some(24));
+    assert_eq!(
+        index.fde_for_relative_address(0x104),
+        Some((0x100..0x120, 24))
+    );
+    // 0x130 is after the end of the FDE, and only the offset lookup returns it.
+    assert_eq!(
+        index.fde_for_relative_address(0x130),
+        Some((0x100..0x120, 24))
+    );
+    assert_eq!(index.fde_for_relative_address(0xff), None);
+    assert_eq!(
+        index.entries().collect::<Vec<_>>(),
+        vec![(0x100..0x120, 24)]
+    );
+    assert_eq!(index.len(), 1);
+
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new_with_fde_index(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        section_info,
+        index,
+    ));
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+
+    // An address after the end of the FDE is treated like any address without CFI: the
+    // first frame just returns.
+    let mut regs = UnwindRegsX86_64::new(0x1000130, 0x18, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000130),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_deserialize_unsorted_fde_index() {
+    // Deserializing sorts the entries, so the lookups still work.
+    let json = r#"[[{"start":512,"end":544},64],[{"start":256,"end":288},24]]"#;
+    let index: framehop::FdeIndex = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        index.entries().collect::<Vec<_>>(),
+        vec![(0x100..0x120, 24), (0x200..0x220, 64)]
+    );
+    assert_eq!(index.fde_offset_for_relative_address(0x104), Some(24));
+    assert_eq!(index.fde_offset_for_relative_address(0x210), Some(64));
+    assert_eq!(index.fde_offset_for_relative_address(0xff), None);
+    assert!(serde_json::from_str::<framehop::FdeIndex>("[[256,24]]").is_err());
+}
+
+#[test]
+fn test_fde_evaluations() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[], false);
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let stack = [0x1000400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut cache = CacheX86_64::new();
+    let mut unwind = |cache: &mut CacheX86_64, ip| {
+        let mut regs = UnwindRegsX86_64::new(ip, 0x0, 0x1234);
+        unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(ip),
+            &mut regs,
+            cache,
+            &mut read_stack,
+        )
+    };
+
+    // Nothing is recorded by default.
+    assert_eq!(unwind(&mut cache, 0x1000104), Ok(Some(0x1000400)));
+    assert!(cache.fde_evaluations().is_empty());
+
+    cache.record_fde_evaluations(true);
+    assert_eq!(unwind(&mut cache, 0x1000108), Ok(Some(0x1000400)));
+    assert_eq!(unwind(&mut cache, 0x1000110), Ok(Some(0x1000400)));
+    // This address's rule is cached, so its FDE isn't evaluated again.
+    assert_eq!(unwind(&mut cache, 0x1000104), Ok(Some(0x1000400)));
+    assert_eq!(
+        cache.fde_evaluations().iter().collect::<Vec<_>>(),
+        [(&(0x1000000, 24), &2)]
+    );
+    assert_eq!(unwinder.debug_fde_offset(0x1000108), Some(24));
+
+    cache.reset_fde_evaluations();
+    assert!(cache.fde_evaluations().is_empty());
+}
+
+#[test]
+fn test_debug_fde_offset() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[], false);
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    // The CIE takes up the first 24 bytes.
+    assert_eq!(unwinder.debug_fde_offset(0x1000104), Some(24));
+    assert_eq!(unwinder.debug_fde_offset(0x1000050), None);
+    assert_eq!(unwinder.debug_fde_offset(0x2000000), None);
+
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/fp/nightly-firefox-bin"),
+        0x1000000,
+    );
+    // Looked up with .eh_frame_hdr.
+    assert!(unwinder.debug_fde_offset(0x1000000 + 0xc0db).is_some());
+}
+
+#[test]
+fn test_unwind_frame_detailed() {
+    use framehop::{RuleSource, UnwindFrameOutcome};
+
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [1, 0x1000400, 0x20, 0x1000500];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut unwind = |pc, bp| {
+        let mut regs = UnwindRegsX86_64::new(pc, 0x0, bp);
+        unwinder.unwind_frame_detailed(
+            FrameAddress::from_return_address(pc).unwrap(),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        )
+    };
+    let from_dwarf = UnwindFrameOutcome {
+        next_pc: Some(0x1000400),
+        source: RuleSource::Dwarf,
+        next_is_instruction_pointer: false,
+    };
+    assert_eq!(unwind(0x1000105, 0x0), Ok(from_dwarf));
+    let from_cache = UnwindFrameOutcome {
+        source: RuleSource::Cached,
+        ..from_dwarf
+    };
+    assert_eq!(unwind(0x1000105, 0x0), Ok(from_cache));
+    // There's no FDE for this address, so the frame pointer is used.
+    let from_frame_pointer = UnwindFrameOutcome {
+        next_pc: Some(0x1000500),
+        source: RuleSource::FramePointer,
+        ..from_dwarf
+    };
+    assert_eq!(unwind(0x1000051, 0x10), Ok(from_frame_pointer));
+}
+
+#[test]
+fn test_add_module_with_rules() {
+    use framehop::{Error, RuleSource};
+
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module_with_rules(
+        0x2000000..0x2001000,
+        vec![(
+            0x100..0x120,
+            UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 },
+        )],
+    );
+    assert_eq!(unwinder.max_known_code_address(), 0x2001000);
+
+    let stack = [1, 0x2000400, 0x20, 0x2000500];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, pc| {
+        let mut regs = UnwindRegsX86_64::new(pc, 0x0, 0x10);
+        unwinder
+            .unwind_frame_detailed(
+                FrameAddress::from_return_address(pc).unwrap(),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+            .map(|outcome| (outcome.next_pc, outcome.source))
+    };
+
+    // This caches the range without modules below the module with rules.
+    assert_eq!(
+        unwind(&unwinder, 0x1000000),
+        Err(Error::NoModuleForAddress(0x1000000))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x2000108),
+        Ok((Some(0x2000400), RuleSource::Precomputed))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x2000108),
+        Ok((Some(0x2000400), RuleSource::Cached))
+    );
+    // Addresses without a rule use the frame pointer.
+    assert_eq!(
+        unwind(&unwinder, 0x2000208),
+        Ok((Some(0x2000500), RuleSource::FramePointer))
+    );
+
+    unwinder.remove_module(0x2000000);
+    assert_eq!(
+        unwind(&unwinder, 0x2000108),
+        Err(Error::NoModuleForAddress(0x2000108))
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_deserialized_rule_tables() {
+    use framehop::UnwindRuleTable;
+
+    let stack = [1, 0x2000400, 0x20, 0x2000500];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let rules = UnwindRuleTable::new(vec![
+        (
+            0x100..0x120,
+            UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 },
+        ),
+        (0x200..0x280, UnwindRuleX86_64::UseFramePointer),
+    ]);
+    let json = serde_json::to_string(&rules).unwrap();
+    let deserialized: UnwindRuleTable<UnwindRuleX86_64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, rules);
+
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module_with_rules(0x2000000..0x2001000, deserialized);
+    let mut regs = UnwindRegsX86_64::new(0x2000108, 0x0, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x2000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x2000400)));
+    assert_eq!(regs.sp(), 0x10);
+    let mut regs = UnwindRegsX86_64::new(0x2000208, 0x0, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x2000208).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x2000500)));
+    assert_eq!((regs.sp(), regs.bp()), (0x20, 0x20));
+
+    let rules = UnwindRuleTable::new(vec![(
+        0x100..0x120,
+        UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
+            sp_offset_by_16: 2,
+            fp_storage_offset_from_sp_by_8: 2,
+            lr_storage_offset_from_sp_by_8: 3,
+        },
+    )]);
+    let json = serde_json::to_string(&rules).unwrap();
+    let deserialized: UnwindRuleTable<UnwindRuleAarch64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, rules);
+
+    let mut cache = CacheAarch64::new();
+    let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+    unwinder.add_module_with_rules(0x2000000..0x2001000, deserialized);
+    let mut regs = UnwindRegsAarch64::new(0x2000108, 0x0, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x2000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x2000500)));
+    assert_eq!((regs.sp(), regs.fp()), (0x20, 0x20));
+}
+
+#[test]
+fn test_add_framepointer_range() {
+    use framehop::{Error, RuleSource};
+
+    let mut cache = CacheAarch64::new();
+    let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+    unwinder.add_framepointer_range(0x3000000..0x3001000);
+    assert_eq!(unwinder.max_known_code_address(), 0x3001000);
+
+    // The frame record at fp = 0x10 holds the caller's fp and the return address.
+    let stack = [1, 2, 0x30, 0x3000400, 5, 6, 0, 0];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderAarch64<Vec<u8>>, pc| {
+        let mut regs = UnwindRegsAarch64::new(0x3000200, 0x8, 0x10);
+        unwinder
+            .unwind_frame_detailed(
+                FrameAddress::from_return_address(pc).unwrap(),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+            .map(|outcome| (outcome.next_pc, outcome.source, regs.sp(), regs.fp()))
+    };
+
+    assert_eq!(
+        unwind(&unwinder, 0x3000108),
+        Ok((Some(0x3000400), RuleSource::FramePointer, 0x20, 0x30))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x3000108),
+        Ok((Some(0x3000400), RuleSource::Cached, 0x20, 0x30))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x3001004),
+        Err(Error::NoModuleForAddress(0x3001004))
+    );
+
+    unwinder.remove_module(0x3000000);
+    assert_eq!(
+        unwind(&unwinder, 0x3000108),
+        Err(Error::NoModuleForAddress(0x3000108))
+    );
+}
+
+#[test]
+fn test_module_for_address() {
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+    ));
+    unwinder.add_framepointer_range(0x2000000..0x2001000);
+
+    let module = unwinder.module_for_address(0x1000800).unwrap();
+    assert_eq!(module.name(), "jit");
+    assert!(module.contains_address(0x1000000));
+    assert!(!module.contains_address(0x1001000));
+    assert!(unwinder.module_for_address(0x1001000).is_none());
+    assert!(unwinder.module_for_address(0x2000800).is_none());
+
+    assert!(unwinder.covers_address(0x1000800));
+    assert!(unwinder.covers_address(0x2000800));
+    assert!(!unwinder.covers_address(0x1001000));
+    assert!(!unwinder.covers_address(0x3000000));
+}
+
+#[test]
+fn test_unwind_frame_in_module() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let module = &unwinder.modules()[0];
+
+    let stack = [1, 0x1000400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_in_module(
+        module,
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x10);
+
+    let mut regs = UnwindRegsX86_64::new(0x2000000, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_in_module(
+        module,
+        FrameAddress::from_instruction_pointer(0x2000000),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Err(framehop::Error::NoModuleForAddress(0x2000000)));
+}
+
+#[test]
+fn test_cfa_from_other_register() {
+    // DW_CFA_def_cfa: r10+8, e.g. after stack realignment. This can't be translated into
+    // an unwind rule, so the CFA is computed by the DWARF unwinder.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0c, 0x0a, 0x08], false);
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+    regs.set(Reg::R10, 0x18);
+    let res = unwinder.unwind_frame_detailed(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x1000400), framehop::RuleSource::Dwarf))
+    );
+    assert_eq!(regs.sp(), 0x20);
+}
+
+#[test]
+fn test_dwarf_rules() {
+    use framehop::ConversionError;
+
+    // DW_CFA_def_cfa_offset: 16, DW_CFA_advance_loc: 4,
+    // DW_CFA_def_cfa_expression: DW_OP_breg7 (rsp) +16, DW_CFA_advance_loc: 4,
+    // DW_CFA_def_cfa: rsp+8, DW_CFA_advance_loc: 4, DW_CFA_def_cfa: rsp+8.
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x10, 0x44, 0x0f, 0x02, 0x77, 0x10, 0x44, 0x0c, 0x07, 0x08, 0x44, 0x0c, 0x07,
+            0x08,
+        ],
+        false,
+    );
+    let unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    let module = synthetic_module(eh_frame);
+    // The last two rows have the same rule and are merged.
+    assert_eq!(
+        unwinder.dwarf_rules(&module),
+        vec![
+            (
+                0x1000100..0x1000104,
+                Ok(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 })
+            ),
+            (0x1000104..0x1000108, Err(ConversionError::CfaIsExpression)),
+            (
+                0x1000108..0x1000120,
+                Ok(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 })
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_unwind_info_from_debug_file() {
+    // The mapped image has an empty .eh_frame, like a stripped section, and the CFI is in
+    // the debug file. DW_CFA_def_cfa_offset: 16.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let image = framehop::ExplicitModuleSectionInfo {
+        base_svma: 0,
+        text_svma: Some(0x100..0x200),
+        eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+        eh_frame: Some(Vec::new()),
+        ..Default::default()
+    };
+    let debug_file = framehop::ExplicitModuleSectionInfo {
+        base_svma: 0,
+        eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+        eh_frame: Some(eh_frame),
+        ..Default::default()
+    };
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ModuleSectionInfoWithDebugFile::new(image, debug_file),
+    ));
+
+    let stack = [1, 0x1000400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_detailed(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x1000400), framehop::RuleSource::Dwarf))
+    );
+    assert_eq!(regs.sp(), 0x10);
+}
+
+#[test]
+fn test_unwind_frame_with_raw_eh_frame() {
+    // DW_CFA_def_cfa_offset: 16. The FDE is at offset 24.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let stack = [1, 0x400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x104, 0x0, 0x1234);
+    let res = framehop::x86_64::unwind_frame_with_raw_eh_frame(
+        &eh_frame,
+        0x800,
+        24,
+        FrameAddress::from_instruction_pointer(0x104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x400)));
+    assert_eq!(regs.sp(), 0x10);
+
+    // Offset 4 is in the middle of the CIE.
+    let mut regs = UnwindRegsX86_64::new(0x104, 0x0, 0x1234);
+    let res = framehop::x86_64::unwind_frame_with_raw_eh_frame(
+        &eh_frame,
+        0x800,
+        4,
+        FrameAddress::from_instruction_pointer(0x104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert!(
+        matches!(
+            res,
+            Err(framehop::Error::Dwarf(
+                framehop::DwarfUnwinderError::FdeFromOffsetFailed(4, _)
+            ))
+        ),
+        "{res:?}"
+    );
+
+    // The error chain ends with the gimli error.
+    let err = res.unwrap_err();
+    let dwarf_err = std::error::Error::source(&err).unwrap();
+    assert!(dwarf_err
+        .to_string()
+        .starts_with("Could not get the FDE at offset 0x4: "));
+    let gimli_err = dwarf_err.source().unwrap();
+    assert!(gimli_err.downcast_ref::<gimli::Error>().is_some());
+    assert!(gimli_err.source().is_none());
+}
+
+#[test]
+fn test_unsupported_rule_error() {
+    use framehop::{
+        CfaRuleDescription, DwarfUnwinderError, RegisterRuleDescription, UnsupportedRuleDescription,
+    };
+
+    let stack = [1, 0x400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // DW_CFA_def_cfa_expression: DW_OP_breg17 (xmm0) +0. xmm0 isn't tracked.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0f, 0x02, 0x81, 0x00], false);
+    let mut regs = UnwindRegsX86_64::new(0x104, 0x0, 0x1234);
+    let res = framehop::x86_64::unwind_frame_with_raw_eh_frame(
+        &eh_frame,
+        0x800,
+        24,
+        FrameAddress::from_instruction_pointer(0x104),
+        &mut regs,
+        &mut read_stack,
+    );
+    let description = UnsupportedRuleDescription {
+        cfa: CfaRuleDescription::Expression,
+        return_address: RegisterRuleDescription::Offset(-8),
+    };
+    assert_eq!(
+        res,
+        Err(framehop::Error::Dwarf(DwarfUnwinderError::UnsupportedRule(
+            description
+        )))
+    );
+    assert_eq!(
+        std::error::Error::source(&res.unwrap_err())
+            .unwrap()
+            .to_string(),
+        "Unsupported DWARF CFI rule: CFA=expression, RA=offset(-8)"
+    );
+
+    // DW_CFA_def_cfa_expression: DW_OP_breg7 (rsp) +0; DW_OP_deref. The expression can
+    // be evaluated, but the stack read fails.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0f, 0x03, 0x77, 0x00, 0x06], false);
+    let mut regs = UnwindRegsX86_64::new(0x104, 0x100, 0x1234);
+    let res = framehop::x86_64::unwind_frame_with_raw_eh_frame(
+        &eh_frame,
+        0x800,
+        24,
+        FrameAddress::from_instruction_pointer(0x104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::Dwarf(
+            DwarfUnwinderError::CouldNotRecoverCfa
+        ))
+    );
+
+    // On aarch64, the CIE sets up CFA=sp, and the FDE says CFA=sp+16, fp is unchanged,
+    // and the return address is in x17, which isn't tracked.
+    let eh_frame = synthetic_eh_frame_with_cie(
+        4,
+        30,                  // lr
+        &[0x0c, 0x1f, 0x00], // DW_CFA_def_cfa: sp+0
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=sp+16
+            0x08, 0x1d, // DW_CFA_same_value: fp
+            0x09, 0x1e, 0x11, // DW_CFA_register: lr is in x17
+        ],
+        false,
+    );
+    let mut regs = UnwindRegsAarch64::new(0x1234, 0x0, 0x20);
+    let res = framehop::aarch64::unwind_frame_with_raw_eh_frame(
+        &eh_frame,
+        0x800,
+        24,
+        FrameAddress::from_return_address(0x104).unwrap(),
+        &mut regs,
+        &mut read_stack,
+    );
+    let err = res.unwrap_err();
+    assert_eq!(
+        std::error::Error::source(&err).unwrap().to_string(),
+        "Unsupported DWARF CFI rule: CFA=SP+16, RA=register(X17)"
+    );
+}
+
+#[test]
+fn test_relocated_module() {
+    // DW_CFA_def_cfa_offset: 16.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let module = synthetic_module(eh_frame);
+    let relocated = module.relocated(0x7000000..0x7001000, 0x7000000);
+    assert_eq!(relocated.avma_range(), 0x7000000..0x7001000);
+    assert_eq!(relocated.base_avma(), 0x7000000);
+    assert_eq!(relocated.name(), "synthetic");
+
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(relocated);
+    let mut cache = CacheX86_64::new();
+    let stack = [1, 0x7000400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x7000104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_detailed(
+        FrameAddress::from_instruction_pointer(0x7000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x7000400), framehop::RuleSource::Dwarf))
+    );
+    assert_eq!(regs.sp(), 0x10);
+}
+
+#[test]
+fn test_avma_svma_conversion() {
+    // A module whose stated base address is nonzero, as in mach-O binaries.
+    let module: framehop::Module<Vec<u8>> = framehop::Module::new(
+        "synthetic".to_string(),
+        0x1004000..0x1008000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0x100000000,
+            ..Default::default()
+        },
+    );
+    assert_eq!(module.avma_to_svma(0x1004567), 0x100004567);
+    assert_eq!(module.svma_to_avma(0x100004567), 0x1004567);
+    assert_eq!(module.avma_to_svma(0x1000000), 0x100000000);
+
+    let relocated = module.relocated(0x7004000..0x7008000, 0x7000000);
+    assert_eq!(relocated.avma_to_svma(0x7004567), 0x100004567);
+    assert_eq!(relocated.svma_to_avma(0x100004567), 0x7004567);
+}
+
+#[test]
+fn test_lookup_at_function_entry() {
+    // DW_CFA_advance_loc: 1, DW_CFA_def_cfa_offset: 16, i.e. `push rbp` at the first byte.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x41, 0x0e, 0x10], false);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let stack = [0x1000400, 0x1000500, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // At the first byte, the first frame uses the CIE's rule: CFA=rsp+8, return address
+    // at [rsp].
+    let mut cache = CacheX86_64::new();
+    let mut regs = UnwindRegsX86_64::new(0x1000100, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000100),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x8);
+
+    // After the first byte, CFA=rsp+16.
+    let mut cache = CacheX86_64::new();
+    let mut regs = UnwindRegsX86_64::new(0x1000101, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000101),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000500)));
+    assert_eq!(regs.sp(), 0x10);
+
+    // A return address of 0x1000101 belongs to a call at the first byte, so it's looked
+    // up at 0x1000100.
+    let mut cache = CacheX86_64::new();
+    let mut regs = UnwindRegsX86_64::new(0x1000101, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000101).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x8);
+}
+
+#[test]
+fn test_unwind_frame_checked() {
+    // DW_CFA_def_cfa_offset: 16, so the return address is at [rsp + 8].
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let stack = [0, 1, 0x30, 0x1000400, 0x40, 0x1000500, 6];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut cache = CacheX86_64::new();
+    let address = FrameAddress::from_return_address(0x1000108).unwrap();
+
+    // rbp points to the frame record at 0x10, which agrees with the CFI.
+    let mut regs = UnwindRegsX86_64::new(0x1000108, 0x10, 0x10);
+    let res = unwinder.unwind_frame_checked(address, &mut regs, &mut cache, &mut read_stack);
+    let checked = res.unwrap();
+    assert_eq!(checked.outcome.next_pc, Some(0x1000400));
+    assert_eq!(checked.disagreement, None);
+    assert_eq!(regs.sp(), 0x20);
+
+    // rbp points to a different frame record.
+    let mut regs = UnwindRegsX86_64::new(0x1000108, 0x10, 0x20);
+    let res = unwinder.unwind_frame_checked(address, &mut regs, &mut cache, &mut read_stack);
+    let checked = res.unwrap();
+    assert_eq!(checked.outcome.next_pc, Some(0x1000400));
+    assert_eq!(checked.disagreement, Some((0x1000400, 0x1000500)));
+    assert_eq!(regs.sp(), 0x20);
+}
+
+#[test]
+fn test_personality_and_lsda_augmentation() {
+    // A CIE with the augmentation "zPLR", like the ones C++ compilers emit for functions
+    // with exception handling: an indirect pc-relative personality pointer, a
+    // pc-relative LSDA pointer encoding and an absolute FDE pointer encoding.
+    let mut cie = vec![];
+    cie.extend_from_slice(&0u32.to_le_bytes()); // CIE id
+    cie.push(1); // version
+    cie.extend_from_slice(b"zPLR\0"); // augmentation
+    cie.push(1); // code alignment factor
+    cie.push(0x78); // data alignment factor: -8
+    cie.push(16); // return address register: rip
+    cie.push(8); // augmentation data length
+    cie.push(0x9b); // personality encoding: DW_EH_PE_indirect | pcrel | sdata4
+    cie.extend_from_slice(&0x200i32.to_le_bytes()); // personality pointer
+    cie.push(0x1b); // LSDA encoding: DW_EH_PE_pcrel | sdata4
+    cie.push(0x00); // FDE pointer encoding: DW_EH_PE_absptr
+    cie.extend_from_slice(&[0x0c, 0x07, 0x08]); // DW_CFA_def_cfa: rsp+8
+    cie.extend_from_slice(&[0x90, 0x01]); // DW_CFA_offset: rip at CFA-8
+    while (cie.len() + 4) % 8 != 0 {
+        cie.push(0); // DW_CFA_nop
+    }
+    let mut eh_frame = vec![];
+    eh_frame.extend_from_slice(&(cie.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(&cie);
+
+    let fde_start = eh_frame.len();
+    let mut fde = vec![];
+    fde.extend_from_slice(&((fde_start + 4) as u32).to_le_bytes()); // CIE pointer
+    fde.extend_from_slice(&0x100u64.to_le_bytes()); // pc_begin
+    fde.extend_from_slice(&0x20u64.to_le_bytes()); // pc_range
+    fde.push(4); // augmentation data length
+    fde.extend_from_slice(&0x300i32.to_le_bytes()); // LSDA pointer
+    fde.extend_from_slice(&[0x0e, 0x10]); // DW_CFA_def_cfa_offset: 16
+    while (fde.len() + 4) % 8 != 0 {
+        fde.push(0); // DW_CFA_nop
+    }
+    eh_frame.extend_from_slice(&(fde.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(&fde);
+    eh_frame.extend_from_slice(&0u32.to_le_bytes()); // terminator
+
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [1, 0x1000400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_detailed(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x1000400), framehop::RuleSource::Dwarf))
+    );
+    assert_eq!(regs.sp(), 0x10);
+}
+
+#[test]
+fn test_debug_fde_encoding() {
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    assert_eq!(unwinder.debug_fde_offset(0x1000104), Some(24));
+    assert_eq!(
+        unwinder.debug_fde_encoding(0x1000104),
+        Some(framehop::FdeEncoding {
+            version: 1,
+            address_size: 8,
+            is_dwarf64: false,
+        })
+    );
+    assert_eq!(unwinder.debug_fde_encoding(0x2000000), None);
+}
+
+#[test]
+fn test_lazy_module() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let load_count = Arc::new(AtomicUsize::new(0));
+    let module = {
+        let load_count = load_count.clone();
+        framehop::Module::new_lazy(
+            "synthetic".to_string(),
+            0x1000000..0x1001000,
+            0x1000000,
+            move || {
+                load_count.fetch_add(1, Ordering::SeqCst);
+                // DW_CFA_def_cfa_offset: CFA=rsp+16
+                let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+                framehop::ExplicitModuleSectionInfo {
+                    base_svma: 0,
+                    eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+                    eh_frame: Some(eh_frame),
+                    ..Default::default()
+                }
+            },
+        )
+    };
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(module.clone());
+    let mut other_unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    other_unwinder.add_module(module.relocated(0x2000000..0x2001000, 0x2000000));
+    assert!(unwinder.module_for_address(0x1000104).is_some());
+    assert_eq!(load_count.load(Ordering::SeqCst), 0);
+
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [1, 2, 0x1000400, 4];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, pc| {
+        let mut regs = UnwindRegsX86_64::new(pc, 0x8, 0x1234);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(pc),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        (res, regs.sp())
+    };
+    assert_eq!(unwind(&unwinder, 0x1000104), (Ok(Some(0x1000400)), 0x18));
+    assert_eq!(load_count.load(Ordering::SeqCst), 1);
+
+    // The loaded information is shared with clones and relocated modules.
+    assert_eq!(unwind(&unwinder, 0x1000108), (Ok(Some(0x1000400)), 0x18));
+    assert_eq!(
+        unwind(&other_unwinder, 0x2000104),
+        (Ok(Some(0x1000400)), 0x18)
+    );
+    assert_eq!(load_count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_lazy_module_load_and_panic() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let load_count = Arc::new(AtomicUsize::new(0));
+    let module = {
+        let load_count = load_count.clone();
+        framehop::Module::<Vec<u8>>::new_lazy(
+            "synthetic".to_string(),
+            0x1000000..0x1001000,
+            0x1000000,
+            move || {
+                load_count.fetch_add(1, Ordering::SeqCst);
+                framehop::ExplicitModuleSectionInfo::default()
+            },
+        )
+    };
+    module.load();
+    assert_eq!(load_count.load(Ordering::SeqCst), 1);
+    module.load();
+    assert_eq!(load_count.load(Ordering::SeqCst), 1);
+
+    // A panicking closure leaves the module without unwind information, so the frame
+    // pointer is used.
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new_lazy(
+        "panics".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        || -> framehop::ExplicitModuleSectionInfo<Vec<u8>> { panic!("cannot load") },
+    ));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [1, 2, 0x20, 0x1000400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000104).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.bp(), 0x20);
+}
+
+#[test]
+fn test_clone_unwinder_for_threads() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let load_count = Arc::new(AtomicUsize::new(0));
+    let module = {
+        let load_count = load_count.clone();
+        framehop::Module::new_lazy(
+            "synthetic".to_string(),
+            0x1000000..0x1001000,
+            0x1000000,
+            move || {
+                load_count.fetch_add(1, Ordering::SeqCst);
+                // DW_CFA_def_cfa_offset: CFA=rsp+16
+                let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+                framehop::ExplicitModuleSectionInfo {
+                    base_svma: 0,
+                    eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+                    eh_frame: Some(eh_frame),
+                    ..Default::default()
+                }
+            },
+        )
+    };
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(module);
+    unwinder.set_end_of_stack_return_addresses(vec![0x1000400]);
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let unwinder = unwinder.clone();
+            std::thread::spawn(move || {
+                let mut cache = CacheX86_64::new();
+                let stack = [1, 2, 0x1000400, 4];
+                let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+                let mut regs = UnwindRegsX86_64::new(0x1000104, 0x8, 0x1234);
+                unwinder.unwind_frame(
+                    FrameAddress::from_instruction_pointer(0x1000104),
+                    &mut regs,
+                    &mut cache,
+                    &mut read_stack,
+                )
+            })
+        })
+        .collect();
+    for thread in threads {
+        // The clones have the same configuration: 0x1000400 ends the stack.
+        assert_eq!(thread.join().unwrap(), Ok(None));
+    }
+    // The unwind information was loaded once, and shared by all clones.
+    assert_eq!(load_count.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_shared_unwinder() {
+    let shared = framehop::SharedUnwinder::new(UnwinderX86_64::<Vec<u8>>::new());
+    let module = |start: u64| {
+        framehop::Module::new_framepointer_only("jit".to_string(), start..start + 0x1000, start)
+    };
+    shared.add_module(module(0x1000000));
+    let before = shared.snapshot();
+
+    // Changes are published as new snapshots, and don't affect existing ones.
+    shared.add_module(module(0x2000000));
+    shared.remove_module(0x1000000);
+    let after = shared.snapshot();
+    assert!(before.module_for_address(0x1000100).is_some());
+    assert!(before.module_for_address(0x2000100).is_none());
+    assert!(after.module_for_address(0x1000100).is_none());
+    assert!(after.module_for_address(0x2000100).is_some());
+    assert_eq!(shared.try_add_module(module(0x3000000)), Ok(()));
+
+    // Modules can be added while another thread unwinds.
+    let shared = std::sync::Arc::new(shared);
+    let sampler = {
+        let shared = shared.clone();
+        std::thread::spawn(move || {
+            let mut cache = CacheX86_64::new();
+            let stack = [0x0, 0x2000400];
+            let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+            for _ in 0..100 {
+                let mut regs = UnwindRegsX86_64::new(0x2000100, 0x0, 0x0);
+                let res = shared.snapshot().unwind_frame(
+                    FrameAddress::from_return_address(0x2000100).unwrap(),
+                    &mut regs,
+                    &mut cache,
+                    &mut read_stack,
+                );
+                // The frame pointer chain ends at the null frame pointer.
+                assert_eq!(res, Ok(None));
+            }
+        })
+    };
+    for i in 0..100 {
+        shared.add_module(module(0x10000000 + i * 0x1000));
+    }
+    sampler.join().unwrap();
+    assert_eq!(shared.snapshot().modules().len(), 102);
+}
+
+#[test]
+fn test_broken_cfi_error_names_module() {
+    // DW_CFA_def_cfa_expression: DW_OP_breg7 (rsp) +0x1000, which is outside the stack,
+    // so the return address at CFA-8 can't be read.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0f, 0x03, 0x77, 0x80, 0x20], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "libbroken.so".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            ..Default::default()
+        },
+    ));
+    let stack = [1, 2, 0x30, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut cache = CacheX86_64::<_>::new();
+
+    // With a valid frame pointer, the fallback rule unwinds the frame.
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+
+    // Otherwise the error names the module, also when unwinding at the address again.
+    for _ in 0..2 {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1000);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x1000104),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        let err = res.unwrap_err();
+        assert!(
+            matches!(&err, framehop::Error::DwarfInModule { module, .. } if &**module == "libbroken.so"),
+            "{err:?}"
+        );
+        assert!(err.to_string().contains("libbroken.so"), "{err}");
+    }
+}
+
+#[test]
+fn test_unknown_frame_pointer() {
+    let make_unwinder = |fde_instructions: &[u8]| {
+        let eh_frame = synthetic_eh_frame(0x100, 0x20, fde_instructions, false);
+        let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+        unwinder.add_module(synthetic_module(eh_frame));
+        unwinder
+    };
+    let stack = [1, 2, 0x30, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let new_regs = || {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0);
+        regs.set_unknown_registers(framehop::RegisterSet::FP);
+        regs
+    };
+
+    // DW_CFA_def_cfa_offset: CFA=rsp+16. The rule only needs rsp, and rbp stays unknown.
+    let unwinder = make_unwinder(&[0x0e, 0x10]);
+    let mut cache = CacheX86_64::<_>::new();
+    let mut regs = new_regs();
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.unknown_registers(), framehop::RegisterSet::FP);
+
+    // The caller isn't covered by the unwind info, and the frame pointer fallback needs rbp.
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::MissingRegisterForRule(
+            framehop::RegisterSet::FP
+        ))
+    );
+
+    // DW_CFA_def_cfa_expression: DW_OP_breg6 (rbp) +16. The expression can't be evaluated.
+    let unwinder = make_unwinder(&[0x0f, 0x02, 0x76, 0x10]);
+    let mut cache = CacheX86_64::<_>::new();
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut new_regs(),
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::MissingRegisterForRule(
+            framehop::RegisterSet::FP
+        ))
+    );
+
+    // DW_CFA_def_cfa_offset: CFA=rsp+16, and DW_CFA_offset: rbp at CFA-16. rbp is
+    // restored from the stack, so the caller's rbp is known.
+    let unwinder = make_unwinder(&[0x0e, 0x10, 0x86, 0x02]);
+    let mut cache = CacheX86_64::<_>::new();
+    let mut regs = new_regs();
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.bp(), 0x30);
+    assert!(regs.unknown_registers().is_empty());
+}
+
+#[test]
+fn test_iter_frames_from_return_address() {
+    // DW_CFA_def_cfa_offset: CFA=rsp+16
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 2, /* 0x10: */ 3,
+        /* 0x18: */ 0x1000400, // return address for the FDE's CFA
+        /* 0x20: */ 0, // stored bp
+        /* 0x28: */ 0, // return address for the frame pointer
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x20);
+
+    // Resume unwinding at a saved return address which is inside the FDE.
+    let frames: Vec<_> = unwinder
+        .iter_frames_from(
+            FrameAddress::from_return_address(0x1000104).unwrap(),
+            regs,
+            &mut cache,
+            &mut read_stack,
+        )
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_return_address(0x1000104).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1000400).unwrap()),
+        ]
+    );
+
+    // 0x1000100 is the first byte covered by the FDE. As a return address, it is looked
+    // up at 0x10000ff, which is not covered, so the frame pointer is used instead.
+    let frames: Vec<_> = unwinder
+        .iter_frames(0x1000100, regs, &mut cache, &mut read_stack)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_instruction_pointer(0x1000100)),
+            Ok(FrameAddress::from_return_address(0x1000400).unwrap()),
+        ]
+    );
+    let frames: Vec<_> = unwinder
+        .iter_frames_from(
+            FrameAddress::from_return_address(0x1000100).unwrap(),
+            regs,
+            &mut cache,
+            &mut read_stack,
+        )
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![Ok(FrameAddress::from_return_address(0x1000100).unwrap())]
+    );
+
+    // resume_unwind takes the pc of the captured frame as a return address.
+    let frames: Vec<_> = unwinder
+        .resume_unwind(0x1000104, regs, &mut cache, &mut read_stack)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_return_address(0x1000104).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1000400).unwrap()),
+        ]
+    );
+    let mut iter = unwinder.resume_unwind(0, regs, &mut cache, &mut read_stack);
+    assert_eq!(iter.next(), Ok(None));
+}
+
+#[test]
+fn test_resume_unwind_at_function_boundary() {
+    // Two adjacent functions: the one at 0x100..0x120 has CFA=rsp+16, and the one at
+    // 0x120..0x140 has CFA=rsp+32.
+    let eh_frame = synthetic_eh_frame_with_fdes(
+        &[
+            (0x100, 0x20, &[0x0e, 0x10]), // DW_CFA_def_cfa_offset: CFA=rsp+16
+            (0x120, 0x20, &[0x0e, 0x20]), // DW_CFA_def_cfa_offset: CFA=rsp+32
+        ],
+        false,
+    );
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 0x1000400, // return address for CFA=rsp+16
+        /* 0x10: */ 3, /* 0x18: */ 0x1000500, // return address for CFA=rsp+32
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let regs = UnwindRegsX86_64::new(0x1000120, 0x0, 0x1234);
+
+    // A call at the end of the first function returns to 0x1000120, the start of the
+    // second function. resume_unwind looks up 0x100011f, in the first function.
+    let mut iter = unwinder.resume_unwind(0x1000120, regs, &mut cache, &mut read_stack);
+    assert_eq!(
+        iter.next(),
+        Ok(Some(FrameAddress::from_return_address(0x1000120).unwrap()))
+    );
+    assert_eq!(
+        iter.next(),
+        Ok(Some(FrameAddress::from_return_address(0x1000400).unwrap()))
+    );
+
+    // As an instruction pointer, 0x1000120 is looked up in the second function.
+    let mut iter = unwinder.iter_frames(0x1000120, regs, &mut cache, &mut read_stack);
+    assert_eq!(
+        iter.next(),
+        Ok(Some(FrameAddress::from_instruction_pointer(0x1000120)))
+    );
+    assert_eq!(
+        iter.next(),
+        Ok(Some(FrameAddress::from_return_address(0x1000500).unwrap()))
+    );
+}
+
+#[test]
+fn test_unwind_samples() {
+    // DW_CFA_def_cfa_offset: CFA=rsp+16
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 0x1000400, // return address of the sample at sp 0x0
+        /* 0x10: */ 3,
+        /* 0x18: */ 0, // null return address of the sample at sp 0x10
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let samples = [
+        (0x1000110, UnwindRegsX86_64::new(0x1000110, 0x10, 0x1234)),
+        (0x1000104, UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234)),
+        // Not in any module.
+        (0x2000000, UnwindRegsX86_64::new(0x2000000, 0x0, 0x1234)),
+    ];
+    let stacks = unwinder.unwind_samples(&samples, &mut cache, &mut read_stack);
+    assert_eq!(
+        stacks,
+        vec![
+            (
+                vec![FrameAddress::from_instruction_pointer(0x1000110)],
+                None
+            ),
+            (
+                vec![
+                    FrameAddress::from_instruction_pointer(0x1000104),
+                    FrameAddress::from_return_address(0x1000400).unwrap(),
+                ],
+                // 0x1000400 has no FDE, and the frame pointer 0x1234 is not on the stack.
+                Some(framehop::Error::CouldNotReadStack(
+                    0x1234,
+                    ReadMemErrorKind::Unknown
+                ))
+            ),
+            (
+                vec![FrameAddress::from_instruction_pointer(0x2000000)],
+                Some(framehop::Error::NoModuleForAddress(0x2000000))
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_unwind_frame_with_rule() {
+    // No modules: the rule is applied without any lookup.
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 2, /* 0x10: */ 0x30, // stored bp
+        /* 0x18: */ 0x1000400, // return address
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x10);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleX86_64::UseFramePointer,
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.ip(), regs.sp(), regs.bp()), (0x1000400, 0x20, 0x30));
+
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 },
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+
+    unwinder.set_end_of_stack_return_addresses(vec![0x1000400]);
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 },
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(None));
+}
+
+#[test]
+fn test_unwind_frame_with_rule_ptr_auth_mask() {
+    let mut unwinder: UnwinderAarch64<Vec<u8>> = UnwinderAarch64::new();
+    unwinder.set_ptr_auth_mask(PtrAuthMask::new_24_40());
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 0x30, // stored fp
+        /* 0x18: */ 0xa5_0000_0000_0000 + 0x1000400, // stored signed lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsAarch64::new(0x1000200, 0x0, 0x10);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleAarch64::UseFramePointer,
+        FrameAddress::from_return_address(0x1000200).unwrap(),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.lr(), regs.sp(), regs.fp()), (0x1000400, 0x20, 0x30));
+}
+
+#[test]
+fn test_unwind_frame_with_rule_x86() {
+    use framehop::x86::*;
+
+    let unwinder: UnwinderX86<Vec<u8>> = UnwinderX86::new();
+    let stack = [1, 2, 0x20, 0x10400];
+    let mut memory = Stack32(&stack);
+    let mut regs = UnwindRegsX86::new(0x10104, 0x0, 0x8);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleX86::UseFramePointer,
+        FrameAddress::from_instruction_pointer(0x10104),
+        &mut regs,
+        &mut memory,
+    );
+    assert_eq!(res, Ok(Some(0x10400)));
+    assert_eq!((regs.sp(), regs.bp()), (0x10, 0x20));
+}
+
+#[test]
+fn test_register_rule_expressions() {
+    // DW_CFA_def_cfa_offset: CFA=rsp+32,
+    // DW_CFA_expression: rip at [CFA + 8] (DW_OP_plus_uconst 8),
+    // DW_CFA_val_expression: rbp = CFA + 16 (DW_OP_plus_uconst 16).
+    // The CFA is pushed onto the stack before the expressions are evaluated.
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[
+            0x0e, 0x20, 0x10, 0x10, 0x02, 0x23, 0x08, 0x16, 0x06, 0x02, 0x23, 0x10,
+        ],
+        false,
+    );
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 2, /* 0x10: */ 3, /* 0x18: */ 4,
+        /* 0x20: */ 5,
+        /* 0x28: */ 0x1000500, // CFA-8, where the return address would usually be
+        /* 0x30: */ 7, /* 0x38: */ 0x1000400, // [CFA + 8]
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x30);
+    assert_eq!(regs.bp(), 0x40);
+}
+
+#[test]
+fn test_recover_with_frame_pointer_on_missing_info() {
+    use framehop::RuleSource;
+
+    // DW_CFA_def_cfa_offset: CFA=rsp+16.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 0, /* 0x8: */ 0, /* 0x10: */ 0,
+        /* 0x18: */ 0x2000010, // return address into code without a module
+        /* 0x20: */ 0, /* 0x28: */ 0x40, // stored bp, pointed to by bp
+        /* 0x30: */ 0x1000108, // return address for the frame pointer
+        /* 0x38: */ 0, /* 0x40: */ 0, // null return address for CFA=rsp+16
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>| {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x28);
+        let mut address = FrameAddress::from_instruction_pointer(0x1000104);
+        let mut frames = vec![];
+        loop {
+            let outcome =
+                unwinder.unwind_frame_detailed(address, &mut regs, &mut cache, &mut read_stack);
+            match outcome {
+                Ok(outcome) => {
+                    frames.push(Ok((outcome.next_pc, outcome.source)));
+                    match outcome.next_pc.and_then(FrameAddress::from_return_address) {
+                        Some(next) => address = next,
+                        None => return frames,
+                    }
+                }
+                Err(err) => {
+                    frames.push(Err(err));
+                    return frames;
+                }
+            }
+        }
+    };
+
+    assert_eq!(
+        unwind(&unwinder),
+        vec![
+            Ok((Some(0x2000010), RuleSource::Dwarf)),
+            Err(framehop::Error::NoModuleForAddress(0x2000010)),
+        ]
+    );
+
+    unwinder.set_recover_with_frame_pointer_on_missing_info(true);
+    assert_eq!(
+        unwind(&unwinder),
+        vec![
+            Ok((Some(0x2000010), RuleSource::Cached)),
+            Ok((Some(0x1000108), RuleSource::FramePointerRecovery)),
+            Ok((None, RuleSource::Dwarf)),
+        ]
+    );
+}
+
+#[test]
+fn test_try_add_module() {
+    let module = |eh_frame: Vec<u8>, eh_frame_hdr: Option<Vec<u8>>| {
+        framehop::Module::new(
+            "synthetic".to_string(),
+            0x1000000..0x1001000,
+            0x1000000,
+            framehop::ExplicitModuleSectionInfo {
+                base_svma: 0,
+                eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+                eh_frame: Some(eh_frame),
+                eh_frame_hdr_svma: eh_frame_hdr
+                    .as_ref()
+                    .map(|hdr| 0x700..0x700 + hdr.len() as u64),
+                eh_frame_hdr,
+                ..Default::default()
+            },
+        )
+    };
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+
+    // The FDE's length runs past the end of the section.
+    let truncated = vec![0x20, 0, 0, 0, 0, 0, 0, 0, 1];
+    assert!(matches!(
+        unwinder.try_add_module(module(truncated, None)),
+        Err(framehop::AddModuleError::FdeIndex(_))
+    ));
+    // Only the terminator.
+    assert_eq!(
+        unwinder.try_add_module(module(vec![0, 0, 0, 0], None)),
+        Err(framehop::AddModuleError::NoCie)
+    );
+    // An .eh_frame_hdr with an unknown version.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[], false);
+    assert!(matches!(
+        unwinder.try_add_module(module(eh_frame.clone(), Some(vec![2, 0xff, 0xff, 0xff]))),
+        Err(framehop::AddModuleError::EhFrameHdr(_))
+    ));
+    assert_eq!(unwinder.modules().len(), 0);
+
+    assert_eq!(unwinder.try_add_module(module(eh_frame, None)), Ok(()));
+    assert_eq!(unwinder.modules().len(), 1);
+}
+
+#[test]
+fn test_one_mib_stack_frame() {
+    // DW_CFA_def_cfa_offset_sf: CFA=rsp+0x100010 (-0x20002 * data alignment -8),
+    // DW_CFA_offset: rbp at CFA-16.
+    // The offset doesn't fit into an UnwindRuleX86_64, so the frame is unwound by
+    // evaluating the CFI directly.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x13, 0xfe, 0xff, 0x77, 0x86, 0x02], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    let mut cache = CacheX86_64::<_>::new();
+    let mut stack = vec![0u64; 0x100020 / 8];
+    stack[0x100010 / 8] = 0x30; // stored bp
+    stack[0x100018 / 8] = 0x1000400; // return address
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    for _ in 0..2 {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x1000104),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x1000400)));
+        assert_eq!(regs.sp(), 0x100020);
+        assert_eq!(regs.bp(), 0x30);
+    }
+}
+
+#[test]
+fn test_precompute_rules() {
+    use framehop::{PrecomputedRules, RuleSource};
+
+    let mut unwinder = UnwinderX86_64::new();
+    // DW_CFA_def_cfa_offset: 16, which can be translated into an unwind rule, and
+    // DW_CFA_def_cfa_expression: DW_OP_breg7 (rsp) +16, which can't.
+    for (start, fde_instructions) in [
+        (0x1000000, &[0x0e, 0x10][..]),
+        (0x3000000, &[0x0f, 0x02, 0x77, 0x10][..]),
+    ] {
+        let eh_frame = synthetic_eh_frame(0x100, 0x20, fde_instructions, false);
+        unwinder.add_module(framehop::Module::new(
+            "synthetic".to_string(),
+            start..start + 0x1000,
+            start,
+            framehop::ExplicitModuleSectionInfo {
+                base_svma: 0,
+                eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+                eh_frame: Some(eh_frame),
+                ..Default::default()
+            },
+        ));
+    }
+
+    let mut cache = CacheX86_64::new();
+    cache.record_fde_evaluations(true);
+    let result = unwinder.precompute_rules(
+        &[
+            FrameAddress::from_return_address(0x1000108).unwrap(),
+            FrameAddress::from_return_address(0x1000110).unwrap(),
+            FrameAddress::from_return_address(0x2000000).unwrap(),
+            FrameAddress::from_return_address(0x3000108).unwrap(),
+        ],
+        &mut cache,
+    );
+    assert_eq!(
+        result,
+        PrecomputedRules {
+            cached: 2,
+            failed: 2
+        }
+    );
+    // Only unwinding is counted, not precomputing.
+    assert!(cache.fde_evaluations().is_empty());
+    #[cfg(feature = "stats")]
+    assert_eq!(cache.stats().total(), 0);
+
+    let stack = [1, 0x1000400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000108, 0x0, 0x1234);
+    let res = unwinder.unwind_frame_detailed(
+        FrameAddress::from_return_address(0x1000108).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x1000400), RuleSource::Cached))
+    );
+    assert!(cache.fde_evaluations().is_empty());
+    #[cfg(feature = "stats")]
+    assert_eq!(cache.stats().hits(), 1);
+}
+
+#[test]
+fn test_end_of_stack() {
+    // DW_CFA_def_cfa_expression: DW_OP_breg7 (rsp) +16. Expressions can't be translated
+    // into unwind rules, so the return address is recovered by the DWARF unwinder.
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0f, 0x02, 0x77, 0x10], false);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, return_address| {
+        let mut cache = CacheX86_64::new();
+        let stack = [1, return_address, 3];
+        let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+        unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x1000104),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        )
+    };
+
+    assert_eq!(unwind(&unwinder, 0), Ok(None));
+    assert_eq!(unwind(&unwinder, 0x1000400), Ok(Some(0x1000400)));
+
+    unwinder.set_end_of_stack_return_addresses(vec![0x1000400]);
+    assert_eq!(unwind(&unwinder, 0x1000400), Ok(None));
+    assert_eq!(unwind(&unwinder, 0x1000500), Ok(Some(0x1000500)));
+
+    // Like a return address to just after the start of Go's runtime.goexit.
+    unwinder.set_end_of_stack_functions(vec![0x1000600..0x1000620, 0x1000610..0x1000640]);
+    assert_eq!(unwind(&unwinder, 0x1000601), Ok(None));
+    assert_eq!(unwind(&unwinder, 0x1000640), Ok(None));
+    assert_eq!(unwind(&unwinder, 0x1000600), Ok(Some(0x1000600)));
+    assert_eq!(unwind(&unwinder, 0x1000641), Ok(Some(0x1000641)));
+
+    assert_eq!(unwind(&unwinder, 1), Ok(Some(1)));
+    unwinder.set_end_of_stack_below_address(0x1000);
+    assert_eq!(unwind(&unwinder, 1), Ok(None));
+    assert_eq!(unwind(&unwinder, 0xfff), Ok(None));
+    assert_eq!(unwind(&unwinder, 0x1000), Ok(Some(0x1000)));
+}
+
+#[test]
+fn test_end_of_stack_at_stack_end() {
+    // The CIE's rules: CFA=rsp+8, rip=[CFA-8].
+    let eh_frame = synthetic_eh_frame(0x100, 0x20, &[], false);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    // The caller's rsp is 0x18. Use the same cache for all calls, so that the later ones
+    // use the cached rule.
+    let mut cache = CacheX86_64::new();
+    let mut unwind = |stack_end: Option<u64>| {
+        let stack = [1, 2, 0x1000400, 4];
+        let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+        if let Some(stack_end) = stack_end {
+            regs.set_stack_end(stack_end);
+        }
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x1000104),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(regs.stack_end(), stack_end);
+        res
+    };
+
+    assert_eq!(unwind(None), Ok(Some(0x1000400)));
+    assert_eq!(unwind(Some(0x20)), Ok(Some(0x1000400)));
+    assert_eq!(unwind(Some(0x18)), Ok(None));
+    assert_eq!(unwind(Some(0x10)), Ok(None));
+}
+
+#[test]
+fn test_eh_frame_hdr_with_relative_encodings() {
+    // Two FDEs for the same address range: the first one at offset 24 has CFA=rsp+16, the
+    // second one has CFA=rsp+32. Only the .eh_frame_hdr table points to the second one.
+    let mut eh_frame = synthetic_eh_frame(0x100, 0x20, &[0x0e, 0x10], false);
+    eh_frame.truncate(eh_frame.len() - 4); // remove the terminator
+    let fde_offset = eh_frame.len();
+    eh_frame.extend_from_slice(&28u32.to_le_bytes()); // length
+    eh_frame.extend_from_slice(&((fde_offset + 4) as u32).to_le_bytes()); // CIE pointer
+    eh_frame.extend_from_slice(&0x100u64.to_le_bytes());
+    eh_frame.extend_from_slice(&0x20u64.to_le_bytes());
+    eh_frame.extend_from_slice(&[0, 0x0e, 0x20, 0]); // DW_CFA_def_cfa_offset: 32
+    eh_frame.extend_from_slice(&0u32.to_le_bytes()); // terminator
+
+    let eh_frame_hdr_svma = 0x700;
+    let eh_frame_svma = 0x800;
+    let mut eh_frame_hdr = vec![
+        1,    // version
+        0x1b, // eh_frame_ptr encoding: DW_EH_PE_pcrel | DW_EH_PE_sdata4
+        0x03, // fde_count encoding: DW_EH_PE_udata4
+        0x3b, // table encoding: DW_EH_PE_datarel | DW_EH_PE_sdata4
+    ];
+    eh_frame_hdr
+        .extend_from_slice(&((eh_frame_svma - (eh_frame_hdr_svma + 4)) as i32).to_le_bytes());
+    eh_frame_hdr.extend_from_slice(&1u32.to_le_bytes());
+    eh_frame_hdr.extend_from_slice(&((0x100 - eh_frame_hdr_svma) as i32).to_le_bytes());
+    eh_frame_hdr.extend_from_slice(
+        &((eh_frame_svma + fde_offset as i64 - eh_frame_hdr_svma) as i32).to_le_bytes(),
+    );
+
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(eh_frame_svma as u64..eh_frame_svma as u64 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            eh_frame_hdr_svma: Some(
+                eh_frame_hdr_svma as u64..eh_frame_hdr_svma as u64 + eh_frame_hdr.len() as u64,
+            ),
+            eh_frame_hdr: Some(eh_frame_hdr),
+            ..Default::default()
+        },
+    ));
+    assert_eq!(
+        unwinder.debug_fde_offset(0x1000104),
+        Some(fde_offset as u32)
+    );
+
+    let mut cache = CacheX86_64::new();
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+}
+
+#[test]
+fn test_unwind_sections_read_at_file_offsets() {
+    // An ELF file on disk, whose sections are at different file offsets than their
+    // addresses: the segment at file offset 0x0 has the address 0x2000. The function is at
+    // 0x1100..0x1120, with CFA=rsp+16.
+    let eh_frame = synthetic_eh_frame(0x1100, 0x20, &[0x0e, 0x10], false);
+    let (eh_frame_hdr_offset, eh_frame_hdr_svma) = (0x100, 0x2100i64);
+    let (eh_frame_offset, eh_frame_svma) = (0x200, 0x2200i64);
+    let fde_offset = 24;
+    let mut eh_frame_hdr = vec![
+        1,    // version
+        0x1b, // eh_frame_ptr encoding: DW_EH_PE_pcrel | DW_EH_PE_sdata4
+        0x03, // fde_count encoding: DW_EH_PE_udata4
+        0x3b, // table encoding: DW_EH_PE_datarel | DW_EH_PE_sdata4
+    ];
+    eh_frame_hdr
+        .extend_from_slice(&((eh_frame_svma - (eh_frame_hdr_svma + 4)) as i32).to_le_bytes());
+    eh_frame_hdr.extend_from_slice(&1u32.to_le_bytes());
+    eh_frame_hdr.extend_from_slice(&((0x1100 - eh_frame_hdr_svma) as i32).to_le_bytes());
+    eh_frame_hdr.extend_from_slice(
+        &((eh_frame_svma + fde_offset - eh_frame_hdr_svma) as i32).to_le_bytes(),
+    );
+    let mut file = vec![0; 0x300];
+    file[eh_frame_hdr_offset..][..eh_frame_hdr.len()].copy_from_slice(&eh_frame_hdr);
+    file[eh_frame_offset..][..eh_frame.len()].copy_from_slice(&eh_frame);
+
+    // The file is loaded with this load bias, but it isn't mapped.
+    let bias = 0x7f0000000000;
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "on-disk".to_string(),
+        bias..bias + 0x3000,
+        bias,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(
+                eh_frame_svma as u64..(eh_frame_svma as usize + eh_frame.len()) as u64,
+            ),
+            eh_frame: Some(&file[eh_frame_offset..][..eh_frame.len()]),
+            eh_frame_hdr_svma: Some(
+                eh_frame_hdr_svma as u64..(eh_frame_hdr_svma as usize + eh_frame_hdr.len()) as u64,
+            ),
+            eh_frame_hdr: Some(&file[eh_frame_hdr_offset..][..eh_frame_hdr.len()]),
+            ..Default::default()
+        },
+    ));
+    assert_eq!(
+        unwinder.debug_fde_offset(bias + 0x1104),
+        Some(fde_offset as u32)
+    );
+
+    let mut cache = CacheX86_64::new();
+    let stack = [1, bias + 0x1400, 3];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(bias + 0x1104, 0x0, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(bias + 0x1104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(bias + 0x1400)));
+    assert_eq!(regs.sp(), 0x10);
+}
+
+#[test]
+fn test_prologue_analysis_for_first_frame() {
+    use framehop::RuleSource;
+
+    // push rbp; mov rbp, rsp; sub rsp, 0x10
+    let text = vec![0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x10];
+    let module =
+        framehop::Module::new_framepointer_only("jit".to_string(), 0x1000000..0x1001000, 0x1000000);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(module.with_prologue_analysis(0x100..0x108, text));
+    let mut cache = CacheX86_64::new();
+
+    let stack = [0x1000400, 0x1000500, 0x30, 0x1000600];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |address| {
+        let mut regs = UnwindRegsX86_64::new(0, 0x0, 0x10);
+        unwinder
+            .unwind_frame_detailed(address, &mut regs, &mut cache, &mut read_stack)
+            .map(|outcome| (outcome.next_pc, outcome.source))
+    };
+
+    // Before the push, the return address is at rsp.
+    assert_eq!(
+        unwind(FrameAddress::from_instruction_pointer(0x1000100)),
+        Ok((Some(0x1000400), RuleSource::InstructionAnalysis))
+    );
+    // After the push, the return address is at rsp + 8.
+    assert_eq!(
+        unwind(FrameAddress::from_instruction_pointer(0x1000101)),
+        Ok((Some(0x1000500), RuleSource::InstructionAnalysis))
+    );
+    // Return addresses are unwound with the frame pointer.
+    assert_eq!(
+        unwind(FrameAddress::from_return_address(0x1000101).unwrap()),
+        Ok((Some(0x1000600), RuleSource::FramePointer))
+    );
+
+    // stp x29, x30, [sp, #-16]!; mov x29, sp
+    let text = vec![0xfd, 0x7b, 0xbf, 0xa9, 0xfd, 0x03, 0x00, 0x91];
+    let module =
+        framehop::Module::new_framepointer_only("jit".to_string(), 0x1000000..0x1001000, 0x1000000);
+    let mut unwinder = UnwinderAarch64::new();
+    unwinder.add_module(module.with_prologue_analysis(0x100..0x108, text));
+    let mut cache = CacheAarch64::new();
+    // At the function entry, the return address is still in lr.
+    let mut regs = UnwindRegsAarch64::new(0x1000700, 0x0, 0x10);
+    let outcome = unwinder.unwind_frame_detailed(
+        FrameAddress::from_instruction_pointer(0x1000100),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        outcome.map(|outcome| (outcome.next_pc, outcome.source)),
+        Ok((Some(0x1000700), RuleSource::InstructionAnalysis))
+    );
+}
+
+#[test]
+fn test_big_endian_eh_frame() {
+    let eh_frame = synthetic_eh_frame(
+        0x100,
+        0x20,
+        &[0x0e, 0x10], // DW_CFA_def_cfa_offset: CFA=rsp+16
+        true,
+    );
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            endianness: framehop::Endianness::Big,
+            ..Default::default()
+        },
+    ));
+
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x1234);
+}
+
+#[test]
+fn test_riscv64_synthetic_eh_frame() {
+    use framehop::riscv64::{CacheRiscV64, UnwindRegsRiscV64, UnwinderRiscV64};
+
+    // A function at 0x100..0x120 which saves ra and s0, and then sets up s0 as the frame
+    // pointer.
+    let eh_frame = synthetic_eh_frame_with_cie(
+        1,
+        1,                   // ra
+        &[0x0c, 0x02, 0x00], // DW_CFA_def_cfa: sp+0
+        0x100,
+        0x20,
+        &[
+            0x42, // DW_CFA_advance_loc: 2
+            0x0e, 0x10, // DW_CFA_def_cfa_offset: CFA=sp+16
+            0x44, // DW_CFA_advance_loc: 4
+            0x81, 0x01, // DW_CFA_offset: ra at CFA-8
+            0x88, 0x02, // DW_CFA_offset: s0 at CFA-16
+            0x42, // DW_CFA_advance_loc: 2
+            0x0c, 0x08, 0x00, // DW_CFA_def_cfa: s0+0
+        ],
+        false,
+    );
+    let mut cache = CacheRiscV64::new();
+    let mut unwinder = UnwinderRiscV64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 2, /* 0x10: */ 3, /* 0x18: */ 4,
+        /* 0x20: */ 5, /* 0x28: */ 6,
+        /* 0x30: */ 0x60, // stored s0 of the second frame
+        /* 0x38: */ 0x1000400, // return address of the second frame
+        /* 0x40: */ 7, /* 0x48: */ 8,
+        /* 0x50: */ 0, // stored s0 of the third frame
+        /* 0x58: */ 0, // null return address of the third frame
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // The first frame is in the prologue, before ra is saved. The second frame has set
+    // up the frame pointer. The third frame at 0x1000400 is not covered by an FDE, so it
+    // is unwound with the frame pointer.
+    let regs = UnwindRegsRiscV64::new(0x1000104, 0x100010a, 0x0, 0x40);
+    let frames: Vec<_> = unwinder
+        .iter_frames(0x1000104, regs, &mut cache, &mut read_stack)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_instruction_pointer(0x1000104)),
+            Ok(FrameAddress::from_return_address(0x100010a).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1000400).unwrap()),
+        ]
+    );
+
+    let mut regs = UnwindRegsRiscV64::new(0x1000104, 0x100010a, 0x0, 0x40);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x100010a)));
+    assert_eq!((regs.sp(), regs.fp()), (0x10, 0x40));
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x100010a).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!((regs.sp(), regs.fp(), regs.ra()), (0x40, 0x60, 0x1000400));
+}
+
+#[test]
+fn test_arm_mixed_mode_stack() {
+    use framehop::arm::{CacheArm, UnwindRegsArm, UnwinderArm};
+
+    let cie: &[u8] = &[
+        0, 0, 0, 0, // CIE id
+        1, // version
+        b'z', b'R', 0,    // augmentation
+        2,    // code alignment factor
+        0x7c, // data alignment factor: -4
+        14,   // return address register: lr
+        1,    // augmentation data length
+        0x00, // FDE pointer encoding: DW_EH_PE_absptr
+        0x0c, 0x0d, 0x00, // DW_CFA_def_cfa: sp+0
+    ];
+    // A Thumb function at 0x100..0x120 which starts with this prologue:
+    //   0x100: push {r7, lr}
+    //   0x102: mov r7, sp
+    let fde: &[u8] = &[
+        24, 0, 0, 0, // CIE pointer
+        0x00, 0x01, 0, 0, // pc_begin: 0x100
+        0x20, 0, 0, 0,    // pc_range: 0x20
+        0,    // augmentation data length
+        0x41, // DW_CFA_advance_loc: 2
+        0x0e, 0x08, // DW_CFA_def_cfa_offset: CFA=sp+8
+        0x87, 0x02, // DW_CFA_offset: r7 at CFA-8
+        0x8e, 0x01, // DW_CFA_offset: lr at CFA-4
+        0x41, // DW_CFA_advance_loc: 2
+        0x0d, 0x07, // DW_CFA_def_cfa_register: r7
+        0,    // DW_CFA_nop
+    ];
+    let mut eh_frame = vec![];
+    eh_frame.extend_from_slice(&(cie.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(cie);
+    eh_frame.extend_from_slice(&(fde.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(fde);
+    eh_frame.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut cache = CacheArm::new();
+    let mut unwinder = UnwinderArm::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            address_size: Some(4),
+            ..Default::default()
+        },
+    ));
+
+    let mut stack = [0; 18];
+    // The frame record of the Thumb function at 0x100, found with its CFI.
+    stack[0x10 / 4] = 0x38; // stored r7
+    stack[0x14 / 4] = 0x1000300; // return address into ARM code
+                                 // The r11 frame record of the ARM code at 0x300, which has no CFI.
+    stack[0x28 / 4] = 0x40; // stored r11
+    stack[0x2c / 4] = 0x1000201; // return address into Thumb code
+                                 // The r7 frame record of the Thumb code at 0x200, which ends the stack.
+    stack[0x38 / 4] = 0;
+    stack[0x3c / 4] = 0;
+    // Where r11 points in the Thumb code at 0x200, a plausible frame record, which
+    // would continue the stack if r11 was tried before r7.
+    stack[0x40 / 4] = 0x50;
+    stack[0x44 / 4] = 0x1000667;
+    let mut read_stack = |addr| stack.get((addr / 4) as usize).cloned().ok_or(());
+
+    let regs = UnwindRegsArm::new(0x1000109, 0x1234, 0x8, 0x10, 0x28);
+    let frames: Vec<_> = unwinder
+        .iter_frames(0x1000109, regs, &mut cache, &mut read_stack)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_instruction_pointer(0x1000109)),
+            Ok(FrameAddress::from_return_address(0x1000300).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1000200).unwrap()),
+        ]
+    );
+}
+
+#[test]
+fn test_eh_frame_with_4_byte_addresses() {
+    use framehop::x86::{CacheX86, UnwindRegsX86, UnwinderX86};
+
+    // A 32-bit .eh_frame with absolute (DW_EH_PE_absptr) 4-byte addresses.
+    let cie: &[u8] = &[
+        0, 0, 0, 0, // CIE id
+        1, // version
+        b'z', b'R', 0,    // augmentation
+        1,    // code alignment factor
+        0x7c, // data alignment factor: -4
+        8,    // return address register: eip
+        1,    // augmentation data length
+        0x00, // FDE pointer encoding: DW_EH_PE_absptr
+        0x0c, 0x04, 0x04, // DW_CFA_def_cfa: esp+4
+        0x88, 0x01, // DW_CFA_offset: eip at CFA-4
+        0, 0, 0, 0, 0, 0, // DW_CFA_nop
+    ];
+    let fde: &[u8] = &[
+        32, 0, 0, 0, // CIE pointer
+        0x00, 0x01, 0, 0, // pc_begin: 0x100
+        0x20, 0, 0, 0, // pc_range: 0x20
+        0, // augmentation data length
+        0x0e, 0x08, // DW_CFA_def_cfa_offset: CFA=esp+8
+        0,    // DW_CFA_nop
+    ];
+    let mut eh_frame = vec![];
+    eh_frame.extend_from_slice(&(cie.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(cie);
+    eh_frame.extend_from_slice(&(fde.len() as u32).to_le_bytes());
+    eh_frame.extend_from_slice(fde);
+    eh_frame.extend_from_slice(&0u32.to_le_bytes());
+
+    let mut cache = CacheX86::new();
+    let mut unwinder = UnwinderX86::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000..0x2000,
+        0x1000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            address_size: Some(4),
+            ..Default::default()
+        },
+    ));
+
+    let stack: [u32; 8] = [1, 2, 3, 4, 5, 0x1400, 7, 8];
+    let mut read_stack = |addr| {
+        stack
+            .get((addr / 4) as usize)
+            .map(|v| u64::from(*v))
+            .ok_or(())
+    };
+    let mut regs = UnwindRegsX86::new(0x1104, 0x10, 0x1234);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1400)));
+    assert_eq!(regs.sp(), 0x18);
+    assert_eq!(regs.bp(), 0x1234);
+}
+
+#[test]
+fn test_try_unwind_frame() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x1000000..0x1010000,
+        0x1000000,
+    ));
+
+    let stack = [1, 2, 0x40, 0x1000400];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    // bp points outside of the stack, so the frame pointer can't be followed.
+    let mut regs = UnwindRegsX86_64::new(0x1000800, 0x8, 0x100);
+    let res = unwinder.try_unwind_frame(
+        FrameAddress::from_return_address(0x1000800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::CouldNotReadStack(
+            0x100,
+            ReadMemErrorKind::Unknown
+        ))
+    );
+    assert_eq!(regs, UnwindRegsX86_64::new(0x1000800, 0x8, 0x100));
+
+    let mut regs = regs.with_bp(0x10);
+    let res = unwinder.try_unwind_frame(
+        FrameAddress::from_return_address(0x1000800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x40);
+}
+
 #[test]
 fn test_pthread_cfa_expr() {
     let mut cache = CacheX86_64::<_>::new();
@@ -435,3 +3461,346 @@ fn test_root_func() {
     );
     assert_eq!(res, Ok(None));
 }
+
+/// A reader whose reads are always interrupted.
+struct InterruptedReader;
+
+impl MemoryAccess for InterruptedReader {
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        Err(ReadMemError::new(addr, ReadMemErrorKind::Interrupted))
+    }
+}
+
+#[test]
+fn test_read_error_kind() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+    ));
+    let mut regs = UnwindRegsX86_64::new(0x1000800, 0x8, 0x100);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut InterruptedReader,
+    );
+    assert_eq!(
+        res,
+        Err(framehop::Error::CouldNotReadStack(
+            0x100,
+            ReadMemErrorKind::Interrupted
+        ))
+    );
+}
+
+/// A stack with 4-byte slots which refuses 8-byte reads.
+struct Stack32<'a>(&'a [u32]);
+
+impl MemoryAccess for Stack32<'_> {
+    fn read_u64(&mut self, addr: u64) -> Result<u64, ReadMemError> {
+        Err(ReadMemError::new(addr, ReadMemErrorKind::Unavailable))
+    }
+
+    fn read_u32(&mut self, addr: u64) -> Result<u32, ReadMemError> {
+        self.0
+            .get((addr / 4) as usize)
+            .cloned()
+            .ok_or(ReadMemError::new(addr, ReadMemErrorKind::Unavailable))
+    }
+}
+
+#[test]
+fn test_x86_reads_u32_slots() {
+    use framehop::x86::*;
+
+    let mut cache = CacheX86::new();
+    let mut unwinder = UnwinderX86::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x10000..0x20000,
+        0x10000,
+    ));
+
+    let stack = [1, 2, 0x10, 0x10400, 0x0, 0x10300];
+    let mut memory = Stack32(&stack);
+    let mut regs = UnwindRegsX86::new(0x10800, 0x4, 0x8);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x10800).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut memory,
+    );
+    assert_eq!(res, Ok(Some(0x10400)));
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x10400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut memory,
+    );
+    assert_eq!(res, Ok(Some(0x10300)));
+    assert_eq!(regs.bp(), 0x0);
+}
+
+#[test]
+fn test_signal_trampoline() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "libc".to_string(),
+        0x1000000..0x1010000,
+        0x1000000,
+    ));
+    unwinder.set_signal_trampoline_recognizer(|address| address == 0x1000400);
+
+    // The ucontext starts at 0x10. uc_mcontext starts at 0x38, and contains rbp at 0x88,
+    // rsp at 0xb0 and rip at 0xb8.
+    let mut stack = [0; 32];
+    stack[0x88 / 8] = 0x40;
+    stack[0xb0 / 8] = 0x30;
+    stack[0xb8 / 8] = 0x1000800;
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000400, 0x10, 0x0);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000800)));
+    assert_eq!(regs.sp(), 0x30);
+    assert_eq!(regs.bp(), 0x40);
+
+    // The other entry points recognize the trampoline too.
+    let mut regs = UnwindRegsX86_64::new(0x1000400, 0x10, 0x0);
+    let res = unwinder.unwind_frame_checked(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    let outcome = res.unwrap().outcome;
+    assert_eq!(outcome.next_pc, Some(0x1000800));
+    assert_eq!(outcome.source, framehop::RuleSource::SignalFrame);
+    assert!(outcome.next_is_instruction_pointer);
+    let mut regs = UnwindRegsX86_64::new(0x1000400, 0x10, 0x0);
+    let res = unwinder.unwind_frame_with_rule(
+        UnwindRuleX86_64::UseFramePointer,
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1000800)));
+    assert_eq!(regs.sp(), 0x30);
+
+    // The interrupted instruction pointer is checked for the end of the stack.
+    unwinder.set_end_of_stack_return_addresses(vec![0x1000800]);
+    let mut regs = UnwindRegsX86_64::new(0x1000400, 0x10, 0x0);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1000400).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(None));
+}
+
+#[test]
+fn test_iter_frames_through_signal_trampoline() {
+    use fallible_iterator::FallibleIterator;
+
+    // The FDE covers the interrupted function from its first byte. The CFA is rsp+8 and
+    // the return address is at CFA-8 there.
+    let eh_frame = synthetic_eh_frame(0x800, 0x100, &[], false);
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(synthetic_module(eh_frame));
+    unwinder.set_signal_trampoline_recognizer(|address| address == 0x1000400);
+
+    // The signal interrupted the function at 0x1000800 before its first instruction ran,
+    // with rsp = 0x30 and rbp = 0x40. Its caller is at 0x1000a00, which has no FDE and is
+    // unwound with the frame pointer.
+    let mut stack = [0; 32];
+    stack[0x30 / 8] = 0x1000a00;
+    stack[0x88 / 8] = 0x40;
+    stack[0xb0 / 8] = 0x30;
+    stack[0xb8 / 8] = 0x1000800;
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let regs = UnwindRegsX86_64::new(0x1000400, 0x10, 0x0);
+    let frames: Vec<_> = unwinder
+        .iter_frames(0x1000400, regs, &mut cache, &mut read_stack)
+        .collect()
+        .unwrap();
+    assert_eq!(
+        frames,
+        vec![
+            FrameAddress::from_instruction_pointer(0x1000400),
+            FrameAddress::from_instruction_pointer(0x1000800),
+            FrameAddress::from_return_address(0x1000a00).unwrap(),
+        ]
+    );
+}
+
+#[cfg(feature = "object")]
+#[test]
+fn test_module_from_object() {
+    let data = std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/fp/nightly-firefox-bin"),
+    )
+    .unwrap();
+    let file = object::File::parse(&data[..]).unwrap();
+    let module = framehop::Module::<Vec<u8>>::from_object("firefox".to_string(), &file, 0x1000000);
+    assert_eq!(module.avma_range().start, 0x1000000);
+    assert!(module.avma_range().contains(&(0x1000000 + 0xc0db)));
+
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(module);
+
+    // The PLT stub from test_plt_cfa_expr.
+    let stack = [1, 2, 3, 4, 5, 0xa, 0x123456, 6, 7, 8, 9];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x1000000 + 0xc0db, 0x28, 0x345);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x1000000 + 0xc0db),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x123456)));
+}
+
+#[test]
+fn test_modules_and_clear_modules() {
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    for (name, start) in [("b", 0x2000000), ("a", 0x1000000)] {
+        unwinder.add_module(framehop::Module::new_framepointer_only(
+            name.to_string(),
+            start..start + 0x10000,
+            start,
+        ));
+    }
+    let ranges: Vec<_> = unwinder.modules().iter().map(|m| m.avma_range()).collect();
+    assert_eq!(ranges, vec![0x1000000..0x1010000, 0x2000000..0x2010000]);
+    assert_eq!(unwinder.modules()[1].name(), "b");
+
+    unwinder.clear_modules();
+    assert!(unwinder.modules().is_empty());
+    assert_eq!(unwinder.max_known_code_address(), 0);
+}
+
+#[test]
+fn test_no_module_for_address() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x1000000..0x1010000,
+        0x1000000,
+    ));
+
+    let stack = [1, 2, 0x40, 0x1000400, 5, 6, 7, 8, 0x0, 0x1000300];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsX86_64::new(0x3000000, 0x8, 0x10);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x3000000).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Err(framehop::Error::NoModuleForAddress(0x3000000)));
+    assert_eq!(regs, UnwindRegsX86_64::new(0x3000000, 0x8, 0x10));
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn test_no_module_lookups_are_cached() {
+    let mut cache = CacheX86_64::new();
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "libc".to_string(),
+        0x1000000..0x1010000,
+        0x1000000,
+    ));
+
+    let stack = [1, 2, 0x40, 0x3000400, 5, 6, 7, 8, 0x0, 0x1000300];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, cache: &mut CacheX86_64, pc| {
+        let mut regs = UnwindRegsX86_64::new(pc, 0x8, 0x10);
+        unwinder.unwind_frame(
+            FrameAddress::from_return_address(pc).unwrap(),
+            &mut regs,
+            cache,
+            &mut read_stack,
+        )
+    };
+
+    // Both addresses are in the JIT region after libc, so only the first one goes
+    // through the rule cache and the module lookup.
+    assert_eq!(
+        unwind(&unwinder, &mut cache, 0x3000800),
+        Err(framehop::Error::NoModuleForAddress(0x3000800))
+    );
+    assert_eq!(
+        unwind(&unwinder, &mut cache, 0x3000c00),
+        Err(framehop::Error::NoModuleForAddress(0x3000c00))
+    );
+    assert_eq!(cache.stats().total(), 1);
+
+    // Adding a module for the JIT region makes its addresses unwindable.
+    unwinder.add_module(framehop::Module::new_framepointer_only(
+        "jit".to_string(),
+        0x3000000..0x3010000,
+        0x3000000,
+    ));
+    assert_eq!(
+        unwind(&unwinder, &mut cache, 0x3000c00),
+        Ok(Some(0x3000400))
+    );
+}
+
+#[test]
+fn test_add_module_policy() {
+    use framehop::{AddModulePolicy, Error, Module};
+
+    let module = |name: &str, range: std::ops::Range<u64>| {
+        Module::<Vec<u8>>::new_framepointer_only(name.to_string(), range.clone(), range.start)
+    };
+    let names = |unwinder: &UnwinderX86_64<Vec<u8>>| -> Vec<String> {
+        unwinder
+            .modules()
+            .iter()
+            .map(|m| m.name().to_string())
+            .collect()
+    };
+
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    unwinder.add_module(module("a", 0x1000..0x3000));
+    unwinder.add_module(module("b", 0x4000..0x5000));
+
+    assert_eq!(
+        unwinder.add_module_with_policy(
+            module("c", 0x2000..0x4800),
+            AddModulePolicy::RejectOverlapping
+        ),
+        Err(Error::OverlappingModule(0x1000))
+    );
+    assert_eq!(names(&unwinder), vec!["a", "b"]);
+
+    assert_eq!(
+        unwinder.add_module_with_policy(
+            module("d", 0x2000..0x2800),
+            AddModulePolicy::AllowOverlapping
+        ),
+        Ok(())
+    );
+    assert_eq!(names(&unwinder), vec!["a", "d", "b"]);
+
+    // The default policy evicts all modules which overlap with the new one.
+    unwinder.add_module(module("e", 0x2400..0x4001));
+    assert_eq!(names(&unwinder), vec!["e"]);
+}
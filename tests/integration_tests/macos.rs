@@ -3,8 +3,8 @@ use std::path::Path;
 use fallible_iterator::FallibleIterator;
 use framehop::aarch64::*;
 use framehop::x86_64::*;
-use framehop::FrameAddress;
 use framehop::Unwinder;
+use framehop::{Error, FrameAddress, ReadMemErrorKind};
 
 use super::common;
 
@@ -73,6 +73,112 @@ fn test_basic() {
     assert_eq!(res, Ok(None));
 }
 
+#[test]
+fn test_ptr_auth_mask() {
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/fp/query-api"),
+        0x1003fc000,
+    );
+    unwinder.set_ptr_auth_mask(PtrAuthMask::new_24_40());
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 3,
+        /* 0x18: */ 4,
+        /* 0x20: */ 0x40, // stored fp
+        /* 0x28: */ 0xa5_0000_0000_0000 + 0x1003fc000 + 0x100dc4, // stored signed lr
+        /* 0x30: */ 5,
+        /* 0x38: */ 6,
+        /* 0x40: */ 0x70, // stored fp
+        /* 0x48: */ 0x1003fc000 + 0x12ca28, // stored lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsAarch64::new(0x1003fc000 + 0xe4830, 0x10, 0x20);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1003fc000 + 0xe4830).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1003fc000 + 0x100dc4)));
+    assert_eq!(regs.lr(), 0x1003fc000 + 0x100dc4);
+    assert_eq!(regs.fp(), 0x40);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1003fc000 + 0x100dc4).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x1003fc000 + 0x12ca28)));
+}
+
+#[test]
+fn test_ptr_auth_mask_checked() {
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/fp/query-api"),
+        0x1003fc000,
+    );
+    unwinder.set_ptr_auth_mask(PtrAuthMask::new_24_40());
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 3,
+        /* 0x18: */ 4,
+        /* 0x20: */ 0x40, // stored fp
+        /* 0x28: */ 0xa5_0000_0000_0000 + 0x1003fc000 + 0x100dc4, // stored signed lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsAarch64::new(0x1003fc000 + 0xe4830, 0x10, 0x20);
+    let res = unwinder.unwind_frame_checked(
+        FrameAddress::from_return_address(0x1003fc000 + 0xe4830).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    // Both the regular and the frame pointer result are stripped, so they agree.
+    let res = res.unwrap();
+    assert_eq!(res.outcome.next_pc, Some(0x1003fc000 + 0x100dc4));
+    assert_eq!(res.disagreement, None);
+    assert_eq!(regs.lr(), 0x1003fc000 + 0x100dc4);
+}
+
+#[test]
+fn test_ptr_auth_mask_end_of_stack() {
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/fp/query-api"),
+        0x1003fc000,
+    );
+    unwinder.set_ptr_auth_mask(PtrAuthMask::new_24_40());
+    // The mask is applied before the return address is compared to the end of the stack.
+    unwinder.set_end_of_stack_return_addresses(vec![0x1003fc000 + 0x100dc4]);
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 3,
+        /* 0x18: */ 4,
+        /* 0x20: */ 0x40, // stored fp
+        /* 0x28: */ 0xa5_0000_0000_0000 + 0x1003fc000 + 0x100dc4, // stored signed lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsAarch64::new(0x1003fc000 + 0xe4830, 0x10, 0x20);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_return_address(0x1003fc000 + 0xe4830).unwrap(),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(None));
+}
+
 #[test]
 fn test_root_doc_comment() {
     use framehop::aarch64::{CacheAarch64, UnwindRegsAarch64, UnwinderAarch64};
@@ -192,6 +298,98 @@ fn test_basic_iterator() {
     );
 }
 
+#[test]
+fn test_basic_iterator_for_loop() {
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/fp/query-api"),
+        0x1003fc000,
+    );
+    // Same stack as in test_basic_iterator, but truncated after the second frame record.
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 3,
+        /* 0x18: */ 4,
+        /* 0x20: */ 0x40, // stored fp
+        /* 0x28: */ 0x1003fc000 + 0x100dc4, // stored lr
+        /* 0x30: */ 5,
+        /* 0x38: */ 6,
+        /* 0x40: */ 0x70, // stored fp
+        /* 0x48: */ 0x1003fc000 + 0x12ca28, // stored lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut frames = Vec::new();
+    for frame in unwinder.iter_frames(
+        0x1003fc000 + 0x1292c0,
+        UnwindRegsAarch64::new(0x1003fc000 + 0xe4830, 0x10, 0x20),
+        &mut cache,
+        &mut read_stack,
+    ) {
+        frames.push(frame);
+    }
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_instruction_pointer(
+                0x1003fc000 + 0x1292c0
+            )),
+            Ok(FrameAddress::from_return_address(0x1003fc000 + 0xe4830).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1003fc000 + 0x100dc4).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1003fc000 + 0x12ca28).unwrap()),
+            Err(Error::CouldNotReadStack(0x78, ReadMemErrorKind::Unknown)),
+        ]
+    );
+}
+
+#[test]
+fn test_iterator_frame_limit() {
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/fp/query-api"),
+        0x1003fc000,
+    );
+    // Same stack as in test_basic_iterator_for_loop.
+    let stack = [
+        /* 0x0: */ 1,
+        /* 0x8: */ 2,
+        /* 0x10: */ 3,
+        /* 0x18: */ 4,
+        /* 0x20: */ 0x40, // stored fp
+        /* 0x28: */ 0x1003fc000 + 0x100dc4, // stored lr
+        /* 0x30: */ 5,
+        /* 0x38: */ 6,
+        /* 0x40: */ 0x70, // stored fp
+        /* 0x48: */ 0x1003fc000 + 0x12ca28, // stored lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let frames: Vec<_> = unwinder
+        .iter_frames(
+            0x1003fc000 + 0x1292c0,
+            UnwindRegsAarch64::new(0x1003fc000 + 0xe4830, 0x10, 0x20),
+            &mut cache,
+            &mut read_stack,
+        )
+        .with_limit(3)
+        .into_iter()
+        .collect();
+    assert_eq!(
+        frames,
+        vec![
+            Ok(FrameAddress::from_instruction_pointer(
+                0x1003fc000 + 0x1292c0
+            )),
+            Ok(FrameAddress::from_return_address(0x1003fc000 + 0xe4830).unwrap()),
+            Ok(FrameAddress::from_return_address(0x1003fc000 + 0x100dc4).unwrap()),
+            Err(Error::FrameLimitReached),
+        ]
+    );
+}
+
 #[test]
 fn test_epilogue() {
     // This test checks that we don't blindly trust the "use framepointer" __unwind_info
@@ -380,6 +578,41 @@ fn test_prologue_nofp() {
     do_check(pc, UnwindRegsAarch64::new(0xe4830, 0x20, 0x1e0), &s);
 }
 
+// In rustup's __unwind_info, the function at 0x471c has a UNWIND_ARM64_MODE_DWARF entry
+// which refers to the FDE at __eh_frame offset 0x38:
+//
+//   0x10000471c: CFA=reg31
+//   0x100004724: CFA=reg31+32: reg19=[CFA-32], reg20=[CFA-24], reg29=[CFA-16], reg30=[CFA-8]
+#[test]
+fn test_compact_unwind_dwarf_mode_arm64() {
+    use framehop::RuleSource;
+
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/macos/arm64/nofp/rustup"),
+        0,
+    );
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 2, /* 0x10: */ 0x40, // parent fp
+        /* 0x18: */ 0x9876, // parent lr
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut regs = UnwindRegsAarch64::new(0x4740, 0x0, 0x1234);
+    let res = unwinder
+        .unwind_frame_detailed(
+            FrameAddress::from_return_address(0x4740).unwrap(),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        )
+        .map(|outcome| (outcome.next_pc, outcome.source));
+    assert_eq!(res, Ok((Some(0x9876), RuleSource::Dwarf)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.fp(), 0x40);
+}
+
 fn make_stack(size_in_bytes: usize) -> Vec<u64> {
     let ptr_count = size_in_bytes / 8;
     (0..ptr_count).map(|i| 0xf000 + i as u64 * 8).collect()
@@ -1144,3 +1377,102 @@ fn test_go_debug_frame() {
     );
     assert_eq!(res, Ok(None));
 }
+
+/// Build an `__unwind_info` section with one regular second-level page. `functions`
+/// are (function start address, opcode) pairs, sorted by address, and `end` is the
+/// end of the last function. Addresses are relative to the image base.
+fn synthetic_unwind_info(functions: &[(u32, u32)], end: u32) -> Vec<u8> {
+    let header_len = 28;
+    let pages_len = 2 * 12;
+    let page_offset = header_len + pages_len;
+
+    let mut unwind_info = vec![];
+    for value in [1, header_len, 0, header_len, 0, header_len, 2] {
+        unwind_info.extend_from_slice(&u32::to_le_bytes(value));
+    }
+    // The page entry and the sentinel page entry for the end address.
+    for value in [functions[0].0, page_offset, 0, end, 0, 0] {
+        unwind_info.extend_from_slice(&u32::to_le_bytes(value));
+    }
+    unwind_info.extend_from_slice(&2u32.to_le_bytes()); // regular page
+    unwind_info.extend_from_slice(&8u16.to_le_bytes()); // functions offset
+    unwind_info.extend_from_slice(&(functions.len() as u16).to_le_bytes());
+    for (address, opcode) in functions {
+        unwind_info.extend_from_slice(&address.to_le_bytes());
+        unwind_info.extend_from_slice(&opcode.to_le_bytes());
+    }
+    unwind_info
+}
+
+#[test]
+fn test_unwind_source_preference() {
+    use framehop::{RuleSource, UnwindSourcePreference};
+
+    // Three functions in __unwind_info: 0x100 uses the frame pointer, 0x120 has no
+    // info (null opcode), and 0x140 uses the frame pointer again.
+    let unwind_info = synthetic_unwind_info(
+        &[(0x100, 0x01000000), (0x120, 0), (0x140, 0x01000000)],
+        0x160,
+    );
+    // __eh_frame only covers the first two functions, with CFA=rsp+16: rip=[CFA-8].
+    let eh_frame = super::linux::synthetic_eh_frame(0x100, 0x40, &[0x0e, 0x10], false);
+    let mut unwinder: UnwinderX86_64<Vec<u8>> = UnwinderX86_64::new();
+    unwinder.add_module(framehop::Module::new(
+        "synthetic".to_string(),
+        0x1000000..0x1001000,
+        0x1000000,
+        framehop::ExplicitModuleSectionInfo {
+            base_svma: 0,
+            unwind_info: Some(unwind_info),
+            eh_frame_svma: Some(0x800..0x800 + eh_frame.len() as u64),
+            eh_frame: Some(eh_frame),
+            ..Default::default()
+        },
+    ));
+    let mut cache = CacheX86_64::<_>::new();
+    let stack = [
+        /* 0x0: */ 1, /* 0x8: */ 0x1000400, // return address for CFA=rsp+16
+        /* 0x10: */ 0x20, // stored bp
+        /* 0x18: */ 0x1000500, // return address for the frame pointer
+        /* 0x20: */ 0,
+    ];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+    let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, return_address| {
+        let mut regs = UnwindRegsX86_64::new(return_address, 0x0, 0x10);
+        unwinder
+            .unwind_frame_detailed(
+                FrameAddress::from_return_address(return_address).unwrap(),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+            .map(|outcome| (outcome.next_pc, outcome.source))
+    };
+
+    assert_eq!(
+        unwind(&unwinder, 0x1000110),
+        Ok((Some(0x1000500), RuleSource::CompactUnwind))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x1000130),
+        Ok((Some(0x1000400), RuleSource::DwarfFallback))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x1000150),
+        Ok((Some(0x1000500), RuleSource::CompactUnwind))
+    );
+
+    unwinder.set_unwind_source_preference(UnwindSourcePreference::PreferDwarf);
+    assert_eq!(
+        unwind(&unwinder, 0x1000110),
+        Ok((Some(0x1000400), RuleSource::Dwarf))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x1000130),
+        Ok((Some(0x1000400), RuleSource::Dwarf))
+    );
+    assert_eq!(
+        unwind(&unwinder, 0x1000150),
+        Ok((Some(0x1000500), RuleSource::CompactUnwindFallback))
+    );
+}
@@ -0,0 +1,296 @@
+//! Checks that unwinding with a `MustNotAllocateDuringUnwind` cache doesn't allocate.
+//!
+//! This is a separate test binary because it installs a global allocator. The allocator
+//! only counts allocations on threads which have opted in, so that the test harness and
+//! concurrently running tests don't affect the count.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::future::Future;
+use std::task::{Context, Poll, Waker};
+
+use framehop::test_utils::{module_with_eh_frame, CfiInstruction, EhFrameBuilder};
+use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+use framehop::{AsyncUnwinder, FrameAddress, MustNotAllocateDuringUnwind, Unwinder};
+
+struct CountingAllocator;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATION_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count_allocation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+fn count_allocation() {
+    // try_with, because the thread-locals may already be gone during thread teardown.
+    let _ = COUNTING.try_with(|counting| {
+        if counting.get() {
+            ALLOCATION_COUNT.with(|count| count.set(count.get() + 1));
+        }
+    });
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Runs `f` and returns its result together with the number of allocations it made on
+/// the current thread.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    ALLOCATION_COUNT.with(|count| count.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let result = f();
+    COUNTING.with(|counting| counting.set(false));
+    (result, ALLOCATION_COUNT.with(|count| count.get()))
+}
+
+/// Drives `future` to completion on the current thread, and returns its output together
+/// with the number of times it was polled.
+fn block_on<T>(future: impl Future<Output = T>) -> (T, usize) {
+    let mut future = std::pin::pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(value) = future.as_mut().poll(&mut context) {
+            return (value, polls);
+        }
+    }
+}
+
+/// An unwinder with a module whose code at 0x1000100..0x1000120 is covered by an FDE with
+/// the given instructions.
+fn unwinder_with_fde(
+    fde_instructions: &[CfiInstruction],
+) -> UnwinderX86_64<Vec<u8>, MustNotAllocateDuringUnwind> {
+    let mut builder = EhFrameBuilder::x86_64();
+    builder.add_fde(0x100..0x120, fde_instructions);
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(module_with_eh_frame(
+        "synthetic",
+        0x1000000..0x1001000,
+        builder.build(),
+    ));
+    unwinder
+}
+
+#[test]
+fn test_counting_allocator() {
+    let (_, allocations) = count_allocations(|| std::hint::black_box(vec![1u8]));
+    assert_eq!(allocations, 1);
+}
+
+#[test]
+fn test_rule_fast_path_does_not_allocate() {
+    // CFA=rsp+16, and rbp at CFA-16. This can be translated into an unwind rule, which
+    // is cached after the first lookup.
+    let unwinder = unwinder_with_fde(&[
+        CfiInstruction::DefCfaOffset(16),
+        CfiInstruction::Offset {
+            register: 6,
+            offset: -16,
+        },
+    ]);
+    let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+    let stack = [1, 2, 0x30, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    for _ in 0..2 {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+        let (res, allocations) = count_allocations(|| {
+            unwinder.unwind_frame(
+                FrameAddress::from_instruction_pointer(0x1000104),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+        });
+        assert_eq!(res, Ok(Some(0x1000400)));
+        assert_eq!(regs.sp(), 0x20);
+        assert_eq!(regs.bp(), 0x30);
+        assert_eq!(allocations, 0);
+    }
+}
+
+#[test]
+fn test_dwarf_slow_path_does_not_allocate() {
+    // CFA=DW_OP_breg7 (rsp) +16. Expressions can't be translated into unwind rules, so
+    // every unwind evaluates the CFI with gimli.
+    let unwinder = unwinder_with_fde(&[CfiInstruction::DefCfaExpression(&[0x77, 0x10])]);
+    let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    for _ in 0..2 {
+        let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+        let (res, allocations) = count_allocations(|| {
+            unwinder.unwind_frame(
+                FrameAddress::from_instruction_pointer(0x1000104),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+        });
+        assert_eq!(res, Ok(Some(0x1000400)));
+        assert_eq!(regs.sp(), 0x20);
+        assert_eq!(allocations, 0);
+    }
+}
+
+#[test]
+fn test_unwind_context_at_its_limits_does_not_allocate() {
+    // DW_CFA_remember_state is nested as deep as the unwind context storage allows, and
+    // the row at 0x1000110 has the rules from before the first DW_CFA_remember_state.
+    let mut instructions = vec![
+        CfiInstruction::DefCfaOffset(16),
+        CfiInstruction::Offset {
+            register: 6,
+            offset: -16,
+        },
+    ];
+    instructions.extend([CfiInstruction::RememberState; 3]);
+    instructions.extend([
+        CfiInstruction::AdvanceLoc(4),
+        CfiInstruction::DefCfaOffset(32),
+    ]);
+    instructions.extend([CfiInstruction::RestoreState; 3]);
+    instructions.push(CfiInstruction::AdvanceLoc(4));
+    // 190 more register rules, for a total of 192 with rbp and the return address.
+    instructions.extend((17..207).map(CfiInstruction::SameValue));
+    let unwinder = unwinder_with_fde(&instructions);
+    let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+    let stack = [1, 2, 0x30, 0x1000400, 5];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut regs = UnwindRegsX86_64::new(0x1000110, 0x10, 0x1234);
+    let (res, allocations) = count_allocations(|| {
+        unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x1000110),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        )
+    });
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(regs.bp(), 0x30);
+    assert_eq!(allocations, 0);
+}
+
+#[test]
+fn test_unwind_context_beyond_its_limits_does_not_allocate() {
+    // Each FDE exceeds one of the limits of the unwind context storage, so its evaluation
+    // fails, and the address is unwound as if no FDE covered it: in the first frame, the
+    // return address is at rsp.
+    let too_deep = [CfiInstruction::RememberState; 4].to_vec();
+    let too_many_registers = (17..210).map(CfiInstruction::SameValue).collect();
+    for instructions in [too_deep, too_many_registers] {
+        let unwinder = unwinder_with_fde(&instructions);
+        let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+        let stack = [1, 2, 0x30, 0x1000400, 5];
+        let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+        for _ in 0..2 {
+            let mut regs = UnwindRegsX86_64::new(0x1000104, 0x18, 0x1234);
+            let (res, allocations) = count_allocations(|| {
+                unwinder.unwind_frame(
+                    FrameAddress::from_instruction_pointer(0x1000104),
+                    &mut regs,
+                    &mut cache,
+                    &mut read_stack,
+                )
+            });
+            assert_eq!(res, Ok(Some(0x1000400)));
+            assert_eq!(regs.sp(), 0x20);
+            assert_eq!(regs.bp(), 0x1234);
+            assert_eq!(allocations, 0);
+        }
+    }
+}
+
+#[test]
+fn test_unwind_stack_into_does_not_allocate() {
+    let unwinder = unwinder_with_fde(&[
+        CfiInstruction::DefCfaOffset(16),
+        CfiInstruction::Offset {
+            register: 6,
+            offset: -16,
+        },
+    ]);
+    let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+    // The second frame is unwound with the frame pointer and has a null return address.
+    let stack = [1, 2, 0x30, 0x1000400, 5, 6, 0x40, 0];
+    let mut read_stack = |addr| stack.get((addr / 8) as usize).cloned().ok_or(());
+
+    let mut out = [0; 4];
+    let regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let (outcome, allocations) = count_allocations(|| {
+        unwinder.unwind_stack_into(0x1000104, regs, &mut cache, &mut read_stack, &mut out)
+    });
+    assert_eq!(outcome.frame_count, 2);
+    assert_eq!(outcome.error, None);
+    assert_eq!(out[..2], [0x1000104, 0x1000400]);
+    assert_eq!(allocations, 0);
+
+    let mut out = [0; 1];
+    let outcome =
+        unwinder.unwind_stack_into(0x1000104, regs, &mut cache, &mut read_stack, &mut out);
+    assert_eq!(outcome.frame_count, 1);
+    assert!(outcome.is_truncated());
+}
+
+#[test]
+fn test_async_dwarf_slow_path_does_not_allocate() {
+    // Same CFI as in test_dwarf_slow_path_does_not_allocate, but each read is pending
+    // once before its value arrives.
+    let unwinder = unwinder_with_fde(&[CfiInstruction::DefCfaExpression(&[0x77, 0x10])]);
+    let mut cache = CacheX86_64::<MustNotAllocateDuringUnwind>::new_in();
+    let stack = [1, 2, 3, 0x1000400, 5];
+    let mut requested = Vec::with_capacity(4);
+    let mut read_stack = |addr| {
+        requested.push(addr);
+        let value = stack.get((addr / 8) as usize).cloned().ok_or(());
+        let mut arrived = false;
+        std::future::poll_fn(move |_| match std::mem::replace(&mut arrived, true) {
+            true => Poll::Ready(value),
+            false => Poll::Pending,
+        })
+    };
+
+    let mut regs = UnwindRegsX86_64::new(0x1000104, 0x10, 0x1234);
+    let future = unwinder.unwind_frame_async(
+        FrameAddress::from_instruction_pointer(0x1000104),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    fn assert_send<T: Send>(_: &T) {}
+    assert_send(&future);
+    let ((res, polls), allocations) = count_allocations(|| block_on(future));
+    assert_eq!(res, Ok(Some(0x1000400)));
+    assert_eq!(regs.sp(), 0x20);
+    assert_eq!(allocations, 0);
+    // The return address at CFA-8 is the only read. The unwinder waits for it once and
+    // then continues where it left off.
+    assert_eq!(requested, [0x18]);
+    assert_eq!(polls, 2);
+}